@@ -0,0 +1,80 @@
+//! Building the contexts of a 3000 transaction block independently, then
+//! through a `ContextFactory` recycling each context once done with it. Heap
+//! allocations are counted by a wrapper around the system allocator.
+//!
+//! Run with `cargo bench --bench context_factory`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::{ContextFactory, TransactionContext};
+
+const TRANSACTIONS: usize = 3000;
+const ITERATIONS: usize = 20;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// A swap between a few token accounts, one of them created by the transaction
+fn transaction(seed: usize) -> ConfirmedTransaction {
+    let key = |i: usize| pubkey((seed * 7 + i) as u8);
+    let (user, mint, created) = (key(0), key(1), key(2));
+    let builder = TransactionBuilder::new()
+        .signer(user)
+        .instruction(TOKEN_PROGRAM_ID, &[created, mint], [vec![18], user.0.to_vec()].concat())
+        .signature(&[(seed % 256) as u8; 64]);
+    (3..8).fold(builder, |builder, i| builder.token_account(key(i), mint, user, Some(i as u64), Some(0))).build()
+}
+
+fn bench<'a, F: FnMut(&'a ConfirmedTransaction) -> usize>(name: &str, block: &'a [ConfirmedTransaction], mut f: F) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut accounts = 0;
+    for _ in 0..ITERATIONS {
+        accounts = block.iter().map(|transaction| black_box(f(transaction))).sum::<usize>();
+    }
+    let elapsed = start.elapsed() / ITERATIONS as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS;
+    println!("{name:<24} {elapsed:>12?} and {allocations:>7} allocations per block, {accounts} token accounts");
+}
+
+fn main() {
+    let block: Vec<_> = (0..TRANSACTIONS).map(transaction).collect();
+
+    bench("independent builds", &block, |transaction| {
+        TransactionContext::build(transaction).unwrap().token_accounts.len()
+    });
+
+    let mut factory = ContextFactory::new();
+    bench("context factory", &block, |transaction| {
+        let context = factory.context_for(transaction).unwrap();
+        let accounts = context.token_accounts.len();
+        factory.recycle(context);
+        accounts
+    });
+}
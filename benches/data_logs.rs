@@ -0,0 +1,76 @@
+//! Structuring a block of event-heavy transactions, then decoding the events of
+//! every instruction, or only those of the program a handler cares about.
+//! `Program data` payloads are decoded on first access, so events filtered out
+//! by program id are never decoded.
+//!
+//! Run with `cargo bench --bench data_logs`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const PUMP_FUN: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+const TRANSACTIONS: usize = 500;
+const SWAPS: usize = 4;
+const EVENTS_PER_SWAP: usize = 10;
+const EVENT_LEN: usize = 256;
+const ITERATIONS: u32 = 20;
+
+/// A Jupiter route through pump.fun swaps, each swap emitting several events
+fn transaction(seed: usize) -> ConfirmedTransaction {
+    let (jupiter, pump_fun) = (Pubkey::try_from_string(JUPITER).unwrap(), Pubkey::try_from_string(PUMP_FUN).unwrap());
+    let user = pubkey(1);
+    let event = |i: usize| base64::engine::general_purpose::STANDARD.encode(vec![(seed + i) as u8; EVENT_LEN]);
+
+    let mut logs = vec![format!("Program {JUPITER} invoke [1]"), "Program log: Instruction: Route".to_string()];
+    let mut builder = TransactionBuilder::new().signer(user).instruction(jupiter, &[user, pump_fun], vec![0]);
+    for swap in 0..SWAPS {
+        builder = builder.inner(pump_fun, &[user], vec![swap as u8]);
+        logs.push(format!("Program {PUMP_FUN} invoke [2]"));
+        logs.extend((0..EVENTS_PER_SWAP).map(|i| format!("Program data: {}", event(swap * EVENTS_PER_SWAP + i))));
+        logs.push(format!("Program {PUMP_FUN} success"));
+    }
+    logs.push(format!("Program data: {}", event(0)));
+    logs.push(format!("Program {JUPITER} success"));
+    builder.logs(&logs.iter().map(String::as_str).collect::<Vec<_>>()).build()
+}
+
+fn bench<F: Fn(&ConfirmedTransaction) -> usize>(name: &str, block: &[ConfirmedTransaction], f: F) -> Duration {
+    let start = Instant::now();
+    let mut events = 0;
+    for _ in 0..ITERATIONS {
+        events = block.iter().map(|transaction| black_box(f(transaction))).sum::<usize>();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{name:<24} {elapsed:>12?} per block, {events} events decoded");
+    elapsed
+}
+
+fn main() {
+    let jupiter = Pubkey::try_from_string(JUPITER).unwrap();
+    let block: Vec<_> = (0..TRANSACTIONS).map(transaction).collect();
+
+    let structure = bench("structure only", &block, |transaction| {
+        black_box(get_structured_instructions(transaction).unwrap());
+        0
+    });
+    let all = bench("decode every event", &block, |transaction| {
+        let instructions = get_structured_instructions(transaction).unwrap();
+        instructions.flattened().iter().map(|instruction| instruction.events().len()).sum()
+    });
+    let filtered = bench("decode Jupiter events", &block, |transaction| {
+        let instructions = get_structured_instructions(transaction).unwrap();
+        instructions.flattened().iter()
+            .filter(|instruction| instruction.program_id() == jupiter)
+            .map(|instruction| instruction.events().len())
+            .sum()
+    });
+    println!("decoding saved by filtering: {:?} per block", all.saturating_sub(filtered));
+    println!("decoding share of the unfiltered run: {:.0}%", 100.0 * all.saturating_sub(structure).as_secs_f64() / all.as_secs_f64());
+}
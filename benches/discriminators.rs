@@ -0,0 +1,48 @@
+//! Matching instruction data against a list of 8-byte discriminators, by a
+//! linear scan of `starts_with` as protocol detection does, and through a
+//! `DiscriminatorSet`. Sizes range from a protocol table of a few instructions
+//! to an indexer matching thousands of discriminators.
+//!
+//! Run with `cargo bench --bench discriminators`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use substreams_solana_utils::programs::{anchor_sighash, DiscriminatorSet};
+
+const SIZES: &[usize] = &[4, 16, 128, 1024];
+const INSTRUCTIONS: usize = 100_000;
+const ITERATIONS: u32 = 20;
+
+/// Instruction data, one in four starting with one of the `discriminators`
+fn instructions(discriminators: &[[u8; 8]]) -> Vec<Vec<u8>> {
+    (0..INSTRUCTIONS).map(|i| match i % 4 {
+        0 => [&discriminators[i % discriminators.len()][..], &[1; 24]].concat(),
+        1 => vec![(i % 16) as u8; 9],
+        _ => [&anchor_sighash("global", &format!("unknown_{i}"))[..], &[2; 24]].concat(),
+    }).collect()
+}
+
+fn bench<F: Fn(&[u8]) -> bool>(name: &str, instructions: &[Vec<u8>], f: F) -> Duration {
+    let start = Instant::now();
+    let mut matches = 0;
+    for _ in 0..ITERATIONS {
+        matches = instructions.iter().filter(|data| black_box(f(data))).count();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{name:<24} {elapsed:>12?} per {INSTRUCTIONS} instructions, {matches} matches");
+    elapsed
+}
+
+fn main() {
+    for &size in SIZES {
+        let discriminators: Vec<[u8; 8]> = (0..size).map(|i| anchor_sighash("global", &format!("instruction_{i}"))).collect();
+        let set = DiscriminatorSet::new(discriminators.iter().copied());
+        let instructions = instructions(&discriminators);
+
+        println!("{size} discriminators");
+        let linear = bench("  linear starts_with", &instructions, |data| discriminators.iter().any(|x| data.starts_with(x)));
+        let sorted = bench("  DiscriminatorSet", &instructions, |data| set.matches(data));
+        println!("  speedup of the set: {:.1}x", linear.as_secs_f64() / sorted.as_secs_f64());
+    }
+}
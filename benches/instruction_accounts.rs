@@ -0,0 +1,93 @@
+//! Structuring a block of instruction-heavy transactions and counting the
+//! instructions of one program, without reading any account, reading one
+//! account through `account_at`, then reading the `accounts` of every
+//! instruction, as structuring used to. Accounts are only looked up when read,
+//! so the first two save one allocation per instruction. Heap allocations are
+//! counted by a wrapper around the system allocator.
+//!
+//! Run with `cargo bench --bench instruction_accounts`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const TRANSACTIONS: usize = 200;
+const INSTRUCTIONS: usize = 200;
+const ITERATIONS: usize = 20;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Top level instructions of a few programs, each with several accounts, every
+/// fourth one making a token transfer through CPI
+fn transaction(seed: usize, program: Pubkey) -> ConfirmedTransaction {
+    let key = |i: usize| pubkey((seed * 13 + i) as u8);
+    let (user, source, destination) = (key(0), key(1), key(2));
+    let transfer = [vec![3], 100u64.to_le_bytes().to_vec()].concat();
+    let mut builder = TransactionBuilder::new().signer(user);
+    for i in 0..INSTRUCTIONS {
+        let accounts = [user, source, destination, key(3 + i % 5), key(8 + i % 3)];
+        builder = match i % 4 {
+            0 => builder.instruction(program, &accounts, vec![i as u8]).inner(TOKEN_PROGRAM_ID, &[source, destination, user], transfer.clone()),
+            _ => builder.instruction(key(11 + i % 3), &accounts, vec![i as u8]),
+        };
+    }
+    builder.build()
+}
+
+fn bench<F: Fn(&ConfirmedTransaction) -> usize>(name: &str, block: &[ConfirmedTransaction], f: F) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut matched = 0;
+    for _ in 0..ITERATIONS {
+        matched = block.iter().map(|transaction| black_box(f(transaction))).sum::<usize>();
+    }
+    let elapsed = start.elapsed() / ITERATIONS as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS;
+    println!("{name:<20} {elapsed:>12?} and {allocations:>7} allocations per block, {matched} matched");
+}
+
+fn main() {
+    let program = pubkey(200);
+    let block: Vec<_> = (0..TRANSACTIONS).map(|seed| transaction(seed, program)).collect();
+
+    bench("program id only", &block, |transaction| {
+        let instructions = get_structured_instructions(transaction).unwrap();
+        instructions.flattened().iter().filter(|x| x.program_id() == program).count()
+    });
+    bench("account_at", &block, |transaction| {
+        let instructions = get_structured_instructions(transaction).unwrap();
+        instructions.flattened().iter().filter(|x| x.program_id() == program && x.account_at(0).is_some()).count()
+    });
+    bench("all accounts", &block, |transaction| {
+        let instructions = get_structured_instructions(transaction).unwrap();
+        instructions.flattened().iter().filter(|x| !x.accounts().is_empty() && x.program_id() == program).count()
+    });
+}
@@ -0,0 +1,112 @@
+//! Structuring a block of swap transactions into `Rc` trees, then into arena
+//! trees, reading the accounts and logs of every instruction in flattened
+//! order. Heap allocations are counted by a wrapper around the system
+//! allocator. Both trees are checked to flatten in the same order first.
+//!
+//! Run with `cargo bench --bench instruction_tree`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+use substreams_solana_utils::instruction_tree::get_instruction_tree;
+use substreams_solana_utils::programs::KnownProgram;
+use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const TRANSACTIONS: usize = 500;
+const SWAPS: usize = 8;
+const TRANSFERS_PER_SWAP: usize = 3;
+const ITERATIONS: usize = 20;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// A compute budget instruction, then a route through several pools, each
+/// making token transfers at stack height 3, with the logs of every invocation
+fn transaction(seed: usize) -> ConfirmedTransaction {
+    let key = |i: usize| pubkey((seed * 11 + i) as u8);
+    let (user, router, vault) = (key(0), key(1), key(2));
+    let compute_budget = KnownProgram::ComputeBudget.program_id();
+    let transfer = [vec![3], 100u64.to_le_bytes().to_vec()].concat();
+    let invoke = |program: String, depth: usize| vec![format!("Program {program} invoke [{depth}]")];
+    let success = |program: String| vec![format!("Program {program} consumed 2000 of 200000 compute units"), format!("Program {program} success")];
+
+    let mut builder = TransactionBuilder::new()
+        .signer(user)
+        .instruction(compute_budget, &[], vec![2, 0, 0, 0, 0])
+        .instruction(router, &[user, vault], vec![0]);
+    let mut logs = [invoke(compute_budget.to_string(), 1), success(compute_budget.to_string()), invoke(router.to_string(), 1)].concat();
+    logs.push("Program log: Instruction: Route".to_string());
+    for swap in 0..SWAPS {
+        let pool = key(3 + swap);
+        builder = builder.inner(pool, &[user, vault], vec![swap as u8]);
+        logs.extend(invoke(pool.to_string(), 2));
+        logs.push("Program log: Instruction: Swap".to_string());
+        for _ in 0..TRANSFERS_PER_SWAP {
+            builder = builder.inner_at(3, TOKEN_PROGRAM_ID, &[vault, user, pool], transfer.clone());
+            logs.extend(invoke(TOKEN_PROGRAM_ID.to_string(), 3));
+            logs.push("Program log: Instruction: Transfer".to_string());
+            logs.extend(success(TOKEN_PROGRAM_ID.to_string()));
+        }
+        logs.extend(success(pool.to_string()));
+    }
+    logs.extend(success(router.to_string()));
+    builder.logs(&logs.iter().map(String::as_str).collect::<Vec<_>>()).build()
+}
+
+fn bench<F: Fn(&ConfirmedTransaction) -> usize>(name: &str, block: &[ConfirmedTransaction], f: F) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut read = 0;
+    for _ in 0..ITERATIONS {
+        read = block.iter().map(|transaction| black_box(f(transaction))).sum::<usize>();
+    }
+    let elapsed = start.elapsed() / ITERATIONS as u32;
+    let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS;
+    println!("{name:<12} {elapsed:>12?} and {allocations:>7} allocations per block, {read} accounts and logs read");
+}
+
+fn main() {
+    let block: Vec<_> = (0..TRANSACTIONS).map(transaction).collect();
+
+    for transaction in &block {
+        let instructions = get_structured_instructions(transaction).unwrap();
+        let tree = get_instruction_tree(transaction).unwrap();
+        let rc_order: Vec<_> = instructions.flattened().iter().map(|x| (x.program_id(), x.data().clone(), x.parent_instruction().map(|x| x.flat_index() as u32))).collect();
+        let arena_order: Vec<_> = tree.flattened().iter().map(|x| (x.program_id(), x.data().clone(), x.parent())).collect();
+        assert!(rc_order == arena_order, "arena tree flattened in another order");
+    }
+
+    bench("Rc tree", &block, |transaction| {
+        let instructions = get_structured_instructions(transaction).unwrap();
+        instructions.flattened().iter().map(|x| x.accounts().len() + x.logs().as_ref().map_or(0, Vec::len)).sum()
+    });
+    bench("arena tree", &block, |transaction| {
+        let tree = get_instruction_tree(transaction).unwrap();
+        tree.flattened().iter().map(|x| x.accounts().len() + x.logs().map_or(0, Vec::len)).sum()
+    });
+}
@@ -0,0 +1,98 @@
+//! Parsing a realistic 200-line log vector: classifying each line, then reading
+//! the message of `Program log` lines and decoding the payload of `Program
+//! data` and `Program return` lines. Before, each of these compiled a regex on
+//! every call; the same patterns are reproduced here with the `regex` crate.
+//! After, the payloads are sliced off their prefix.
+//!
+//! Run with `cargo bench --bench log_parsing`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use regex::Regex;
+use substreams_solana_utils::log::Log;
+
+const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const WHIRLPOOL: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGvk1ygAnUk";
+const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGNMYWsEs31ZNvDMfxTnoj3U";
+const LINES: usize = 200;
+const ITERATIONS: u32 = 50;
+
+/// The logs of a Jupiter route through Whirlpool swaps, each swap making two
+/// token transfers and emitting an event, cut to `LINES` lines
+fn logs() -> Vec<String> {
+    let mut logs = vec![format!("Program {JUPITER} invoke [1]"), "Program log: Instruction: Route".to_string()];
+    let mut swap = 0u8;
+    while logs.len() < LINES {
+        logs.push(format!("Program {WHIRLPOOL} invoke [2]"));
+        logs.push("Program log: Instruction: Swap".to_string());
+        for _ in 0..2 {
+            logs.push(format!("Program {TOKEN} invoke [3]"));
+            logs.push("Program log: Instruction: Transfer".to_string());
+            logs.push(format!("Program {TOKEN} consumed 4645 of 1326587 compute units"));
+            logs.push(format!("Program {TOKEN} success"));
+        }
+        logs.push(format!("Program data: {}", STANDARD.encode([swap; 96])));
+        logs.push(format!("Program {WHIRLPOOL} consumed 45123 of 1360012 compute units"));
+        logs.push(format!("Program return: {WHIRLPOOL} {}", STANDARD.encode([swap; 8])));
+        logs.push(format!("Program {WHIRLPOOL} success"));
+        swap = swap.wrapping_add(1);
+    }
+    logs.truncate(LINES);
+    logs
+}
+
+/// Parsing as before, with a regex compiled on every call
+fn parse_with_regexes(line: &str) -> usize {
+    let log = Log::new(line);
+    if log.is_program() {
+        let captures = Regex::new(r"Program log: (.+)").unwrap().captures(line);
+        captures.map_or(0, |x| x.get(1).unwrap().as_str().len())
+    } else if log.is_data() {
+        let captures = Regex::new(r"Program data: (.+)").unwrap().captures(line);
+        captures.and_then(|x| STANDARD.decode(x.get(1).unwrap().as_str()).ok()).map_or(0, |x| x.len())
+    } else if log.is_return() {
+        // `program_id` and `data` each compiled their own
+        let program_id = Regex::new(r"Program return: (.+) (.+)").unwrap().captures(line);
+        let data = Regex::new(r"Program return: (.+) (.+)").unwrap().captures(line);
+        program_id.map_or(0, |x| x.get(1).unwrap().as_str().len())
+            + data.and_then(|x| STANDARD.decode(x.get(2).unwrap().as_str()).ok()).map_or(0, |x| x.len())
+    } else {
+        0
+    }
+}
+
+fn parse(line: &str) -> usize {
+    let log = Log::new(line);
+    if let Some(program_log) = log.as_program() {
+        program_log.message().map_or(0, str::len)
+    } else if let Some(data_log) = log.as_data() {
+        data_log.data().map_or(0, |x| x.len())
+    } else if let Some(return_log) = log.as_return() {
+        return_log.program_id().map_or(0, str::len) + return_log.data().map_or(0, |x| x.len())
+    } else {
+        0
+    }
+}
+
+fn bench<F: Fn(&str) -> usize>(name: &str, logs: &[String], f: F) -> Duration {
+    let start = Instant::now();
+    let mut parsed = 0;
+    for _ in 0..ITERATIONS {
+        parsed = logs.iter().map(|line| black_box(f(line))).sum::<usize>();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{name:<16} {elapsed:>12?} per {LINES} lines, {parsed} bytes parsed");
+    elapsed
+}
+
+fn main() {
+    let logs = logs();
+    assert_eq!(logs.iter().map(|x| parse_with_regexes(x)).collect::<Vec<_>>(), logs.iter().map(|x| parse(x)).collect::<Vec<_>>());
+
+    let before = bench("regexes", &logs, parse_with_regexes);
+    let after = bench("prefixes", &logs, parse);
+    println!("{:.0}x faster without regexes", before.as_secs_f64() / after.as_secs_f64());
+}
@@ -0,0 +1,77 @@
+//! Building the contexts of a block mostly made of transactions involving no
+//! token program, whose instructions are not scanned, against the same block
+//! once every transaction lists the token program and has to be scanned. Blocks
+//! of transactions reaching the token program only through CPI, or only through
+//! a lookup table, are scanned either way and shown for comparison.
+//!
+//! Run with `cargo bench --bench token_tracking`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::programs::KnownProgram;
+use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::TransactionContext;
+
+const TRANSACTIONS: usize = 3000;
+const INSTRUCTIONS: usize = 12;
+const ITERATIONS: u32 = 20;
+
+/// How a transaction reaches the token program
+#[derive(Clone, Copy)]
+enum TokenProgram {
+    None,
+    Listed,
+    ThroughCpi,
+    ThroughLookupTable,
+}
+
+/// A transaction of several system transfers and memos, then a router
+/// instruction initializing a token account through CPI unless `token_program`
+/// is `None` or `Listed`
+fn transaction(seed: usize, token_program: TokenProgram) -> ConfirmedTransaction {
+    let key = |i: usize| pubkey((seed * 5 + i) as u8);
+    let (user, recipient, router, account, mint) = (key(0), key(1), key(2), key(3), key(4));
+    let transfer = [2u32.to_le_bytes().to_vec(), 1_000u64.to_le_bytes().to_vec()].concat();
+    let mut builder = TransactionBuilder::new().signer(user).signature(&[(seed % 256) as u8; 64]);
+    for i in 0..INSTRUCTIONS {
+        builder = match i % 2 {
+            0 => builder.instruction(KnownProgram::System.program_id(), &[user, recipient], transfer.clone()),
+            _ => builder.instruction(KnownProgram::Memo.program_id(), &[user], b"memo".to_vec()),
+        };
+    }
+    let initialize = |builder: TransactionBuilder| builder
+        .instruction(router, &[user, account, mint], vec![0])
+        .inner(TOKEN_PROGRAM_ID, &[account, mint], [vec![18], user.0.to_vec()].concat());
+    match token_program {
+        TokenProgram::None => builder,
+        TokenProgram::Listed => builder.readonly(TOKEN_PROGRAM_ID),
+        TokenProgram::ThroughCpi => initialize(builder),
+        TokenProgram::ThroughLookupTable => initialize(builder.lookup(TOKEN_PROGRAM_ID, false).lookup(router, false)),
+    }.build()
+}
+
+fn block(token_program: TokenProgram) -> Vec<ConfirmedTransaction> {
+    (0..TRANSACTIONS).map(|seed| transaction(seed, token_program)).collect()
+}
+
+fn bench(name: &str, block: &[ConfirmedTransaction]) -> Duration {
+    let start = Instant::now();
+    let mut skipped = 0;
+    for _ in 0..ITERATIONS {
+        skipped = block.iter().filter(|transaction| black_box(TransactionContext::build(transaction).unwrap()).token_tracking_skipped).count();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{name:<32} {elapsed:>12?} per {TRANSACTIONS} transactions, {skipped} skipped");
+    elapsed
+}
+
+fn main() {
+    let skipped = bench("no token program", &block(TokenProgram::None));
+    let scanned = bench("token program listed", &block(TokenProgram::Listed));
+    println!("{:.1}x faster when skipped", scanned.as_secs_f64() / skipped.as_secs_f64());
+    bench("token program through CPI", &block(TokenProgram::ThroughCpi));
+    bench("token program from lookup table", &block(TokenProgram::ThroughLookupTable));
+}
@@ -17,12 +17,12 @@ pub fn instruction_logs(block: Block) -> Result<InstructionLogsOutput, Error> {
         let mut instructions: Vec<pb::instruction_logs::Instruction> = Vec::new();
 
         for instruction in get_structured_instructions(&transaction)?.flattened() {
-            let logs: Vec<String> = instruction.logs().iter().map(|log| log.to_string()).collect();
+            let logs: Vec<String> = instruction.logs().iter().flatten().map(|log| log.to_string()).collect();
             let program_id = instruction.program_id().to_string();
             instructions.push(pb::instruction_logs::Instruction { logs, program_id });
         }
 
-        let signature = get_signature(&transaction);
+        let signature = get_signature(&transaction)?;
         output.transactions.push(pb::instruction_logs::Transaction {
             signature,
             instructions,
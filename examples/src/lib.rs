@@ -2,3 +2,6 @@ pub mod pb;
 
 pub mod instruction_logs;
 pub use instruction_logs::instruction_logs;
+
+pub mod store_token_balances;
+pub use store_token_balances::store_token_balances;
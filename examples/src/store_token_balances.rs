@@ -0,0 +1,31 @@
+use substreams::store::{StoreDelete, StoreSet, StoreSetString};
+use substreams_solana::pb::sf::solana::r#type::v1::Block;
+
+use substreams_solana_utils as utils;
+use utils::pubkey::Pubkey;
+use utils::store_ops::{balance_key, from_context, owner_key, StoreOp};
+use utils::transaction::TransactionContext;
+
+#[substreams::handlers::store]
+pub fn store_token_balances(block: Block, store: StoreSetString) {
+    let mut ordinal = 0;
+
+    for transaction in block.transactions() {
+        let Ok(context) = TransactionContext::build(transaction) else {
+            continue;
+        };
+
+        for op in from_context(&context, transaction) {
+            match op {
+                StoreOp::SetBalance { key, value } => store.set(ordinal, key, &value.to_string()),
+                StoreOp::SetOwner { key, value } => store.set(ordinal, key, &value.to_string()),
+                StoreOp::DeleteAccount { key } => {
+                    let address = Pubkey::try_from_string(&key).expect("account keys are addresses");
+                    store.delete_prefix(ordinal as i64, &balance_key(&address));
+                    store.delete_prefix(ordinal as i64, &owner_key(&address));
+                },
+            }
+            ordinal += 1;
+        }
+    }
+}
@@ -1,5 +1,77 @@
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::pubkey::PubkeyRef;
+
 #[derive(Clone, Debug)]
 pub struct AccountBalance {
     pub pre_balance: u64,
     pub post_balance: u64,
 }
+
+/// Lamports of an account before and after a transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SolBalance {
+    pub pre: u64,
+    pub post: u64,
+}
+
+impl SolBalance {
+    /// Lamports gained, negative when lost
+    pub fn delta(&self) -> i128 {
+        self.post as i128 - self.pre as i128
+    }
+}
+
+/// Layout of the account list of a transaction: the static account keys, signers
+/// first, then the addresses loaded from lookup tables, writable ones first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccountLayout {
+    pub num_static_accounts: u32,
+    pub num_required_signatures: u32,
+    pub num_readonly_signed_accounts: u32,
+    pub num_readonly_unsigned_accounts: u32,
+    pub num_loaded_writable_accounts: u32,
+}
+
+impl AccountLayout {
+    pub fn of(transaction: &ConfirmedTransaction) -> Option<Self> {
+        let message = transaction.transaction.as_ref()?.message.as_ref()?;
+        let header = message.header.as_ref()?;
+        let meta = transaction.meta.as_ref()?;
+        Some(Self {
+            num_static_accounts: message.account_keys.len() as u32,
+            num_required_signatures: header.num_required_signatures,
+            num_readonly_signed_accounts: header.num_readonly_signed_accounts,
+            num_readonly_unsigned_accounts: header.num_readonly_unsigned_accounts,
+            num_loaded_writable_accounts: meta.loaded_writable_addresses.len() as u32,
+        })
+    }
+
+    /// Whether the account at `index` in the resolved accounts signed the transaction
+    pub fn is_signer(&self, index: usize) -> bool {
+        index < self.num_required_signatures as usize
+    }
+
+    /// Whether the message requests the account at `index` in the resolved
+    /// accounts as writable. The runtime may still demote it, e.g. for invoked
+    /// programs.
+    pub fn is_writable(&self, index: usize) -> bool {
+        let signers = self.num_required_signatures as usize;
+        let static_accounts = self.num_static_accounts as usize;
+        if index < signers {
+            index < signers.saturating_sub(self.num_readonly_signed_accounts as usize)
+        } else if index < static_accounts {
+            index < static_accounts.saturating_sub(self.num_readonly_unsigned_accounts as usize)
+        } else {
+            index < static_accounts + self.num_loaded_writable_accounts as usize
+        }
+    }
+}
+
+/// Account of an instruction, with its flags in the transaction message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccountMeta<'a> {
+    pub pubkey: PubkeyRef<'a>,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use anyhow::{anyhow, Error};
+
+use crate::error::ParseError;
+use crate::pubkey::{Pubkey, PubkeyRef};
+use crate::instruction::get_flattened_instructions;
+use super::{AddressLookupTableInstruction, ADDRESS_LOOKUP_TABLE_PROGRAM_ID, to_pubkeys};
+
+/// Lifecycle event of an address lookup table
+#[derive(Clone, Debug, PartialEq)]
+pub enum LookupTableEvent {
+    Created {
+        table: Pubkey,
+        authority: Pubkey,
+        payer: Pubkey,
+        recent_slot: u64,
+    },
+    Extended {
+        table: Pubkey,
+        authority: Pubkey,
+        new_addresses: Vec<Pubkey>,
+        /// Index of the first new address in the table. Only known when the table
+        /// was created earlier in the same transaction; otherwise it is the table
+        /// length before this instruction, which only the caller's own state knows.
+        start_index: Option<u32>,
+    },
+    Frozen {
+        table: Pubkey,
+        authority: Pubkey,
+    },
+    Deactivated {
+        table: Pubkey,
+        authority: Pubkey,
+    },
+    Closed {
+        table: Pubkey,
+        authority: Pubkey,
+        recipient: Pubkey,
+    },
+}
+
+/// Collects the lookup table lifecycle events of a transaction, in execution order,
+/// including instructions invoked through CPI. Instructions of the program that
+/// cannot be decoded, e.g. added by a later runtime version, are skipped.
+pub fn get_lookup_table_events(transaction: &ConfirmedTransaction) -> Result<Vec<LookupTableEvent>, Error> {
+    let instructions = get_flattened_instructions(transaction)?;
+    let accounts = transaction.resolved_accounts();
+    let mut events = Vec::new();
+    let mut table_lengths: HashMap<Pubkey, u32> = HashMap::new();
+    let resolve = |index: usize| accounts.get(index).ok_or(ParseError::AccountIndexOutOfBounds { index, len: accounts.len() });
+
+    for instruction in instructions {
+        if PubkeyRef(resolve(instruction.program_id_index() as usize)?) != ADDRESS_LOOKUP_TABLE_PROGRAM_ID {
+            continue;
+        }
+        let account = |i: usize| -> Result<Pubkey, Error> {
+            let index = *instruction.accounts().get(i).ok_or(anyhow!("Missing AddressLookupTable instruction account {}", i))?;
+            Pubkey::try_from(resolve(index as usize)?)
+        };
+        let Ok(unpacked) = AddressLookupTableInstruction::unpack(instruction.data()) else { continue };
+        let event = match unpacked {
+            AddressLookupTableInstruction::CreateLookupTable { recent_slot, bump_seed: _ } => {
+                let table = account(0)?;
+                table_lengths.insert(table, 0);
+                LookupTableEvent::Created { table, authority: account(1)?, payer: account(2)?, recent_slot }
+            },
+            AddressLookupTableInstruction::ExtendLookupTable { new_addresses } => {
+                let table = account(0)?;
+                let new_addresses = to_pubkeys(new_addresses);
+                let start_index = table_lengths.get(&table).copied();
+                if let Some(length) = table_lengths.get_mut(&table) {
+                    *length += new_addresses.len() as u32;
+                }
+                LookupTableEvent::Extended { table, authority: account(1)?, new_addresses, start_index }
+            },
+            AddressLookupTableInstruction::FreezeLookupTable => {
+                LookupTableEvent::Frozen { table: account(0)?, authority: account(1)? }
+            },
+            AddressLookupTableInstruction::DeactivateLookupTable => {
+                LookupTableEvent::Deactivated { table: account(0)?, authority: account(1)? }
+            },
+            AddressLookupTableInstruction::CloseLookupTable => {
+                LookupTableEvent::Closed { table: account(0)?, authority: account(1)?, recipient: account(2)? }
+            },
+        };
+        events.push(event);
+    }
+
+    Ok(events)
+}
@@ -0,0 +1,99 @@
+use crate::pubkey::Pubkey;
+use anyhow::{anyhow, Error};
+
+#[derive(Debug)]
+pub enum AddressLookupTableInstruction<'a> {
+    /// Create an address lookup table
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` Uninitialized address lookup table account
+    ///   1. `[SIGNER]` Account used to derive and control the new address lookup table.
+    ///   2. `[SIGNER, WRITE]` Account that will fund the new address lookup table.
+    ///   3. `[]` System program for CPI.
+    CreateLookupTable {
+        /// A recent slot must be used in the derivation path
+        /// for each initialized table.
+        recent_slot: u64,
+        /// Address tables are always initialized at program-derived
+        /// addresses using the funding address, recent blockhash, and
+        /// the user-passed `bump_seed`.
+        bump_seed: u8,
+    },
+
+    /// Permanently freeze an address lookup table, making it immutable.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` Address lookup table account to freeze
+    ///   1. `[SIGNER]` Current authority
+    FreezeLookupTable,
+
+    /// Extend an address lookup table with new addresses. Funding account and
+    /// system program account references are only required if the lookup table
+    /// account requires additional lamports to cover the rent-exempt balance
+    /// after being extended.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` Address lookup table account to extend
+    ///   1. `[SIGNER]` Current authority
+    ///   2. `[SIGNER, WRITE, OPTIONAL]` Account that will fund the table reallocation
+    ///   3. `[OPTIONAL]` System program for CPI.
+    ExtendLookupTable {
+        /// The appended addresses, 32 bytes each, borrowed from the instruction data
+        new_addresses: &'a [u8],
+    },
+
+    /// Deactivate an address lookup table, making it unusable and
+    /// eligible for closure after a short period of time.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` Address lookup table account to deactivate
+    ///   1. `[SIGNER]` Current authority
+    DeactivateLookupTable,
+
+    /// Close an address lookup table account
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` Address lookup table account to close
+    ///   1. `[SIGNER]` Current authority
+    ///   2. `[WRITE]` Recipient of closed account lamports
+    CloseLookupTable,
+}
+
+impl<'a> AddressLookupTableInstruction<'a> {
+    pub fn unpack(data: &'a [u8]) -> Result<Self, Error> {
+        if data.len() < 4 {
+            return Err(anyhow!("Failed to unpack AddressLookupTable instruction."));
+        }
+        let (tag, data) = data.split_at(4);
+        match u32::from_le_bytes(tag.try_into()?) {
+            0 => {
+                let recent_slot = data.get(..8).ok_or(anyhow!("Failed to unpack CreateLookupTable instruction"))?;
+                let &bump_seed = data.get(8).ok_or(anyhow!("Failed to unpack CreateLookupTable instruction"))?;
+                Ok(Self::CreateLookupTable { recent_slot: u64::from_le_bytes(recent_slot.try_into()?), bump_seed })
+            },
+            1 => Ok(Self::FreezeLookupTable),
+            2 => {
+                let length = data.get(..8).ok_or(anyhow!("Failed to unpack ExtendLookupTable instruction"))?;
+                let length = u64::from_le_bytes(length.try_into()?) as usize;
+                let new_addresses = length.checked_mul(32)
+                    .and_then(|size| size.checked_add(8))
+                    .and_then(|end| data.get(8..end))
+                    .ok_or(anyhow!("Failed to unpack ExtendLookupTable instruction"))?;
+                Ok(Self::ExtendLookupTable { new_addresses })
+            },
+            3 => Ok(Self::DeactivateLookupTable),
+            4 => Ok(Self::CloseLookupTable),
+            _ => Err(anyhow!("Failed to unpack AddressLookupTable instruction.")),
+        }
+    }
+}
+
+/// Iterates over the addresses of an `ExtendLookupTable` payload without copying them
+pub fn iter_addresses(new_addresses: &[u8]) -> impl Iterator<Item = &[u8]> {
+    new_addresses.chunks_exact(32)
+}
+
+/// Copies the addresses of an `ExtendLookupTable` payload
+pub fn to_pubkeys(new_addresses: &[u8]) -> Vec<Pubkey> {
+    iter_addresses(new_addresses).map(|x| Pubkey::try_from(x).unwrap()).collect()
+}
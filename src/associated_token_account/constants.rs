@@ -0,0 +1,4 @@
+use crate::pubkey::Pubkey;
+use substreams_solana::b58;
+
+pub const ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID: Pubkey = Pubkey(b58!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL"));
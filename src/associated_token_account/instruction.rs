@@ -0,0 +1,49 @@
+/// Instructions supported by the associated token account program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssociatedTokenAccountInstruction {
+    /// Creates an associated token account for the given wallet address and
+    /// token mint. Returns an error if the account exists.
+    ///
+    ///   0. `[writeable,signer]` Funding account (must be a system account)
+    ///   1. `[writeable]` Associated token account address to be created
+    ///   2. `[]` Wallet address for the new associated token account
+    ///   3. `[]` The token mint for the new associated token account
+    ///   4. `[]` System program
+    ///   5. `[]` SPL Token program
+    Create,
+    /// Creates an associated token account for the given wallet address and
+    /// token mint, if it doesn't already exist.  Returns an error if the
+    /// account exists, but with a different owner.
+    ///
+    ///   0. `[writeable,signer]` Funding account (must be a system account)
+    ///   1. `[writeable]` Associated token account address to be created
+    ///   2. `[]` Wallet address for the new associated token account
+    ///   3. `[]` The token mint for the new associated token account
+    ///   4. `[]` System program
+    ///   5. `[]` SPL Token program
+    CreateIdempotent,
+    /// Transfers from and closes a nested associated token account: an
+    /// associated token account owned by an associated token account.
+    ///
+    ///   0. `[writeable]` Nested associated token account, must be owned by `3`
+    ///   1. `[]` Token mint for the nested associated token account
+    ///   2. `[writeable]` Wallet's associated token account
+    ///   3. `[]` Owner associated token account address, must be owned by `5`
+    ///   4. `[]` Token mint for the owner associated token account
+    ///   5. `[writeable, signer]` Wallet address for the owner associated token
+    ///      account
+    ///   6. `[]` SPL Token program
+    RecoverNested,
+}
+
+impl AssociatedTokenAccountInstruction {
+    pub fn unpack(data: &[u8]) -> Result<Self, &'static str> {
+        match data.first() {
+            // The original instruction had no data
+            None | Some(0) => Ok(Self::Create),
+            Some(1) => Ok(Self::CreateIdempotent),
+            Some(2) => Ok(Self::RecoverNested),
+            _ => Err("Invalid instruction"),
+        }
+    }
+}
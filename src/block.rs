@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+use substreams_solana::b58;
+use substreams_solana::pb::sf::solana::r#type::v1::{Block, ConfirmedTransaction};
+
+use crate::pubkey::{Pubkey, PubkeyRef};
+use crate::instruction::{get_flattened_instructions, get_structured_instructions, StructuredInstruction, StructuredInstructions};
+use crate::event::{event_id, EventOrdinal};
+use crate::spl_token::{TokenInstruction, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use crate::associated_token_account::{AssociatedTokenAccountInstruction, ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID};
+use crate::system_program::{SystemInstruction, SYSTEM_PROGRAM_ID};
+use crate::transaction::{get_context, get_signature, TransactionContext};
+
+pub const INCINERATOR: Pubkey = Pubkey(b58!("1nc1nerator11111111111111111111111111111111"));
+
+/// Token-2022 extension instructions of mints whose amounts are not all
+/// visible: transfer fees withheld from transfers, and encrypted amounts
+const TRANSFER_FEE_EXTENSION: u8 = 26;
+const CONFIDENTIAL_TRANSFER_EXTENSION: u8 = 27;
+const CONFIDENTIAL_TRANSFER_FEE_EXTENSION: u8 = 37;
+const CONFIDENTIAL_MINT_BURN_EXTENSION: u8 = 42;
+/// `Mint` and `Burn` of the confidential mint and burn extension, whose
+/// accounts start with the token account and the mint
+const CONFIDENTIAL_MINT: u8 = 3;
+const CONFIDENTIAL_BURN: u8 = 4;
+
+/// Net change of a mint's supply over a block
+#[derive(Clone, Debug)]
+pub struct SupplyDelta {
+    pub mint: Pubkey,
+    pub minted: u128,
+    pub burned: u128,
+    pub net: i128,
+    /// Amount transferred to incinerator owned accounts. These tokens are
+    /// unusable, but they are not removed from the on-chain supply.
+    pub incinerated: u128,
+    /// Set for Token-2022 mints whose amounts are not all visible: mints with
+    /// confidential amounts, like confidential mints and burns which are not
+    /// counted in `minted` and `burned`, and mints with transfer fees. Only
+    /// extension instructions within the block reveal them.
+    pub amounts_unknown: bool,
+    pub minting_authorities: Vec<Pubkey>,
+    /// Token accounts the burned tokens were taken from
+    pub burning_sources: Vec<Pubkey>,
+    pub transaction_signatures: Vec<String>,
+}
+
+impl SupplyDelta {
+    fn new(mint: Pubkey) -> Self {
+        Self {
+            mint,
+            minted: 0,
+            burned: 0,
+            net: 0,
+            incinerated: 0,
+            amounts_unknown: false,
+            minting_authorities: Vec::new(),
+            burning_sources: Vec::new(),
+            transaction_signatures: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SupplyDeltaOptions {
+    /// Also report transfers into incinerator owned token accounts, for the
+    /// mints whose supply changed in the block
+    pub include_incinerator_transfers: bool,
+}
+
+/// Aggregates MintTo and Burn instructions of the token programs in all
+/// successful transactions of the block, per mint. Mints only seen in transfers
+/// are not reported. The output is sorted by mint. Transactions without a
+/// signature cannot be attributed and are skipped.
+///
+/// ```
+/// use substreams_solana::pb::sf::solana::r#type::v1::Block;
+/// use substreams_solana_utils::block::supply_deltas;
+/// use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (authority, mint, destination) = (pubkey(1), pubkey(2), pubkey(3));
+/// let mut mint_to = vec![7];
+/// mint_to.extend(1000u64.to_le_bytes());
+/// let mut burn = vec![8];
+/// burn.extend(300u64.to_le_bytes());
+/// let transactions = vec![
+///     TransactionBuilder::new().signer(authority).instruction(TOKEN_PROGRAM_ID, &[mint, destination, authority], mint_to.clone()).build(),
+///     TransactionBuilder::new().signer(authority).instruction(TOKEN_PROGRAM_ID, &[destination, mint, authority], burn).build(),
+///     TransactionBuilder::new().signer(authority).instruction(TOKEN_PROGRAM_ID, &[mint, destination, authority], mint_to).failed().build(),
+/// ];
+/// let block = Block { transactions, ..Default::default() };
+///
+/// let deltas = supply_deltas(&block);
+/// assert_eq!(deltas.len(), 1);
+/// assert_eq!((deltas[0].mint, deltas[0].minted, deltas[0].burned, deltas[0].net), (mint, 1000, 300, 700));
+/// assert_eq!(deltas[0].minting_authorities, [authority]);
+/// ```
+pub fn supply_deltas(block: &Block) -> Vec<SupplyDelta> {
+    supply_deltas_with_options(block, &SupplyDeltaOptions::default())
+}
+
+pub fn supply_deltas_with_options(block: &Block, options: &SupplyDeltaOptions) -> Vec<SupplyDelta> {
+    let mut collected = CollectedSupplyDeltas::default();
+    for transaction in &block.transactions {
+        match &transaction.meta {
+            Some(meta) if meta.err.is_none() => collect_supply_deltas(transaction, options, &mut collected),
+            _ => continue,
+        }
+    }
+    let CollectedSupplyDeltas { deltas, supply_changed, amounts_unknown } = collected;
+    deltas.into_values()
+        .filter(|delta| supply_changed.contains(&delta.mint.0))
+        .map(|delta| SupplyDelta { amounts_unknown: amounts_unknown.contains(&delta.mint.0), ..delta })
+        .collect()
+}
+
+#[derive(Default)]
+struct CollectedSupplyDeltas {
+    deltas: BTreeMap<[u8; 32], SupplyDelta>,
+    /// Mints minted or burned, as opposed to only transferred
+    supply_changed: HashSet<[u8; 32]>,
+    /// Accounts of the Token-2022 instructions hiding amounts, mints included
+    amounts_unknown: HashSet<[u8; 32]>,
+}
+
+fn collect_supply_deltas(transaction: &ConfirmedTransaction, options: &SupplyDeltaOptions, collected: &mut CollectedSupplyDeltas) {
+    let accounts = transaction.resolved_accounts();
+    let context = if options.include_incinerator_transfers { get_context(transaction).ok() } else { None };
+    let Ok(signature) = get_signature(transaction) else { return };
+    let account = |instruction_accounts: &Vec<u8>, i: usize| -> Option<Pubkey> {
+        let index = *instruction_accounts.get(i)? as usize;
+        Pubkey::try_from(accounts.get(index)?).ok()
+    };
+    let CollectedSupplyDeltas { deltas, supply_changed, amounts_unknown } = collected;
+
+    for instruction in get_flattened_instructions(transaction).unwrap_or_default() {
+        let Some(program_id) = accounts.get(instruction.program_id_index() as usize).map(|x| PubkeyRef(x)) else { continue };
+        if program_id != TOKEN_PROGRAM_ID && program_id != TOKEN_2022_PROGRAM_ID {
+            continue;
+        }
+        let instruction_accounts = instruction.accounts();
+        if program_id == TOKEN_2022_PROGRAM_ID {
+            match instruction.data()[..] {
+                [CONFIDENTIAL_MINT_BURN_EXTENSION, CONFIDENTIAL_MINT | CONFIDENTIAL_BURN, ..] => {
+                    let Some(mint) = account(instruction_accounts, 1) else { continue };
+                    supply_changed.insert(mint.0);
+                    amounts_unknown.insert(mint.0);
+                    let delta = deltas.entry(mint.0).or_insert_with(|| SupplyDelta::new(mint));
+                    if delta.transaction_signatures.last() != Some(&signature) {
+                        delta.transaction_signatures.push(signature.clone());
+                    }
+                    continue;
+                },
+                [TRANSFER_FEE_EXTENSION | CONFIDENTIAL_TRANSFER_EXTENSION | CONFIDENTIAL_TRANSFER_FEE_EXTENSION | CONFIDENTIAL_MINT_BURN_EXTENSION, ..] => {
+                    // The position of the mint depends on the instruction, but
+                    // only mints are matched against these accounts
+                    amounts_unknown.extend((0..instruction_accounts.len()).filter_map(|i| account(instruction_accounts, i)).map(|x| x.0));
+                    continue;
+                },
+                _ => (),
+            }
+        }
+        let delta = match TokenInstruction::unpack(instruction.data()) {
+            Ok(TokenInstruction::MintTo { amount }) |
+            Ok(TokenInstruction::MintToChecked { amount, decimals: _ }) => {
+                let (Some(mint), Some(authority)) = (account(instruction_accounts, 0), account(instruction_accounts, 2)) else { continue };
+                supply_changed.insert(mint.0);
+                let delta = deltas.entry(mint.0).or_insert_with(|| SupplyDelta::new(mint));
+                delta.minted += amount as u128;
+                push_unique(&mut delta.minting_authorities, authority);
+                delta
+            },
+            Ok(TokenInstruction::Burn { amount }) |
+            Ok(TokenInstruction::BurnChecked { amount, decimals: _ }) => {
+                let (Some(source), Some(mint)) = (account(instruction_accounts, 0), account(instruction_accounts, 1)) else { continue };
+                supply_changed.insert(mint.0);
+                let delta = deltas.entry(mint.0).or_insert_with(|| SupplyDelta::new(mint));
+                delta.burned += amount as u128;
+                push_unique(&mut delta.burning_sources, source);
+                delta
+            },
+            Ok(TokenInstruction::Transfer { amount }) => {
+                let Some(mint) = incinerated_mint(context.as_ref(), instruction_accounts, 1) else { continue };
+                let delta = deltas.entry(mint.0).or_insert_with(|| SupplyDelta::new(mint));
+                delta.incinerated += amount as u128;
+                delta
+            },
+            Ok(TokenInstruction::TransferChecked { amount, decimals: _ }) => {
+                let Some(mint) = incinerated_mint(context.as_ref(), instruction_accounts, 2) else { continue };
+                let delta = deltas.entry(mint.0).or_insert_with(|| SupplyDelta::new(mint));
+                delta.incinerated += amount as u128;
+                delta
+            },
+            _ => continue,
+        };
+        delta.net = delta.minted as i128 - delta.burned as i128;
+        if delta.transaction_signatures.last() != Some(&signature) {
+            delta.transaction_signatures.push(signature.clone());
+        }
+    }
+}
+
+/// Returns the mint of the transfer destination if it is owned by the incinerator
+fn incinerated_mint(context: Option<&TransactionContext>, instruction_accounts: &[u8], destination: usize) -> Option<Pubkey> {
+    let destination = context?.accounts.get(*instruction_accounts.get(destination)? as usize)?;
+    let token_account = context?.get_token_account(destination)?;
+    if token_account.owner == INCINERATOR { Some(token_account.mint) } else { None }
+}
+
+fn push_unique(pubkeys: &mut Vec<Pubkey>, pubkey: Pubkey) {
+    if !pubkeys.contains(&pubkey) {
+        pubkeys.push(pubkey);
+    }
+}
+
+/// Associated token account creation, either direct or through CPI
+#[derive(Clone, Debug)]
+pub struct AtaCreation {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub ata_address: Pubkey,
+    /// Funding account of the associated token account instruction
+    pub payer: Pubkey,
+    pub transaction_signature: String,
+    /// Set for `CreateIdempotent` instructions on an account that already existed
+    pub idempotent_noop: bool,
+    /// Lamports moved out of `payer` by the creation, for the new account's rent
+    pub rent: Option<u64>,
+    /// Account that funded `payer` for the rent, when `payer` is e.g. a program
+    /// PDA the user transferred lamports to earlier in the transaction
+    pub economic_payer: Option<EconomicPayer>,
+    /// Senders of the system transfers to `payer` that could have funded the
+    /// rent. `economic_payer` is only set when there is a single one.
+    pub funder_candidates: Vec<Pubkey>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EconomicPayer {
+    pub payer: Pubkey,
+    /// Event id of the funding system transfer, the closest preceding one
+    pub transfer_event_id: String,
+}
+
+impl AtaCreation {
+    pub fn key(&self) -> String {
+        ata_creation_key(&self.owner, &self.mint)
+    }
+}
+
+/// Store key for an (owner, mint) pair, to dedupe creations across blocks
+pub fn ata_creation_key(owner: &Pubkey, mint: &Pubkey) -> String {
+    format!("{}:{}", owner.to_string(), mint.to_string())
+}
+
+/// Collects the associated token account creations of all successful transactions
+/// in the block, in execution order. Transactions without a signature are skipped.
+pub fn ata_creations(block: &Block) -> Vec<AtaCreation> {
+    let mut creations = Vec::new();
+    for transaction in &block.transactions {
+        match &transaction.meta {
+            Some(meta) if meta.err.is_none() => collect_ata_creations(transaction, &mut creations),
+            _ => continue,
+        }
+    }
+    creations
+}
+
+fn collect_ata_creations(transaction: &ConfirmedTransaction, creations: &mut Vec<AtaCreation>) {
+    let Ok(instructions) = get_structured_instructions(transaction) else { return };
+    let Ok(signature) = get_signature(transaction) else { return };
+
+    let flattened = instructions.flattened();
+    for (index, instruction) in flattened.iter().enumerate() {
+        if instruction.program_id() != ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID {
+            continue;
+        }
+        let idempotent = match AssociatedTokenAccountInstruction::unpack(instruction.data()) {
+            Ok(AssociatedTokenAccountInstruction::Create) => false,
+            Ok(AssociatedTokenAccountInstruction::CreateIdempotent) => true,
+            _ => continue,
+        };
+        let account = |i: usize| instruction.accounts().get(i)?.to_pubkey().ok();
+        let (Some(payer), Some(ata_address), Some(owner), Some(mint)) = (account(0), account(1), account(2), account(3)) else {
+            continue;
+        };
+        // A real creation always assigns and initializes the new token account through CPIs
+        let initialized = instruction.inner_instructions().iter().any(|inner| creates_account(inner, &ata_address));
+        let rent = instruction.inner_instructions().iter().find_map(|inner| match system_transfer(inner) {
+            Some((from, _, lamports)) if from == payer => Some(lamports),
+            _ => None,
+        });
+        let (economic_payer, funder_candidates) = match rent {
+            Some(rent) => find_economic_payer(&flattened[..index], &payer, rent, &signature),
+            None => (None, Vec::new()),
+        };
+        creations.push(AtaCreation {
+            owner,
+            mint,
+            ata_address,
+            payer,
+            transaction_signature: signature.clone(),
+            idempotent_noop: idempotent && !initialized,
+            rent,
+            economic_payer,
+            funder_candidates,
+        });
+    }
+}
+
+/// Whether `inner`, a CPI of an associated token account instruction, creates
+/// `ata_address`: the system program assigning it, whether or not it was funded
+/// beforehand, or a token program initializing it.
+fn creates_account(inner: &StructuredInstruction, ata_address: &Pubkey) -> bool {
+    let is_ata = |i: usize| inner.accounts().get(i).is_some_and(|x| x == ata_address);
+    let program_id = inner.program_id();
+    if program_id == SYSTEM_PROGRAM_ID && inner.data().len() >= 4 {
+        match SystemInstruction::unpack(inner.data()) {
+            Ok(SystemInstruction::CreateAccount(_)) => is_ata(1),
+            Ok(SystemInstruction::Assign(_)) => is_ata(0),
+            _ => false,
+        }
+    } else if program_id == TOKEN_PROGRAM_ID || program_id == TOKEN_2022_PROGRAM_ID {
+        is_ata(0) && matches!(
+            TokenInstruction::unpack(inner.data()),
+            Ok(TokenInstruction::InitializeAccount) | Ok(TokenInstruction::InitializeAccount2 { .. }) | Ok(TokenInstruction::InitializeAccount3 { .. })
+        )
+    } else {
+        false
+    }
+}
+
+/// Looks for system transfers of at least `rent` lamports to `payer` among the
+/// `preceding` instructions, going one hop back only.
+fn find_economic_payer(
+    preceding: &[Rc<StructuredInstruction>],
+    payer: &Pubkey,
+    rent: u64,
+    signature: &str,
+) -> (Option<EconomicPayer>, Vec<Pubkey>) {
+    let mut closest = None;
+    let mut funder_candidates = Vec::new();
+    for instruction in preceding.iter().rev() {
+        let Some((from, to, lamports)) = system_transfer(instruction) else { continue };
+        if to != *payer || from == *payer || lamports < rent {
+            continue;
+        }
+        if closest.is_none() {
+            closest = Some((from, instruction));
+        }
+        push_unique(&mut funder_candidates, from);
+    }
+    let economic_payer = match closest {
+        Some((payer, instruction)) if funder_candidates.len() == 1 => {
+            Some(EconomicPayer { payer, transfer_event_id: transfer_event_id(instruction, signature) })
+        },
+        _ => None,
+    };
+    (economic_payer, funder_candidates)
+}
+
+/// Source, destination and amount of a system program transfer or account creation
+fn system_transfer(instruction: &StructuredInstruction) -> Option<(Pubkey, Pubkey, u64)> {
+    if instruction.program_id() != SYSTEM_PROGRAM_ID || instruction.data().len() < 4 {
+        return None;
+    }
+    let account = |i: usize| instruction.accounts().get(i)?.to_pubkey().ok();
+    match SystemInstruction::unpack(instruction.data()).ok()? {
+        SystemInstruction::Transfer(transfer) => Some((account(0)?, account(1)?, transfer.lamports)),
+        SystemInstruction::TransferWithSeed(transfer) => Some((account(0)?, account(2)?, transfer.lamports)),
+        SystemInstruction::CreateAccount(create) => Some((account(0)?, account(1)?, create.lamports)),
+        SystemInstruction::CreateAccountWithSeed(create) => Some((account(0)?, account(1)?, create.lamports)),
+        _ => None,
+    }
+}
+
+/// Event id of a system transfer, ordered among the inner instructions of its
+/// parent, or among the top level instructions, see `event_id`
+fn transfer_event_id(transfer: &StructuredInstruction, signature: &str) -> String {
+    match (transfer.parent_instruction(), EventOrdinal::from_inner_instruction(transfer)) {
+        (Some(parent), Some(ordinal)) => event_id(signature, &parent.path(), &ordinal),
+        _ => event_id(signature, &[], &EventOrdinal::from_instruction_position(transfer.top_level_index())),
+    }
+}
@@ -0,0 +1,5 @@
+use crate::pubkey::Pubkey;
+use substreams_solana::b58;
+
+pub const BPF_LOADER_2_PROGRAM_ID: Pubkey = Pubkey(b58!("BPFLoader2111111111111111111111111111111111"));
+pub const BPF_LOADER_UPGRADEABLE_PROGRAM_ID: Pubkey = Pubkey(b58!("BPFLoaderUpgradeab1e11111111111111111111111"));
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Error};
+
+use crate::payload::{Payload, PayloadOptions};
+
+#[derive(Debug)]
+pub enum UpgradeableLoaderInstruction<'a> {
+    /// Initialize a Buffer account.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` source account to initialize.
+    ///   1. `[]` Buffer authority, optional, if omitted then the buffer will be immutable.
+    InitializeBuffer,
+
+    /// Write program data into a Buffer account.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` Buffer account to write program data to.
+    ///   1. `[SIGNER]` Buffer authority
+    Write {
+        /// Offset at which to write the given bytes.
+        offset: u32,
+        /// Serialized program data
+        bytes: Payload<'a>,
+    },
+
+    /// Deploy an executable program.
+    ///
+    /// # Account references
+    ///   0. `[WRITE, SIGNER]` The payer account that will pay to create the ProgramData account.
+    ///   1. `[WRITE]` The uninitialized ProgramData account.
+    ///   2. `[WRITE]` The uninitialized Program account.
+    ///   3. `[WRITE]` The Buffer account where the program data has been written.
+    ///   4. `[]` Rent sysvar.
+    ///   5. `[]` Clock sysvar.
+    ///   6. `[]` System program.
+    ///   7. `[SIGNER]` The program's authority
+    DeployWithMaxDataLen {
+        /// Maximum length that the program can be upgraded to.
+        max_data_len: u64,
+    },
+
+    /// Upgrade a program.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` The ProgramData account.
+    ///   1. `[WRITE]` The Program account.
+    ///   2. `[WRITE]` The Buffer account where the program data has been written.
+    ///   3. `[WRITE]` The spill account.
+    ///   4. `[]` Rent sysvar.
+    ///   5. `[]` Clock sysvar.
+    ///   6. `[SIGNER]` The program's authority.
+    Upgrade,
+
+    /// Set a new authority that is allowed to write the buffer or upgrade the program.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` The Buffer or ProgramData account to change the authority of.
+    ///   1. `[SIGNER]` The current authority.
+    ///   2. `[]` The new authority, optional, if omitted then the program will not be upgradeable.
+    SetAuthority,
+
+    /// Closes an account owned by the upgradeable loader of all lamports and
+    /// withdraws all the lamports
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` The account to close, if closing a program must be the ProgramData account.
+    ///   1. `[WRITE]` The account to deposit the closed account's lamports.
+    ///   2. `[SIGNER]` The account's authority, Optional, required for initialized accounts.
+    ///   3. `[WRITE]` The associated Program account if the account to close is a ProgramData account.
+    Close,
+
+    /// Extend a program's ProgramData account by the specified number of bytes.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` The ProgramData account.
+    ///   1. `[WRITE]` The ProgramData account's associated Program account.
+    ///   2. `[]` System program, optional, used to transfer lamports from the payer to the ProgramData account.
+    ///   3. `[WRITE, SIGNER]` The payer account, optional, that will pay necessary rent exemption costs.
+    ExtendProgram {
+        /// Number of bytes to extend the program data.
+        additional_bytes: u32,
+    },
+
+    /// Set a new authority that is allowed to write the buffer or upgrade the
+    /// program, requiring the new authority to sign.
+    ///
+    /// # Account references
+    ///   0. `[WRITE]` The Buffer or ProgramData account to change the authority of.
+    ///   1. `[SIGNER]` The current authority.
+    ///   2. `[SIGNER]` The new authority.
+    SetAuthorityChecked,
+}
+
+impl<'a> UpgradeableLoaderInstruction<'a> {
+    /// Decodes an instruction, borrowing any `Write` payload from `data`
+    pub fn unpack(data: &'a [u8]) -> Result<Self, Error> {
+        Self::unpack_with_options(data, &PayloadOptions::default())
+    }
+
+    pub fn unpack_with_options(data: &'a [u8], options: &PayloadOptions) -> Result<Self, Error> {
+        if data.len() < 4 {
+            return Err(anyhow!("Failed to unpack UpgradeableLoader instruction."));
+        }
+        let (tag, data) = data.split_at(4);
+        match u32::from_le_bytes(tag.try_into()?) {
+            0 => Ok(Self::InitializeBuffer),
+            1 => {
+                let (offset, bytes) = unpack_write(data, options)?;
+                Ok(Self::Write { offset, bytes })
+            },
+            2 => {
+                let max_data_len = data.get(..8).ok_or_else(|| anyhow!("Failed to unpack DeployWithMaxDataLen instruction"))?;
+                Ok(Self::DeployWithMaxDataLen { max_data_len: u64::from_le_bytes(max_data_len.try_into()?) })
+            },
+            3 => Ok(Self::Upgrade),
+            4 => Ok(Self::SetAuthority),
+            5 => Ok(Self::Close),
+            6 => {
+                let additional_bytes = data.get(..4).ok_or_else(|| anyhow!("Failed to unpack ExtendProgram instruction"))?;
+                Ok(Self::ExtendProgram { additional_bytes: u32::from_le_bytes(additional_bytes.try_into()?) })
+            },
+            7 => Ok(Self::SetAuthorityChecked),
+            _ => Err(anyhow!("Failed to unpack UpgradeableLoader instruction.")),
+        }
+    }
+}
+
+/// Instructions of the non-upgradeable BPF loaders
+#[derive(Debug)]
+pub enum LoaderInstruction<'a> {
+    /// Write program data into an account
+    ///
+    /// # Account references
+    ///   0. `[WRITE, SIGNER]` Account to write to
+    Write {
+        /// Offset at which to write the given bytes
+        offset: u32,
+        /// Serialized program data
+        bytes: Payload<'a>,
+    },
+
+    /// Finalize an account loaded with program data for execution
+    ///
+    /// # Account references
+    ///   0. `[WRITE, SIGNER]` The account to prepare for execution
+    ///   1. `[]` Rent sysvar
+    Finalize,
+}
+
+impl<'a> LoaderInstruction<'a> {
+    /// Decodes an instruction, borrowing any `Write` payload from `data`
+    pub fn unpack(data: &'a [u8]) -> Result<Self, Error> {
+        Self::unpack_with_options(data, &PayloadOptions::default())
+    }
+
+    pub fn unpack_with_options(data: &'a [u8], options: &PayloadOptions) -> Result<Self, Error> {
+        if data.len() < 4 {
+            return Err(anyhow!("Failed to unpack Loader instruction."));
+        }
+        let (tag, data) = data.split_at(4);
+        match u32::from_le_bytes(tag.try_into()?) {
+            0 => {
+                let (offset, bytes) = unpack_write(data, options)?;
+                Ok(Self::Write { offset, bytes })
+            },
+            1 => Ok(Self::Finalize),
+            _ => Err(anyhow!("Failed to unpack Loader instruction.")),
+        }
+    }
+}
+
+/// Reads the bincode encoded `offset: u32, bytes: Vec<u8>` of a write instruction
+fn unpack_write<'a>(data: &'a [u8], options: &PayloadOptions) -> Result<(u32, Payload<'a>), Error> {
+    let offset = data.get(..4).ok_or_else(|| anyhow!("Failed to unpack Write instruction"))?;
+    let length = data.get(4..12).ok_or_else(|| anyhow!("Failed to unpack Write instruction"))?;
+    let length = usize::try_from(u64::from_le_bytes(length.try_into()?))?;
+    let bytes = length.checked_add(12)
+        .and_then(|end| data.get(12..end))
+        .ok_or_else(|| anyhow!("Failed to unpack Write instruction"))?;
+    Ok((u32::from_le_bytes(offset.try_into()?), Payload::new(bytes, options)))
+}
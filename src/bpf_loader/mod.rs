@@ -0,0 +1,4 @@
+pub mod constants;
+pub use constants::*;
+pub mod instruction;
+pub use instruction::*;
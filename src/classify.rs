@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::instruction::get_flattened_instructions;
+use crate::programs::{KnownProgram, ProgramCategory};
+use crate::pubkey::PubkeyRef;
+
+/// Coarse category of a transaction, for filtering and dashboards
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransactionCategory {
+    Vote,
+    /// Only system program instructions, e.g. SOL transfers
+    SystemOnly,
+    /// Only token program instructions, alongside system and other native programs
+    TokenTransferOnly,
+    DexSwap,
+    NftActivity,
+    ProgramDeploy,
+    StakeOps,
+    /// Unrecognized programs moving tokens
+    Mixed,
+    Unknown,
+}
+
+/// Classifies a transaction from the set of programs it invokes, including
+/// through CPI, and the presence of token balances. Logs are not looked at.
+///
+/// When several categories apply the first match wins, in this order: Vote,
+/// ProgramDeploy, DexSwap, NftActivity, StakeOps. Otherwise, a transaction
+/// invoking only native programs is SystemOnly if it invokes the system program,
+/// and TokenTransferOnly if it also invokes a token program. Transactions
+/// invoking unrecognized programs are Mixed when they have token balances and
+/// Unknown otherwise, as are transactions missing their message or meta.
+pub fn transaction_category(transaction: &ConfirmedTransaction) -> TransactionCategory {
+    let Ok(instructions) = get_flattened_instructions(transaction) else {
+        return TransactionCategory::Unknown;
+    };
+    let accounts = transaction.resolved_accounts();
+    let mut invoked: HashSet<u32> = HashSet::new();
+    for instruction in instructions {
+        invoked.insert(instruction.program_id_index());
+    }
+
+    let mut categories: HashSet<ProgramCategory> = HashSet::new();
+    let mut programs: HashSet<KnownProgram> = HashSet::new();
+    let mut has_unknown_program = false;
+    for index in invoked {
+        match accounts.get(index as usize).and_then(|x| KnownProgram::from_program_id(&PubkeyRef(x))) {
+            Some(program) => {
+                categories.insert(program.category());
+                programs.insert(program);
+            }
+            None => has_unknown_program = true,
+        }
+    }
+
+    for (category, transaction_category) in [
+        (ProgramCategory::Vote, TransactionCategory::Vote),
+        (ProgramCategory::Loader, TransactionCategory::ProgramDeploy),
+        (ProgramCategory::Dex, TransactionCategory::DexSwap),
+        (ProgramCategory::Nft, TransactionCategory::NftActivity),
+        (ProgramCategory::Stake, TransactionCategory::StakeOps),
+    ] {
+        if categories.contains(&category) {
+            return transaction_category;
+        }
+    }
+
+    if has_unknown_program {
+        let meta = transaction.meta.as_ref().unwrap();
+        if meta.pre_token_balances.is_empty() && meta.post_token_balances.is_empty() {
+            return TransactionCategory::Unknown;
+        }
+        return TransactionCategory::Mixed;
+    }
+    if categories.contains(&ProgramCategory::Token) {
+        return TransactionCategory::TokenTransferOnly;
+    }
+    if programs.contains(&KnownProgram::System) {
+        return TransactionCategory::SystemOnly;
+    }
+    TransactionCategory::Unknown
+}
@@ -0,0 +1,53 @@
+//! Deprecated names kept for crates pinned to the older API.
+//!
+//! Everything in this module is deprecated since 0.1.5 and will be removed in
+//! 0.2.0. Each deprecation note says what to call instead.
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::error::ParseError;
+use crate::spl_token::TokenAccount;
+use crate::transaction::TransactionContext;
+
+impl<'a> TransactionContext<'a> {
+    #[deprecated(since = "0.1.5", note = "use `TransactionContext::build(transaction)`, which has the same signature; removed in 0.2.0")]
+    pub fn construct(transaction: &'a ConfirmedTransaction) -> Result<Self, ParseError> {
+        Self::build(transaction)
+    }
+
+    /// The account at `index` of the transaction, `None` past its accounts
+    #[deprecated(since = "0.1.5", note = "use `context.accounts.get(index)` instead; it yields a `PubkeyRef`, whose `.0` is the former `&Vec<u8>`; removed in 0.2.0")]
+    pub fn get_account_from_index(&self, index: usize) -> Option<&'a Vec<u8>> {
+        self.accounts.get(index).map(|x| x.0)
+    }
+
+    #[deprecated(since = "0.1.5", note = "use `get_token_account(&PubkeyRef(address))`; token accounts are keyed by `PubkeyRef` wrapping the same `&Vec<u8>`; removed in 0.2.0")]
+    pub fn get_token_account_from_address(&self, address: &Vec<u8>) -> Option<&TokenAccount<'a>> {
+        self.token_accounts.get(address.as_slice())
+    }
+}
+
+/// Token account with base58 string fields, as returned before `TokenAccount`
+/// switched to `PubkeyRef`/`Pubkey` keys.
+#[deprecated(since = "0.1.5", note = "use `spl_token::TokenAccount`; call `.to_string()` on its `address`, `mint` and `owner` to get these strings; removed in 0.2.0")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LegacyTokenAccount {
+    pub address: String,
+    pub mint: String,
+    pub owner: String,
+    pub pre_balance: Option<u64>,
+    pub post_balance: Option<u64>,
+}
+
+#[allow(deprecated)]
+impl From<&TokenAccount<'_>> for LegacyTokenAccount {
+    fn from(value: &TokenAccount<'_>) -> Self {
+        Self {
+            address: value.address.to_string(),
+            mint: value.mint.to_string(),
+            owner: value.owner.to_string(),
+            pre_balance: value.pre_balance,
+            post_balance: value.post_balance,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::log::{build_structured_logs, is_truncated, Log};
+use crate::pubkey::PubkeyRef;
+use super::{ComputeBudgetInstruction, COMPUTE_BUDGET_PROGRAM_ID};
+
+/// Compute budget requested by a transaction. `None` when the transaction does
+/// not set it, the runtime defaults applying.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComputeBudget {
+    pub unit_limit: Option<u32>,
+    /// Price of a compute unit, in millionths of a lamport
+    pub unit_price_micro_lamports: Option<u64>,
+    /// Heap size, in bytes
+    pub heap_frame: Option<u32>,
+}
+
+/// Compute budget requested by the top-level instructions of `transaction`, the
+/// only ones the runtime reads it from. Instructions that fail to unpack are
+/// skipped.
+///
+/// ```
+/// use substreams_solana_utils::compute_budget::{get_compute_budget, ComputeBudget, COMPUTE_BUDGET_PROGRAM_ID};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let transaction = TransactionBuilder::new()
+///     .signer(pubkey(1))
+///     .instruction(COMPUTE_BUDGET_PROGRAM_ID, &[], vec![2, 0x40, 0x0d, 0x03, 0x00])
+///     .instruction(COMPUTE_BUDGET_PROGRAM_ID, &[], [&[3][..], &50_000u64.to_le_bytes()].concat())
+///     .instruction(COMPUTE_BUDGET_PROGRAM_ID, &[], vec![9])
+///     .instruction(pubkey(2), &[pubkey(1)], vec![0])
+///     .build();
+///
+/// let budget = get_compute_budget(&transaction);
+/// assert_eq!(budget, ComputeBudget { unit_limit: Some(200_000), unit_price_micro_lamports: Some(50_000), heap_frame: None });
+/// ```
+pub fn get_compute_budget(transaction: &ConfirmedTransaction) -> ComputeBudget {
+    let mut budget = ComputeBudget::default();
+    let Some(message) = transaction.transaction.as_ref().and_then(|x| x.message.as_ref()) else {
+        return budget;
+    };
+    for instruction in &message.instructions {
+        match message.account_keys.get(instruction.program_id_index as usize) {
+            Some(program_id) if PubkeyRef(program_id) == COMPUTE_BUDGET_PROGRAM_ID => (),
+            _ => continue,
+        }
+        match ComputeBudgetInstruction::unpack(&instruction.data) {
+            Ok(ComputeBudgetInstruction::SetComputeUnitLimit(units)) |
+            Ok(ComputeBudgetInstruction::RequestUnitsDeprecated { units, .. }) => budget.unit_limit = Some(units),
+            Ok(ComputeBudgetInstruction::SetComputeUnitPrice(price)) => budget.unit_price_micro_lamports = Some(price),
+            Ok(ComputeBudgetInstruction::RequestHeapFrame(bytes)) => budget.heap_frame = Some(bytes),
+            _ => (),
+        }
+    }
+    budget
+}
+
+/// Compute units consumed by `transaction`. Taken from the transaction meta
+/// when it records them, then from the runtime's final `consumed <units> of
+/// <budget> compute units` log, then summed over the `consumed` logs of the
+/// top level invocations, which leaves out builtins as they do not log it.
+/// `None` when none is available, or when the logs to sum are truncated.
+///
+/// ```
+/// use substreams_solana_utils::compute_budget::total_compute_units;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (program, program_id) = (pubkey(2), pubkey(2).to_string());
+/// let build = |logs: &[&str]| TransactionBuilder::new()
+///     .signer(pubkey(1))
+///     .instruction(program, &[pubkey(1)], vec![0])
+///     .instruction(program, &[pubkey(1)], vec![1])
+///     .logs(logs)
+///     .build();
+/// let invocation = [
+///     format!("Program {program_id} invoke [1]"),
+///     format!("Program {program_id} consumed 1500 of 200000 compute units"),
+///     format!("Program {program_id} success"),
+/// ];
+/// let logs: Vec<&str> = invocation.iter().chain(&invocation).map(String::as_str).collect();
+///
+/// assert_eq!(total_compute_units(&build(&logs)), Some(3000));
+/// let with_summary = [&logs[..], &["consumed 3150 of 400000 compute units"]].concat();
+/// assert_eq!(total_compute_units(&build(&with_summary)), Some(3150));
+/// let truncated = [&logs[..4], &["Log truncated"]].concat();
+/// assert_eq!(total_compute_units(&build(&truncated)), None);
+/// ```
+pub fn total_compute_units(transaction: &ConfirmedTransaction) -> Option<u64> {
+    let meta = transaction.meta.as_ref()?;
+    if let Some(units) = meta.compute_units_consumed {
+        return Some(units);
+    }
+    let summary = meta.log_messages.iter().rev().find_map(|log| match Log::new(log) {
+        Log::Consumed(consumed_log) if consumed_log.program_id() == Ok(None) => consumed_log.consumed().ok(),
+        _ => None,
+    });
+    if summary.is_some() {
+        return summary;
+    }
+    if meta.log_messages.is_empty() || is_truncated(&meta.log_messages) {
+        return None;
+    }
+    let structured_logs = build_structured_logs(&meta.log_messages).ok()?;
+    Some(structured_logs.iter().filter_map(|x| x.compute_units_consumed()).sum())
+}
@@ -0,0 +1,4 @@
+use crate::pubkey::Pubkey;
+use substreams_solana::b58;
+
+pub const COMPUTE_BUDGET_PROGRAM_ID: Pubkey = Pubkey(b58!("ComputeBudget111111111111111111111111111111"));
@@ -0,0 +1,50 @@
+use anyhow::{anyhow, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputeBudgetInstruction {
+    /// Deprecated, replaced by `SetComputeUnitLimit` and `SetComputeUnitPrice`
+    RequestUnitsDeprecated {
+        /// Units to request
+        units: u32,
+        /// Additional fee to pay, in lamports
+        additional_fee: u32,
+    },
+    /// Request a specific transaction-wide program heap region size in bytes.
+    /// The value requested must be a multiple of 1024.
+    RequestHeapFrame(u32),
+    /// Set a specific compute unit limit that the transaction is allowed to consume.
+    SetComputeUnitLimit(u32),
+    /// Set a compute unit price in "micro-lamports" to pay a higher transaction
+    /// fee for higher transaction prioritization.
+    SetComputeUnitPrice(u64),
+    /// Set a specific transaction-wide account data size limit, in bytes, is allowed to load.
+    SetLoadedAccountsDataSizeLimit(u32),
+    /// An instruction added to the program after this decoder, with its discriminator
+    Unknown(u8),
+}
+
+impl ComputeBudgetInstruction {
+    /// Unpacks the instruction. Unknown discriminators decode to `Unknown`, but
+    /// known ones with missing data fail.
+    pub fn unpack(data: &[u8]) -> Result<Self, Error> {
+        let (&tag, data) = data.split_first().ok_or(anyhow!("Failed to unpack ComputeBudget instruction: empty data"))?;
+        match tag {
+            0 => Ok(Self::RequestUnitsDeprecated { units: unpack_u32(data)?, additional_fee: unpack_u32(data.get(4..).unwrap_or_default())? }),
+            1 => unpack_u32(data).map(Self::RequestHeapFrame),
+            2 => unpack_u32(data).map(Self::SetComputeUnitLimit),
+            3 => unpack_u64(data).map(Self::SetComputeUnitPrice),
+            4 => unpack_u32(data).map(Self::SetLoadedAccountsDataSizeLimit),
+            _ => Ok(Self::Unknown(tag)),
+        }
+    }
+}
+
+fn unpack_u32(data: &[u8]) -> Result<u32, Error> {
+    let bytes = data.get(..4).ok_or(anyhow!("Failed to unpack ComputeBudget instruction: expected 4 bytes, found {}", data.len()))?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn unpack_u64(data: &[u8]) -> Result<u64, Error> {
+    let bytes = data.get(..8).ok_or(anyhow!("Failed to unpack ComputeBudget instruction: expected 8 bytes, found {}", data.len()))?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
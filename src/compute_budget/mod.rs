@@ -0,0 +1,6 @@
+pub mod constants;
+pub use constants::*;
+pub mod instruction;
+pub use instruction::*;
+pub mod budget;
+pub use budget::*;
@@ -0,0 +1,146 @@
+//! Detection of the protocols a transaction interacts with, combining program
+//! ids, Anchor discriminators, account patterns and logs.
+//!
+//! Evidence is only gathered for instructions related to a protocol: those of its
+//! program, and those passing its program id as an account, e.g. through a proxy
+//! program. A fork copying a protocol's instruction layout under its own program
+//! id is therefore never detected as the protocol.
+
+use std::rc::Rc;
+
+use crate::instruction::{StructuredInstruction, StructuredInstructions};
+use crate::log::Log;
+use crate::programs::{KnownProgram, ProgramCategory, ANCHOR_EVENT_IX_TAG};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+/// Signal pointing at a protocol. `instruction_index` is the position of the
+/// instruction in the flattened instructions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Evidence {
+    /// The instruction belongs to the protocol program
+    ProgramIdMatch { instruction_index: usize },
+    /// The instruction data starts with a protocol instruction discriminator
+    DiscriminatorMatch { instruction_index: usize, name: &'static str },
+    /// A protocol event was emitted, through a `Program data` log or `emit_cpi!`
+    EventDiscriminatorMatch { instruction_index: usize, name: &'static str },
+    /// The protocol program id is one of the instruction accounts
+    PatternMatch { instruction_index: usize },
+    /// The instruction logged `Instruction: <name>` with a protocol instruction name
+    LoggedInstructionName { instruction_index: usize, name: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct ProtocolDetection {
+    pub protocol: KnownProgram,
+    pub confidence: Confidence,
+    pub evidence: Vec<Evidence>,
+}
+
+impl ProtocolDetection {
+    /// High for the program id together with a discriminator. Medium for the
+    /// program id alone, or for a discriminator together with an account
+    /// pattern. Low otherwise.
+    fn confidence(evidence: &[Evidence]) -> Confidence {
+        let program_id = evidence.iter().any(|x| matches!(x, Evidence::ProgramIdMatch { .. }));
+        let pattern = evidence.iter().any(|x| matches!(x, Evidence::PatternMatch { .. }));
+        let discriminator = evidence.iter().any(|x| matches!(x, Evidence::DiscriminatorMatch { .. } | Evidence::EventDiscriminatorMatch { .. }));
+        if program_id && discriminator {
+            Confidence::High
+        } else if program_id || (pattern && discriminator) {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        }
+    }
+}
+
+/// Detects the DEX and NFT protocols of the transaction, in order of first
+/// evidence.
+pub fn protocols(instructions: &Vec<Rc<StructuredInstruction>>) -> Vec<ProtocolDetection> {
+    let mut detections: Vec<(KnownProgram, Vec<Evidence>)> = Vec::new();
+    for (instruction_index, instruction) in instructions.flattened().iter().enumerate() {
+        for protocol in KnownProgram::iter().filter(is_protocol) {
+            let evidence = instruction_evidence(instruction_index, instruction, protocol);
+            if evidence.is_empty() {
+                continue;
+            }
+            match detections.iter_mut().find(|(x, _)| *x == protocol) {
+                Some((_, detected)) => detected.extend(evidence),
+                None => detections.push((protocol, evidence)),
+            }
+        }
+    }
+    detections.into_iter().map(|(protocol, evidence)| ProtocolDetection {
+        protocol,
+        confidence: ProtocolDetection::confidence(&evidence),
+        evidence,
+    }).collect()
+}
+
+fn is_protocol(program: &KnownProgram) -> bool {
+    matches!(program.category(), ProgramCategory::Dex | ProgramCategory::Nft)
+}
+
+fn instruction_evidence(instruction_index: usize, instruction: &StructuredInstruction, protocol: KnownProgram) -> Vec<Evidence> {
+    let program_id = protocol.program_id();
+    let mut evidence = Vec::new();
+    if instruction.program_id() == program_id {
+        evidence.push(Evidence::ProgramIdMatch { instruction_index });
+    } else if instruction.accounts().iter().any(|x| *x == program_id) {
+        evidence.push(Evidence::PatternMatch { instruction_index });
+    } else {
+        return evidence;
+    }
+
+    // A linear scan rather than `discriminator_in`: evidence needs the name of the
+    // matched instruction, which a `DiscriminatorSet` does not keep, and protocol
+    // tables hold a handful of discriminators, below the size where a set is
+    // faster, see `benches/discriminators.rs`.
+    if let Some((name, _)) = protocol.instruction_discriminators().find(|(_, x)| instruction.data_starts_with(*x)) {
+        evidence.push(Evidence::DiscriminatorMatch { instruction_index, name });
+    }
+    if instruction.data_starts_with(&ANCHOR_EVENT_IX_TAG) {
+        if let Some((name, _)) = protocol.event_discriminators().find(|(_, x)| instruction.data()[8..].starts_with(*x)) {
+            evidence.push(Evidence::EventDiscriminatorMatch { instruction_index, name });
+        }
+    }
+
+    if let Some(logs) = instruction.logs().as_ref() {
+        for log in logs {
+            match log {
+                Log::Data(data_log) => {
+                    let Ok(data) = data_log.bytes() else { continue };
+                    if let Some((name, _)) = protocol.event_discriminators().find(|(_, x)| data.starts_with(*x)) {
+                        evidence.push(Evidence::EventDiscriminatorMatch { instruction_index, name });
+                    }
+                },
+                Log::Program(program_log) => {
+                    let Ok(message) = program_log.message() else { continue };
+                    let Some(logged_name) = message.strip_prefix("Instruction: ") else { continue };
+                    if protocol.instruction_discriminators().any(|(name, _)| pascal_case(name) == logged_name) {
+                        evidence.push(Evidence::LoggedInstructionName { instruction_index, name: logged_name.to_string() });
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+    evidence
+}
+
+/// `shared_accounts_route` to `SharedAccountsRoute`, as logged by Anchor programs
+fn pascal_case(name: &str) -> String {
+    name.split('_').map(|word| {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+            None => String::new(),
+        }
+    }).collect()
+}
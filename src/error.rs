@@ -1,9 +1,124 @@
-// use thiserror::Error;
-
-// #[derive(Error, Debug)]
-// pub enum ParseError {
-//     #[error("Cannot parse a failed transaction")]
-//     FailedTransaction,
-//     #[error("Instruction parsing error: {0}")]
-//     Instruction(String),
-// }
+use thiserror::Error;
+
+/// Errors of `get_structured_instructions` and the functions structuring
+/// instructions with their logs.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Cannot structure instructions of a failed transaction")]
+    FailedTransaction,
+    #[error("Transaction did not fail")]
+    SuccessfulTransaction,
+    #[error("Transaction has no meta")]
+    MissingMeta,
+    #[error("Confirmed transaction has no transaction")]
+    MissingTransaction,
+    #[error("Transaction has no message")]
+    MissingMessage,
+    /// A transaction without signatures, which has no id to attribute its events to
+    #[error("Transaction has no signature")]
+    MissingSignature,
+    /// An instruction referencing an account index past the `len` accounts of
+    /// the transaction, e.g. through a malformed message.
+    ///
+    /// ```
+    /// use substreams_solana_utils::error::ParseError;
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let mut transaction = TransactionBuilder::new()
+    ///     .signer(pubkey(1))
+    ///     .instruction(pubkey(2), &[pubkey(1), pubkey(3), pubkey(4), pubkey(5)], vec![0])
+    ///     .build();
+    /// let message = transaction.transaction.as_mut().unwrap().message.as_mut().unwrap();
+    /// message.instructions[0].accounts[3] = 255;
+    ///
+    /// let error = get_structured_instructions(&transaction).unwrap_err();
+    /// assert_eq!(error, ParseError::AccountIndexOutOfBounds { index: 255, len: 5 });
+    /// ```
+    #[error("Account index {index} is out of bounds of the {len} transaction accounts")]
+    AccountIndexOutOfBounds { index: usize, len: usize },
+    /// A message header requiring more signatures than the `len` accounts of the
+    /// transaction
+    ///
+    /// ```
+    /// use substreams_solana_utils::error::ParseError;
+    /// use substreams_solana_utils::transaction::TransactionContext;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let mut transaction = TransactionBuilder::new().signer(pubkey(1)).build();
+    /// let message = transaction.transaction.as_mut().unwrap().message.as_mut().unwrap();
+    /// message.header.as_mut().unwrap().num_required_signatures = 3;
+    ///
+    /// let error = TransactionContext::build(&transaction).err().unwrap();
+    /// assert_eq!(error, ParseError::TooManySignatures { num_required_signatures: 3, len: 1 });
+    /// ```
+    #[error("Message header requires {num_required_signatures} signatures, more than the {len} transaction accounts")]
+    TooManySignatures { num_required_signatures: usize, len: usize },
+    /// A token balance of the transaction meta whose amount is missing or not a
+    /// `u64`, or whose mint or owner is not an address
+    #[error("Token balance of account {account_index} is malformed")]
+    MalformedTokenBalance { account_index: u32 },
+    /// An invoke log where the invocation of the instruction was expected to end,
+    /// e.g. for an inner instruction missing from the transaction meta
+    #[error("Unexpected invoke log at position {position}")]
+    UnexpectedInvoke { position: usize },
+    /// An invoke, success or failed log of another program than the instruction's
+    #[error("Log at position {position} belongs to {found} instead of {expected}")]
+    UnexpectedProgram { position: usize, expected: String, found: String },
+    /// An invoke log whose depth is not the stack height of the instruction,
+    /// see `StructureOptions::validate_invoke_depth`
+    #[error("Invoke log at position {position} has depth {found} instead of {expected}")]
+    UnexpectedInvokeDepth { position: usize, expected: u32, found: u32 },
+    /// A log attributed to no instruction, see `StructureOptions::reject_orphan_logs`
+    #[error("Log at position {position} belongs to no instruction")]
+    OrphanLog { position: usize },
+    /// See `StructureOptions::max_depth`
+    #[error("Instruction {flat_index} has stack height {stack_height}, above the maximum of {max}")]
+    MaxDepthExceeded { flat_index: usize, stack_height: u32, max: u32 },
+    /// See `StructureOptions::max_instructions`
+    #[error("Transaction has {count} instructions, above the maximum of {max}")]
+    TooManyInstructions { count: usize, max: usize },
+}
+
+/// Errors of `build_structured_logs`
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LogStructureError {
+    /// A success or failed log of another program than the invocation it closes
+    #[error("Log at position {position} closes an invocation of {expected} as {found}")]
+    UnexpectedTerminator { position: usize, expected: String, found: String },
+}
+
+/// Errors decoding the payload of a `Program data` or `Program return` log.
+/// `line_index` is the index of the line in `log_messages`, when known.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LogDecodeError {
+    #[error("Malformed log{}", at_line(.line_index))]
+    Malformed { line_index: Option<usize> },
+    #[error("Invalid base64 payload{}: {source}", at_line(.line_index))]
+    Base64 { line_index: Option<usize>, source: base64::DecodeError },
+}
+
+impl LogDecodeError {
+    pub fn with_line_index(self, line_index: usize) -> Self {
+        match self {
+            Self::Malformed { .. } => Self::Malformed { line_index: Some(line_index) },
+            Self::Base64 { source, .. } => Self::Base64 { line_index: Some(line_index), source },
+        }
+    }
+}
+
+fn at_line(line_index: &Option<usize>) -> String {
+    line_index.map(|x| format!(" at line {}", x)).unwrap_or_default()
+}
+
+/// Errors of `StructuredInstruction::try_decode`
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("Instruction data has {len} bytes, fewer than the {expected} expected")]
+    DataTooShort { len: usize, expected: usize },
+    #[error("Instruction data does not start with the expected discriminator")]
+    DiscriminatorMismatch,
+    /// The borsh error, which is not `Clone`, as a message
+    #[error("Failed to deserialize instruction data: {0}")]
+    Borsh(String),
+}
@@ -0,0 +1,94 @@
+//! Ordinals for events extracted from a single instruction.
+//!
+//! An event derived from an inner instruction is ordered by that inner
+//! instruction's position in its parent's inner instruction list, and one
+//! derived from a top level instruction by its `top_level_index`. An event
+//! derived from a log is ordered by the log's position in the instruction's own
+//! logs. Instruction-derived ordinals sort before log-derived ones, and events
+//! sharing the same origin are told apart by `sub_index`.
+//!
+//! Because positions are taken relative to the originating instruction (and
+//! logs are only ever truncated at the tail), an event keeps its ordinal when
+//! unrelated data later in the transaction is missing. Event ids pair the
+//! ordinal with the path of that instruction, see `event_id`.
+
+use std::rc::Rc;
+
+use crate::instruction::StructuredInstruction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OrdinalSource {
+    Instruction,
+    Log,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EventOrdinal {
+    pub source: OrdinalSource,
+    pub position: u32,
+    pub sub_index: u32,
+}
+
+impl EventOrdinal {
+    pub fn from_instruction_position(position: u32) -> Self {
+        Self { source: OrdinalSource::Instruction, position, sub_index: 0 }
+    }
+
+    pub fn from_log_position(position: u32) -> Self {
+        Self { source: OrdinalSource::Log, position, sub_index: 0 }
+    }
+
+    /// Ordinal of an event originating from `instruction`, based on its position
+    /// within the parent's inner instructions. Returns `None` for top level
+    /// instructions. Its event id takes the path of the parent.
+    pub fn from_inner_instruction(instruction: &StructuredInstruction) -> Option<Self> {
+        let parent = instruction.parent_instruction()?;
+        let position = parent.inner_instructions().iter().position(|x| std::ptr::eq(Rc::as_ptr(x), instruction))?;
+        Some(Self::from_instruction_position(position as u32))
+    }
+
+    /// Tiebreak between several events derived from the same origin
+    pub fn with_sub_index(self, sub_index: u32) -> Self {
+        Self { sub_index, ..self }
+    }
+}
+
+/// Builds an event id from the transaction signature, the path of the
+/// instruction the ordinal is relative to, and the event ordinal: the parent for
+/// an event derived from an inner instruction, the instruction holding the logs
+/// for a log-derived event, and an empty path for an event derived from a top
+/// level instruction. See `StructuredInstruction::path`.
+///
+/// ```
+/// use substreams_solana_utils::event::{event_id, EventOrdinal};
+/// use substreams_solana_utils::instruction::get_structured_instructions;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(router, &[user], vec![0])
+///     .inner(pool, &[user], vec![1])
+///     .inner_at(3, pool, &[user], vec![2])
+///     .build();
+///
+/// let instructions = get_structured_instructions(&transaction).unwrap();
+/// let swap = instructions[0].inner_instructions()[0].clone();
+/// let ordinal = EventOrdinal::from_inner_instruction(&swap.inner_instructions()[0]).unwrap();
+/// assert_eq!(event_id("signature", &swap.path(), &ordinal), "signature-0.0-i0.0");
+/// assert_eq!(event_id("signature", &swap.path(), &EventOrdinal::from_log_position(2)), "signature-0.0-l2.0");
+/// assert_eq!(event_id("signature", &instructions[0].path(), &EventOrdinal::from_log_position(2)), "signature-0-l2.0");
+/// assert_eq!(event_id("signature", &[], &EventOrdinal::from_instruction_position(0)), "signature-i0.0");
+/// ```
+pub fn event_id(signature: &str, path: &[u32], ordinal: &EventOrdinal) -> String {
+    let source = match ordinal.source {
+        OrdinalSource::Instruction => "i",
+        OrdinalSource::Log => "l",
+    };
+    let ordinal = format!("{}{}.{}", source, ordinal.position, ordinal.sub_index);
+    if path.is_empty() {
+        return format!("{}-{}", signature, ordinal);
+    }
+    let path: Vec<String> = path.iter().map(|x| x.to_string()).collect();
+    format!("{}-{}-{}", signature, path.join("."), ordinal)
+}
@@ -0,0 +1,354 @@
+//! Routing of instructions to per-protocol event extractors.
+//!
+//! Each program id is claimed by at most one extractor, and every instruction of
+//! a transaction, inner instructions included, is handed to the extractor of its
+//! program exactly once, in execution order.
+//!
+//! Every event carries the `schema_version` of its output type. A version is
+//! bumped whenever a field of the event type changes meaning or is removed, so
+//! that consumers can tell old and new outputs apart.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use anyhow::{anyhow, Error};
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::address_lookup_table::{AddressLookupTableInstruction, LookupTableEvent, ADDRESS_LOOKUP_TABLE_PROGRAM_ID, to_pubkeys};
+use crate::associated_token_account::{AssociatedTokenAccountInstruction, ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID};
+use crate::instruction::{StructuredInstruction, StructuredInstructions};
+use crate::programs::KnownProgram;
+use crate::pubkey::Pubkey;
+use crate::spl_token::{TokenInstruction, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use crate::system_program::{SystemInstruction, SYSTEM_PROGRAM_ID};
+use crate::transaction::TransactionContext;
+
+pub const TOKEN_EVENT_SCHEMA_VERSION: u16 = 1;
+pub const SYSTEM_EVENT_SCHEMA_VERSION: u16 = 1;
+pub const ASSOCIATED_TOKEN_ACCOUNT_EVENT_SCHEMA_VERSION: u16 = 1;
+pub const LOOKUP_TABLE_EVENT_SCHEMA_VERSION: u16 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenEvent {
+    Transfer { source: Pubkey, destination: Pubkey, authority: Pubkey, amount: u64 },
+    MintTo { mint: Pubkey, account: Pubkey, amount: u64 },
+    Burn { account: Pubkey, mint: Pubkey, amount: u64 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SystemEvent {
+    Transfer { from: Pubkey, to: Pubkey, lamports: u64 },
+    CreateAccount { from: Pubkey, to: Pubkey, lamports: u64, space: u64, owner: Pubkey },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssociatedTokenAccountEvent {
+    Create { payer: Pubkey, account: Pubkey, wallet: Pubkey, mint: Pubkey, idempotent: bool },
+}
+
+/// Event of a user-defined extractor, encoded by the extractor itself, e.g. as a
+/// protobuf message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomEvent {
+    pub extractor: &'static str,
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProtocolEvent {
+    Token { schema_version: u16, event: TokenEvent },
+    System { schema_version: u16, event: SystemEvent },
+    AssociatedTokenAccount { schema_version: u16, event: AssociatedTokenAccountEvent },
+    LookupTable { schema_version: u16, event: LookupTableEvent },
+    Custom { schema_version: u16, event: CustomEvent },
+}
+
+impl ProtocolEvent {
+    pub fn schema_version(&self) -> u16 {
+        match self {
+            Self::Token { schema_version, .. } |
+            Self::System { schema_version, .. } |
+            Self::AssociatedTokenAccount { schema_version, .. } |
+            Self::LookupTable { schema_version, .. } |
+            Self::Custom { schema_version, .. } => *schema_version,
+        }
+    }
+}
+
+/// Decodes the instructions of the programs it claims into events
+pub trait Extractor {
+    /// Name of the extractor, used in error messages
+    fn name(&self) -> &'static str;
+
+    /// Program ids whose instructions are routed to this extractor
+    fn program_ids(&self) -> Vec<Pubkey>;
+
+    /// Events of a single instruction of one of the claimed programs. Inner
+    /// instructions are routed separately and must not be decoded here.
+    fn extract(&self, instruction: &StructuredInstruction, context: &TransactionContext) -> Vec<ProtocolEvent>;
+}
+
+/// Set of extractors, routing each program id to a single one of them.
+///
+/// `register` refuses an extractor claiming a program id that is already
+/// claimed, while `replace` takes the program ids over from their current
+/// extractor, e.g. to swap a built-in extractor for a custom one.
+pub struct ExtractorRegistry {
+    extractors: Vec<Box<dyn Extractor>>,
+    routes: HashMap<Pubkey, usize>,
+    disabled: HashSet<Pubkey>,
+}
+
+impl ExtractorRegistry {
+    /// Registry without any extractor
+    pub fn empty() -> Self {
+        Self {
+            extractors: Vec::new(),
+            routes: HashMap::new(),
+            disabled: HashSet::new(),
+        }
+    }
+
+    pub fn register(&mut self, extractor: Box<dyn Extractor>) -> Result<(), Error> {
+        let program_ids = extractor.program_ids();
+        if let Some(program_id) = program_ids.iter().find(|x| self.routes.contains_key(x)) {
+            let current = &self.extractors[self.routes[program_id]];
+            return Err(anyhow!("Program {} is already claimed by the {} extractor", program_id.to_string(), current.name()));
+        }
+        self.insert(extractor, program_ids);
+        Ok(())
+    }
+
+    /// Registers `extractor`, taking its program ids over. An extractor left
+    /// without program ids is dropped.
+    pub fn replace(&mut self, extractor: Box<dyn Extractor>) {
+        let program_ids = extractor.program_ids();
+        self.insert(extractor, program_ids);
+        self.remove_unrouted();
+    }
+
+    fn remove_unrouted(&mut self) {
+        let routed: HashSet<usize> = self.routes.values().copied().collect();
+        let mut index = 0;
+        self.extractors.retain(|_| {
+            index += 1;
+            routed.contains(&(index - 1))
+        });
+        for route in self.routes.values_mut() {
+            *route -= (0..*route).filter(|x| !routed.contains(x)).count();
+        }
+    }
+
+    fn insert(&mut self, extractor: Box<dyn Extractor>, program_ids: Vec<Pubkey>) {
+        let index = self.extractors.len();
+        self.extractors.push(extractor);
+        for program_id in program_ids {
+            self.routes.insert(program_id, index);
+        }
+    }
+
+    /// Routes the instructions of `program` again, if it has an extractor
+    pub fn enable(&mut self, program: KnownProgram) {
+        self.disabled.remove(&program.program_id());
+    }
+
+    /// Stops routing the instructions of `program`
+    pub fn disable(&mut self, program: KnownProgram) {
+        self.disabled.insert(program.program_id());
+    }
+
+    pub fn is_enabled(&self, program_id: &Pubkey) -> bool {
+        self.routes.contains_key(program_id) && !self.disabled.contains(program_id)
+    }
+
+    /// Events of every instruction of `instructions`, in execution order. Failed
+    /// transactions have no events.
+    pub fn extract(
+        &self,
+        transaction: &ConfirmedTransaction,
+        context: &TransactionContext,
+        instructions: &Vec<Rc<StructuredInstruction>>,
+    ) -> Vec<ProtocolEvent> {
+        if transaction.meta.as_ref().is_some_and(|x| x.err.is_some()) {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        for instruction in instructions.flattened() {
+            let Ok(program_id) = instruction.program_id().to_pubkey() else { continue };
+            if self.disabled.contains(&program_id) {
+                continue;
+            }
+            if let Some(index) = self.routes.get(&program_id) {
+                events.extend(self.extractors[*index].extract(&instruction, context));
+            }
+        }
+        events
+    }
+}
+
+impl Default for ExtractorRegistry {
+    /// Registry with the extractors of the token, system, associated token
+    /// account and address lookup table programs
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.replace(Box::new(TokenExtractor));
+        registry.replace(Box::new(SystemExtractor));
+        registry.replace(Box::new(AssociatedTokenAccountExtractor));
+        registry.replace(Box::new(LookupTableExtractor));
+        registry
+    }
+}
+
+fn account(instruction: &StructuredInstruction, i: usize) -> Option<Pubkey> {
+    instruction.accounts().get(i)?.to_pubkey().ok()
+}
+
+/// Extractor of the Token and Token-2022 programs
+pub struct TokenExtractor;
+
+impl Extractor for TokenExtractor {
+    fn name(&self) -> &'static str { "token" }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID]
+    }
+
+    fn extract(&self, instruction: &StructuredInstruction, _context: &TransactionContext) -> Vec<ProtocolEvent> {
+        token_event(instruction)
+            .map(|event| ProtocolEvent::Token { schema_version: TOKEN_EVENT_SCHEMA_VERSION, event })
+            .into_iter()
+            .collect()
+    }
+}
+
+fn token_event(instruction: &StructuredInstruction) -> Option<TokenEvent> {
+    let account = |i| account(instruction, i);
+    let event = match TokenInstruction::unpack(instruction.data()).ok()? {
+        TokenInstruction::Transfer { amount } => {
+            TokenEvent::Transfer { source: account(0)?, destination: account(1)?, authority: account(2)?, amount }
+        },
+        TokenInstruction::TransferChecked { amount, decimals: _ } => {
+            TokenEvent::Transfer { source: account(0)?, destination: account(2)?, authority: account(3)?, amount }
+        },
+        TokenInstruction::MintTo { amount } |
+        TokenInstruction::MintToChecked { amount, decimals: _ } => {
+            TokenEvent::MintTo { mint: account(0)?, account: account(1)?, amount }
+        },
+        TokenInstruction::Burn { amount } |
+        TokenInstruction::BurnChecked { amount, decimals: _ } => {
+            TokenEvent::Burn { account: account(0)?, mint: account(1)?, amount }
+        },
+        _ => return None,
+    };
+    Some(event)
+}
+
+/// Extractor of the system program lamport transfers and account creations
+pub struct SystemExtractor;
+
+impl Extractor for SystemExtractor {
+    fn name(&self) -> &'static str { "system" }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![SYSTEM_PROGRAM_ID]
+    }
+
+    fn extract(&self, instruction: &StructuredInstruction, _context: &TransactionContext) -> Vec<ProtocolEvent> {
+        system_event(instruction)
+            .map(|event| ProtocolEvent::System { schema_version: SYSTEM_EVENT_SCHEMA_VERSION, event })
+            .into_iter()
+            .collect()
+    }
+}
+
+fn system_event(instruction: &StructuredInstruction) -> Option<SystemEvent> {
+    if instruction.data().len() < 4 {
+        return None;
+    }
+    let account = |i| account(instruction, i);
+    let event = match SystemInstruction::unpack(instruction.data()).ok()? {
+        SystemInstruction::Transfer(transfer) => {
+            SystemEvent::Transfer { from: account(0)?, to: account(1)?, lamports: transfer.lamports }
+        },
+        SystemInstruction::TransferWithSeed(transfer) => {
+            SystemEvent::Transfer { from: account(0)?, to: account(2)?, lamports: transfer.lamports }
+        },
+        SystemInstruction::CreateAccount(create) => {
+            SystemEvent::CreateAccount { from: account(0)?, to: account(1)?, lamports: create.lamports, space: create.space, owner: create.owner }
+        },
+        SystemInstruction::CreateAccountWithSeed(create) => {
+            SystemEvent::CreateAccount { from: account(0)?, to: account(1)?, lamports: create.lamports, space: create.space, owner: create.owner }
+        },
+        _ => return None,
+    };
+    Some(event)
+}
+
+pub struct AssociatedTokenAccountExtractor;
+
+impl Extractor for AssociatedTokenAccountExtractor {
+    fn name(&self) -> &'static str { "associated-token-account" }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID]
+    }
+
+    fn extract(&self, instruction: &StructuredInstruction, _context: &TransactionContext) -> Vec<ProtocolEvent> {
+        associated_token_account_event(instruction)
+            .map(|event| ProtocolEvent::AssociatedTokenAccount { schema_version: ASSOCIATED_TOKEN_ACCOUNT_EVENT_SCHEMA_VERSION, event })
+            .into_iter()
+            .collect()
+    }
+}
+
+fn associated_token_account_event(instruction: &StructuredInstruction) -> Option<AssociatedTokenAccountEvent> {
+    let idempotent = match AssociatedTokenAccountInstruction::unpack(instruction.data()).ok()? {
+        AssociatedTokenAccountInstruction::Create => false,
+        AssociatedTokenAccountInstruction::CreateIdempotent => true,
+        AssociatedTokenAccountInstruction::RecoverNested => return None,
+    };
+    let account = |i| account(instruction, i);
+    Some(AssociatedTokenAccountEvent::Create { payer: account(0)?, account: account(1)?, wallet: account(2)?, mint: account(3)?, idempotent })
+}
+
+/// Extractor of the address lookup table program. Instructions are decoded one
+/// at a time, so `Extended::start_index` is always `None`.
+pub struct LookupTableExtractor;
+
+impl Extractor for LookupTableExtractor {
+    fn name(&self) -> &'static str { "address-lookup-table" }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        vec![ADDRESS_LOOKUP_TABLE_PROGRAM_ID]
+    }
+
+    fn extract(&self, instruction: &StructuredInstruction, _context: &TransactionContext) -> Vec<ProtocolEvent> {
+        lookup_table_event(instruction)
+            .map(|event| ProtocolEvent::LookupTable { schema_version: LOOKUP_TABLE_EVENT_SCHEMA_VERSION, event })
+            .into_iter()
+            .collect()
+    }
+}
+
+fn lookup_table_event(instruction: &StructuredInstruction) -> Option<LookupTableEvent> {
+    let account = |i| account(instruction, i);
+    let event = match AddressLookupTableInstruction::unpack(instruction.data()).ok()? {
+        AddressLookupTableInstruction::CreateLookupTable { recent_slot, bump_seed: _ } => {
+            LookupTableEvent::Created { table: account(0)?, authority: account(1)?, payer: account(2)?, recent_slot }
+        },
+        AddressLookupTableInstruction::ExtendLookupTable { new_addresses } => {
+            LookupTableEvent::Extended { table: account(0)?, authority: account(1)?, new_addresses: to_pubkeys(new_addresses), start_index: None }
+        },
+        AddressLookupTableInstruction::FreezeLookupTable => {
+            LookupTableEvent::Frozen { table: account(0)?, authority: account(1)? }
+        },
+        AddressLookupTableInstruction::DeactivateLookupTable => {
+            LookupTableEvent::Deactivated { table: account(0)?, authority: account(1)? }
+        },
+        AddressLookupTableInstruction::CloseLookupTable => {
+            LookupTableEvent::Closed { table: account(0)?, authority: account(1)?, recipient: account(2)? }
+        },
+    };
+    Some(event)
+}
@@ -0,0 +1,433 @@
+//! Golden data harness for the instruction log attribution.
+//!
+//! A golden case is a pair of files in the same directory:
+//! - `<name>.tx.json`: the transaction, as returned by the `getTransaction` RPC
+//!   method with `"encoding": "json"`, either bare or wrapped in the RPC response.
+//! - `<name>.expected.json`: the expected per-instruction logs, in the shape of the
+//!   `instruction_logs` example output:
+//!   `{"signature": "...", "instructions": [{"program_id": "...", "logs": ["..."]}]}`.
+//!   `logs` is `null` for instructions whose logs were truncated away.
+//!
+//! The comparison ignores leading and trailing whitespace of every string, and
+//! nothing else. Each case is also cross-validated: the logs structured from the
+//! log messages alone must match those attached to the instructions.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Error};
+use base64::Engine;
+use serde_json::Value;
+use substreams_solana::pb::sf::solana::r#type::v1 as pb;
+
+use crate::instruction::{get_structured_instructions, get_structured_instructions_with_orphan_logs, StructureOptions, StructuredInstructions};
+use crate::log::{build_structured_logs, is_truncated, Log, ProgramStructuredLogs};
+use crate::pubkey::Pubkey;
+use crate::transaction::{get_return_data, get_signature};
+use crate::transaction_error::{InstructionError, TransactionError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderedInstruction {
+    pub program_id: String,
+    pub logs: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderedTransaction {
+    pub signature: String,
+    pub instructions: Vec<RenderedInstruction>,
+}
+
+/// A field that differs between the expected and the actual rendering
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    /// Location of the field, e.g. `instructions[2].logs[0]`
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:\n  expected: {}\n  actual:   {}", self.path, self.expected, self.actual)
+    }
+}
+
+#[derive(Debug)]
+pub struct GoldenReport {
+    pub name: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Structures the transaction and renders its per-instruction logs the way the
+/// `instruction_logs` example does.
+pub fn render_instruction_logs(transaction: &pb::ConfirmedTransaction) -> Result<RenderedTransaction, Error> {
+    let instructions = get_structured_instructions(transaction)?.flattened().iter().map(|instruction| {
+        RenderedInstruction {
+            program_id: instruction.program_id().to_string(),
+            logs: instruction.logs().as_ref().map(|logs| logs.iter().map(|log| log.to_string()).collect()),
+        }
+    }).collect();
+    Ok(RenderedTransaction { signature: get_signature(transaction)?, instructions })
+}
+
+/// Compares two renderings field by field
+pub fn compare(expected: &RenderedTransaction, actual: &RenderedTransaction) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    compare_str(&mut mismatches, "signature".into(), &expected.signature, &actual.signature);
+    compare_len(&mut mismatches, "instructions".into(), expected.instructions.len(), actual.instructions.len());
+
+    for (i, (expected, actual)) in expected.instructions.iter().zip(&actual.instructions).enumerate() {
+        compare_str(&mut mismatches, format!("instructions[{}].program_id", i), &expected.program_id, &actual.program_id);
+        match (&expected.logs, &actual.logs) {
+            (Some(expected), Some(actual)) => {
+                compare_len(&mut mismatches, format!("instructions[{}].logs", i), expected.len(), actual.len());
+                for (j, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                    compare_str(&mut mismatches, format!("instructions[{}].logs[{}]", i, j), expected, actual);
+                }
+            },
+            (None, None) => (),
+            (expected, actual) => mismatches.push(Mismatch {
+                path: format!("instructions[{}].logs", i),
+                expected: if expected.is_some() { "logs".into() } else { "null".into() },
+                actual: if actual.is_some() { "logs".into() } else { "null".into() },
+            }),
+        }
+    }
+    mismatches
+}
+
+fn compare_str(mismatches: &mut Vec<Mismatch>, path: String, expected: &str, actual: &str) {
+    if expected.trim() != actual.trim() {
+        mismatches.push(Mismatch { path, expected: format!("{:?}", expected), actual: format!("{:?}", actual) });
+    }
+}
+
+fn compare_len(mismatches: &mut Vec<Mismatch>, path: String, expected: usize, actual: usize) {
+    if expected != actual {
+        mismatches.push(Mismatch { path: format!("{}.len()", path), expected: expected.to_string(), actual: actual.to_string() });
+    }
+}
+
+/// Compares the logs attached to the instructions with those structured by
+/// `build_structured_logs`, wherever both are available: instructions whose
+/// logs were truncated and programs that do not log are left out. Logs
+/// attributed to no instruction are reported as mismatches too.
+pub fn cross_validate(transaction: &pb::ConfirmedTransaction) -> Result<Vec<Mismatch>, Error> {
+    fn flatten<'b, 'a>(nodes: &'b [ProgramStructuredLogs<'a>], output: &mut Vec<&'b ProgramStructuredLogs<'a>>) {
+        for node in nodes {
+            output.push(node);
+            flatten(&node.inner, output);
+        }
+    }
+    let log_messages = &transaction.meta.as_ref().unwrap().log_messages;
+    let structured_logs = build_structured_logs(log_messages)?;
+    let mut nodes = Vec::new();
+    flatten(&structured_logs, &mut nodes);
+    nodes.retain(|node| !node.truncated);
+
+    let output = get_structured_instructions_with_orphan_logs(transaction, &StructureOptions::default())?;
+    let instructions: Vec<_> = output.instructions.flattened().into_iter()
+        .filter(|instruction| instruction.logs().as_ref().is_some_and(|logs| !logs.is_empty()))
+        .collect();
+
+    let mut mismatches = Vec::new();
+    for (i, log) in output.orphan_logs.iter().enumerate() {
+        mismatches.push(Mismatch { path: format!("orphan_logs[{}]", i), expected: "none".into(), actual: format!("{:?}", log.raw()) });
+    }
+    compare_len(&mut mismatches, "structured_logs".into(), instructions.len(), nodes.len());
+    for (i, (instruction, node)) in instructions.iter().zip(&nodes).enumerate() {
+        if instruction.stack_height().map(|x| x as usize) != Some(node.depth) {
+            mismatches.push(Mismatch {
+                path: format!("structured_logs[{}].depth", i),
+                expected: format!("{:?}", instruction.stack_height()),
+                actual: node.depth.to_string(),
+            });
+        }
+        let logs = instruction.logs();
+        let logs = logs.as_ref().unwrap();
+        compare_len(&mut mismatches, format!("structured_logs[{}].logs", i), logs.len(), node.logs.len());
+        for (j, (expected, actual)) in logs.iter().zip(&node.logs).enumerate() {
+            compare_str(&mut mismatches, format!("structured_logs[{}].logs[{}]", i, j), expected.raw(), actual.raw());
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Compares `meta.return_data` with the last `Program return` log, unless the
+/// meta does not record return data or the logs are truncated.
+///
+/// ```
+/// use substreams_solana::pb::sf::solana::r#type::v1::ReturnData;
+/// use substreams_solana_utils::golden::validate_return_data;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let program_id = pubkey(2).to_string();
+/// let mut transaction = TransactionBuilder::new()
+///     .signer(pubkey(1))
+///     .instruction(pubkey(2), &[pubkey(1)], vec![0])
+///     .logs(&[
+///         &format!("Program {program_id} invoke [1]"),
+///         &format!("Program return: {program_id} AQID"),
+///         &format!("Program {program_id} success"),
+///     ])
+///     .build();
+/// let meta = transaction.meta.as_mut().unwrap();
+/// meta.return_data = Some(ReturnData { program_id: pubkey(2).0.to_vec(), data: vec![1, 2, 3] });
+/// assert!(validate_return_data(&transaction).is_empty());
+///
+/// transaction.meta.as_mut().unwrap().return_data.as_mut().unwrap().data = vec![1, 2];
+/// assert_eq!(validate_return_data(&transaction)[0].path, "return_data.data");
+/// ```
+pub fn validate_return_data(transaction: &pb::ConfirmedTransaction) -> Vec<Mismatch> {
+    let meta = transaction.meta.as_ref().unwrap();
+    if meta.return_data_none || is_truncated(&meta.log_messages) {
+        return Vec::new();
+    }
+    let logged = meta.log_messages.iter().rev().find_map(|log| match Log::new(log) {
+        Log::Return(return_log) => Some((return_log.program_id().ok()?.to_string(), return_log.data().ok()?)),
+        _ => None,
+    });
+    let recorded = get_return_data(transaction).map(|(program_id, data)| (program_id.to_string(), data));
+    let mut mismatches = Vec::new();
+    match (recorded, logged) {
+        (Some((expected_program_id, expected_data)), Some((program_id, data))) => {
+            compare_str(&mut mismatches, "return_data.program_id".into(), &expected_program_id, &program_id);
+            if expected_data != data {
+                mismatches.push(Mismatch { path: "return_data.data".into(), expected: format!("{:?}", expected_data), actual: format!("{:?}", data) });
+            }
+        },
+        (None, None) => (),
+        (recorded, logged) => mismatches.push(Mismatch { path: "return_data".into(), expected: format!("{:?}", recorded), actual: format!("{:?}", logged) }),
+    }
+    mismatches
+}
+
+/// Runs every golden case found in `dir`, in file name order. A case missing
+/// either of its two files is an error.
+pub fn run_golden_cases(dir: &Path) -> Result<Vec<GoldenReport>, Error> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Cannot read golden directory {}", dir.display()))? {
+        let file_name = entry?.file_name().to_string_lossy().into_owned();
+        let name = file_name.strip_suffix(".tx.json").or_else(|| file_name.strip_suffix(".expected.json"));
+        if let Some(name) = name {
+            if !names.iter().any(|x| x == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+
+    let mut reports = Vec::new();
+    for name in names {
+        let transaction = fs::read_to_string(dir.join(format!("{}.tx.json", name)))
+            .with_context(|| format!("Golden case {} is missing its .tx.json file", name))?;
+        let expected = fs::read_to_string(dir.join(format!("{}.expected.json", name)))
+            .with_context(|| format!("Golden case {} is missing its .expected.json file", name))?;
+        let transaction = transaction_from_rpc_json(&transaction).with_context(|| format!("Golden case {}", name))?;
+        let expected = rendered_from_json(&expected).with_context(|| format!("Golden case {}", name))?;
+        let actual = render_instruction_logs(&transaction).with_context(|| format!("Golden case {}", name))?;
+        let mut mismatches = compare(&expected, &actual);
+        mismatches.extend(cross_validate(&transaction)?);
+        mismatches.extend(validate_return_data(&transaction));
+        reports.push(GoldenReport { name, mismatches });
+    }
+    Ok(reports)
+}
+
+/// Parses an `.expected.json` file
+pub fn rendered_from_json(input: &str) -> Result<RenderedTransaction, Error> {
+    let value: Value = serde_json::from_str(input)?;
+    let instructions = array(&value, "instructions")?.iter().map(|instruction| {
+        let logs = match instruction.get("logs") {
+            Some(Value::Null) | None => None,
+            Some(_) => Some(array(instruction, "logs")?.iter().map(|log| as_str(log).map(String::from)).collect::<Result<_, _>>()?),
+        };
+        Ok(RenderedInstruction { program_id: string(instruction, "program_id")?, logs })
+    }).collect::<Result<_, Error>>()?;
+    Ok(RenderedTransaction { signature: string(&value, "signature")?, instructions })
+}
+
+/// Converts a `getTransaction` RPC response, with `"encoding": "json"`, into the
+/// protobuf transaction used by substreams.
+pub fn transaction_from_rpc_json(input: &str) -> Result<pb::ConfirmedTransaction, Error> {
+    let value: Value = serde_json::from_str(input)?;
+    let value = value.get("result").unwrap_or(&value);
+    let transaction = field(value, "transaction")?;
+    let message = field(transaction, "message")?;
+    let meta = field(value, "meta")?;
+
+    let header = field(message, "header")?;
+    let header = pb::MessageHeader {
+        num_required_signatures: u64_field(header, "numRequiredSignatures")? as u32,
+        num_readonly_signed_accounts: u64_field(header, "numReadonlySignedAccounts")? as u32,
+        num_readonly_unsigned_accounts: u64_field(header, "numReadonlyUnsignedAccounts")? as u32,
+    };
+    let instructions = array(message, "instructions")?.iter().map(|instruction| {
+        Ok(pb::CompiledInstruction {
+            program_id_index: u64_field(instruction, "programIdIndex")? as u32,
+            accounts: account_indexes(instruction)?,
+            data: b58_field(instruction, "data")?,
+        })
+    }).collect::<Result<_, Error>>()?;
+    let address_table_lookups = match message.get("addressTableLookups") {
+        Some(Value::Array(lookups)) => lookups.iter().map(|lookup| {
+            Ok(pb::MessageAddressTableLookup {
+                account_key: b58_field(lookup, "accountKey")?,
+                writable_indexes: u8_array(lookup, "writableIndexes")?,
+                readonly_indexes: u8_array(lookup, "readonlyIndexes")?,
+            })
+        }).collect::<Result<_, Error>>()?,
+        _ => Vec::new(),
+    };
+
+    let inner_instructions = array(meta, "innerInstructions")?.iter().map(|inner| {
+        let instructions = array(inner, "instructions")?.iter().map(|instruction| {
+            Ok(pb::InnerInstruction {
+                program_id_index: u64_field(instruction, "programIdIndex")? as u32,
+                accounts: account_indexes(instruction)?,
+                data: b58_field(instruction, "data")?,
+                stack_height: instruction.get("stackHeight").and_then(Value::as_u64).map(|x| x as u32),
+            })
+        }).collect::<Result<_, Error>>()?;
+        Ok(pb::InnerInstructions { index: u64_field(inner, "index")? as u32, instructions })
+    }).collect::<Result<_, Error>>()?;
+    let (loaded_writable_addresses, loaded_readonly_addresses) = match meta.get("loadedAddresses") {
+        Some(loaded) if !loaded.is_null() => (b58_array(loaded, "writable")?, b58_array(loaded, "readonly")?),
+        _ => (Vec::new(), Vec::new()),
+    };
+
+    Ok(pb::ConfirmedTransaction {
+        transaction: Some(pb::Transaction {
+            signatures: b58_array(transaction, "signatures")?,
+            message: Some(pb::Message {
+                header: Some(header),
+                account_keys: b58_array(message, "accountKeys")?,
+                recent_blockhash: b58_field(message, "recentBlockhash")?,
+                instructions,
+                versioned: !address_table_lookups.is_empty(),
+                address_table_lookups,
+            }),
+        }),
+        meta: Some(pb::TransactionStatusMeta {
+            err: match meta.get("err") {
+                Some(Value::Null) | None => None,
+                Some(err) => Some(pb::TransactionError { err: transaction_error(err)?.encode() }),
+            },
+            fee: u64_field(meta, "fee")?,
+            pre_balances: u64_array(meta, "preBalances")?,
+            post_balances: u64_array(meta, "postBalances")?,
+            inner_instructions,
+            log_messages: array(meta, "logMessages")?.iter().map(|log| as_str(log).map(String::from)).collect::<Result<_, _>>()?,
+            pre_token_balances: token_balances(meta, "preTokenBalances")?,
+            post_token_balances: token_balances(meta, "postTokenBalances")?,
+            loaded_writable_addresses,
+            loaded_readonly_addresses,
+            compute_units_consumed: meta.get("computeUnitsConsumed").and_then(Value::as_u64),
+            return_data: return_data(meta)?,
+            return_data_none: meta.get("returnData").is_none(),
+            ..Default::default()
+        }),
+    })
+}
+
+/// `{"programId": "...", "data": ["<base64>", "base64"]}`, or null when unset
+/// Reads the `err` of an RPC transaction, e.g. `{"InstructionError": [2, {"Custom": 6001}]}`
+fn transaction_error(err: &Value) -> Result<TransactionError, Error> {
+    let unsupported = || anyhow!("Unsupported transaction error {}", err);
+    if let Some(name) = err.as_str() {
+        return TransactionError::from_name(name).ok_or_else(unsupported);
+    }
+    let (name, fields) = err.as_object().and_then(|x| x.iter().next()).ok_or_else(unsupported)?;
+    let u8_value = |value: &Value| value.as_u64().and_then(|x| u8::try_from(x).ok()).ok_or_else(unsupported);
+    match name.as_str() {
+        "InstructionError" => {
+            let [index, error] = fields.as_array().map(Vec::as_slice).ok_or_else(unsupported)? else {
+                return Err(unsupported());
+            };
+            let error = match error {
+                Value::String(name) => InstructionError::from_name(name),
+                Value::Object(error) => match error.iter().next() {
+                    Some((name, Value::Number(code))) if name == "Custom" => code.as_u64().and_then(|x| u32::try_from(x).ok()).map(InstructionError::Custom),
+                    Some((name, Value::String(message))) if name == "BorshIoError" => Some(InstructionError::BorshIoError(message.clone())),
+                    _ => None,
+                },
+                _ => None,
+            };
+            Ok(TransactionError::InstructionError { index: u8_value(index)?, error: error.ok_or_else(unsupported)? })
+        },
+        "DuplicateInstruction" => Ok(TransactionError::DuplicateInstruction(u8_value(fields)?)),
+        "InsufficientFundsForRent" => Ok(TransactionError::InsufficientFundsForRent { account_index: u8_value(field(fields, "account_index")?)? }),
+        "ProgramExecutionTemporarilyRestricted" => Ok(TransactionError::ProgramExecutionTemporarilyRestricted { account_index: u8_value(field(fields, "account_index")?)? }),
+        _ => Err(unsupported()),
+    }
+}
+
+fn return_data(meta: &Value) -> Result<Option<pb::ReturnData>, Error> {
+    let Some(return_data) = meta.get("returnData").filter(|x| !x.is_null()) else { return Ok(None) };
+    let data = as_str(array(return_data, "data")?.first().ok_or_else(|| anyhow!("Missing return data"))?)?;
+    Ok(Some(pb::ReturnData {
+        program_id: Pubkey::try_from_string(&string(return_data, "programId")?)?.0.to_vec(),
+        data: base64::engine::general_purpose::STANDARD.decode(data)?,
+    }))
+}
+
+fn token_balances(value: &Value, key: &str) -> Result<Vec<pb::TokenBalance>, Error> {
+    let Some(balances) = value.get(key).and_then(Value::as_array) else { return Ok(Vec::new()) };
+    balances.iter().map(|balance| {
+        let amount = field(balance, "uiTokenAmount")?;
+        Ok(pb::TokenBalance {
+            account_index: u64_field(balance, "accountIndex")? as u32,
+            mint: string(balance, "mint")?,
+            owner: balance.get("owner").and_then(Value::as_str).unwrap_or_default().to_string(),
+            program_id: balance.get("programId").and_then(Value::as_str).unwrap_or_default().to_string(),
+            ui_token_amount: Some(pb::UiTokenAmount {
+                ui_amount: amount.get("uiAmount").and_then(Value::as_f64).unwrap_or_default(),
+                decimals: u64_field(amount, "decimals")? as u32,
+                amount: string(amount, "amount")?,
+                ui_amount_string: amount.get("uiAmountString").and_then(Value::as_str).unwrap_or_default().to_string(),
+            }),
+        })
+    }).collect()
+}
+
+fn field<'v>(value: &'v Value, key: &str) -> Result<&'v Value, Error> {
+    value.get(key).ok_or_else(|| anyhow!("Missing field {}", key))
+}
+
+fn array<'v>(value: &'v Value, key: &str) -> Result<&'v Vec<Value>, Error> {
+    field(value, key)?.as_array().ok_or_else(|| anyhow!("Field {} is not an array", key))
+}
+
+fn as_str(value: &Value) -> Result<&str, Error> {
+    value.as_str().ok_or_else(|| anyhow!("Expected a string, found {}", value))
+}
+
+fn string(value: &Value, key: &str) -> Result<String, Error> {
+    Ok(as_str(field(value, key)?)?.to_string())
+}
+
+fn u64_field(value: &Value, key: &str) -> Result<u64, Error> {
+    field(value, key)?.as_u64().ok_or_else(|| anyhow!("Field {} is not an integer", key))
+}
+
+fn u64_array(value: &Value, key: &str) -> Result<Vec<u64>, Error> {
+    array(value, key)?.iter().map(|x| x.as_u64().ok_or_else(|| anyhow!("Field {} is not an integer array", key))).collect()
+}
+
+fn u8_array(value: &Value, key: &str) -> Result<Vec<u8>, Error> {
+    u64_array(value, key)?.into_iter().map(|x| u8::try_from(x).map_err(|_| anyhow!("Field {} is not a byte array", key))).collect()
+}
+
+fn account_indexes(instruction: &Value) -> Result<Vec<u8>, Error> {
+    u8_array(instruction, "accounts")
+}
+
+fn b58_field(value: &Value, key: &str) -> Result<Vec<u8>, Error> {
+    Ok(bs58::decode(as_str(field(value, key)?)?).into_vec()?)
+}
+
+fn b58_array(value: &Value, key: &str) -> Result<Vec<Vec<u8>>, Error> {
+    array(value, key)?.iter().map(|x| Ok(bs58::decode(as_str(x)?).into_vec()?)).collect()
+}
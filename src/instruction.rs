@@ -1,20 +1,29 @@
+use std::fmt;
 use std::rc::{Rc, Weak};
-use std::cell::{Ref, RefCell};
-use std::iter::Peekable;
+use std::cell::{Cell, OnceCell, Ref, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::iter::{Cloned, Peekable};
+use std::ops::ControlFlow;
+use borsh::BorshDeserialize;
 use substreams_solana::b58;
 use substreams_solana::pb::sf::solana::r#type::v1 as pb;
-use anyhow::{anyhow, Error};
 
-use crate::log::Log;
+use crate::account::{AccountLayout, AccountMeta};
+use crate::error::{DecodeError, ParseError};
+use crate::log::{ConsumedLog, Log, LogDialect, LogParseReport, ProgramError};
+use crate::owned_instruction::OwnedStructuredInstruction;
+use crate::payload::{Payload, LARGE_PAYLOAD_THRESHOLD};
+use crate::programs::{DiscriminatorSet, ANCHOR_EVENT_IX_TAG};
+use crate::render::{render, RenderOptions};
 use crate::pubkey::{Pubkey, PubkeyRef};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum WrappedInstruction<'a> {
     Compiled(&'a pb::CompiledInstruction),
     Inner(&'a pb::InnerInstruction),
 }
 
-impl WrappedInstruction<'_> {
+impl<'a> WrappedInstruction<'a> {
     pub fn program_id_index(&self) -> u32 {
         match self {
             Self::Compiled(instruction) => instruction.program_id_index,
@@ -27,7 +36,7 @@ impl WrappedInstruction<'_> {
             Self::Inner(instruction) => &instruction.accounts,
         }
     }
-    pub fn data(&self) -> &Vec<u8> {
+    pub fn data(&self) -> &'a Vec<u8> {
         match self {
             Self::Compiled(instruction) => &instruction.data,
             Self::Inner(instruction) => &instruction.data,
@@ -39,6 +48,18 @@ impl WrappedInstruction<'_> {
             Self::Inner(instruction) => instruction.stack_height,
         }
     }
+    /// First byte of the data, the discriminator of native and most non-Anchor programs
+    pub fn discriminator_u8(&self) -> Option<u8> {
+        self.data().first().copied()
+    }
+    /// First 8 bytes of the data, see `programs::anchor_sighash`
+    pub fn anchor_discriminator(&self) -> Option<[u8; 8]> {
+        self.data().get(..8)?.try_into().ok()
+    }
+    /// Data following a discriminator of `n` bytes. `None` if the data is shorter.
+    pub fn data_after_discriminator(&self, n: usize) -> Option<&[u8]> {
+        self.data().get(n..)
+    }
 }
 
 impl<'a> From<&'a pb::CompiledInstruction> for WrappedInstruction<'a> {
@@ -53,202 +74,2335 @@ impl<'a> From<&'a pb::InnerInstruction> for WrappedInstruction<'a> {
     }
 }
 
-const PROGRAMS_WITHOUT_LOGGING: &[Pubkey] = &[
+/// Program id and accounts of `instruction`, looked up in the transaction `accounts`
+pub(crate) fn resolve_accounts<'a>(instruction: &WrappedInstruction, accounts: &[&'a Vec<u8>]) -> Result<(PubkeyRef<'a>, Vec<PubkeyRef<'a>>), ParseError> {
+    check_account_indices(instruction, accounts.len())?;
+    let instruction_accounts = instruction.accounts().iter().map(|i| PubkeyRef(accounts[*i as usize])).collect();
+    Ok((PubkeyRef(accounts[instruction.program_id_index() as usize]), instruction_accounts))
+}
+
+/// Fails if the program id or an account of `instruction` is not among the `len`
+/// transaction accounts, so that they can then be indexed without checks.
+pub(crate) fn check_account_indices(instruction: &WrappedInstruction, len: usize) -> Result<(), ParseError> {
+    let index = instruction.accounts().iter().fold(instruction.program_id_index() as usize, |max, x| max.max(*x as usize));
+    if index >= len {
+        return Err(ParseError::AccountIndexOutOfBounds { index, len });
+    }
+    Ok(())
+}
+
+/// Programs that do not log their invocations, e.g. precompiles
+pub const PROGRAMS_WITHOUT_LOGGING: &[Pubkey] = &[
     Pubkey(b58!("Ed25519SigVerify111111111111111111111111111")),
     Pubkey(b58!("KeccakSecp256k11111111111111111111111111111")),
 ];
 
+/// Accounts of a transaction, shared by its structured instructions
+struct ResolvedAccounts<'a>(Vec<&'a Vec<u8>>);
+
+impl fmt::Debug for ResolvedAccounts<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ResolvedAccounts({} accounts)", self.0.len())
+    }
+}
+
 #[derive(Debug)]
 pub struct StructuredInstruction<'a> {
     pub instruction: WrappedInstruction<'a>,
-    accounts: Vec<PubkeyRef<'a>>,
+    resolved_accounts: Rc<ResolvedAccounts<'a>>,
+    /// Built on the first call to `accounts`
+    accounts: OnceCell<Vec<PubkeyRef<'a>>>,
     program_id: PubkeyRef<'a>,
+    account_layout: Option<AccountLayout>,
     inner_instructions: RefCell<Vec<Rc<Self>>>,
     parent_instruction: RefCell<Option<Weak<Self>>>,
+    previous_sibling: RefCell<Option<Weak<Self>>>,
+    next_sibling: RefCell<Option<Weak<Self>>>,
+    sibling_index: Cell<Option<usize>>,
     logs: RefCell<Option<Vec<Log<'a>>>>,
+    log_line_indices: RefCell<Option<Vec<usize>>>,
+    log_range: RefCell<Option<(usize, usize)>>,
+    return_data: OnceCell<Vec<u8>>,
+    program_error: OnceCell<ProgramError>,
+    events: OnceCell<Vec<Vec<u8>>>,
+    stack_height: Option<u32>,
+    top_level_index: u32,
+    flat_index: usize,
+    did_execute: Cell<bool>,
+    logs_truncated: Cell<bool>,
+    log_mismatch: Cell<bool>,
+    status: RefCell<InstructionStatus>,
 }
 
 impl<'a> StructuredInstruction<'a> {
-    fn new(instruction: WrappedInstruction<'a>, inner_instructions: RefCell<Vec<Rc<Self>>>, accounts: &Vec<&'a Vec<u8>>) -> Self {
-        let instruction_accounts: Vec<_> = instruction.accounts().iter().map(|i| PubkeyRef(accounts[*i as usize])).collect();
+    fn new(instruction: WrappedInstruction<'a>, inner_instructions: RefCell<Vec<Rc<Self>>>, resolved_accounts: &Rc<ResolvedAccounts<'a>>, account_layout: Option<AccountLayout>, stack_height: Option<u32>, top_level_index: u32, flat_index: usize) -> Result<Self, ParseError> {
+        let accounts = &resolved_accounts.0;
+        check_account_indices(&instruction, accounts.len())?;
         let program_id = PubkeyRef(accounts[instruction.program_id_index() as usize]);
-        Self {
+        Ok(Self {
             instruction,
             program_id,
-            accounts: instruction_accounts,
+            resolved_accounts: Rc::clone(resolved_accounts),
+            accounts: OnceCell::new(),
             inner_instructions: inner_instructions,
+            account_layout,
             parent_instruction: RefCell::new(None),
+            previous_sibling: RefCell::new(None),
+            next_sibling: RefCell::new(None),
+            sibling_index: Cell::new(None),
             logs: RefCell::new(None),
-        }
+            log_line_indices: RefCell::new(None),
+            log_range: RefCell::new(None),
+            return_data: OnceCell::new(),
+            program_error: OnceCell::new(),
+            events: OnceCell::new(),
+            stack_height,
+            top_level_index,
+            flat_index,
+            did_execute: Cell::new(true),
+            logs_truncated: Cell::new(false),
+            log_mismatch: Cell::new(false),
+            status: RefCell::new(InstructionStatus::Unknown),
+        })
     }
     pub fn program_id(&self) -> PubkeyRef<'a> { self.program_id }
     pub fn program_id_index(&self) -> u32 { self.instruction.program_id_index() }
-    pub fn accounts(&self) -> &Vec<PubkeyRef> { &self.accounts }
+    /// Accounts of the instruction, looked up on the first call. Prefer
+    /// `account_at` to read a few of them.
+    pub fn accounts(&self) -> &Vec<PubkeyRef<'a>> {
+        self.accounts.get_or_init(|| self.instruction.accounts().iter().map(|x| self.resolved_account(*x)).collect())
+    }
+    /// Account at `index` in the instruction accounts, without building `accounts`
+    pub fn account_at(&self, index: usize) -> Option<PubkeyRef<'a>> {
+        self.instruction.accounts().get(index).map(|x| self.resolved_account(*x))
+    }
+    /// Whether `pubkey` is the program id or one of the accounts of the
+    /// instruction. `pubkey` can be a `Pubkey` or a `PubkeyRef`. Does not build
+    /// `accounts`.
+    pub fn touches_account<P>(&self, pubkey: &P) -> bool
+    where
+        PubkeyRef<'a>: PartialEq<P>
+    {
+        self.program_id == *pubkey || self.instruction.accounts().iter().any(|x| self.resolved_account(*x) == *pubkey)
+    }
+    /// Indices are checked against the transaction accounts in `new`
+    fn resolved_account(&self, index: u8) -> PubkeyRef<'a> {
+        PubkeyRef(self.resolved_accounts.0[index as usize])
+    }
+    /// Accounts with their signer and writable flags, as requested by the message.
+    /// The flags are unset when structuring without the transaction, e.g. with
+    /// `structure_flattened_instructions`.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (payer, authority, program, pool, vault, mint) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5), pubkey(6));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(payer)
+    ///     .signer(authority)
+    ///     .readonly(authority)
+    ///     .readonly(program)
+    ///     .lookup(vault, true)
+    ///     .lookup(mint, false)
+    ///     .instruction(program, &[mint, vault, pool, authority, payer], vec![1])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let flags: Vec<_> = instructions[0].account_metas().iter().map(|x| (x.is_signer, x.is_writable)).collect();
+    /// assert_eq!(flags, [(false, false), (false, true), (false, true), (true, false), (true, true)]);
+    /// assert_eq!(instructions[0].account_metas()[1].pubkey, vault);
+    /// ```
+    pub fn account_metas(&self) -> Vec<AccountMeta<'a>> {
+        self.instruction.accounts().iter().map(|index| AccountMeta {
+            pubkey: self.resolved_account(*index),
+            is_signer: self.account_layout.is_some_and(|x| x.is_signer(*index as usize)),
+            is_writable: self.account_layout.is_some_and(|x| x.is_writable(*index as usize)),
+        }).collect()
+    }
     pub fn data(&self) -> &Vec<u8> { self.instruction.data() }
-    pub fn stack_height(&self) -> Option<u32> { self.instruction.stack_height() }
+    pub fn data_starts_with(&self, prefix: &[u8]) -> bool { self.instruction.data().starts_with(prefix) }
+    /// See `WrappedInstruction::discriminator_u8`
+    pub fn discriminator_u8(&self) -> Option<u8> { self.instruction.discriminator_u8() }
+    /// See `WrappedInstruction::anchor_discriminator`
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::programs::anchor_sighash;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, pool) = (pubkey(1), pubkey(2));
+    /// let swap = [anchor_sighash("global", "swap").to_vec(), vec![1, 2]].concat();
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(pool, &[user], swap)
+    ///     .instruction(pool, &[user], vec![])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// assert_eq!(instructions[0].anchor_discriminator(), Some(anchor_sighash("global", "swap")));
+    /// assert_eq!(instructions[0].data_after_discriminator(8), Some(&[1, 2][..]));
+    /// assert_eq!(instructions[1].discriminator_u8(), None);
+    /// assert_eq!(instructions[1].anchor_discriminator(), None);
+    /// assert_eq!(instructions[1].data_after_discriminator(0), Some(&[][..]));
+    /// assert_eq!(instructions[1].data_after_discriminator(1), None);
+    /// ```
+    pub fn anchor_discriminator(&self) -> Option<[u8; 8]> { self.instruction.anchor_discriminator() }
+    /// See `WrappedInstruction::data_after_discriminator`
+    pub fn data_after_discriminator(&self, n: usize) -> Option<&[u8]> { self.instruction.data_after_discriminator(n) }
+    /// Whether the data starts with one of the discriminators of `set`. Faster
+    /// than a scan of `data_starts_with` from a few dozen discriminators on.
+    pub fn discriminator_in(&self, set: &DiscriminatorSet) -> bool { set.matches(self.instruction.data()) }
+    /// Stack height of the instruction, derived from the invoke logs in blocks
+    /// that do not record it, see `derive_stack_heights`
+    pub fn stack_height(&self) -> Option<u32> { self.stack_height }
+    /// Index in `message.instructions` of the top level instruction this one
+    /// belongs to, same as `meta.inner_instructions[i].index` for inner instructions
+    pub fn top_level_index(&self) -> u32 { self.top_level_index }
+    /// Position in the flattened instructions, see `get_flattened_instructions`
+    pub fn flat_index(&self) -> usize { self.flat_index }
+    /// Whether the instruction ran. Only instructions following the failing one
+    /// in a failed transaction did not, see `get_structured_instructions_of_failed`.
+    pub fn did_execute(&self) -> bool { self.did_execute.get() }
     pub fn inner_instructions(&self) -> Ref<Vec<Rc<Self>>> { self.inner_instructions.borrow() }
-    pub fn parent_instruction(&self) -> Option<Rc<Self>> { self.parent_instruction.borrow().as_ref().map(|x| x.upgrade().unwrap()) }
+    pub fn parent_instruction(&self) -> Option<Rc<Self>> { self.parent_instruction.borrow().as_ref().and_then(|x| x.upgrade()) }
+    /// Position among the inner instructions of the parent, or among the top
+    /// level instructions
+    pub fn sibling_index(&self) -> Option<usize> { self.sibling_index.get() }
+    /// Instruction following this one under the same parent, or at the top level
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, pool, token) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(pool, &[user], vec![0])
+    ///     .inner(token, &[user], vec![1])
+    ///     .inner(token, &[user], vec![2])
+    ///     .instruction(pool, &[user], vec![3])
+    ///     .inner(token, &[user], vec![4])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let swap = &instructions[0];
+    /// let first = swap.inner_instructions()[0].clone();
+    /// assert_eq!(first.next_sibling().unwrap().data()[0], 2);
+    /// assert!(first.previous_sibling().is_none());
+    /// assert_eq!(swap.next_sibling().unwrap().sibling_index(), Some(1));
+    /// let only = instructions[1].inner_instructions()[0].clone();
+    /// assert!(only.next_sibling().is_none() && only.previous_sibling().is_none());
+    /// ```
+    pub fn next_sibling(&self) -> Option<Rc<Self>> { self.next_sibling.borrow().as_ref().and_then(|x| x.upgrade()) }
+    /// Instruction preceding this one under the same parent, or at the top level
+    pub fn previous_sibling(&self) -> Option<Rc<Self>> { self.previous_sibling.borrow().as_ref().and_then(|x| x.upgrade()) }
     pub fn logs(&self) -> Ref<Option<Vec<Log<'a>>>> { self.logs.borrow() }
+    /// Index in the transaction's `log_messages` of each of the `logs()`.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let (router_id, pool_id) = (router.to_string(), pool.to_string());
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user, pool], vec![0])
+    ///     .inner(pool, &[user], vec![1])
+    ///     .logs(&[
+    ///         &format!("Program {router_id} invoke [1]"),
+    ///         &format!("Program {pool_id} invoke [2]"),
+    ///         "Program log: Instruction: Swap",
+    ///         &format!("Program {pool_id} success"),
+    ///         &format!("Program {router_id} success"),
+    ///     ])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// assert_eq!(instructions[0].log_line_indices().as_deref(), Some(&[0, 4][..]));
+    /// let swap = &instructions[0].inner_instructions()[0];
+    /// assert_eq!(swap.log_line_indices().as_deref(), Some(&[1, 2, 3][..]));
+    /// let (start, end) = instructions[0].raw_log_range().unwrap();
+    /// assert_eq!(format!("log lines {}..={}", start, end - 1), "log lines 0..=4");
+    /// ```
+    pub fn log_line_indices(&self) -> Ref<'_, Option<Vec<usize>>> { self.log_line_indices.borrow() }
+    /// Whether the logs of this instruction, or of one of its inner instructions,
+    /// were cut by a "Log truncated" line. `logs()` is then `None`.
+    pub fn logs_truncated(&self) -> bool { self.logs_truncated.get() }
+    /// Whether the logs stopped matching the instructions before the invocation
+    /// ended, when structuring with `LogAssociation::Lenient`. `logs()` is then `None`.
+    pub fn log_mismatch(&self) -> bool { self.log_mismatch.get() }
+    /// Span `[start, end)` of this instruction's invocation in the transaction's `log_messages`,
+    /// including the logs of its inner instructions. When `logs_truncated()`, the span
+    /// ends with the "Log truncated" line, if the invocation started before it.
+    pub fn raw_log_range(&self) -> Option<(usize, usize)> { *self.log_range.borrow() }
 
-    pub fn top_instruction(&self) -> Option<Rc<Self>> {
-        if let Some(instruction) = self.parent_instruction() {
-            let mut top_instruction = instruction;
-            while let Some(parent_instruction) = top_instruction.parent_instruction() {
-                top_instruction = parent_instruction;
+    /// Instruction data as a payload borrowed from the transaction, if it is larger
+    /// than `LARGE_PAYLOAD_THRESHOLD` bytes
+    pub fn large_payload(&self) -> Option<Payload<'a>> {
+        let data: &'a [u8] = match self.instruction {
+            WrappedInstruction::Compiled(instruction) => &instruction.data,
+            WrappedInstruction::Inner(instruction) => &instruction.data,
+        };
+        if data.len() > LARGE_PAYLOAD_THRESHOLD { Some(Payload::borrowed(data)) } else { None }
+    }
+
+    /// Error the invocation failed with, from its `failed` log. The raw error
+    /// is also in `status`.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::get_structured_instructions_of_failed;
+    /// use substreams_solana_utils::log::ProgramError;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let (router_id, pool_id) = (router.to_string(), pool.to_string());
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .logs(&[
+    ///         &format!("Program {router_id} invoke [1]"),
+    ///         &format!("Program {pool_id} invoke [2]"),
+    ///         &format!("Program {pool_id} failed: custom program error: 0x1771"),
+    ///         &format!("Program {router_id} failed: insufficient funds for instruction"),
+    ///     ])
+    ///     .failed()
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions_of_failed(&transaction).unwrap();
+    /// assert_eq!(instructions[0].inner_instructions()[0].error(), Some(&ProgramError::Custom(6001)));
+    /// assert_eq!(instructions[0].error(), Some(&ProgramError::InsufficientFunds));
+    /// ```
+    pub fn error(&self) -> Option<&ProgramError> { self.program_error.get() }
+
+    /// Outcome of the invocation, from the log ending it
+    pub fn status(&self) -> InstructionStatus { self.status.borrow().clone() }
+
+    /// Data set by the program with `set_return_data` and logged by its
+    /// invocation. Taken from `meta.return_data` for the last instruction of
+    /// the returning program when its logs are missing or truncated.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let (router_id, pool_id) = (router.to_string(), pool.to_string());
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user, pool], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .logs(&[
+    ///         &format!("Program {router_id} invoke [1]"),
+    ///         &format!("Program {pool_id} invoke [2]"),
+    ///         &format!("Program return: {pool_id} ZAAAAAAAAAA="),
+    ///         &format!("Program {pool_id} success"),
+    ///         &format!("Program {router_id} success"),
+    ///     ])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let swap = &instructions[0].inner_instructions()[0];
+    /// assert_eq!(swap.return_data(), Some(&100u64.to_le_bytes()[..]));
+    /// assert_eq!(instructions[0].return_data(), None);
+    /// ```
+    pub fn return_data(&self) -> Option<&[u8]> { self.return_data.get().map(Vec::as_slice) }
+
+    /// `return_data`, deserialized. `None` without return data, or if it fails
+    /// to deserialize.
+    pub fn decode_return<T: BorshDeserialize>(&self) -> Option<T> {
+        T::try_from_slice(self.return_data()?).ok()
+    }
+
+    /// Decoded payloads of the `Program data` logs of this invocation, in order,
+    /// excluding those of its inner instructions. Logs that are not valid base64
+    /// are skipped, and reported in debug builds.
+    ///
+    /// ```
+    /// use borsh::BorshDeserialize;
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// #[derive(BorshDeserialize, Debug, PartialEq)]
+    /// struct SwapEvent { amount: u64 }
+    ///
+    /// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let (router_id, pool_id) = (router.to_string(), pool.to_string());
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user, pool], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .logs(&[
+    ///         &format!("Program {router_id} invoke [1]"),
+    ///         "Program data: AQIDBAUGBwhkAAAAAAAAAA==",
+    ///         &format!("Program {pool_id} invoke [2]"),
+    ///         "Program data: AQIDBAUGBwgsAQAAAAAAAA==",
+    ///         &format!("Program {pool_id} success"),
+    ///         "Program data: not base64!",
+    ///         "Program data: CQkJCQkJCQl4",
+    ///         "Program data: AQIDBAUGBwjIAAAAAAAAAA==",
+    ///         &format!("Program {router_id} success"),
+    ///     ])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// assert_eq!(instructions[0].events().len(), 3);
+    /// let swaps: Vec<SwapEvent> = instructions[0].decode_events([1, 2, 3, 4, 5, 6, 7, 8]);
+    /// assert_eq!(swaps, [SwapEvent { amount: 100 }, SwapEvent { amount: 200 }]);
+    /// let swaps: Vec<SwapEvent> = instructions[0].inner_instructions()[0].decode_events([1, 2, 3, 4, 5, 6, 7, 8]);
+    /// assert_eq!(swaps, [SwapEvent { amount: 300 }]);
+    /// ```
+    pub fn events(&self) -> Vec<&[u8]> {
+        let events = self.events.get_or_init(|| {
+            let logs = self.logs();
+            logs.iter().flatten().filter_map(|log| match log {
+                Log::Data(data_log) => match data_log.data() {
+                    Ok(data) => Some(data),
+                    Err(_error) => {
+                        #[cfg(debug_assertions)]
+                        eprintln!("warning: skipped malformed data log: {}: {}", _error, data_log.log);
+                        None
+                    },
+                },
+                _ => None,
+            }).collect()
+        });
+        events.iter().map(Vec::as_slice).collect()
+    }
+
+    /// Events of this invocation starting with `discriminator`, deserialized from
+    /// the bytes following it. Events that fail to deserialize are skipped.
+    pub fn decode_events<T: BorshDeserialize>(&self, discriminator: [u8; 8]) -> Vec<T> {
+        self.events().into_iter()
+            .filter_map(|event| event.strip_prefix(&discriminator[..]))
+            .filter_map(|mut payload| T::deserialize(&mut payload).ok())
+            .collect()
+    }
+
+    /// Deserializes the data following the first `skip` bytes, typically 1 for
+    /// native programs and 8 for Anchor programs. Bytes left after `T` are
+    /// ignored.
+    ///
+    /// ```
+    /// use borsh::BorshDeserialize;
+    /// use substreams_solana_utils::error::DecodeError;
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::programs::anchor_sighash;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// #[derive(BorshDeserialize, Debug, PartialEq)]
+    /// struct Swap { amount_in: u64, minimum_amount_out: u64 }
+    ///
+    /// let swap = anchor_sighash("global", "swap");
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(pubkey(1))
+    ///     .instruction(pubkey(2), &[pubkey(1)], [&swap[..], &100u64.to_le_bytes(), &90u64.to_le_bytes()].concat())
+    ///     .instruction(pubkey(2), &[pubkey(1)], [&swap[..], &[1, 2, 3]].concat())
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// assert_eq!(instructions[0].try_decode_anchor::<Swap>(swap), Ok(Swap { amount_in: 100, minimum_amount_out: 90 }));
+    /// assert_eq!(instructions[0].try_decode_anchor::<Swap>(anchor_sighash("global", "route")), Err(DecodeError::DiscriminatorMismatch));
+    /// assert!(matches!(instructions[1].try_decode::<Swap>(8), Err(DecodeError::Borsh(_))));
+    /// assert_eq!(instructions[1].try_decode::<Swap>(12), Err(DecodeError::DataTooShort { len: 11, expected: 12 }));
+    /// ```
+    pub fn try_decode<T: BorshDeserialize>(&self, skip: usize) -> Result<T, DecodeError> {
+        let data = self.instruction.data();
+        let mut payload = data.get(skip..).ok_or(DecodeError::DataTooShort { len: data.len(), expected: skip })?;
+        T::deserialize(&mut payload).map_err(|error| DecodeError::Borsh(error.to_string()))
+    }
+
+    /// Same as `try_decode(8)`, failing unless the data starts with `discriminator`
+    pub fn try_decode_anchor<T: BorshDeserialize>(&self, discriminator: [u8; 8]) -> Result<T, DecodeError> {
+        match self.anchor_discriminator() {
+            Some(found) if found == discriminator => self.try_decode(8),
+            Some(_) => Err(DecodeError::DiscriminatorMismatch),
+            None => Err(DecodeError::DataTooShort { len: self.instruction.data().len(), expected: 8 }),
+        }
+    }
+
+    fn set_log_frame(&self, frame: Option<LogFrame<'a>>, log_stack: &LogStack) {
+        match frame {
+            Some(frame) => {
+                if let Some(data) = return_data_of(&frame.logs, self.program_id) {
+                    let _ = self.return_data.set(data);
+                }
+                if let Some(Log::Failed(failed_log)) = frame.logs.last() {
+                    if let Some(program_error) = failed_log.program_error() {
+                        let _ = self.program_error.set(program_error);
+                    }
+                }
+                *self.status.borrow_mut() = InstructionStatus::of(&frame.logs);
+                *self.logs.borrow_mut() = Some(frame.logs);
+                *self.log_line_indices.borrow_mut() = Some(frame.line_indices);
+                *self.log_range.borrow_mut() = frame.range;
+            },
+            None => {
+                self.logs_truncated.set(log_stack.truncated_at().is_some());
+                self.log_mismatch.set(log_stack.mismatch_at().is_some());
+                *self.log_range.borrow_mut() = log_stack.truncated_range();
+            },
+        }
+    }
+
+    /// Compute units consumed by the invocation, inner invocations included, from
+    /// its `consumed` log. `None` when the logs are truncated before it, and for
+    /// programs that do not log.
+    pub fn compute_units_consumed(&self) -> Option<u64> {
+        self.consumed_log(|x| x.consumed().ok())
+    }
+
+    /// Compute units the invocation was allowed to consume, from its `consumed` log
+    pub fn compute_units_budget(&self) -> Option<u64> {
+        self.consumed_log(|x| x.budget().ok())
+    }
+
+    fn consumed_log<T>(&self, f: impl Fn(&ConsumedLog) -> Option<T>) -> Option<T> {
+        self.logs().as_ref()?.iter().rev().find_map(|log| match log {
+            Log::Consumed(consumed_log) if is_program(log, self.program_id) => f(consumed_log),
+            _ => None,
+        })
+    }
+
+    /// Copies the instruction and its inner instructions into values that do
+    /// not borrow from the transaction, see `OwnedStructuredInstruction`
+    pub fn to_owned_instruction(&self) -> Result<OwnedStructuredInstruction, anyhow::Error> {
+        OwnedStructuredInstruction::new(self)
+    }
+
+    /// Messages of the `Program log` logs of this invocation and of its inner
+    /// invocations, in the order they were logged: those of an invocation
+    /// logged after an inner invocation returned come after the inner logs.
+    /// Instructions without logs, e.g. truncated, contribute nothing.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::get_structured_instructions;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool, token) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let (router_id, pool_id, token_id) = (router.to_string(), pool.to_string(), token.to_string());
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user], vec![0])
+    ///     .inner(pool, &[user], vec![1])
+    ///     .inner_at(3, token, &[user], vec![2])
+    ///     .inner(token, &[user], vec![3])
+    ///     .logs(&[
+    ///         &format!("Program {router_id} invoke [1]"),
+    ///         "Program log: Instruction: Route",
+    ///         &format!("Program {pool_id} invoke [2]"),
+    ///         "Program log: Instruction: Swap",
+    ///         &format!("Program {token_id} invoke [3]"),
+    ///         "Program log: Instruction: Transfer",
+    ///         &format!("Program {token_id} success"),
+    ///         "Program log: swapped",
+    ///         &format!("Program {pool_id} success"),
+    ///         "Program log: routed",
+    ///         &format!("Program {token_id} invoke [2]"),
+    ///         "Program log: Instruction: TransferFee",
+    ///         &format!("Program {token_id} success"),
+    ///         "Program log: done",
+    ///         &format!("Program {router_id} success"),
+    ///     ])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// assert_eq!(instructions[0].all_program_logs(), [
+    ///     "Instruction: Route",
+    ///     "Instruction: Swap",
+    ///     "Instruction: Transfer",
+    ///     "swapped",
+    ///     "routed",
+    ///     "Instruction: TransferFee",
+    ///     "done",
+    /// ]);
+    /// let swap = &instructions[0].inner_instructions()[0];
+    /// assert_eq!(swap.all_program_logs(), ["Instruction: Swap", "Instruction: Transfer", "swapped"]);
+    /// ```
+    pub fn all_program_logs(self: &Rc<Self>) -> Vec<&'a str> {
+        let mut messages = Vec::new();
+        for instruction in self.iter_dfs() {
+            let (logs, line_indices) = (instruction.logs(), instruction.log_line_indices());
+            let (Some(logs), Some(line_indices)) = (logs.as_ref(), line_indices.as_ref()) else { continue };
+            for (log, line_index) in logs.iter().zip(line_indices) {
+                if let Log::Program(program_log) = log {
+                    if let Ok(message) = program_log.message() {
+                        messages.push((*line_index, message));
+                    }
+                }
             }
-            Some(top_instruction)
-        } else {
-            None
         }
+        messages.sort_by_key(|(line_index, _)| *line_index);
+        messages.into_iter().map(|(_, message)| message).collect()
+    }
+
+    /// Lazily walks this instruction and its inner instructions, in execution order
+    pub fn iter_dfs(self: &Rc<Self>) -> DepthFirst<'a, std::iter::Once<Rc<Self>>> {
+        DepthFirst::new(std::iter::once(Rc::clone(self)))
+    }
+
+    /// This instruction and its inner instructions that belong to `program_id`,
+    /// in execution order
+    pub fn filter_by_program_id<P>(self: &Rc<Self>, program_id: &P) -> Vec<Rc<Self>>
+    where
+        PubkeyRef<'a>: PartialEq<P>
+    {
+        self.iter_dfs().filter(|x| x.program_id() == *program_id).collect()
+    }
+
+    /// First instruction matching `predicate` in this instruction and its inner
+    /// instructions, in execution order
+    pub fn find_first<F>(self: &Rc<Self>, predicate: F) -> Option<Rc<Self>>
+    where
+        F: Fn(&Rc<Self>) -> bool
+    {
+        find_first(std::slice::from_ref(self), predicate)
+    }
+
+    /// Instructions matching `predicate` in this instruction and its inner
+    /// instructions, in execution order
+    pub fn find_all<F>(self: &Rc<Self>, predicate: F) -> Vec<Rc<Self>>
+    where
+        F: Fn(&Rc<Self>) -> bool
+    {
+        find_all(std::slice::from_ref(self), predicate)
+    }
+
+    /// Sibling indices from the top level down to this instruction: `[2, 0, 3]` is
+    /// the 4th inner instruction of the 1st inner instruction of the 3rd top level
+    /// instruction. Paths only depend on the transaction, and sorting them
+    /// lexicographically gives the order of `flattened`. See
+    /// `StructuredInstructions::get_by_path`.
+    pub fn path(&self) -> Vec<u32> {
+        let mut path: Vec<u32> = std::iter::once(self.sibling_index())
+            .chain(self.ancestors().map(|x| x.sibling_index()))
+            .map(|x| x.unwrap_or_default() as u32)
+            .collect();
+        path.reverse();
+        path
+    }
+
+    pub fn top_instruction(&self) -> Option<Rc<Self>> {
+        self.ancestors().last()
+    }
+
+    /// Parent, grandparent and so on up to the top level instruction. Stops
+    /// early if the tree the instruction belongs to was dropped.
+    pub fn ancestors(&self) -> Ancestors<'a> {
+        Ancestors { next: self.parent_instruction() }
+    }
+
+    /// Invoke depth, 1 for top level instructions like `stack_height`
+    pub fn depth(&self) -> u32 {
+        self.ancestors().count() as u32 + 1
+    }
+
+    /// Whether the instruction was invoked, directly or not, by an instruction of `program_id`
+    pub fn has_ancestor_program<P>(&self, program_id: &P) -> bool
+    where
+        PubkeyRef<'a>: PartialEq<P>
+    {
+        self.ancestors().any(|x| x.program_id() == *program_id)
+    }
+
+    /// Program that invoked this instruction through CPI, `None` for top level
+    /// instructions, invoked by the runtime
+    pub fn caller_program_id(&self) -> Option<PubkeyRef<'a>> {
+        self.parent_instruction.borrow().as_ref()?.upgrade().map(|x| x.program_id())
+    }
+
+    /// Program ids from the top level instruction down to this one. Costs one
+    /// step per invoke depth.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, aggregator, dex, token) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(aggregator, &[user], vec![1])
+    ///     .inner(dex, &[user], vec![2])
+    ///     .inner_at(3, token, &[user], vec![3])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let transfer = &instructions.flattened()[2];
+    /// assert_eq!(transfer.caller_program_id().unwrap(), dex);
+    /// assert_eq!(transfer.invocation_chain(), [aggregator, dex, token]);
+    /// assert_eq!(instructions[0].caller_program_id(), None);
+    /// assert_eq!(instructions[0].invocation_chain(), [aggregator]);
+    /// ```
+    pub fn invocation_chain(&self) -> Vec<PubkeyRef<'a>> {
+        let mut chain: Vec<PubkeyRef<'a>> = self.ancestors().map(|x| x.program_id()).collect();
+        chain.reverse();
+        chain.push(self.program_id);
+        chain
+    }
+
+    /// The event of an Anchor `emit_cpi!` instruction: an inner instruction of
+    /// the program invoked by itself, whose data is `ANCHOR_EVENT_IX_TAG`
+    /// followed by the event discriminator and payload. Programs emitting
+    /// events through `Program data` logs are read with `events` instead.
+    ///
+    /// ```
+    /// use borsh::BorshDeserialize;
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::programs::{anchor_sighash, ANCHOR_EVENT_IX_TAG};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// #[derive(BorshDeserialize, Debug, PartialEq)]
+    /// struct TradeEvent { sol_amount: u64, is_buy: bool }
+    ///
+    /// let (user, router, amm, event_authority) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let discriminator = anchor_sighash("event", "TradeEvent");
+    /// let event_data = [&ANCHOR_EVENT_IX_TAG[..], &discriminator, &500u64.to_le_bytes(), &[1]].concat();
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user, amm], vec![0])
+    ///     .inner(amm, &[user, event_authority], vec![1])
+    ///     .inner_at(3, amm, &[event_authority], event_data.clone())
+    ///     .instruction(router, &[amm], event_data)
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let events = instructions.anchor_cpi_events();
+    /// assert_eq!(events.len(), 1);
+    /// assert_eq!(events[0].program_id, amm);
+    /// assert_eq!(events[0].discriminator, discriminator);
+    /// assert_eq!(events[0].decode::<TradeEvent>(), Some(TradeEvent { sol_amount: 500, is_buy: true }));
+    /// // Not invoked by its own program
+    /// assert!(instructions[1].as_anchor_cpi_event().is_none());
+    /// ```
+    pub fn as_anchor_cpi_event(&self) -> Option<AnchorCpiEvent<'a>> {
+        let data = self.instruction.data().strip_prefix(&ANCHOR_EVENT_IX_TAG[..])?;
+        if self.caller_program_id()? != self.program_id {
+            return None;
+        }
+        let discriminator = data.get(..8)?.try_into().ok()?;
+        Some(AnchorCpiEvent { program_id: self.program_id, discriminator, data: &data[8..] })
     }
 }
 
+/// Event emitted by an Anchor program through `emit_cpi!`, see
+/// `StructuredInstruction::as_anchor_cpi_event`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnchorCpiEvent<'a> {
+    pub program_id: PubkeyRef<'a>,
+    /// See `programs::anchor_sighash`, with the `event` namespace
+    pub discriminator: [u8; 8],
+    /// Borsh serialized event, following the discriminator
+    pub data: &'a [u8],
+}
+
+impl AnchorCpiEvent<'_> {
+    /// Deserializes the event. `None` if it does not deserialize as `T`.
+    pub fn decode<T: BorshDeserialize>(&self) -> Option<T> {
+        T::deserialize(&mut &self.data[..]).ok()
+    }
+}
+
+/// Outcome of an invocation, from its success or failed log
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "kind", rename_all = "snake_case"))]
+pub enum InstructionStatus {
+    Success,
+    Failed {
+        /// Error of the failed log, e.g. `custom program error: 0x1`
+        error: String,
+        /// Code of a custom program error
+        custom_code: Option<u32>,
+    },
+    /// The logs do not tell: they were truncated or are missing, the program does
+    /// not log, or the instruction did not execute
+    Unknown,
+}
+
+impl InstructionStatus {
+    /// Status of the invocation whose own logs are `logs`, ended by its terminator
+    pub fn of(logs: &[Log]) -> Self {
+        match logs.last() {
+            Some(Log::Success(_)) => Self::Success,
+            Some(Log::Failed(failed_log)) => Self::Failed {
+                error: failed_log.error().unwrap_or_default().to_string(),
+                custom_code: failed_log.custom_error_code(),
+            },
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Logs of a single program invocation, and their span in the transaction's `log_messages`
+#[derive(Debug)]
+pub struct LogFrame<'a> {
+    pub logs: Vec<Log<'a>>,
+    /// Index in the log messages of each of the `logs`
+    pub line_indices: Vec<usize>,
+    pub range: Option<(usize, usize)>,
+}
+
+/// How to handle logs that do not match the instructions being structured
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogAssociation {
+    /// Fail with the `ParseError`
+    #[default]
+    Strict,
+    /// Stop associating logs for the rest of the transaction. Instructions whose
+    /// invocation did not end before the mismatch get no logs.
+    Lenient,
+    /// Attach no logs, leaving every `logs()` as `None`
+    Disabled,
+}
+
+/// Options of `get_structured_instructions_with_options`
+#[derive(Clone, Debug)]
+pub struct StructureOptions {
+    /// Programs whose invocations have no logs, `PROGRAMS_WITHOUT_LOGGING` by default
+    pub programs_without_logging: Vec<Pubkey>,
+    /// Also treat an instruction as not logging when the next invocation log
+    /// belongs to another program, or the next log ends its parent, instead of
+    /// failing with `ParseError::UnexpectedProgram`. Off by default.
+    pub detect_programs_without_logging: bool,
+    /// Check that the depth of each invoke log is the stack height of its
+    /// instruction, failing with `ParseError::UnexpectedInvokeDepth`. Off by default.
+    pub validate_invoke_depth: bool,
+    pub association: LogAssociation,
+    /// Fail with `ParseError::OrphanLog` when a log is attributed to no
+    /// instruction, see `StructureOutput::orphan_logs`. Off by default.
+    pub reject_orphan_logs: bool,
+    /// Highest stack height accepted, failing with `ParseError::MaxDepthExceeded`
+    /// above it. `Some(16)` by default, well above the nesting the runtime allows.
+    pub max_depth: Option<u32>,
+    /// Most instructions accepted, inner instructions included, failing with
+    /// `ParseError::TooManyInstructions` above it before anything is built.
+    /// `Some(1024)` by default, well above the instruction trace length the
+    /// runtime allows.
+    ///
+    /// ```
+    /// use substreams_solana_utils::error::ParseError;
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, get_structured_instructions_with_options, StructureOptions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, program) = (pubkey(1), pubkey(2));
+    /// let transaction = (1..10_000).fold(TransactionBuilder::new().signer(user).instruction(program, &[user], vec![0]), |builder, _| {
+    ///     builder.inner(program, &[user], vec![1])
+    /// }).build();
+    /// let error = get_structured_instructions(&transaction).unwrap_err();
+    /// assert_eq!(error, ParseError::TooManyInstructions { count: 10_000, max: 1024 });
+    ///
+    /// let options = StructureOptions { max_instructions: None, ..Default::default() };
+    /// let instructions = get_structured_instructions_with_options(&transaction, &options).unwrap();
+    /// assert_eq!(instructions[0].inner_instructions().len(), 9_999);
+    ///
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(program, &[user], vec![0])
+    ///     .inner_at(40, program, &[user], vec![1])
+    ///     .build();
+    /// let error = get_structured_instructions(&transaction).unwrap_err();
+    /// assert_eq!(error, ParseError::MaxDepthExceeded { flat_index: 1, stack_height: 40, max: 16 });
+    /// ```
+    pub max_instructions: Option<usize>,
+}
+
+impl Default for StructureOptions {
+    fn default() -> Self {
+        Self {
+            programs_without_logging: PROGRAMS_WITHOUT_LOGGING.to_vec(),
+            detect_programs_without_logging: false,
+            validate_invoke_depth: false,
+            association: LogAssociation::default(),
+            reject_orphan_logs: false,
+            max_depth: Some(16),
+            max_instructions: Some(1024),
+        }
+    }
+}
+
+/// How the logs of an invocation opened by `LogStack::open` were found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OpenInvocation {
+    /// Its invoke log opened a frame
+    Framed,
+    /// The program does not log
+    WithoutLogging,
+    /// The logs ended before its invoke log
+    Unread,
+}
+
+/// State machine matching log lines to invocations, shared by
+/// `get_structured_instructions`, which drives it with the instructions, and
+/// `log::build_structured_logs`, which drives it with the invoke and terminator
+/// logs. It lives with the instruction structuring, as `StructureOptions`
+/// configures it and its errors are `ParseError`s; the log module only depends
+/// on it through `build_structured_logs`.
 pub struct LogStack<'a> {
-    stack: Vec<Vec<Log<'a>>>,
+    stack: Vec<LogFrame<'a>>,
+    invocations: Vec<OpenInvocation>,
+    programs_without_logging: Vec<Pubkey>,
+    detect_programs_without_logging: bool,
+    validate_invoke_depth: bool,
     is_truncated: bool,
+    is_abandoned: bool,
+    position: usize,
+    truncated_at: Option<usize>,
+    truncated_range: Option<(usize, usize)>,
+    mismatch_at: Option<usize>,
+    orphan_logs: Vec<Log<'a>>,
+    first_orphan_at: Option<usize>,
+    recovered_error: Option<ParseError>,
 }
 
 impl<'a> LogStack<'a> {
     pub fn new() -> Self {
-        Self { stack: Vec::new(), is_truncated: false }
+        Self::with_options(&StructureOptions::default())
+    }
+
+    /// A log stack for structuring with `association`. It never reads logs when
+    /// association is disabled.
+    pub fn with_association(association: LogAssociation) -> Self {
+        Self::with_options(&StructureOptions { association, ..Default::default() })
+    }
+
+    pub fn with_options(options: &StructureOptions) -> Self {
+        Self {
+            stack: Vec::new(),
+            invocations: Vec::new(),
+            programs_without_logging: options.programs_without_logging.clone(),
+            detect_programs_without_logging: options.detect_programs_without_logging,
+            validate_invoke_depth: options.validate_invoke_depth,
+            is_truncated: false,
+            is_abandoned: options.association == LogAssociation::Disabled,
+            position: 0,
+            truncated_at: None,
+            truncated_range: None,
+            mismatch_at: None,
+            orphan_logs: Vec::new(),
+            first_orphan_at: None,
+            recovered_error: None,
+        }
+    }
+
+    /// Index of the "Log truncated" line in `log_messages`, if it was reached
+    pub fn truncated_at(&self) -> Option<usize> {
+        self.truncated_at
+    }
+
+    /// Span of the invocation last closed, if its logs were cut by the "Log
+    /// truncated" line, which the span includes
+    pub fn truncated_range(&self) -> Option<(usize, usize)> {
+        self.truncated_range
+    }
+
+    /// Number of log lines consumed when logs stopped matching the instructions,
+    /// see `LogAssociation::Lenient`
+    pub fn mismatch_at(&self) -> Option<usize> {
+        self.mismatch_at
+    }
+
+    /// Logs read outside of any invocation, or left unread, see `drain_unread`
+    pub fn orphan_logs(&self) -> &[Log<'a>] {
+        &self.orphan_logs
+    }
+
+    /// Index in `log_messages` of the first orphan log
+    pub fn first_orphan_at(&self) -> Option<usize> {
+        self.first_orphan_at
+    }
+
+    /// Number of log lines consumed so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Depth of the innermost open invocation, 0 outside of any
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Whether log association was given up on, see `LogAssociation::Lenient`
+    pub fn is_abandoned(&self) -> bool {
+        self.is_abandoned
     }
 
-    pub fn open<I>(&mut self, logs: &mut Peekable<I>, program_id: PubkeyRef)
+    /// Error log association was given up on, see `recover`
+    pub fn recovered_error(&self) -> Option<&ParseError> {
+        self.recovered_error.as_ref()
+    }
+
+    pub(crate) fn next_log<I>(&mut self, logs: &mut Peekable<I>) -> Option<Log<'a>>
     where
         I: Iterator<Item = Log<'a>>
     {
-        if PROGRAMS_WITHOUT_LOGGING.iter().any(|x| *x == program_id) || self.is_truncated {
-            return;
+        let log = logs.next()?;
+        if log.is_truncated() {
+            self.is_truncated = true;
+            self.truncated_at = Some(self.position);
         }
-        loop {
-            let log = logs.next().unwrap();
-
-            if log.is_truncated() {
-                self.is_truncated = true;
-                break;
-            } else if log.is_invoke() {
-                self.stack.push(vec![log]);
-                break;
-            } else {
-                self.stack.last_mut().unwrap().push(log);
+        self.position += 1;
+        Some(log)
+    }
+
+    /// Opens a frame for the invocation starting with `invoke`, the last log read
+    pub(crate) fn push_frame(&mut self, invoke: Log<'a>) {
+        self.stack.push(LogFrame { logs: vec![invoke], line_indices: vec![self.position - 1], range: Some((self.position - 1, self.position)) });
+    }
+
+    /// Adds a log to the innermost open frame. Logs outside of any invocation are orphans.
+    pub(crate) fn append(&mut self, log: Log<'a>) {
+        match self.stack.last_mut() {
+            Some(frame) => {
+                frame.logs.push(log);
+                frame.line_indices.push(self.position - 1);
+            },
+            None => self.push_orphan(log),
+        }
+    }
+
+    /// Keeps `log`, the last log read, as attributed to no instruction
+    fn push_orphan(&mut self, log: Log<'a>) {
+        self.first_orphan_at.get_or_insert(self.position - 1);
+        self.orphan_logs.push(log);
+    }
+
+    /// Reads the logs left once every invocation closed, e.g. after logs stopped
+    /// matching the instructions, as orphans. The "Log truncated" line is not
+    /// one, see `truncated_at`.
+    pub(crate) fn drain_unread<I>(&mut self, logs: &mut Peekable<I>)
+    where
+        I: Iterator<Item = Log<'a>>
+    {
+        while let Some(log) = self.next_log(logs) {
+            if !log.is_truncated() {
+                self.push_orphan(log);
             }
         }
     }
 
-    pub fn close<I>(&mut self, logs: &mut Peekable<I>, program_id: PubkeyRef) -> Option<Vec<Log<'a>>>
+    /// Closes the innermost frame with `terminator`, the last log read
+    pub(crate) fn pop_frame(&mut self, terminator: Log<'a>) -> Option<LogFrame<'a>> {
+        self.append(terminator);
+        let mut frame = self.stack.pop()?;
+        frame.range = frame.range.map(|(start, _)| (start, self.position));
+        Some(frame)
+    }
+
+    /// Frames left open, e.g. by truncation, innermost first
+    pub(crate) fn drain_frames(&mut self) -> Vec<LogFrame<'a>> {
+        let position = self.position;
+        let mut frames: Vec<_> = self.stack.drain(..).collect();
+        frames.reverse();
+        for frame in &mut frames {
+            frame.range = frame.range.map(|(start, _)| (start, position));
+        }
+        frames
+    }
+
+    pub fn open<I>(&mut self, logs: &mut Peekable<I>, program_id: PubkeyRef) -> Result<(), ParseError>
     where
         I: Iterator<Item = Log<'a>>
     {
-        if PROGRAMS_WITHOUT_LOGGING.iter().any(|x| *x == program_id) {
-            return Some(Vec::new());
+        if self.is_abandoned {
+            return Ok(());
+        }
+        if self.programs_without_logging.iter().any(|x| *x == program_id) {
+            self.invocations.push(OpenInvocation::WithoutLogging);
+            return Ok(());
         }
         if self.is_truncated {
-            return None;
+            self.invocations.push(OpenInvocation::Unread);
+            return Ok(());
         }
-
+        // Logs end early in failed transactions, at the failing instruction
         loop {
-            let log = logs.next().unwrap();
-
-            if log.is_truncated() {
-                self.is_truncated = true;
-                return None;
-            } else if log.is_invoke() {
-                panic!("Unexpected invoke log");
+            if self.detect_programs_without_logging && logs.peek().is_some_and(|log| Self::is_unrelated(log, program_id)) {
+                self.invocations.push(OpenInvocation::WithoutLogging);
+                return Ok(());
             }
+            match self.next_log(logs) {
+                Some(log) if log.is_invoke() => {
+                    self.expect_program(&log, program_id)?;
+                    if self.validate_invoke_depth {
+                        self.expect_invoke_depth(&log)?;
+                    }
+                    self.push_frame(log);
+                    self.invocations.push(OpenInvocation::Framed);
+                    return Ok(());
+                },
+                Some(log) if !log.is_truncated() => self.append(log),
+                _ => {
+                    self.invocations.push(OpenInvocation::Unread);
+                    return Ok(());
+                },
+            }
+        }
+    }
 
-            let is_success = log.is_success();
-            self.stack.last_mut().unwrap().push(log);
-            if is_success {
-                return self.stack.pop()
+    pub fn close<I>(&mut self, logs: &mut Peekable<I>, program_id: PubkeyRef) -> Result<Option<LogFrame<'a>>, ParseError>
+    where
+        I: Iterator<Item = Log<'a>>
+    {
+        self.truncated_range = None;
+        if self.is_abandoned {
+            return Ok(None);
+        }
+        match self.invocations.pop() {
+            Some(OpenInvocation::WithoutLogging) => return Ok(Some(LogFrame { logs: Vec::new(), line_indices: Vec::new(), range: None })),
+            Some(OpenInvocation::Framed) if !self.is_truncated => (),
+            Some(OpenInvocation::Framed) => return Ok(self.cut_frame()),
+            _ => return Ok(None),
+        }
+
+        while let Some(log) = self.next_log(logs) {
+            match &log {
+                Log::Truncated(_) => return Ok(self.cut_frame()),
+                Log::Invoke(_) => return Err(ParseError::UnexpectedInvoke { position: self.position - 1 }),
+                Log::Success(_) | Log::Failed(_) => self.expect_program(&log, program_id)?,
+                _ => {
+                    self.append(log);
+                    continue;
+                },
             }
+            return Ok(self.pop_frame(log));
+        }
+        Ok(None)
+    }
+
+    /// Drops the innermost frame, cut by truncation, keeping its span in
+    /// `truncated_range`. Its logs are incomplete, so none is returned.
+    fn cut_frame(&mut self) -> Option<LogFrame<'a>> {
+        let frame = self.stack.pop()?;
+        self.truncated_range = frame.range.map(|(start, _)| (start, self.position));
+        None
+    }
+
+    /// Handles an error of `open` or `close` according to `association`
+    pub fn recover<T: Default>(&mut self, error: ParseError, association: LogAssociation) -> Result<T, ParseError> {
+        match association {
+            LogAssociation::Strict => Err(error),
+            LogAssociation::Lenient | LogAssociation::Disabled => {
+                self.is_abandoned = true;
+                self.mismatch_at = Some(self.position);
+                self.recovered_error.get_or_insert(error);
+                Ok(T::default())
+            },
+        }
+    }
+
+    /// Whether `log`, read while looking for the invoke log of `program_id`,
+    /// shows that the invocation did not log: it invokes another program, or
+    /// ends an invocation.
+    fn is_unrelated(log: &Log, program_id: PubkeyRef) -> bool {
+        match log {
+            Log::Invoke(_) => !is_program(log, program_id),
+            Log::Success(_) | Log::Failed(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Checks that the depth of `invoke`, the last log read, is that of the
+    /// invocation it opens
+    fn expect_invoke_depth(&self, invoke: &Log) -> Result<(), ParseError> {
+        let Log::Invoke(invoke_log) = invoke else { return Ok(()) };
+        let expected = self.invocations.len() as u32 + 1;
+        match invoke_log.invoke_depth() {
+            Ok(found) if found != expected => Err(ParseError::UnexpectedInvokeDepth { position: self.position - 1, expected, found }),
+            _ => Ok(()),
         }
     }
+
+    /// Checks that `log`, the last log read, belongs to `expected`
+    fn expect_program(&self, log: &Log, expected: PubkeyRef) -> Result<(), ParseError> {
+        if is_program(log, expected) {
+            return Ok(());
+        }
+        let found = log.program_id().unwrap_or_default();
+        Err(ParseError::UnexpectedProgram { position: self.position - 1, expected: expected.to_string(), found: found.to_string() })
+    }
+}
+
+/// Whether `log` is an invoke, success or failed log of `program_id`. Compares
+/// the decoded program id to avoid allocating on every invocation.
+fn is_program(log: &Log, program_id: PubkeyRef) -> bool {
+    let mut decoded = [0u8; 32];
+    let found = log.program_id().unwrap_or_default();
+    matches!(bs58::decode(found).onto(&mut decoded), Ok(32) if decoded[..] == program_id.0[..])
+}
+
+/// Data returned by `program_id`, from the last `Program return` log of its invocation
+pub(crate) fn return_data_of(logs: &[Log], program_id: PubkeyRef) -> Option<Vec<u8>> {
+    logs.iter().rev().find_map(|log| match log {
+        Log::Return(return_log) if is_program(log, program_id) => return_log.data().ok(),
+        _ => None,
+    })
 }
 
 pub fn structure_flattened_instructions_with_logs<'a, I>(
     flattened_instructions: Vec<WrappedInstruction<'a>>,
     logs: &mut Peekable<I>,
     accounts: Vec<&'a Vec<u8>>,
-) -> Vec<Rc<StructuredInstruction<'a>>>
+) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError>
+where
+    I: Iterator<Item = Log<'a>>
+{
+    structure_flattened_instructions(flattened_instructions, logs, accounts, LogAssociation::Strict)
+}
+
+/// Same as `structure_flattened_instructions_with_logs`, handling logs that do
+/// not match the instructions according to `association`
+pub fn structure_flattened_instructions<'a, I>(
+    flattened_instructions: Vec<WrappedInstruction<'a>>,
+    logs: &mut Peekable<I>,
+    accounts: Vec<&'a Vec<u8>>,
+    association: LogAssociation,
+) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError>
+where
+    I: Iterator<Item = Log<'a>>
+{
+    structure(flattened_instructions, logs, accounts, &StructureOptions { association, ..Default::default() }, None).map(|x| x.instructions)
+}
+
+/// Same as `structure_flattened_instructions_with_logs`, structuring with `options`
+pub fn structure_flattened_instructions_with_options<'a, I>(
+    flattened_instructions: Vec<WrappedInstruction<'a>>,
+    logs: &mut Peekable<I>,
+    accounts: Vec<&'a Vec<u8>>,
+    options: &StructureOptions,
+) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError>
 where
     I: Iterator<Item = Log<'a>>
 {
+    structure(flattened_instructions, logs, accounts, options, None).map(|x| x.instructions)
+}
+
+/// Same as `structure_flattened_instructions_with_options`, also returning the
+/// logs attributed to no instruction
+pub fn structure_flattened_instructions_with_orphan_logs<'a, I>(
+    flattened_instructions: Vec<WrappedInstruction<'a>>,
+    logs: &mut Peekable<I>,
+    accounts: Vec<&'a Vec<u8>>,
+    options: &StructureOptions,
+) -> Result<StructureOutput<'a>, ParseError>
+where
+    I: Iterator<Item = Log<'a>>
+{
+    structure(flattened_instructions, logs, accounts, options, None)
+}
+
+/// Structured instructions, and the logs attributed to none of them
+#[derive(Debug)]
+pub struct StructureOutput<'a> {
+    pub instructions: Vec<Rc<StructuredInstruction<'a>>>,
+    /// Logs read outside of any invocation, e.g. runtime messages between top
+    /// level instructions, and logs left once every invocation closed or once
+    /// association was given up on, in order. Empty when association is disabled.
+    pub orphan_logs: Vec<Log<'a>>,
+    /// Error association was given up on, see `LogAssociation::Lenient`
+    pub recovered_error: Option<ParseError>,
+}
+
+/// Structures the instructions, with the account layout of their message if known.
+/// Stack heights missing from the instructions are derived from the logs.
+fn structure<'a, I>(
+    flattened_instructions: Vec<WrappedInstruction<'a>>,
+    logs: &mut Peekable<I>,
+    accounts: Vec<&'a Vec<u8>>,
+    options: &StructureOptions,
+    account_layout: Option<AccountLayout>,
+) -> Result<StructureOutput<'a>, ParseError>
+where
+    I: Iterator<Item = Log<'a>>
+{
+    if let Some(max) = options.max_instructions.filter(|max| flattened_instructions.len() > *max) {
+        return Err(ParseError::TooManyInstructions { count: flattened_instructions.len(), max });
+    }
+    if flattened_instructions.iter().all(|x| x.stack_height().is_some()) {
+        let stack_heights = flattened_instructions.iter().map(|x| x.stack_height()).collect();
+        return structure_with_stack_heights(flattened_instructions, logs, accounts, options, account_layout, stack_heights);
+    }
+    let logs: Vec<Log<'a>> = logs.collect();
+    let stack_heights = derive_stack_heights(&flattened_instructions, &accounts, &logs);
+    structure_with_stack_heights(flattened_instructions, &mut logs.into_iter().peekable(), accounts, options, account_layout, stack_heights)
+}
+
+fn structure_with_stack_heights<'a, I>(
+    flattened_instructions: Vec<WrappedInstruction<'a>>,
+    logs: &mut Peekable<I>,
+    accounts: Vec<&'a Vec<u8>>,
+    options: &StructureOptions,
+    account_layout: Option<AccountLayout>,
+    stack_heights: Vec<Option<u32>>,
+) -> Result<StructureOutput<'a>, ParseError>
+where
+    I: Iterator<Item = Log<'a>>
+{
+    let association = options.association;
     let mut structured_instructions: Vec<Rc<StructuredInstruction<'a>>> = Vec::new();
     let mut instruction_stack: Vec<Rc<StructuredInstruction<'a>>> = Vec::new();
-    let mut log_stack = LogStack::new();
+    let mut log_stack = LogStack::with_options(options);
+    let resolved_accounts = Rc::new(ResolvedAccounts(accounts));
 
-    for instruction in flattened_instructions {
-        let structured_instruction = Rc::new(StructuredInstruction::new(instruction, Vec::new().into(), &accounts));
+    let mut compiled_instructions: u32 = 0;
+    for (flat_index, (instruction, stack_height)) in flattened_instructions.into_iter().zip(stack_heights).enumerate() {
+        if let WrappedInstruction::Compiled(_) = instruction {
+            compiled_instructions += 1;
+        }
+        let top_level_index = compiled_instructions.saturating_sub(1);
+        if let (Some(stack_height), Some(max)) = (stack_height, options.max_depth) {
+            if stack_height > max {
+                return Err(ParseError::MaxDepthExceeded { flat_index, stack_height, max });
+            }
+        }
+        let structured_instruction = Rc::new(StructuredInstruction::new(instruction, Vec::new().into(), &resolved_accounts, account_layout, stack_height, top_level_index, flat_index)?);
 
         while !instruction_stack.is_empty() && instruction_stack.last().unwrap().stack_height() >= structured_instruction.stack_height() {
             let popped_instruction = instruction_stack.pop().unwrap();
-            *popped_instruction.logs.borrow_mut() = log_stack.close(logs, popped_instruction.program_id());
+            let frame = log_stack.close(logs, popped_instruction.program_id()).or_else(|error| log_stack.recover(error, association))?;
+            popped_instruction.set_log_frame(frame, &log_stack);
 
             if !instruction_stack.is_empty() {
                 *popped_instruction.parent_instruction.borrow_mut() = Some(Rc::downgrade(instruction_stack.last().unwrap()));
-                instruction_stack.last_mut().unwrap().inner_instructions.borrow_mut().push(popped_instruction);
+                push_sibling(&mut instruction_stack.last_mut().unwrap().inner_instructions.borrow_mut(), popped_instruction);
             } else {
-                structured_instructions.push(popped_instruction);
+                push_sibling(&mut structured_instructions, popped_instruction);
             }
         }
 
-        log_stack.open(logs, structured_instruction.program_id());
+        log_stack.open(logs, structured_instruction.program_id()).or_else(|error| log_stack.recover(error, association))?;
         instruction_stack.push(structured_instruction);
     }
 
     while !instruction_stack.is_empty() {
         let popped_instruction = instruction_stack.pop().unwrap();
-        *popped_instruction.logs.borrow_mut() = log_stack.close(logs, popped_instruction.program_id());
+        let frame = log_stack.close(logs, popped_instruction.program_id()).or_else(|error| log_stack.recover(error, association))?;
+        popped_instruction.set_log_frame(frame, &log_stack);
 
         if !instruction_stack.is_empty() {
-            instruction_stack.last_mut().unwrap().inner_instructions.borrow_mut().push(popped_instruction);
+            *popped_instruction.parent_instruction.borrow_mut() = Some(Rc::downgrade(instruction_stack.last().unwrap()));
+            push_sibling(&mut instruction_stack.last_mut().unwrap().inner_instructions.borrow_mut(), popped_instruction);
         } else {
-            structured_instructions.push(popped_instruction)
+            push_sibling(&mut structured_instructions, popped_instruction)
         }
     }
 
-    structured_instructions
+    if association != LogAssociation::Disabled {
+        log_stack.drain_unread(logs);
+    }
+    if let Some(position) = log_stack.first_orphan_at().filter(|_| options.reject_orphan_logs) {
+        return Err(ParseError::OrphanLog { position });
+    }
+    Ok(StructureOutput { instructions: structured_instructions, orphan_logs: log_stack.orphan_logs, recovered_error: log_stack.recovered_error })
 }
 
-pub fn get_flattened_instructions(confirmed_transaction: &pb::ConfirmedTransaction) -> Vec<WrappedInstruction> {
-    let compiled_instructions = confirmed_transaction.transaction.as_ref().map(|x| x.message.as_ref().map(|y| &y.instructions)).unwrap().unwrap();
-    let inner_instructions = confirmed_transaction.meta.as_ref().map(|x| &x.inner_instructions).unwrap();
+/// Stack heights of the flattened instructions, taking those missing from the
+/// invoke depths of the logs, as in blocks produced before inner instructions
+/// recorded their stack height. Invoke logs are matched to the instructions by
+/// program id, in order, without crossing into the logs of the next top level
+/// instruction. An inner instruction without a matching invoke log, e.g. of a
+/// program that does not log, is assumed to be invoked by its top level
+/// instruction.
+///
+/// ```
+/// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, router, pool, token) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+/// let build = |builder: TransactionBuilder| builder
+///     .signer(user)
+///     .instruction(router, &[user, pool], vec![1])
+///     .inner(pool, &[user, token], vec![2])
+///     .inner_at(3, token, &[user], vec![3])
+///     .inner_at(3, token, &[user], vec![4])
+///     .inner(token, &[user], vec![5])
+///     .instruction(pool, &[user], vec![6])
+///     .inner(router, &[user], vec![7])
+///     .build();
+/// let (modern, old) = (build(TransactionBuilder::new()), build(TransactionBuilder::new().without_stack_heights()));
+///
+/// let shape = |instructions: &Vec<_>| instructions.flattened().iter()
+///     .map(|x| (x.stack_height(), x.parent_instruction().map(|p| p.flat_index())))
+///     .collect::<Vec<_>>();
+/// let instructions = get_structured_instructions(&old).unwrap();
+/// assert_eq!(shape(&instructions), shape(&get_structured_instructions(&modern).unwrap()));
+/// assert_eq!(instructions[0].inner_instructions()[0].inner_instructions().len(), 2);
+/// ```
+pub fn derive_stack_heights(flattened_instructions: &[WrappedInstruction], accounts: &[&Vec<u8>], logs: &[Log]) -> Vec<Option<u32>> {
+    let invokes: Vec<(&Log, u32)> = logs.iter().filter_map(|log| match log {
+        Log::Invoke(invoke_log) => Some((log, invoke_log.invoke_depth().ok()?)),
+        _ => None,
+    }).collect();
+    let mut next = 0;
+    flattened_instructions.iter().map(|instruction| {
+        let is_compiled = matches!(instruction, WrappedInstruction::Compiled(_));
+        let program_id = accounts.get(instruction.program_id_index() as usize).map(|x| PubkeyRef(x));
+        let found = invokes[next..].iter()
+            .take_while(|(_, depth)| is_compiled || *depth > 1)
+            .position(|(log, depth)| (*depth == 1) == is_compiled && program_id.is_some_and(|x| is_program(log, x)));
+        match found {
+            Some(offset) => {
+                next += offset + 1;
+                instruction.stack_height().or(Some(invokes[next - 1].1))
+            },
+            None => instruction.stack_height().or(Some(2)),
+        }
+    }).collect()
+}
+
+/// Appends `instruction` to its siblings, linking it to the previous one
+fn push_sibling<'a>(siblings: &mut Vec<Rc<StructuredInstruction<'a>>>, instruction: Rc<StructuredInstruction<'a>>) {
+    if let Some(previous) = siblings.last() {
+        *previous.next_sibling.borrow_mut() = Some(Rc::downgrade(&instruction));
+        *instruction.previous_sibling.borrow_mut() = Some(Rc::downgrade(previous));
+    }
+    instruction.sibling_index.set(Some(siblings.len()));
+    siblings.push(instruction);
+}
+
+/// Compiled instructions, each followed by its inner instructions. The inner
+/// instruction groups of `meta` are matched by their `index`, so they need not
+/// be sorted.
+///
+/// ```
+/// use substreams_solana_utils::instruction::get_flattened_instructions;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, program, token) = (pubkey(1), pubkey(2), pubkey(3));
+/// let mut transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(program, &[user], vec![1])
+///     .inner(token, &[user], vec![2])
+///     .instruction(program, &[user], vec![3])
+///     .instruction(program, &[user], vec![4])
+///     .inner(token, &[user], vec![5])
+///     .inner(token, &[user], vec![6])
+///     .build();
+/// transaction.meta.as_mut().unwrap().inner_instructions.reverse();
+///
+/// let order: Vec<u8> = get_flattened_instructions(&transaction).unwrap().iter().map(|x| x.data()[0]).collect();
+/// assert_eq!(order, [1, 2, 3, 4, 5, 6]);
+/// ```
+pub fn get_flattened_instructions(confirmed_transaction: &pb::ConfirmedTransaction) -> Result<Vec<WrappedInstruction>, ParseError> {
+    let (message, meta) = get_message_and_meta(confirmed_transaction)?;
+    let compiled_instructions = &message.instructions;
+    let mut inner_instructions: Vec<&pb::InnerInstructions> = meta.inner_instructions.iter().collect();
+    // Stable, and linear on the usual sorted groups
+    inner_instructions.sort_by_key(|x| x.index);
 
     let mut wrapped_instructions: Vec<WrappedInstruction> = Vec::new();
     let mut j = 0;
     for (i, instr) in compiled_instructions.iter().enumerate() {
         wrapped_instructions.push(instr.into());
-        if let Some(inner) = inner_instructions.get(j) {
-            if inner.index == i as u32 {
-                wrapped_instructions.extend(inner_instructions[j].instructions.iter().map(|x| WrappedInstruction::from(x)));
-                j += 1;
-            }
+        while let Some(inner) = inner_instructions.get(j).filter(|x| x.index == i as u32) {
+            wrapped_instructions.extend(inner.instructions.iter().map(|x| WrappedInstruction::from(x)));
+            j += 1;
         }
     }
-    wrapped_instructions
+    debug_assert!(j == inner_instructions.len(), "inner instructions of {} missing compiled instructions", inner_instructions.len() - j);
+    Ok(wrapped_instructions)
+}
+
+/// Message and meta of the transaction, which are needed to resolve its
+/// accounts. `ConfirmedTransaction::resolved_accounts` panics without them.
+///
+/// ```
+/// use substreams_solana_utils::error::ParseError;
+/// use substreams_solana_utils::instruction::{get_flattened_instructions, get_structured_instructions};
+/// use substreams_solana_utils::transaction::TransactionContext;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let transaction = TransactionBuilder::new()
+///     .signer(pubkey(1))
+///     .instruction(pubkey(2), &[pubkey(1)], vec![1])
+///     .build();
+///
+/// let mut without_meta = transaction.clone();
+/// without_meta.meta = None;
+/// let mut without_transaction = transaction.clone();
+/// without_transaction.transaction = None;
+/// let mut without_message = transaction.clone();
+/// without_message.transaction.as_mut().unwrap().message = None;
+///
+/// for (transaction, error) in [
+///     (without_meta, ParseError::MissingMeta),
+///     (without_transaction, ParseError::MissingTransaction),
+///     (without_message, ParseError::MissingMessage),
+/// ] {
+///     assert_eq!(get_flattened_instructions(&transaction).unwrap_err(), error);
+///     assert_eq!(get_structured_instructions(&transaction).unwrap_err(), error);
+///     assert_eq!(TransactionContext::build(&transaction).err(), Some(error));
+/// }
+/// ```
+pub fn get_message_and_meta(confirmed_transaction: &pb::ConfirmedTransaction) -> Result<(&pb::Message, &pb::TransactionStatusMeta), ParseError> {
+    let transaction = confirmed_transaction.transaction.as_ref().ok_or(ParseError::MissingTransaction)?;
+    let message = transaction.message.as_ref().ok_or(ParseError::MissingMessage)?;
+    let meta = confirmed_transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?;
+    Ok((message, meta))
+}
+
+/// Structures the instructions of a successful transaction into trees of CPI
+/// calls, attaching its logs to each instruction.
+///
+/// ```
+/// use std::rc::Rc;
+/// use substreams_solana_utils::instruction::get_structured_instructions;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(router, &[user, pool], vec![1])
+///     .inner(pool, &[user], vec![2])
+///     .instruction(router, &[user], vec![3])
+///     .build();
+///
+/// let instructions = get_structured_instructions(&transaction).unwrap();
+/// assert_eq!(instructions.len(), 2);
+/// let swap = Rc::clone(&instructions[0].inner_instructions()[0]);
+/// assert_eq!(swap.program_id(), pool);
+/// assert!(Rc::ptr_eq(&swap.parent_instruction().unwrap(), &instructions[0]));
+/// assert_eq!(swap.logs().as_ref().unwrap()[0].to_string(), format!("Program {} invoke [2]", pool.to_string()));
+/// ```
+pub fn get_structured_instructions<'a>(transaction: &'a pb::ConfirmedTransaction) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError> {
+    if transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.err.is_some() {
+        return Err(ParseError::FailedTransaction);
+    }
+    structure_transaction(transaction, &StructureOptions::default())
+}
+
+/// Same as `get_structured_instructions`, structuring with `options`. Programs
+/// that do not log can be configured, or detected from the logs.
+///
+/// ```
+/// use substreams_solana_utils::instruction::{get_structured_instructions, get_structured_instructions_with_options, StructureOptions};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, silent, program) = (pubkey(1), pubkey(2), pubkey(3));
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(silent, &[user], vec![1])
+///     .instruction(program, &[user], vec![2])
+///     .logs(&[
+///         &format!("Program {} invoke [1]", program.to_string()),
+///         &format!("Program {} success", program.to_string()),
+///     ])
+///     .build();
+///
+/// assert!(get_structured_instructions(&transaction).is_err());
+/// let options = StructureOptions { detect_programs_without_logging: true, ..Default::default() };
+/// let instructions = get_structured_instructions_with_options(&transaction, &options).unwrap();
+/// assert!(instructions[0].logs().as_ref().unwrap().is_empty());
+/// assert_eq!(instructions[1].logs().as_ref().unwrap().len(), 2);
+///
+/// let mut options = StructureOptions::default();
+/// options.programs_without_logging.push(silent);
+/// let instructions = get_structured_instructions_with_options(&transaction, &options).unwrap();
+/// assert!(instructions[0].logs().as_ref().unwrap().is_empty());
+/// ```
+pub fn get_structured_instructions_with_options<'a>(transaction: &'a pb::ConfirmedTransaction, options: &StructureOptions) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError> {
+    if transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.err.is_some() {
+        return Err(ParseError::FailedTransaction);
+    }
+    structure_transaction(transaction, options)
+}
+
+/// Same as `get_structured_instructions_with_options`, also returning the logs
+/// attributed to no instruction, see `StructureOutput::orphan_logs`.
+///
+/// ```
+/// use substreams_solana_utils::error::ParseError;
+/// use substreams_solana_utils::instruction::{get_structured_instructions_with_orphan_logs, StructureOptions};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, program) = (pubkey(1), pubkey(2));
+/// let program_id = program.to_string();
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(program, &[user], vec![1])
+///     .instruction(program, &[user], vec![2])
+///     .logs(&[
+///         &format!("Program {program_id} invoke [1]"),
+///         &format!("Program {program_id} consumed 100 of 200000 compute units"),
+///         &format!("Program {program_id} success"),
+///         "Runtime message",
+///         &format!("Program {program_id} invoke [1]"),
+///         &format!("Program {program_id} success"),
+///         "Trailing message",
+///     ])
+///     .build();
+///
+/// let output = get_structured_instructions_with_orphan_logs(&transaction, &StructureOptions::default()).unwrap();
+/// assert_eq!(output.instructions[0].logs().as_ref().unwrap().len(), 3);
+/// let orphan_logs: Vec<String> = output.orphan_logs.iter().map(|x| x.to_string()).collect();
+/// assert_eq!(orphan_logs, ["Runtime message", "Trailing message"]);
+///
+/// let options = StructureOptions { reject_orphan_logs: true, ..Default::default() };
+/// let error = get_structured_instructions_with_orphan_logs(&transaction, &options).unwrap_err();
+/// assert_eq!(error, ParseError::OrphanLog { position: 3 });
+/// ```
+pub fn get_structured_instructions_with_orphan_logs<'a>(transaction: &'a pb::ConfirmedTransaction, options: &StructureOptions) -> Result<StructureOutput<'a>, ParseError> {
+    if transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.err.is_some() {
+        return Err(ParseError::FailedTransaction);
+    }
+    structure_transaction_with_orphan_logs(transaction, options)
 }
 
-pub fn get_structured_instructions<'a>(transaction: &'a pb::ConfirmedTransaction) -> Result<Vec<Rc<StructuredInstruction<'a>>>, Error> {
-    if let Some(_) = transaction.meta.as_ref().unwrap().err {
-        return Err(anyhow!("Cannot structure instructions of a failed transaction."));
+/// Same as `get_structured_instructions_with_options`, accumulating the outcome
+/// in `report`: the lines read, orphan logs, and the error structuring failed
+/// on or recovered from.
+///
+/// ```
+/// use substreams_solana_utils::instruction::{get_structured_instructions_with_report, LogAssociation, StructureOptions};
+/// use substreams_solana_utils::log::LogParseReport;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, program, other) = (pubkey(1), pubkey(2), pubkey(3));
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(program, &[user], vec![1])
+///     .logs(&[
+///         &format!("Program {} invoke [1]", program.to_string()),
+///         &format!("Program {} invoke [2]", other.to_string()),
+///     ])
+///     .build();
+///
+/// let options = StructureOptions { association: LogAssociation::Lenient, ..Default::default() };
+/// let mut report = LogParseReport::default();
+/// get_structured_instructions_with_report(&transaction, &options, &mut report).unwrap();
+/// assert_eq!((report.total_lines, report.unknown_lines, report.errors.len()), (2, 0, 1));
+/// ```
+pub fn get_structured_instructions_with_report<'a>(transaction: &'a pb::ConfirmedTransaction, options: &StructureOptions, report: &mut LogParseReport) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError> {
+    let meta = transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?;
+    if meta.err.is_some() {
+        return Err(ParseError::FailedTransaction);
     }
-    let flattened_instructions: Vec<WrappedInstruction> = get_flattened_instructions(transaction);
-    let logs: &Vec<_> = transaction.meta.as_ref().unwrap().log_messages.as_ref();
+    report.add_lines(&meta.log_messages);
+    match structure_transaction_with_orphan_logs(transaction, options) {
+        Ok(output) => {
+            report.orphan_lines += output.orphan_logs.len();
+            report.errors.extend(output.recovered_error.map(|error| error.to_string()));
+            Ok(output.instructions)
+        },
+        Err(error) => {
+            report.errors.push(error.to_string());
+            Err(error)
+        },
+    }
+}
+
+/// Same as `get_structured_instructions`, except that logs that do not match the
+/// instructions are dropped instead of failing, see `LogAssociation::Lenient`.
+///
+/// ```
+/// use substreams_solana_utils::instruction::{get_structured_instructions, get_structured_instructions_lenient};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, program, other) = (pubkey(1), pubkey(2), pubkey(3));
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(program, &[user], vec![1])
+///     .logs(&[
+///         &format!("Program {} invoke [1]", program.to_string()),
+///         &format!("Program {} invoke [2]", other.to_string()),
+///     ])
+///     .build();
+///
+/// assert!(get_structured_instructions(&transaction).is_err());
+/// let instructions = get_structured_instructions_lenient(&transaction).unwrap();
+/// assert!(instructions[0].logs().is_none());
+/// ```
+pub fn get_structured_instructions_lenient<'a>(transaction: &'a pb::ConfirmedTransaction) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError> {
+    if transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.err.is_some() {
+        return Err(ParseError::FailedTransaction);
+    }
+    structure_transaction(transaction, &StructureOptions { association: LogAssociation::Lenient, ..Default::default() })
+}
+
+/// Structures the instructions of a failed transaction, as far as it executed.
+///
+/// The failing instruction and its ancestors get their logs up to their
+/// `failed` log. The top level instructions following it never ran: they are
+/// marked with `did_execute() == false` and get no logs. When the logs hold no
+/// `failed` log, e.g. when truncated or for a failing precompile, every
+/// instruction is assumed to have run.
+pub fn get_structured_instructions_of_failed<'a>(transaction: &'a pb::ConfirmedTransaction) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError> {
+    if transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.err.is_none() {
+        return Err(ParseError::SuccessfulTransaction);
+    }
+    let structured_instructions = structure_transaction(transaction, &StructureOptions::default())?;
+    let failing = structured_instructions.iter().rposition(|x| x.error().is_some());
+    if let Some(failing) = failing {
+        for instruction in structured_instructions[failing + 1..].iter().flat_map(|x| x.iter_dfs()) {
+            instruction.did_execute.set(false);
+            *instruction.logs.borrow_mut() = Some(Vec::new());
+            *instruction.log_line_indices.borrow_mut() = Some(Vec::new());
+            *instruction.log_range.borrow_mut() = None;
+        }
+    }
+    Ok(structured_instructions)
+}
+
+/// Structures the instructions into trees of CPI calls from their stack heights
+/// alone, without reading the logs: every `logs()` is `None`. The trees have
+/// the same shape as those of `get_structured_instructions`, including for
+/// transactions with missing or truncated logs, and failed transactions.
+///
+/// ```
+/// use substreams_solana_utils::instruction::{get_structured_instructions, get_structured_instructions_without_logs, StructuredInstructions};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(router, &[user, pool], vec![1])
+///     .inner(pool, &[user], vec![2])
+///     .inner_at(3, router, &[user], vec![3])
+///     .instruction(router, &[user], vec![4])
+///     .build();
+///
+/// let shape = |instructions: &Vec<_>| instructions.flattened().iter()
+///     .map(|x| (x.flat_index(), x.parent_instruction().map(|p| p.flat_index())))
+///     .collect::<Vec<_>>();
+/// let instructions = get_structured_instructions_without_logs(&transaction).unwrap();
+/// assert_eq!(shape(&instructions), shape(&get_structured_instructions(&transaction).unwrap()));
+/// assert!(instructions.flattened().iter().all(|x| x.logs().is_none()));
+/// ```
+pub fn get_structured_instructions_without_logs<'a>(transaction: &'a pb::ConfirmedTransaction) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError> {
+    let flattened_instructions: Vec<WrappedInstruction> = get_flattened_instructions(transaction)?;
     let accounts = transaction.resolved_accounts();
-    Ok(structure_flattened_instructions_with_logs(flattened_instructions, &mut logs.iter().map(|log| Log::new(log)).peekable(), accounts))
+    let structured_instructions = structure(flattened_instructions, &mut std::iter::empty().peekable(), accounts, &StructureOptions { association: LogAssociation::Disabled, ..Default::default() }, AccountLayout::of(transaction))?.instructions;
+    attach_meta_return_data(&structured_instructions, transaction);
+    Ok(structured_instructions)
+}
+
+fn structure_transaction<'a>(transaction: &'a pb::ConfirmedTransaction, options: &StructureOptions) -> Result<Vec<Rc<StructuredInstruction<'a>>>, ParseError> {
+    structure_transaction_with_orphan_logs(transaction, options).map(|x| x.instructions)
+}
+
+fn structure_transaction_with_orphan_logs<'a>(transaction: &'a pb::ConfirmedTransaction, options: &StructureOptions) -> Result<StructureOutput<'a>, ParseError> {
+    let flattened_instructions: Vec<WrappedInstruction> = get_flattened_instructions(transaction)?;
+    let logs: &Vec<_> = transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.log_messages.as_ref();
+    let accounts = transaction.resolved_accounts();
+    let output = structure(flattened_instructions, &mut logs.iter().map(|log| Log::new(log)).peekable(), accounts, options, AccountLayout::of(transaction))?;
+    attach_meta_return_data(&output.instructions, transaction);
+    Ok(output)
+}
+
+/// Gives `meta.return_data` to the last instruction of the returning program,
+/// unless its logs already did
+fn attach_meta_return_data(structured_instructions: &Vec<Rc<StructuredInstruction>>, transaction: &pb::ConfirmedTransaction) {
+    let Some(return_data) = transaction.meta.as_ref().and_then(|x| x.return_data.as_ref()) else { return };
+    let flattened_instructions = structured_instructions.flattened();
+    if let Some(instruction) = flattened_instructions.iter().rev().find(|x| x.program_id().0[..] == return_data.program_id[..]) {
+        let _ = instruction.return_data.set(return_data.data.clone());
+    }
 }
 
 pub trait StructuredInstructions<'a> {
+    /// Lists the instructions in execution order: each instruction followed by
+    /// its inner instructions, depth first.
+    ///
+    /// This is guaranteed to be the order of `get_flattened_instructions`, so
+    /// `flattened()[i].flat_index() == i`. `iter_dfs`, `walk` in pre-order and
+    /// `InstructionTree::flattened` follow the same order.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, a, b, c) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(a, &[user], vec![1])
+    ///     .inner(b, &[user], vec![2])
+    ///     .inner_at(3, c, &[user], vec![3])
+    ///     .inner(c, &[user], vec![4])
+    ///     .instruction(a, &[user], vec![5])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let order: Vec<u8> = instructions.flattened().iter().map(|x| x.data()[0]).collect();
+    /// assert_eq!(order, [1, 2, 3, 4, 5]);
+    /// assert_eq!(instructions.flattened()[2].stack_height(), Some(3));
+    /// assert!(instructions.flattened().iter().enumerate().all(|(i, x)| x.flat_index() == i));
+    /// ```
     fn flattened(&self) -> Vec<Rc<StructuredInstruction<'a>>>;
+
+    /// Lazily walks the instructions in the order of `flattened`
+    fn iter_dfs(&self) -> DepthFirst<'a, Cloned<std::slice::Iter<'_, Rc<StructuredInstruction<'a>>>>>;
+
+    /// Lazily walks the instructions level by level
+    fn iter_bfs(&self) -> BreadthFirst<'a>;
+
+    /// Calls `visit` on every instruction in execution order, pre-order or
+    /// post-order, until it breaks. No borrow of the tree is held while `visit`
+    /// runs, so it can call `logs()` or `parent_instruction()`.
+    ///
+    /// ```
+    /// use std::ops::ControlFlow;
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions, WalkOrder};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .inner(pool, &[user], vec![3])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let mut visited = Vec::new();
+    /// let found = instructions.walk(WalkOrder::PreOrder, |instruction, context| {
+    ///     visited.push(instruction.data()[0]);
+    ///     if instruction.program_id() == pool {
+    ///         assert_eq!(context.depth, 2);
+    ///         assert_eq!(context.parent.unwrap().program_id(), router);
+    ///         return ControlFlow::Break(());
+    ///     }
+    ///     ControlFlow::Continue(())
+    /// });
+    /// assert!(found.is_break());
+    /// assert_eq!(visited, [1, 2]);
+    /// ```
+    fn walk<F>(&self, order: WalkOrder, visit: F) -> ControlFlow<()>
+    where
+        F: FnMut(&Rc<StructuredInstruction<'a>>, WalkContext<'_, 'a>) -> ControlFlow<()>;
+
+    /// First instruction matching `predicate`, inner instructions included, in
+    /// execution order. Stops at the first match, and holds no borrow of the
+    /// tree while `predicate` runs.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool, token) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(token, &[user], vec![0])
+    ///     .instruction(router, &[user], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .inner_at(3, token, &[user], vec![3])
+    ///     .inner(token, &[user], vec![4])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let route = instructions.find_first(|x| x.program_id() == router && x.inner_instructions().len() > 0).unwrap();
+    /// assert_eq!(instructions.find_all(|x| x.program_id() == token).len(), 3);
+    /// let transfers: Vec<u8> = route.find_all(|x| x.program_id() == token).iter().map(|x| x.data()[0]).collect();
+    /// assert_eq!(transfers, [3, 4]);
+    /// ```
+    fn find_first<F>(&self, predicate: F) -> Option<Rc<StructuredInstruction<'a>>>
+    where
+        F: Fn(&Rc<StructuredInstruction<'a>>) -> bool;
+
+    /// Instructions matching `predicate`, inner instructions included, in execution order
+    fn find_all<F>(&self, predicate: F) -> Vec<Rc<StructuredInstruction<'a>>>
+    where
+        F: Fn(&Rc<StructuredInstruction<'a>>) -> bool;
+
+    /// Instruction at `path`, see `StructuredInstruction::path`
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, a, b) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(a, &[user], vec![1])
+    ///     .instruction(a, &[user], vec![2])
+    ///     .inner(b, &[user], vec![3])
+    ///     .inner_at(3, a, &[user], vec![4])
+    ///     .inner_at(4, b, &[user], vec![5])
+    ///     .inner_at(3, a, &[user], vec![6])
+    ///     .inner_at(4, b, &[user], vec![7])
+    ///     .inner_at(5, a, &[user], vec![8])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let flattened = instructions.flattened();
+    /// assert_eq!(flattened.last().unwrap().path(), [1, 0, 1, 0, 0]);
+    /// for instruction in &flattened {
+    ///     let found = instructions.get_by_path(&instruction.path()).unwrap();
+    ///     assert_eq!(found.flat_index(), instruction.flat_index());
+    /// }
+    /// let mut paths: Vec<_> = flattened.iter().map(|x| x.path()).collect();
+    /// paths.sort();
+    /// assert_eq!(paths, flattened.iter().map(|x| x.path()).collect::<Vec<_>>());
+    /// assert!(instructions.get_by_path(&[1, 1]).is_none());
+    /// assert!(instructions.get_by_path(&[]).is_none());
+    /// ```
+    fn get_by_path(&self, path: &[u32]) -> Option<Rc<StructuredInstruction<'a>>>;
+
+    /// Instructions of `program_id`, inner instructions included, in execution
+    /// order. `program_id` can be a `Pubkey` or a `PubkeyRef`.
+    fn filter_by_program_id<P>(&self, program_id: &P) -> Vec<Rc<StructuredInstruction<'a>>>
+    where
+        PubkeyRef<'a>: PartialEq<P>
+    {
+        self.iter_dfs().filter(|x| x.program_id() == *program_id).collect()
+    }
+
+    /// Instructions grouped by program id in a single walk, in execution order
+    /// within each program. Maps keyed by `PubkeyRef` can be queried with the
+    /// program id bytes.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool, token) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .inner_at(3, token, &[user], vec![3])
+    ///     .inner(token, &[user], vec![4])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let programs = instructions.by_program();
+    /// assert_eq!(programs.len(), 3);
+    /// let transfers: Vec<u8> = programs[&token.0[..]].iter().map(|x| x.data()[0]).collect();
+    /// assert_eq!(transfers, [3, 4]);
+    ///
+    /// let programs = instructions.by_program_in(&[token, pubkey(5)]);
+    /// assert_eq!(programs.len(), 1);
+    /// assert_eq!(programs[&token.0[..]].len(), 2);
+    /// ```
+    fn by_program(&self) -> HashMap<PubkeyRef<'a>, Vec<Rc<StructuredInstruction<'a>>>> {
+        let mut programs: HashMap<PubkeyRef<'a>, Vec<_>> = HashMap::new();
+        for instruction in self.iter_dfs() {
+            programs.entry(instruction.program_id()).or_default().push(instruction);
+        }
+        programs
+    }
+
+    /// Same as `by_program`, for the programs of `program_ids` only. Other
+    /// programs get no entry.
+    fn by_program_in<P>(&self, program_ids: &[P]) -> HashMap<PubkeyRef<'a>, Vec<Rc<StructuredInstruction<'a>>>>
+    where
+        PubkeyRef<'a>: PartialEq<P>
+    {
+        let mut programs: HashMap<PubkeyRef<'a>, Vec<_>> = HashMap::new();
+        for instruction in self.iter_dfs() {
+            if program_ids.iter().any(|x| instruction.program_id() == *x) {
+                programs.entry(instruction.program_id()).or_default().push(instruction);
+            }
+        }
+        programs
+    }
+
+    /// Instruction counts and depth, computed in a single walk that clones no
+    /// `Rc` and no accounts, cheap enough to run on every transaction.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool, token) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .inner_at(3, token, &[user], vec![3])
+    ///     .inner(token, &[user], vec![4])
+    ///     .instruction(token, &[user], vec![5])
+    ///     .build();
+    ///
+    /// let stats = get_structured_instructions(&transaction).unwrap().stats();
+    /// assert_eq!((stats.instructions, stats.inner_instructions, stats.max_depth), (5, 3, 3));
+    /// assert_eq!(stats.unique_programs, [router, pool, token]);
+    /// assert_eq!(stats.program_counts[&token], 3);
+    /// ```
+    fn stats(&self) -> InstructionStats;
+
+    /// Events emitted with `emit_cpi!` by any of the instructions, in execution
+    /// order. See `StructuredInstruction::as_anchor_cpi_event`.
+    fn anchor_cpi_events(&self) -> Vec<AnchorCpiEvent<'a>> {
+        self.iter_dfs().filter_map(|x| x.as_anchor_cpi_event()).collect()
+    }
+
+    /// Instructions whose program id or accounts include `pubkey`, inner
+    /// instructions included, in execution order. See `StructuredInstruction::touches_account`.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool, token, vault) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user, pool], vec![1])
+    ///     .inner(pool, &[user], vec![2])
+    ///     .inner_at(3, token, &[vault, user], vec![3])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// let touching: Vec<u8> = instructions.touching_account(&vault).iter().map(|x| x.data()[0]).collect();
+    /// assert_eq!(touching, [3]);
+    /// let touching: Vec<u8> = instructions.touching_account(&pool).iter().map(|x| x.data()[0]).collect();
+    /// assert_eq!(touching, [1, 2]);
+    /// assert!(!instructions[0].touches_account(&vault));
+    /// ```
+    fn touching_account<P>(&self, pubkey: &P) -> Vec<Rc<StructuredInstruction<'a>>>
+    where
+        PubkeyRef<'a>: PartialEq<P>
+    {
+        self.iter_dfs().filter(|x| x.touches_account(pubkey)).collect()
+    }
+
+    /// Instructions of `program_id` not invoked, directly or not, by another
+    /// instruction of `program_id`, in execution order.
+    fn filter_outermost_by_program_id<P>(&self, program_id: &P) -> Vec<Rc<StructuredInstruction<'a>>>
+    where
+        PubkeyRef<'a>: PartialEq<P>;
+
+    /// Whether the logs were truncated, leaving some instructions without logs.
+    /// Their data can still be decoded, see `StructuredInstruction::logs_truncated`.
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, program) = (pubkey(1), pubkey(2));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(program, &[user], vec![1])
+    ///     .instruction(program, &[user], vec![2])
+    ///     .logs(&[
+    ///         &format!("Program {} invoke [1]", program.to_string()),
+    ///         &format!("Program {} success", program.to_string()),
+    ///         &format!("Program {} invoke [1]", program.to_string()),
+    ///         "Log truncated",
+    ///     ])
+    ///     .build();
+    ///
+    /// let instructions = get_structured_instructions(&transaction).unwrap();
+    /// assert!(instructions.logs_truncated());
+    /// assert!(!instructions[0].logs_truncated() && instructions[0].logs().is_some());
+    /// assert!(instructions[1].logs_truncated() && instructions[1].logs().is_none());
+    /// ```
+    fn logs_truncated(&self) -> bool {
+        self.iter_dfs().any(|instruction| instruction.logs_truncated())
+    }
+
+    /// Whether logs stopped matching the instructions, leaving some instructions
+    /// without logs, see `StructuredInstruction::log_mismatch`.
+    ///
+    /// ```
+    /// use substreams_solana_utils::error::ParseError;
+    /// use substreams_solana_utils::instruction::{get_structured_instructions_with_options, LogAssociation, StructureOptions, StructuredInstructions};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let (router_id, pool_id) = (router.to_string(), pool.to_string());
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(router, &[user], vec![1])
+    ///     .instruction(router, &[user], vec![2])
+    ///     .inner(pool, &[user], vec![3])
+    ///     .logs(&[
+    ///         &format!("Program {router_id} invoke [1]"),
+    ///         &format!("Program {router_id} success"),
+    ///         &format!("Program {router_id} invoke [1]"),
+    ///         &format!("Program {pool_id} invoke [3]"),
+    ///         &format!("Program {pool_id} success"),
+    ///         &format!("Program {router_id} success"),
+    ///     ])
+    ///     .build();
+    ///
+    /// let mut options = StructureOptions { validate_invoke_depth: true, ..Default::default() };
+    /// let error = get_structured_instructions_with_options(&transaction, &options).unwrap_err();
+    /// assert_eq!(error, ParseError::UnexpectedInvokeDepth { position: 3, expected: 2, found: 3 });
+    ///
+    /// options.association = LogAssociation::Lenient;
+    /// let instructions = get_structured_instructions_with_options(&transaction, &options).unwrap();
+    /// assert!(instructions.log_mismatch());
+    /// assert!(!instructions[0].log_mismatch() && instructions[0].logs().is_some());
+    /// assert!(instructions[1].log_mismatch() && instructions[1].logs().is_none());
+    /// ```
+    fn log_mismatch(&self) -> bool {
+        self.iter_dfs().any(|instruction| instruction.log_mismatch())
+    }
+
+    /// Indented text tree of the instructions, for debugging, see `render`
+    fn render(&self) -> String {
+        self.render_with(&RenderOptions::default())
+    }
+
+    fn render_with(&self, options: &RenderOptions) -> String;
+
+    /// Dialect of the invoke and success logs attached to the instructions
+    fn log_dialect(&self) -> LogDialect {
+        self.flattened().iter()
+            .filter_map(|instruction| instruction.logs().as_ref().map(|logs| logs.iter().map(LogDialect::of).max()))
+            .flatten()
+            .max()
+            .unwrap_or_default()
+    }
 }
 
 impl<'a> StructuredInstructions<'a> for Vec<Rc<StructuredInstruction<'a>>> {
     fn flattened(&self) -> Vec<Rc<StructuredInstruction<'a>>> {
-        let mut instructions: Vec<Rc<StructuredInstruction>> = Vec::new();
+        self.iter_dfs().collect()
+    }
+
+    fn iter_dfs(&self) -> DepthFirst<'a, Cloned<std::slice::Iter<'_, Rc<StructuredInstruction<'a>>>>> {
+        DepthFirst::new(self.iter().cloned())
+    }
+
+    fn iter_bfs(&self) -> BreadthFirst<'a> {
+        BreadthFirst::new(self.iter().cloned())
+    }
+
+    fn walk<F>(&self, order: WalkOrder, mut visit: F) -> ControlFlow<()>
+    where
+        F: FnMut(&Rc<StructuredInstruction<'a>>, WalkContext<'_, 'a>) -> ControlFlow<()>
+    {
+        for instruction in self {
+            walk_instruction(instruction, None, 1, order, &mut visit)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn find_first<F>(&self, predicate: F) -> Option<Rc<StructuredInstruction<'a>>>
+    where
+        F: Fn(&Rc<StructuredInstruction<'a>>) -> bool
+    {
+        find_first(self, predicate)
+    }
+
+    fn get_by_path(&self, path: &[u32]) -> Option<Rc<StructuredInstruction<'a>>> {
+        let (first, rest) = path.split_first()?;
+        let mut instruction = Rc::clone(self.get(*first as usize)?);
+        for index in rest {
+            let inner_instruction = instruction.inner_instructions().get(*index as usize).cloned()?;
+            instruction = inner_instruction;
+        }
+        Some(instruction)
+    }
+
+    fn find_all<F>(&self, predicate: F) -> Vec<Rc<StructuredInstruction<'a>>>
+    where
+        F: Fn(&Rc<StructuredInstruction<'a>>) -> bool
+    {
+        find_all(self, predicate)
+    }
+
+    fn render_with(&self, options: &RenderOptions) -> String {
+        render(self, options)
+    }
+
+    fn stats(&self) -> InstructionStats {
+        let mut program_counts: Vec<(PubkeyRef<'a>, usize)> = Vec::new();
+        let mut stats = InstructionStats::default();
+        tally_instructions(self, 1, &mut stats, &mut program_counts);
+        for (program_id, count) in program_counts {
+            let Ok(program_id) = program_id.to_pubkey() else { continue };
+            stats.unique_programs.push(program_id);
+            stats.program_counts.insert(program_id, count);
+        }
+        stats
+    }
+
+    fn filter_outermost_by_program_id<P>(&self, program_id: &P) -> Vec<Rc<StructuredInstruction<'a>>>
+    where
+        PubkeyRef<'a>: PartialEq<P>
+    {
+        let mut instructions = Vec::new();
         for instruction in self {
-            instructions.push(Rc::clone(instruction));
-            instructions.extend(instruction.inner_instructions().flattened().iter().map(Rc::clone));
+            if instruction.program_id() == *program_id {
+                instructions.push(Rc::clone(instruction));
+            } else {
+                instructions.extend(instruction.inner_instructions().filter_outermost_by_program_id(program_id));
+            }
         }
         instructions
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// Each instruction before its inner instructions, as in `flattened`
+    PreOrder,
+    /// Each instruction after its inner instructions
+    PostOrder,
+}
+
+/// Position of the instruction being visited by `StructuredInstructions::walk`
+#[derive(Clone, Copy, Debug)]
+pub struct WalkContext<'w, 'a> {
+    /// Invoke depth, 1 for top level instructions
+    pub depth: u32,
+    pub parent: Option<&'w Rc<StructuredInstruction<'a>>>,
+    pub top_level_index: u32,
+}
+
+/// Statistics of the instructions of a transaction, see `StructuredInstructions::stats`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstructionStats {
+    /// Number of instructions, inner instructions included
+    pub instructions: usize,
+    pub inner_instructions: usize,
+    /// Deepest invoke depth, 1 when there are only top level instructions
+    pub max_depth: u32,
+    /// Number of instructions of each program, inner instructions included
+    pub program_counts: HashMap<Pubkey, usize>,
+    /// Programs invoked, in order of first invocation
+    pub unique_programs: Vec<Pubkey>,
+}
+
+/// Counts `instructions`, at `depth`, and their inner instructions. A
+/// transaction invokes few programs, so they are counted in a `Vec`.
+fn tally_instructions<'a>(instructions: &[Rc<StructuredInstruction<'a>>], depth: u32, stats: &mut InstructionStats, program_counts: &mut Vec<(PubkeyRef<'a>, usize)>) {
+    for instruction in instructions {
+        stats.instructions += 1;
+        if depth > 1 {
+            stats.inner_instructions += 1;
+        }
+        stats.max_depth = stats.max_depth.max(depth);
+        match program_counts.iter_mut().find(|(x, _)| *x == instruction.program_id()) {
+            Some((_, count)) => *count += 1,
+            None => program_counts.push((instruction.program_id(), 1)),
+        }
+        tally_instructions(&instruction.inner_instructions(), depth + 1, stats, program_counts);
+    }
+}
+
+fn find_first<'a, F>(instructions: &[Rc<StructuredInstruction<'a>>], predicate: F) -> Option<Rc<StructuredInstruction<'a>>>
+where
+    F: Fn(&Rc<StructuredInstruction<'a>>) -> bool
+{
+    let mut found = None;
+    for instruction in instructions {
+        let _ = walk_instruction(instruction, None, instruction.depth(), WalkOrder::PreOrder, &mut |x, _| {
+            if predicate(x) {
+                found = Some(Rc::clone(x));
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        });
+        if found.is_some() {
+            break;
+        }
+    }
+    found
+}
+
+fn find_all<'a, F>(instructions: &[Rc<StructuredInstruction<'a>>], predicate: F) -> Vec<Rc<StructuredInstruction<'a>>>
+where
+    F: Fn(&Rc<StructuredInstruction<'a>>) -> bool
+{
+    let mut found = Vec::new();
+    for instruction in instructions {
+        let _ = walk_instruction(instruction, None, instruction.depth(), WalkOrder::PreOrder, &mut |x, _| {
+            if predicate(x) {
+                found.push(Rc::clone(x));
+            }
+            ControlFlow::Continue(())
+        });
+    }
+    found
+}
+
+fn walk_instruction<'a, F>(
+    instruction: &Rc<StructuredInstruction<'a>>,
+    parent: Option<&Rc<StructuredInstruction<'a>>>,
+    depth: u32,
+    order: WalkOrder,
+    visit: &mut F,
+) -> ControlFlow<()>
+where
+    F: FnMut(&Rc<StructuredInstruction<'a>>, WalkContext<'_, 'a>) -> ControlFlow<()>
+{
+    let context = WalkContext { depth, parent, top_level_index: instruction.top_level_index() };
+    if order == WalkOrder::PreOrder {
+        visit(instruction, context)?;
+    }
+    // The inner instructions are only borrowed to clone the next one
+    let mut index = 0;
+    while let Some(inner_instruction) = instruction.inner_instructions().get(index).cloned() {
+        walk_instruction(&inner_instruction, Some(instruction), depth + 1, order, visit)?;
+        index += 1;
+    }
+    if order == WalkOrder::PostOrder {
+        visit(instruction, context)?;
+    }
+    ControlFlow::Continue(())
+}
+
+pub struct Ancestors<'a> {
+    next: Option<Rc<StructuredInstruction<'a>>>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = Rc<StructuredInstruction<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.next.take()?;
+        self.next = instruction.parent_instruction();
+        Some(instruction)
+    }
+}
+
+/// Pre-order walk over instruction trees, in execution order. Only the position
+/// in each open inner instruction list is kept, and inner instructions are
+/// borrowed just long enough to clone the next one, so logs and inner
+/// instructions of visited instructions can be mutated while iterating.
+pub struct DepthFirst<'a, I> {
+    roots: I,
+    stack: Vec<(Rc<StructuredInstruction<'a>>, usize)>,
+}
+
+impl<'a, I: Iterator<Item = Rc<StructuredInstruction<'a>>>> DepthFirst<'a, I> {
+    fn new(roots: I) -> Self {
+        Self { roots, stack: Vec::new() }
+    }
+}
+
+impl<'a, I: Iterator<Item = Rc<StructuredInstruction<'a>>>> Iterator for DepthFirst<'a, I> {
+    type Item = Rc<StructuredInstruction<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((parent, index)) = self.stack.last_mut() {
+            let child = parent.inner_instructions().get(*index).cloned();
+            match child {
+                Some(child) => {
+                    *index += 1;
+                    self.stack.push((Rc::clone(&child), 0));
+                    return Some(child);
+                },
+                None => { self.stack.pop(); },
+            }
+        }
+        let instruction = self.roots.next()?;
+        self.stack.push((Rc::clone(&instruction), 0));
+        Some(instruction)
+    }
+}
+
+/// Level-order walk over instruction trees: top level instructions first, then
+/// their inner instructions, and so on.
+pub struct BreadthFirst<'a> {
+    queue: VecDeque<Rc<StructuredInstruction<'a>>>,
+}
+
+impl<'a> BreadthFirst<'a> {
+    fn new(roots: impl Iterator<Item = Rc<StructuredInstruction<'a>>>) -> Self {
+        Self { queue: roots.collect() }
+    }
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = Rc<StructuredInstruction<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.queue.pop_front()?;
+        self.queue.extend(instruction.inner_instructions().iter().cloned());
+        Some(instruction)
+    }
+}
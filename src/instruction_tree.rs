@@ -0,0 +1,290 @@
+//! Arena-backed instruction trees.
+//!
+//! `InstructionTree` holds the same structure as `get_structured_instructions`
+//! in a single `Vec`, linking instructions by their index instead of `Rc` and
+//! `Weak` pointers. Building it costs one allocation per instruction for its
+//! accounts, plus one per instruction with inner instructions, and no reference
+//! counting.
+//!
+//! The tree is immutable once built and holds no interior mutability, so it is
+//! `Send + Sync`: the trees of a block can be built one by one, then processed
+//! in parallel. A tree from `get_structured_instructions` can be finalized into
+//! one with `InstructionTree::from`.
+//!
+//! ```
+//! use substreams_solana_utils::instruction_tree::{get_instruction_tree, InstructionTree};
+//! use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+//!
+//! let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+//! let transactions: Vec<_> = (0..4).map(|i| TransactionBuilder::new()
+//!     .signer(user)
+//!     .instruction(router, &[user, pool], vec![i])
+//!     .inner(pool, &[user], vec![i])
+//!     .build()
+//! ).collect();
+//! let trees: Vec<InstructionTree> = transactions.iter().map(|x| get_instruction_tree(x).unwrap()).collect();
+//!
+//! // Same as `trees.par_iter().map(...)` with rayon
+//! let inner_instructions: Vec<usize> = std::thread::scope(|scope| {
+//!     let handles: Vec<_> = trees.iter()
+//!         .map(|tree| scope.spawn(move || tree.inner_instructions(0).count()))
+//!         .collect();
+//!     handles.into_iter().map(|x| x.join().unwrap()).collect()
+//! });
+//! assert_eq!(inner_instructions, [1, 1, 1, 1]);
+//! ```
+
+use std::iter::Peekable;
+use std::rc::Rc;
+
+use substreams_solana::pb::sf::solana::r#type::v1 as pb;
+
+use crate::error::ParseError;
+use crate::instruction::{derive_stack_heights, get_flattened_instructions, resolve_accounts, return_data_of, InstructionStatus, LogAssociation, LogStack, StructuredInstruction, WrappedInstruction};
+use crate::log::Log;
+use crate::pubkey::PubkeyRef;
+
+#[derive(Debug)]
+pub struct StructuredInstructionNode<'a> {
+    pub instruction: WrappedInstruction<'a>,
+    program_id: PubkeyRef<'a>,
+    accounts: Vec<PubkeyRef<'a>>,
+    inner_instructions: Vec<u32>,
+    parent: Option<u32>,
+    logs: Option<Vec<Log<'a>>>,
+    log_line_indices: Option<Vec<usize>>,
+    log_range: Option<(usize, usize)>,
+    return_data: Option<Vec<u8>>,
+    stack_height: Option<u32>,
+    top_level_index: u32,
+    logs_truncated: bool,
+    log_mismatch: bool,
+    status: InstructionStatus,
+}
+
+impl<'a> StructuredInstructionNode<'a> {
+    pub fn program_id(&self) -> PubkeyRef<'a> { self.program_id }
+    pub fn program_id_index(&self) -> u32 { self.instruction.program_id_index() }
+    pub fn accounts(&self) -> &Vec<PubkeyRef<'a>> { &self.accounts }
+    pub fn data(&self) -> &Vec<u8> { self.instruction.data() }
+    /// See `StructuredInstruction::stack_height`
+    pub fn stack_height(&self) -> Option<u32> { self.stack_height }
+    /// Indices of the inner instructions in the tree, in execution order
+    pub fn inner_instructions(&self) -> &[u32] { &self.inner_instructions }
+    /// Index of the invoking instruction in the tree
+    pub fn parent(&self) -> Option<u32> { self.parent }
+    pub fn logs(&self) -> Option<&Vec<Log<'a>>> { self.logs.as_ref() }
+    /// See `StructuredInstruction::log_line_indices`
+    pub fn log_line_indices(&self) -> Option<&Vec<usize>> { self.log_line_indices.as_ref() }
+    /// See `StructuredInstruction::raw_log_range`
+    pub fn raw_log_range(&self) -> Option<(usize, usize)> { self.log_range }
+    /// See `StructuredInstruction::return_data`
+    pub fn return_data(&self) -> Option<&[u8]> { self.return_data.as_deref() }
+    /// See `StructuredInstruction::top_level_index`
+    pub fn top_level_index(&self) -> u32 { self.top_level_index }
+    /// See `StructuredInstruction::logs_truncated`
+    pub fn logs_truncated(&self) -> bool { self.logs_truncated }
+    /// See `StructuredInstruction::log_mismatch`
+    pub fn log_mismatch(&self) -> bool { self.log_mismatch }
+    /// See `StructuredInstruction::status`
+    pub fn status(&self) -> &InstructionStatus { &self.status }
+}
+
+/// Instructions of a transaction in execution order, inner instructions
+/// included: the index of an instruction is its position in the flattened
+/// instructions.
+#[derive(Debug)]
+pub struct InstructionTree<'a> {
+    nodes: Vec<StructuredInstructionNode<'a>>,
+    roots: Vec<u32>,
+}
+
+impl<'a> InstructionTree<'a> {
+    /// Builds the tree of the flattened instructions, attaching their logs the
+    /// same way as `structure_flattened_instructions`
+    pub fn from_flattened<I>(
+        flattened_instructions: Vec<WrappedInstruction<'a>>,
+        logs: &mut Peekable<I>,
+        accounts: Vec<&'a Vec<u8>>,
+        association: LogAssociation,
+    ) -> Result<Self, ParseError>
+    where
+        I: Iterator<Item = Log<'a>>
+    {
+        if flattened_instructions.iter().all(|x| x.stack_height().is_some()) {
+            let stack_heights = flattened_instructions.iter().map(|x| x.stack_height()).collect();
+            return Self::from_flattened_with_stack_heights(flattened_instructions, logs, accounts, association, stack_heights);
+        }
+        let logs: Vec<Log<'a>> = logs.collect();
+        let stack_heights = derive_stack_heights(&flattened_instructions, &accounts, &logs);
+        Self::from_flattened_with_stack_heights(flattened_instructions, &mut logs.into_iter().peekable(), accounts, association, stack_heights)
+    }
+
+    fn from_flattened_with_stack_heights<I>(
+        flattened_instructions: Vec<WrappedInstruction<'a>>,
+        logs: &mut Peekable<I>,
+        accounts: Vec<&'a Vec<u8>>,
+        association: LogAssociation,
+        stack_heights: Vec<Option<u32>>,
+    ) -> Result<Self, ParseError>
+    where
+        I: Iterator<Item = Log<'a>>
+    {
+        let mut nodes: Vec<StructuredInstructionNode<'a>> = Vec::with_capacity(flattened_instructions.len());
+        let mut roots = Vec::new();
+        let mut instruction_stack: Vec<u32> = Vec::new();
+        let mut log_stack = LogStack::with_association(association);
+
+        let mut compiled_instructions: u32 = 0;
+        for (instruction, stack_height) in flattened_instructions.into_iter().zip(stack_heights) {
+            if let WrappedInstruction::Compiled(_) = instruction {
+                compiled_instructions += 1;
+            }
+            let (program_id, instruction_accounts) = resolve_accounts(&instruction, &accounts)?;
+            let mut node = StructuredInstructionNode {
+                instruction,
+                program_id,
+                accounts: instruction_accounts,
+                inner_instructions: Vec::new(),
+                parent: None,
+                logs: None,
+                log_line_indices: None,
+                log_range: None,
+                return_data: None,
+                stack_height,
+                top_level_index: compiled_instructions.saturating_sub(1),
+                logs_truncated: false,
+                log_mismatch: false,
+                status: InstructionStatus::Unknown,
+            };
+
+            while let Some(&top) = instruction_stack.last() {
+                if nodes[top as usize].stack_height() < node.stack_height() {
+                    break;
+                }
+                instruction_stack.pop();
+                close(&mut nodes[top as usize], logs, &mut log_stack, association)?;
+            }
+
+            let index = nodes.len() as u32;
+            match instruction_stack.last() {
+                Some(&parent) => {
+                    node.parent = Some(parent);
+                    nodes[parent as usize].inner_instructions.push(index);
+                },
+                None => roots.push(index),
+            }
+            log_stack.open(logs, node.program_id).or_else(|error| log_stack.recover(error, association))?;
+            nodes.push(node);
+            instruction_stack.push(index);
+        }
+
+        while let Some(top) = instruction_stack.pop() {
+            close(&mut nodes[top as usize], logs, &mut log_stack, association)?;
+        }
+
+        Ok(Self { nodes, roots })
+    }
+
+    pub fn get(&self, index: u32) -> Option<&StructuredInstructionNode<'a>> {
+        self.nodes.get(index as usize)
+    }
+
+    /// Indices of the top level instructions
+    pub fn roots(&self) -> &[u32] {
+        &self.roots
+    }
+
+    /// Every instruction in execution order, the same order as
+    /// `StructuredInstructions::flattened`
+    pub fn flattened(&self) -> &[StructuredInstructionNode<'a>] {
+        &self.nodes
+    }
+
+    pub fn inner_instructions(&self, index: u32) -> impl Iterator<Item = &StructuredInstructionNode<'a>> {
+        self.nodes[index as usize].inner_instructions.iter().map(|x| &self.nodes[*x as usize])
+    }
+
+    pub fn parent(&self, index: u32) -> Option<&StructuredInstructionNode<'a>> {
+        self.nodes[index as usize].parent.map(|x| &self.nodes[x as usize])
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl<'a> From<&Vec<Rc<StructuredInstruction<'a>>>> for InstructionTree<'a> {
+    /// Finalizes the instructions into a tree, keeping their logs
+    fn from(instructions: &Vec<Rc<StructuredInstruction<'a>>>) -> Self {
+        let mut nodes = Vec::new();
+        let roots = instructions.iter().map(|x| push_node(&mut nodes, x, None)).collect();
+        Self { nodes, roots }
+    }
+}
+
+/// Pushes `instruction` and its inner instructions, depth first, and returns its index
+fn push_node<'a>(nodes: &mut Vec<StructuredInstructionNode<'a>>, instruction: &StructuredInstruction<'a>, parent: Option<u32>) -> u32 {
+    let index = nodes.len() as u32;
+    nodes.push(StructuredInstructionNode {
+        instruction: instruction.instruction,
+        program_id: instruction.program_id(),
+        accounts: instruction.accounts().clone(),
+        inner_instructions: Vec::new(),
+        parent,
+        logs: instruction.logs().clone(),
+        log_line_indices: instruction.log_line_indices().clone(),
+        log_range: instruction.raw_log_range(),
+        return_data: instruction.return_data().map(<[u8]>::to_vec),
+        stack_height: instruction.stack_height(),
+        top_level_index: instruction.top_level_index(),
+        logs_truncated: instruction.logs_truncated(),
+        log_mismatch: instruction.log_mismatch(),
+        status: instruction.status(),
+    });
+    let inner_instructions = instruction.inner_instructions().iter().map(|x| push_node(nodes, x, Some(index))).collect();
+    nodes[index as usize].inner_instructions = inner_instructions;
+    index
+}
+
+fn close<'a, I>(node: &mut StructuredInstructionNode<'a>, logs: &mut Peekable<I>, log_stack: &mut LogStack<'a>, association: LogAssociation) -> Result<(), ParseError>
+where
+    I: Iterator<Item = Log<'a>>
+{
+    let frame = log_stack.close(logs, node.program_id).or_else(|error| log_stack.recover(error, association))?;
+    match frame {
+        Some(frame) => {
+            node.return_data = return_data_of(&frame.logs, node.program_id);
+            node.status = InstructionStatus::of(&frame.logs);
+            node.logs = Some(frame.logs);
+            node.log_line_indices = Some(frame.line_indices);
+            node.log_range = frame.range;
+        },
+        None => {
+            node.logs_truncated = log_stack.truncated_at().is_some();
+            node.log_mismatch = log_stack.mismatch_at().is_some();
+            node.log_range = log_stack.truncated_range();
+        },
+    }
+    Ok(())
+}
+
+/// Arena-backed counterpart of `get_structured_instructions`
+pub fn get_instruction_tree<'a>(transaction: &'a pb::ConfirmedTransaction) -> Result<InstructionTree<'a>, ParseError> {
+    let meta = transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?;
+    if meta.err.is_some() {
+        return Err(ParseError::FailedTransaction);
+    }
+    let logs = meta.log_messages.iter().map(|log| Log::new(log));
+    let mut tree = InstructionTree::from_flattened(get_flattened_instructions(transaction)?, &mut logs.peekable(), transaction.resolved_accounts(), LogAssociation::Strict)?;
+    if let Some(return_data) = meta.return_data.as_ref() {
+        if let Some(node) = tree.nodes.iter_mut().rev().find(|x| x.program_id.0[..] == return_data.program_id[..]) {
+            node.return_data.get_or_insert_with(|| return_data.data.clone());
+        }
+    }
+    Ok(tree)
+}
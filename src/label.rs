@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use anyhow::{anyhow, Error};
+
+use crate::pubkey::{Pubkey, PubkeyRef};
+use crate::programs::KnownProgram;
+use crate::spl_token::WRAPPED_SOL_MINT;
+
+/// Human readable name attached to a known address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub label: String,
+    pub category: String,
+}
+
+/// Registry of address labels, keyed by the raw 32-byte public key.
+///
+/// `LabelRegistry::default()` comes with the programs listed in `KnownProgram`;
+/// anything inserted or loaded afterwards overrides those built-ins.
+#[derive(Clone, Debug)]
+pub struct LabelRegistry {
+    labels: HashMap<Pubkey, Label>,
+}
+
+impl LabelRegistry {
+    /// Creates an empty registry, without the built-in labels
+    pub fn new() -> Self {
+        Self { labels: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, address: Pubkey, label: &str, category: &str) {
+        self.labels.insert(address, Label { label: label.to_string(), category: category.to_string() });
+    }
+
+    pub fn lookup(&self, address: &Pubkey) -> Option<&Label> {
+        self.labels.get(address)
+    }
+
+    pub fn lookup_ref(&self, address: &PubkeyRef) -> Option<&Label> {
+        self.lookup(&address.to_pubkey().ok()?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Merges `other` into this registry, with `other` taking precedence
+    pub fn extend(&mut self, other: LabelRegistry) {
+        self.labels.extend(other.labels);
+    }
+
+    /// Formats an address for output, appending its label when one is known
+    pub fn annotate(&self, address: &Pubkey) -> String {
+        match self.lookup(address) {
+            Some(label) => format!("{} ({})", address.to_string(), label.label),
+            None => address.to_string(),
+        }
+    }
+
+    /// Loads `address,label,category` rows, overriding existing entries. Fields
+    /// are trimmed, lines starting with `#` are comments, and a first row whose
+    /// first field is `address` is a header.
+    pub fn load_csv(&mut self, input: &str) -> Result<(), Error> {
+        self.load_delimited(input, ',')
+    }
+
+    /// Loads tab separated `address label category` rows, overriding existing entries.
+    pub fn load_tsv(&mut self, input: &str) -> Result<(), Error> {
+        self.load_delimited(input, '\t')
+    }
+
+    pub fn from_csv(input: &str) -> Result<Self, Error> {
+        let mut registry = Self::new();
+        registry.load_csv(input)?;
+        Ok(registry)
+    }
+
+    pub fn from_tsv(input: &str) -> Result<Self, Error> {
+        let mut registry = Self::new();
+        registry.load_tsv(input)?;
+        Ok(registry)
+    }
+
+    fn load_delimited(&mut self, input: &str, delimiter: char) -> Result<(), Error> {
+        let mut first_row = true;
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = split_row(line, delimiter).map_err(|e| anyhow!("Malformed label row {}: {}", i + 1, e))?;
+            let fields: Vec<&str> = fields.iter().map(|x| x.trim()).collect();
+            // A header is only recognized as the first row, after any leading comments
+            if std::mem::take(&mut first_row) && fields[0].eq_ignore_ascii_case("address") {
+                continue;
+            }
+            if fields.len() != 3 {
+                return Err(anyhow!("Malformed label row {}: expected 3 fields, found {}", i + 1, fields.len()));
+            }
+            let address = Pubkey::try_from_string(fields[0]).map_err(|_| anyhow!("Malformed label row {}: invalid address", i + 1))?;
+            self.insert(address, fields[1], fields[2]);
+        }
+        Ok(())
+    }
+}
+
+impl Default for LabelRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        for program in KnownProgram::iter() {
+            registry.insert(program.program_id(), program.name(), "program");
+        }
+        registry.insert(WRAPPED_SOL_MINT, "Wrapped SOL", "mint");
+        registry
+    }
+}
+
+/// Splits a single row, honoring double quoted fields (`""` escapes a quote).
+fn split_row(line: &str, delimiter: char) -> Result<Vec<String>, &'static str> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+        } else if c == '"' && field.trim().is_empty() {
+            // Whitespace before an opening quote is trimmed with the field anyway
+            field.clear();
+            quoted = true;
+        } else if c == delimiter {
+            fields.push(std::mem::take(&mut field));
+        } else {
+            field.push(c);
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field");
+    }
+    fields.push(field);
+    Ok(fields)
+}
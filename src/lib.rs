@@ -1,9 +1,38 @@
 pub mod transaction;
+pub mod transaction_error;
 pub mod instruction;
+pub mod instruction_tree;
+pub mod owned_instruction;
+pub mod structured_transaction;
 pub mod account;
 pub mod log;
 pub mod pubkey;
 pub mod error;
+pub mod label;
+pub mod event;
+pub mod block;
+pub mod compat;
+pub mod programs;
+pub mod classify;
+pub mod payload;
+pub mod report;
+pub mod store_ops;
+pub mod extract;
+pub mod token_registry;
+pub mod detect;
+pub mod rent;
+pub mod render;
+#[cfg(feature = "proto")]
+pub mod pb;
+#[cfg(feature = "test-utils")]
+pub mod golden;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 
 pub mod spl_token;
 pub mod system_program;
+pub mod address_lookup_table;
+pub mod associated_token_account;
+pub mod bpf_loader;
+pub mod compute_budget;
+pub mod memo;
@@ -1,9 +1,47 @@
-#![allow(deprecated)]
+use std::sync::OnceLock;
+use std::collections::{BTreeMap, HashMap};
 
-use regex;
-use base64;
+use base64::alphabet;
+use base64::engine::{general_purpose, DecodePaddingMode, Engine, GeneralPurpose, GeneralPurposeConfig};
 
-#[derive(Debug)]
+use crate::error::{LogDecodeError, LogStructureError};
+// `build_structured_logs` drives the `LogStack` of the instruction structuring,
+// see `LogStack` for why it lives there
+use crate::instruction::LogStack;
+
+/// A log line, classified by its shape.
+///
+/// With the `serde` feature, a log serializes as an object tagged with its
+/// `type`, its fields parsed from the line:
+///
+/// | `type` | fields |
+/// |---|---|
+/// | `invoke` | `program_id`, `depth`, `metadata` if any |
+/// | `success` | `program_id`, `metadata` if any |
+/// | `return` | `program_id`, `data` in base64 |
+/// | `data` | `data` in base64 |
+/// | `program` | `message` |
+/// | `truncated` | |
+/// | `consumed` | `program_id`, null for the transaction total, `consumed`, `budget` |
+/// | `failed` | `program_id`, `error` |
+/// | `runtime` | `event`, an object tagged with its `kind`, see `RuntimeEvent` |
+/// | `unknown` | `log`, the whole line |
+///
+/// Lines that fail to parse as their kind, e.g. a `Program data` log that is
+/// not base64, serialize as `unknown`.
+///
+/// ```
+/// use substreams_solana_utils::log::Log;
+///
+/// let line = "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [2]".to_string();
+/// let json = serde_json::to_value(Log::new(&line)).unwrap();
+/// assert_eq!(json, serde_json::json!({
+///     "type": "invoke",
+///     "program_id": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+///     "depth": 2,
+/// }));
+/// ```
+#[derive(Clone, Debug)]
 pub enum Log<'a> {
     Invoke(InvokeLog<'a>), // "Program {} invoke [{}]",
     Success(SuccessLog<'a>), // Program {} success
@@ -11,26 +49,38 @@ pub enum Log<'a> {
     Data(DataLog<'a>), //  "Program data: {}"
     Program(ProgramLog<'a>), // "Program log: {}"
     Truncated(TruncatedLog<'a>), // "Log truncated"
+    Consumed(ConsumedLog<'a>), // "Program {} consumed {} of {} compute units", or "consumed {} of {} compute units"
+    Failed(FailedLog<'a>), // "Program {} failed: {}"
+    Runtime(RuntimeLog<'a>), // See RuntimeEvent
     Unknown(UnknownLog<'a>),
 }
 
 impl<'a> Log<'a> {
-    pub fn new(log: &'a String) -> Self {
+    pub fn new(log: &'a str) -> Self {
         if log.starts_with("Program log: ") {
             return Self::Program(ProgramLog::new(log));
         }
         if log.starts_with("Program data: ") {
             return Self::Data(DataLog::new(log));
         }
-        if log.starts_with("Program return: ") {
+        if parse_return(log).is_some() {
             return Self::Return(ReturnLog::new(log));
         }
-        if log.split_whitespace().count() == 4 && log.starts_with("Program") && log.split_whitespace().nth(2) == Some("invoke") {
+        if parse_invoke(log).is_some() {
             return Self::Invoke(InvokeLog::new(log));
         }
-        if log.split_whitespace().count() == 3 && log.starts_with("Program") && log.ends_with("success") {
+        if parse_success(log).is_some() {
             return Self::Success(SuccessLog::new(log))
         }
+        if parse_consumed(log).is_some() {
+            return Self::Consumed(ConsumedLog::new(log))
+        }
+        if parse_failed(log).is_some() {
+            return Self::Failed(FailedLog::new(log))
+        }
+        if parse_runtime(log).is_some() {
+            return Self::Runtime(RuntimeLog::new(log))
+        }
         if log == "Log truncated" {
             return Self::Truncated(TruncatedLog::new(log))
         }
@@ -60,163 +110,1319 @@ impl<'a> Log<'a> {
         matches!(self, Self::Truncated(_))
     }
 
+    pub fn is_consumed(&self) -> bool {
+        matches!(self, Self::Consumed(_))
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, Self::Failed(_))
+    }
+
+    pub fn is_runtime(&self) -> bool {
+        matches!(self, Self::Runtime(_))
+    }
+
+    /// Whether the log ends an invocation, successfully or not
+    pub fn is_terminator(&self) -> bool {
+        self.is_success() || self.is_failed()
+    }
+
     pub fn is_unknown(&self) -> bool {
         matches!(self, Self::Unknown(_))
     }
+
+    /// Program id the line was logged for: that of an invoke, success, return,
+    /// failed or consumed log, or of a runtime log reporting a program failing
+    /// to complete. Borrowed from the line.
+    pub fn program_id(&self) -> Option<&'a str> {
+        match self {
+            Self::Invoke(invoke_log) => parse_invoke(invoke_log.log).map(|(program_id, _, _)| program_id),
+            Self::Success(success_log) => parse_success(success_log.log).map(|(program_id, _)| program_id),
+            Self::Failed(failed_log) => parse_failed(failed_log.log).map(|(program_id, _)| program_id),
+            Self::Consumed(consumed_log) => parse_consumed(consumed_log.log).and_then(|(program_id, _, _)| program_id),
+            Self::Return(return_log) => parse_return(return_log.log).map(|(program_id, _)| program_id),
+            Self::Runtime(runtime_log) => match parse_runtime(runtime_log.log)? {
+                RuntimeEvent::FailedToComplete { program_id, .. } => program_id,
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> LogKind {
+        match self {
+            Self::Invoke(_) => LogKind::Invoke,
+            Self::Success(_) => LogKind::Success,
+            Self::Return(_) => LogKind::Return,
+            Self::Data(_) => LogKind::Data,
+            Self::Program(_) => LogKind::Program,
+            Self::Truncated(_) => LogKind::Truncated,
+            Self::Consumed(_) => LogKind::Consumed,
+            Self::Failed(_) => LogKind::Failed,
+            Self::Runtime(_) => LogKind::Runtime,
+            Self::Unknown(_) => LogKind::Unknown,
+        }
+    }
+
+    pub fn as_invoke(&self) -> Option<&InvokeLog<'a>> {
+        match self {
+            Self::Invoke(invoke_log) => Some(invoke_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_success(&self) -> Option<&SuccessLog<'a>> {
+        match self {
+            Self::Success(success_log) => Some(success_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_return(&self) -> Option<&ReturnLog<'a>> {
+        match self {
+            Self::Return(return_log) => Some(return_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_data(&self) -> Option<&DataLog<'a>> {
+        match self {
+            Self::Data(data_log) => Some(data_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_program(&self) -> Option<&ProgramLog<'a>> {
+        match self {
+            Self::Program(program_log) => Some(program_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_truncated(&self) -> Option<&TruncatedLog<'a>> {
+        match self {
+            Self::Truncated(truncated_log) => Some(truncated_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_consumed(&self) -> Option<&ConsumedLog<'a>> {
+        match self {
+            Self::Consumed(consumed_log) => Some(consumed_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_failed(&self) -> Option<&FailedLog<'a>> {
+        match self {
+            Self::Failed(failed_log) => Some(failed_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_runtime(&self) -> Option<&RuntimeLog<'a>> {
+        match self {
+            Self::Runtime(runtime_log) => Some(runtime_log),
+            _ => None,
+        }
+    }
+
+    pub fn as_unknown(&self) -> Option<&UnknownLog<'a>> {
+        match self {
+            Self::Unknown(unknown_log) => Some(unknown_log),
+            _ => None,
+        }
+    }
+
+    /// The original log line, byte for byte
+    pub fn raw(&self) -> &'a str {
+        match self {
+            Self::Data(data_log) => data_log.log,
+            Self::Invoke(invoke_log) => invoke_log.log,
+            Self::Program(program_log) => program_log.log,
+            Self::Return(return_log) => return_log.log,
+            Self::Success(success_log) => success_log.log,
+            Self::Truncated(truncated_log) => truncated_log.log,
+            Self::Consumed(consumed_log) => consumed_log.log,
+            Self::Failed(failed_log) => failed_log.log,
+            Self::Runtime(runtime_log) => runtime_log.log,
+            Self::Unknown(unknown_log) => unknown_log.log,
+        }
+    }
+}
+
+/// Kind of a `Log`, without its line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "snake_case"))]
+pub enum LogKind {
+    Invoke,
+    Success,
+    Return,
+    Data,
+    Program,
+    Truncated,
+    Consumed,
+    Failed,
+    Runtime,
+    Unknown,
 }
 
 impl<'a> std::fmt::Display for Log<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Data(data_log) => write!(f, "{}", data_log.log),
-            Self::Invoke(invoke_log) => write!(f, "{}", invoke_log.log),
-            Self::Program(program_log) => write!(f, "{}", program_log.log),
-            Self::Return(return_log) => write!(f, "{}", return_log.log),
-            Self::Success(success_log) => write!(f, "{}", success_log.log),
-            Self::Truncated(truncated_log) => write!(f, "{}", truncated_log.log),
-            Self::Unknown(unknown_log) => write!(f, "{}", unknown_log.log),
-        }
+        write!(f, "{}", self.raw())
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ProgramLog<'a> {
-    log: &'a String,
+    log: &'a str,
 }
 
 impl<'a> ProgramLog<'a> {
-    fn new(log: &'a String) -> Self {
+    fn new(log: &'a str) -> Self {
         Self { log }
     }
-    pub fn message(&self) -> Result<String, String> {
-        let re = regex::Regex::new(r"Program log: (.+)").unwrap();
-        if let Some(captures) = re.captures(self.log) {
-            let message = captures.get(1).unwrap().as_str().to_string();
-            Ok(message)
-        } else {
-            Err("Error parsing ProgramLog.".into())
+    pub fn message(&self) -> Result<&'a str, String> {
+        match payload(self.log, "Program log: ") {
+            Some(message) => Ok(message),
+            None => Err("Error parsing ProgramLog.".into()),
+        }
+    }
+    /// The message parsed as JSON, when it is a JSON object or array. `None`
+    /// for any other message, malformed JSON included.
+    #[cfg(feature = "json")]
+    pub fn as_json(&self) -> Option<serde_json::Value> {
+        let message = self.message().ok()?.trim();
+        if !message.starts_with('{') && !message.starts_with('[') {
+            return None;
         }
+        serde_json::from_str(message).ok()
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct InvokeLog<'a> {
-    log: &'a String,
+    log: &'a str,
 }
 
 impl<'a> InvokeLog<'a> {
-    pub fn new(log: &'a String) -> Self {
+    pub fn new(log: &'a str) -> Self {
         Self { log }
     }
-    pub fn program_id(&self) -> Result<String, String> {
-        let re = regex::Regex::new(r"Program (.+) invoke \[(\d+)\]").unwrap();
-        if let Some(captures) = re.captures(self.log) {
-            let program_id = captures.get(1).unwrap().as_str().to_string();
-            Ok(program_id)
-        } else {
-            Err("Error parsing InvokeLog.".into())
+    pub fn program_id(&self) -> Result<&'a str, String> {
+        match parse_invoke(self.log) {
+            Some((program_id, _, _)) => Ok(program_id),
+            None => Err("Error parsing InvokeLog.".into()),
         }
     }
 
     pub fn invoke_depth(&self) -> Result<u32, String> {
-        let re = regex::Regex::new(r"Program (.+) invoke \[(\d+)\]").unwrap();
-        if let Some(captures) = re.captures(self.log) {
-            let invoke_depth = captures.get(2).unwrap().as_str().parse::<u32>().unwrap();
-            Ok(invoke_depth)
-        } else {
-            Err("Error parsing InvokeLog.".into())
+        match parse_invoke(self.log) {
+            Some((_, invoke_depth, _)) => Ok(invoke_depth),
+            None => Err("Error parsing InvokeLog.".into()),
         }
     }
+
+    /// Bracketed segments following the invoke depth, see `LogMetadata`
+    pub fn metadata(&self) -> LogMetadata<'a> {
+        parse_invoke(self.log).map(|(_, _, metadata)| metadata).unwrap_or_default()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SuccessLog<'a> {
-    pub log: &'a String,
+    pub log: &'a str,
 }
 
 impl<'a> SuccessLog<'a> {
-    pub fn new(log: &'a String) -> Self {
+    pub fn new(log: &'a str) -> Self {
         Self { log }
     }
-    pub fn program_id(&self) -> Result<String, String> {
-        let re = regex::Regex::new(r"Program (.+) success").unwrap();
-        if let Some(captures) = re.captures(self.log) {
-            let program_id = captures.get(1).unwrap().as_str().to_string();
-            Ok(program_id)
-        } else {
-            Err("Error parsing SuccessLog.".into())
+    pub fn program_id(&self) -> Result<&'a str, String> {
+        match parse_success(self.log) {
+            Some((program_id, _)) => Ok(program_id),
+            None => Err("Error parsing SuccessLog.".into()),
         }
     }
+
+    /// Bracketed segments following `success`, see `LogMetadata`
+    pub fn metadata(&self) -> LogMetadata<'a> {
+        parse_success(self.log).map(|(_, metadata)| metadata).unwrap_or_default()
+    }
+}
+
+/// Bracketed segments trailing an invoke or success log, e.g. `[units=1200]`
+/// in `Program <id> success [units=1200]`. Validators only emitting the
+/// historical log shapes produce no metadata.
+///
+/// A `[key=value]` or `[key: value]` segment maps `key` to `value`. Any other
+/// segment is kept whole as a key with an empty value.
+pub type LogMetadata<'a> = BTreeMap<&'a str, &'a str>;
+
+/// Shape of the invoke and success logs of a transaction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogDialect {
+    /// `Program <id> invoke [<depth>]` and `Program <id> success` only
+    #[default]
+    Legacy,
+    /// Invoke or success logs carrying trailing metadata segments
+    Extended,
+}
+
+impl LogDialect {
+    pub fn of(log: &Log) -> Self {
+        let has_metadata = match log {
+            Log::Invoke(invoke_log) => !invoke_log.metadata().is_empty(),
+            Log::Success(success_log) => !success_log.metadata().is_empty(),
+            _ => false,
+        };
+        if has_metadata { Self::Extended } else { Self::Legacy }
+    }
+}
+
+/// Parses `Program <id> invoke [<depth>]`, followed by optional metadata segments
+fn parse_invoke(log: &str) -> Option<(&str, u32, LogMetadata<'_>)> {
+    let (program_id, rest) = log.strip_prefix("Program ")?.split_once(' ')?;
+    let rest = rest.strip_prefix("invoke [")?;
+    let (depth, rest) = rest.split_once(']')?;
+    if !is_program_id(program_id) || depth.is_empty() || !depth.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    Some((program_id, depth.parse().ok()?, parse_metadata(rest)?))
+}
+
+/// Parses `Program <id> success`, followed by optional metadata segments
+fn parse_success(log: &str) -> Option<(&str, LogMetadata<'_>)> {
+    let (program_id, rest) = log.strip_prefix("Program ")?.split_once(' ')?;
+    let rest = rest.strip_prefix("success")?;
+    if !is_program_id(program_id) {
+        return None;
+    }
+    Some((program_id, parse_metadata(rest)?))
 }
 
-#[derive(Debug)]
+fn is_program_id(program_id: &str) -> bool {
+    let mut decoded = [0u8; 32];
+    matches!(bs58::decode(program_id).onto(&mut decoded), Ok(32))
+}
+
+/// Parses a possibly empty sequence of ` [segment]`. Returns `None` for anything else.
+fn parse_metadata(mut rest: &str) -> Option<LogMetadata<'_>> {
+    let mut metadata = LogMetadata::new();
+    while !rest.is_empty() {
+        let (segment, tail) = rest.strip_prefix(" [")?.split_once(']')?;
+        let (key, value) = segment.split_once('=')
+            .or_else(|| segment.split_once(": "))
+            .unwrap_or((segment, ""));
+        metadata.insert(key, value);
+        rest = tail;
+    }
+    Some(metadata)
+}
+
+#[derive(Clone, Debug)]
 pub struct ReturnLog<'a> {
-    pub log: &'a String,
+    pub log: &'a str,
 }
 
 impl<'a> ReturnLog<'a> {
-    pub fn new(log: &'a String) -> Self {
+    pub fn new(log: &'a str) -> Self {
         Self { log }
     }
-    pub fn program_id(&self) -> Result<String, String> {
-        let re = regex::Regex::new(r"Program return: (.+) (.+)").unwrap();
-        if let Some(captures) = re.captures(self.log) {
-            let program_id = captures.get(1).unwrap().as_str().to_string();
-            Ok(program_id)
-        } else {
-            Err("Error parsing ReturnLog.".into())
+    pub fn program_id(&self) -> Result<&'a str, String> {
+        match parse_return(self.log) {
+            Some((program_id, _)) => Ok(program_id),
+            None => Err("Error parsing ReturnLog.".into()),
         }
     }
 
-    pub fn data(&self) -> Result<Vec<u8>, String> {
-        let re = regex::Regex::new(r"Program return: (.+) (.+)").unwrap();
-        if let Some(captures) = re.captures(self.log) {
-            let encoded_data = captures.get(2).unwrap().as_str();
-            base64::decode(encoded_data).map_err(|_| String::from("Base64 decoding error."))
-        } else {
-            Err("Error parsing ReturnLog.".into())
+    /// The base64 payload as logged, to decode with another configuration
+    /// when `data` fails
+    pub fn encoded_data(&self) -> Result<&'a str, String> {
+        match parse_return(self.log) {
+            Some((_, encoded_data)) => Ok(encoded_data),
+            None => Err("Error parsing ReturnLog.".into()),
         }
     }
+
+    /// Return data, decoded with `RETURN_DATA_ENGINE`
+    pub fn decode(&self) -> Result<Vec<u8>, LogDecodeError> {
+        let (_, encoded_data) = parse_return(self.log).ok_or(LogDecodeError::Malformed { line_index: None })?;
+        decode_payload(&RETURN_DATA_ENGINE, encoded_data)
+    }
+
+    pub fn data(&self) -> Result<Vec<u8>, String> {
+        self.decode().map_err(|error| error.to_string())
+    }
+}
+
+/// Engine of the `Program return` payloads: standard alphabet, padding
+/// optional, non-zero trailing bits rejected
+pub const RETURN_DATA_ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Engine of the `Program data` payloads: standard alphabet, canonical padding
+/// as logged by the runtime, non-zero trailing bits rejected
+pub const DATA_ENGINE: GeneralPurpose = general_purpose::STANDARD;
+
+fn decode_payload(engine: &GeneralPurpose, encoded_data: &str) -> Result<Vec<u8>, LogDecodeError> {
+    engine.decode(encoded_data).map_err(|source| LogDecodeError::Base64 { line_index: None, source })
+}
+
+/// Parses `Program return: <id> <data>`. The program id ends at the first
+/// space, and the rest of the line is the base64 data, decoded lazily.
+fn parse_return(log: &str) -> Option<(&str, &str)> {
+    let (program_id, data) = payload(log, "Program return: ")?.split_once(' ')?;
+    if !is_program_id(program_id) || data.is_empty() {
+        return None;
+    }
+    Some((program_id, data))
 }
 
-#[derive(Debug)]
+/// A `Program data` log. Its payload is decoded on first access only, so that
+/// the events of programs a caller filters out are never decoded.
+#[derive(Clone, Debug)]
 pub struct DataLog<'a> {
-    pub log: &'a String,
+    pub log: &'a str,
+    decoded: OnceLock<Result<Vec<u8>, LogDecodeError>>,
 }
 
 impl<'a> DataLog<'a> {
-    pub fn new(log: &'a String) -> Self {
-        Self { log }
+    pub fn new(log: &'a str) -> Self {
+        Self { log, decoded: OnceLock::new() }
+    }
+    /// The base64 payload as logged, to decode with another configuration
+    /// when `data` fails
+    pub fn encoded_data(&self) -> Result<&'a str, String> {
+        match payload(self.log, "Program data: ") {
+            Some(encoded_data) => Ok(encoded_data),
+            None => Err("Error parsing DataLog.".into()),
+        }
     }
+
+    /// The payload decoded with `DATA_ENGINE`, borrowed from the log
+    ///
+    /// ```
+    /// use substreams_solana_utils::log::Log;
+    ///
+    /// let line = "Program data: AQID".to_string();
+    /// let log = Log::new(&line);
+    /// let data_log = log.as_data().unwrap();
+    /// assert_eq!(data_log.bytes(), Ok(&[1, 2, 3][..]));
+    /// assert!(std::ptr::eq(data_log.bytes().unwrap(), data_log.bytes().unwrap()));
+    /// ```
+    pub fn bytes(&self) -> Result<&[u8], LogDecodeError> {
+        let decoded = self.decoded.get_or_init(|| match payload(self.log, "Program data: ") {
+            Some(encoded_data) => decode_payload(&DATA_ENGINE, encoded_data),
+            None => Err(LogDecodeError::Malformed { line_index: None }),
+        });
+        decoded.as_ref().map(Vec::as_slice).map_err(Clone::clone)
+    }
+
     pub fn data(&self) -> Result<Vec<u8>, String> {
-        let re = regex::Regex::new(r"Program data: (.+)").unwrap();
-        if let Some(captures) = re.captures(self.log) {
-            let encoded_data = captures.get(1).unwrap().as_str();
-            base64::decode(encoded_data).map_err(|_| String::from("Base64 decoding error."))
-        } else {
-            Err("Error parsing DataLog.".into())
+        self.bytes().map(<[u8]>::to_vec).map_err(|error| error.to_string())
+    }
+}
+
+/// The non-empty first line of `log` after `prefix`
+fn payload<'l>(log: &'l str, prefix: &str) -> Option<&'l str> {
+    let rest = log.strip_prefix(prefix)?;
+    let line = rest.split('\n').next().unwrap_or_default();
+    if line.is_empty() { None } else { Some(line) }
+}
+
+/// Compute units consumed by an invocation, or by the whole transaction on
+/// clusters logging a total without a program id
+#[derive(Clone, Debug)]
+pub struct ConsumedLog<'a> {
+    pub log: &'a str,
+}
+
+impl<'a> ConsumedLog<'a> {
+    pub fn new(log: &'a str) -> Self {
+        Self { log }
+    }
+    /// `None` for the transaction total
+    pub fn program_id(&self) -> Result<Option<&'a str>, String> {
+        match parse_consumed(self.log) {
+            Some((program_id, _, _)) => Ok(program_id),
+            None => Err("Error parsing ConsumedLog.".into()),
+        }
+    }
+
+    /// Compute units consumed by the invocation, inner invocations included
+    pub fn consumed(&self) -> Result<u64, String> {
+        match parse_consumed(self.log) {
+            Some((_, consumed, _)) => Ok(consumed),
+            None => Err("Error parsing ConsumedLog.".into()),
+        }
+    }
+
+    /// Compute units the invocation was allowed to consume
+    pub fn budget(&self) -> Result<u64, String> {
+        match parse_consumed(self.log) {
+            Some((_, _, budget)) => Ok(budget),
+            None => Err("Error parsing ConsumedLog.".into()),
+        }
+    }
+}
+
+/// Parses `Program <id> consumed <consumed> of <budget> compute units`, or the
+/// same without `Program <id> ` for the transaction total
+fn parse_consumed(log: &str) -> Option<(Option<&str>, u64, u64)> {
+    let (program_id, rest) = match log.strip_prefix("Program ") {
+        Some(rest) => {
+            let (program_id, rest) = rest.split_once(" consumed ")?;
+            if !is_program_id(program_id) {
+                return None;
+            }
+            (Some(program_id), rest)
+        },
+        None => (None, log.strip_prefix("consumed ")?),
+    };
+    let (consumed, rest) = rest.split_once(" of ")?;
+    let budget = rest.strip_suffix(" compute units")?;
+    Some((program_id, consumed.parse().ok()?, budget.parse().ok()?))
+}
+
+#[derive(Clone, Debug)]
+pub struct FailedLog<'a> {
+    pub log: &'a str,
+}
+
+impl<'a> FailedLog<'a> {
+    pub fn new(log: &'a str) -> Self {
+        Self { log }
+    }
+    pub fn program_id(&self) -> Result<&'a str, String> {
+        match parse_failed(self.log) {
+            Some((program_id, _)) => Ok(program_id),
+            None => Err("Error parsing FailedLog.".into()),
+        }
+    }
+
+    /// Error the invocation failed with, e.g. `custom program error: 0x1`
+    pub fn error(&self) -> Result<&'a str, String> {
+        match parse_failed(self.log) {
+            Some((_, error)) => Ok(error),
+            None => Err("Error parsing FailedLog.".into()),
+        }
+    }
+}
+
+impl FailedLog<'_> {
+    /// Code of a `custom program error: <code>` error
+    pub fn custom_error_code(&self) -> Option<u32> {
+        match self.program_error()? {
+            ProgramError::Custom(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    pub fn program_error(&self) -> Option<ProgramError> {
+        parse_failed(self.log).map(|(_, error)| ProgramError::parse(error))
+    }
+}
+
+/// Error of a failed invocation, parsed from its `failed` log.
+///
+/// ```
+/// use substreams_solana_utils::log::ProgramError;
+///
+/// // Anchor error codes start at 6000
+/// for code in 0x1770..0x1780 {
+///     let error = ProgramError::parse(&format!("custom program error: {:#x}", code));
+///     assert_eq!(error, ProgramError::Custom(code));
+///     assert_eq!(ProgramError::parse(&error.to_string()), error);
+///     assert_eq!(ProgramError::parse(&format!("custom program error: {}", code)), error);
+/// }
+/// assert_eq!(ProgramError::parse("insufficient funds for instruction"), ProgramError::InsufficientFunds);
+/// assert_eq!(ProgramError::parse("invalid instruction data"), ProgramError::InvalidInstructionData);
+/// assert_eq!(ProgramError::parse("Slippage tolerance exceeded"), ProgramError::Other("Slippage tolerance exceeded".into()));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgramError {
+    /// `custom program error: 0x1771`, the code in hex or decimal
+    Custom(u32),
+    InsufficientFunds,
+    InvalidInstructionData,
+    InvalidAccountData,
+    AccountDataTooSmall,
+    MissingRequiredSignature,
+    AccountAlreadyInitialized,
+    UninitializedAccount,
+    IncorrectProgramId,
+    NotEnoughAccountKeys,
+    ComputationalBudgetExceeded,
+    ProgramFailedToComplete,
+    /// Any other error, as logged
+    Other(String),
+}
+
+/// Messages of the runtime instruction errors, as logged
+const PROGRAM_ERROR_MESSAGES: &[(&str, ProgramError)] = &[
+    ("insufficient funds for instruction", ProgramError::InsufficientFunds),
+    ("invalid instruction data", ProgramError::InvalidInstructionData),
+    ("invalid account data for instruction", ProgramError::InvalidAccountData),
+    ("account data too small for instruction", ProgramError::AccountDataTooSmall),
+    ("missing required signature for instruction", ProgramError::MissingRequiredSignature),
+    ("instruction requires an uninitialized account", ProgramError::AccountAlreadyInitialized),
+    ("instruction requires an initialized account", ProgramError::UninitializedAccount),
+    ("incorrect program id for instruction", ProgramError::IncorrectProgramId),
+    ("insufficient account keys for instruction", ProgramError::NotEnoughAccountKeys),
+    ("Computational budget exceeded", ProgramError::ComputationalBudgetExceeded),
+    ("Program failed to complete", ProgramError::ProgramFailedToComplete),
+];
+
+impl ProgramError {
+    /// Parses the error of a `failed` log, the part after `failed: `
+    pub fn parse(error: &str) -> Self {
+        if let Some(code) = error.strip_prefix("custom program error: ") {
+            let code = match code.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => code.parse().ok(),
+            };
+            if let Some(code) = code {
+                return Self::Custom(code);
+            }
+        }
+        let known = PROGRAM_ERROR_MESSAGES.iter().find(|(message, _)| *message == error);
+        match known {
+            Some((_, program_error)) => program_error.clone(),
+            None if error.starts_with("insufficient funds") => Self::InsufficientFunds,
+            None => Self::Other(error.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Custom(code) => write!(f, "custom program error: {:#x}", code),
+            Self::Other(error) => f.write_str(error),
+            known => {
+                let message = PROGRAM_ERROR_MESSAGES.iter().find(|(_, x)| x == known).map(|(message, _)| *message).unwrap_or_default();
+                f.write_str(message)
+            },
+        }
+    }
+}
+
+/// Error of a failed transaction, from the failed log of the invocation it
+/// originated in. The `meta.err` of the transaction only encodes the index of
+/// the failing top level instruction and the error, not the failing program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionLogError {
+    pub program_id: String,
+    /// Code of a `custom program error`, logged in hex or decimal
+    pub code: Option<u32>,
+    /// The error as logged, e.g. `custom program error: 0x1771`
+    pub message: String,
+}
+
+impl TransactionLogError {
+    fn from_failed_log(failed_log: &FailedLog) -> Option<Self> {
+        let (program_id, message) = parse_failed(failed_log.log)?;
+        Some(Self {
+            program_id: program_id.to_string(),
+            code: failed_log.custom_error_code(),
+            message: message.to_string(),
+        })
+    }
+
+    pub fn program_error(&self) -> ProgramError {
+        ProgramError::parse(&self.message)
+    }
+
+    /// The custom error code, which Anchor programs log as the error number
+    /// unchanged: its own errors below 6000, and the `#[error_code]` errors of
+    /// the program from 6000 (`0x1770`) on, as numbered in the IDL `errors`.
+    ///
+    /// ```
+    /// use substreams_solana_utils::log::extract_error;
+    ///
+    /// let log_messages = vec![
+    ///     "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [1]".to_string(),
+    ///     "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc failed: custom program error: 0x1794".to_string(),
+    /// ];
+    /// let error = extract_error(&log_messages).unwrap();
+    /// // AmountOutBelowMinimum in the Whirlpool IDL
+    /// assert_eq!(error.anchor_error_number(), Some(6036));
+    /// ```
+    pub fn anchor_error_number(&self) -> Option<u32> {
+        self.code
+    }
+}
+
+/// Error of a failed transaction, from its first failed log: invoking programs
+/// fail in turn with the same error, after the program it originated in.
+pub fn extract_error<S: AsRef<str>>(log_messages: &[S]) -> Option<TransactionLogError> {
+    log_messages.iter().find_map(|log| match Log::new(log.as_ref()) {
+        Log::Failed(failed_log) => TransactionLogError::from_failed_log(&failed_log),
+        _ => None,
+    })
+}
+
+/// Parses `Program <id> failed: <error>`
+fn parse_failed(log: &str) -> Option<(&str, &str)> {
+    let (program_id, error) = log.strip_prefix("Program ")?.split_once(" failed: ")?;
+    if !is_program_id(program_id) {
+        return None;
+    }
+    Some((program_id, error))
+}
+
+/// Notice or diagnostic of the runtime, a program loader or the system program.
+/// None of them opens or closes an invocation.
+#[derive(Clone, Debug)]
+pub struct RuntimeLog<'a> {
+    pub log: &'a str,
+}
+
+impl<'a> RuntimeLog<'a> {
+    pub fn new(log: &'a str) -> Self {
+        Self { log }
+    }
+    pub fn event(&self) -> Result<RuntimeEvent<'a>, String> {
+        match parse_runtime(self.log) {
+            Some(event) => Ok(event),
+            None => Err("Error parsing RuntimeLog.".into()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(tag = "kind", rename_all = "snake_case"))]
+pub enum RuntimeEvent<'a> {
+    /// `Deployed program <id>`
+    Deployed { program_id: &'a str },
+    /// `Upgraded program <id>`
+    Upgraded { program_id: &'a str },
+    /// `Closed Program <id>`
+    Closed { program_id: &'a str },
+    /// `Transfer: insufficient lamports <available>, need <needed>`
+    InsufficientLamports { available: u64, needed: u64 },
+    /// Any other failure of a system program instruction, e.g.
+    /// `Allocate: account Address { .. } already in use`
+    SystemProgramError { instruction: &'a str, message: &'a str },
+    /// `Program <id> failed to complete: <error>`, the program id being omitted
+    /// by some runtime versions
+    FailedToComplete { program_id: Option<&'a str>, error: &'a str },
+    /// `<account>'s writable privilege escalated`, or `signer`
+    PrivilegeEscalated { account: &'a str, privilege: &'a str },
+    /// `Unknown program <id>`
+    UnknownProgram { program_id: &'a str },
+    /// `Instruction references an unknown account <account>`
+    UnknownAccount { account: &'a str },
+}
+
+/// Prefixes of the system program diagnostics, without their `: `
+const SYSTEM_PROGRAM_INSTRUCTIONS: &[&str] = &[
+    "Create Account",
+    "Allocate",
+    "Assign",
+    "Transfer",
+    "Advance nonce account",
+    "Withdraw nonce account",
+    "Initialize nonce account",
+    "Authorize nonce account",
+];
+
+fn parse_runtime(log: &str) -> Option<RuntimeEvent<'_>> {
+    fn program_id(address: &str) -> Option<&str> {
+        is_program_id(address).then_some(address)
+    }
+    if let Some(rest) = log.strip_prefix("Deployed program ") {
+        return Some(RuntimeEvent::Deployed { program_id: program_id(rest)? });
+    }
+    if let Some(rest) = log.strip_prefix("Upgraded program ") {
+        return Some(RuntimeEvent::Upgraded { program_id: program_id(rest)? });
+    }
+    if let Some(rest) = log.strip_prefix("Closed Program ") {
+        return Some(RuntimeEvent::Closed { program_id: program_id(rest)? });
+    }
+    if let Some(rest) = log.strip_prefix("Unknown program ") {
+        return Some(RuntimeEvent::UnknownProgram { program_id: program_id(rest)? });
+    }
+    if let Some(rest) = log.strip_prefix("Instruction references an unknown account ") {
+        return Some(RuntimeEvent::UnknownAccount { account: program_id(rest)? });
+    }
+    if let Some(rest) = log.strip_prefix("Transfer: insufficient lamports ") {
+        let (available, needed) = rest.split_once(", need ")?;
+        return Some(RuntimeEvent::InsufficientLamports { available: available.parse().ok()?, needed: needed.parse().ok()? });
+    }
+    if let Some(rest) = log.strip_prefix("Program ") {
+        let (program_id, error) = match rest.strip_prefix("failed to complete: ") {
+            Some(error) => (None, error),
+            None => {
+                let (id, error) = rest.split_once(" failed to complete: ")?;
+                (Some(program_id(id)?), error)
+            },
+        };
+        return Some(RuntimeEvent::FailedToComplete { program_id, error });
+    }
+    if let Some((account, privilege)) = log.strip_suffix(" privilege escalated").and_then(|x| x.split_once("'s ")) {
+        if matches!(privilege, "writable" | "signer") {
+            return Some(RuntimeEvent::PrivilegeEscalated { account: program_id(account)?, privilege });
         }
+        return None;
+    }
+    let (instruction, message) = log.split_once(": ")?;
+    if !SYSTEM_PROGRAM_INSTRUCTIONS.contains(&instruction) || message.is_empty() {
+        return None;
     }
+    Some(RuntimeEvent::SystemProgramError { instruction, message })
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct TruncatedLog<'a> {
-    pub log: &'a String,
+    pub log: &'a str,
 }
 
 impl<'a> TruncatedLog<'a> {
-    pub fn new(log: &'a String) -> Self {
+    pub fn new(log: &'a str) -> Self {
         Self { log }
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct UnknownLog<'a> {
-    pub log: &'a String,
+    pub log: &'a str,
 }
 
 impl<'a> UnknownLog<'a> {
-    pub fn new(log: &'a String) -> Self {
+    pub fn new(log: &'a str) -> Self {
         Self { log }
     }
+    pub fn raw(&self) -> &'a str {
+        self.log
+    }
+}
+
+/// Logs of a program invocation, reconstructed from the log messages alone
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProgramStructuredLogs<'a> {
+    /// Logs of the invocation, from its invoke log to its success or failed log, excluding
+    /// those of inner invocations. Same as the logs attached to the corresponding
+    /// `StructuredInstruction`.
+    pub logs: Vec<Log<'a>>,
+    /// Index in the log messages of each of the `logs`
+    pub line_indices: Vec<usize>,
+    pub inner: Vec<ProgramStructuredLogs<'a>>,
+    /// Invoke depth, 1 for top level instructions, from the nesting of the logs
+    pub depth: usize,
+    /// Invoke depth logged by the invoke log, see `LogStructureWarning::UnexpectedInvokeDepth`
+    pub invoke_depth: u32,
+    /// Span `[start, end)` of the invocation in the log messages, inner invocations included
+    pub range: Option<(usize, usize)>,
+    /// Set when the log messages end, or are truncated, before the invocation does
+    pub truncated: bool,
+}
+
+impl<'a> ProgramStructuredLogs<'a> {
+    pub fn program_id(&self) -> Option<&'a str> {
+        match self.logs.first() {
+            Some(Log::Invoke(invoke_log)) => invoke_log.program_id().ok(),
+            _ => None,
+        }
+    }
+
+    /// Decoded payloads of the `Program data` logs, skipping those that are not base64
+    pub fn data(&self) -> Vec<Vec<u8>> {
+        self.logs.iter().filter_map(|log| match log {
+            Log::Data(data_log) => data_log.data().ok(),
+            _ => None,
+        }).collect()
+    }
+
+    /// Payloads of the `Program data` logs as logged, those that are not
+    /// base64 included
+    pub fn encoded_data(&self) -> Vec<&'a str> {
+        self.logs.iter().filter_map(|log| match log {
+            Log::Data(data_log) => data_log.encoded_data().ok(),
+            _ => None,
+        }).collect()
+    }
+
+    /// Errors decoding the `Program data` and `Program return` payloads, with
+    /// the index of their line
+    ///
+    /// ```
+    /// use substreams_solana_utils::error::LogDecodeError;
+    /// use substreams_solana_utils::log::build_structured_logs;
+    ///
+    /// let program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+    /// let log_messages: Vec<String> = [
+    ///     format!("Program {program} invoke [1]"),
+    ///     "Program data: AQID".to_string(),
+    ///     "Program data: AQ-D".to_string(),
+    ///     format!("Program {program} success"),
+    /// ].into();
+    ///
+    /// let structured_logs = build_structured_logs(&log_messages).unwrap();
+    /// let errors = structured_logs[0].decode_errors();
+    /// assert!(matches!(errors[..], [LogDecodeError::Base64 { line_index: Some(2), .. }]));
+    /// assert_eq!(errors[0].to_string(), "Invalid base64 payload at line 2: Invalid symbol 45, offset 2.");
+    /// ```
+    pub fn decode_errors(&self) -> Vec<LogDecodeError> {
+        self.logs.iter().zip(&self.line_indices).filter_map(|(log, line_index)| {
+            let error = match log {
+                Log::Data(data_log) => data_log.bytes().err(),
+                Log::Return(return_log) => return_log.decode().err(),
+                _ => None,
+            };
+            Some(error?.with_line_index(*line_index))
+        }).collect()
+    }
+
+    /// Data of the last `Program return` log
+    pub fn return_data(&self) -> Option<Vec<u8>> {
+        self.logs.iter().rev().find_map(|log| match log {
+            Log::Return(return_log) => return_log.data().ok(),
+            _ => None,
+        })
+    }
+
+    /// Messages of the `Program log` logs
+    pub fn program_logs(&self) -> Vec<&'a str> {
+        self.logs.iter().filter_map(|log| match log {
+            Log::Program(program_log) => program_log.message().ok(),
+            _ => None,
+        }).collect()
+    }
+
+    /// `Program log` messages that are JSON, see `ProgramLog::as_json`
+    #[cfg(feature = "json")]
+    pub fn json_logs(&self) -> Vec<serde_json::Value> {
+        self.logs.iter().filter_map(|log| match log {
+            Log::Program(program_log) => program_log.as_json(),
+            _ => None,
+        }).collect()
+    }
+
+    /// Lines that are none of the known log kinds
+    pub fn unknown_logs(&self) -> Vec<&'a str> {
+        self.logs.iter().filter_map(|log| match log {
+            Log::Unknown(unknown_log) => Some(unknown_log.raw()),
+            _ => None,
+        }).collect()
+    }
+
+    /// The inner invocations, in order
+    pub fn inner_logs(&self) -> std::slice::Iter<'_, ProgramStructuredLogs<'a>> {
+        self.inner.iter()
+    }
+
+    /// This invocation and its inner invocations, in execution order, with their depth
+    ///
+    /// ```
+    /// use substreams_solana_utils::log::build_structured_logs;
+    ///
+    /// let (router, pool, token) = ("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4", "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8", "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+    /// let log_messages: Vec<String> = [
+    ///     format!("Program {router} invoke [1]"),
+    ///     format!("Program {pool} invoke [2]"),
+    ///     format!("Program {token} invoke [3]"),
+    ///     format!("Program {token} success"),
+    ///     format!("Program {pool} success"),
+    ///     format!("Program {token} invoke [2]"),
+    ///     format!("Program {token} success"),
+    ///     format!("Program {router} success"),
+    /// ].into();
+    ///
+    /// let structured_logs = build_structured_logs(&log_messages).unwrap();
+    /// let walked: Vec<_> = structured_logs[0].walk().map(|(depth, x)| (depth, x.program_id().unwrap())).collect();
+    /// assert_eq!(walked, [(1, router), (2, pool), (3, token), (2, token)]);
+    /// ```
+    pub fn walk(&self) -> impl Iterator<Item = (usize, &ProgramStructuredLogs<'a>)> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.inner.iter().rev());
+            Some((node.depth, node))
+        })
+    }
+
+    /// Compute units consumed by the invocation, inner invocations included, from
+    /// its `consumed` log. `None` for programs that do not log it, like builtins.
+    pub fn compute_units_consumed(&self) -> Option<u64> {
+        let program_id = self.program_id()?;
+        self.logs.iter().find_map(|log| match log {
+            Log::Consumed(consumed_log) if consumed_log.program_id() == Ok(Some(program_id)) => consumed_log.consumed().ok(),
+            _ => None,
+        })
+    }
+
+    /// Error the invocation failed with, from the first failed log of the
+    /// invocation or of its inner invocations, see `extract_error`
+    pub fn error(&self) -> Option<TransactionLogError> {
+        self.inner.iter().find_map(|x| x.error()).or_else(|| self.logs.iter().find_map(|log| match log {
+            Log::Failed(failed_log) => TransactionLogError::from_failed_log(failed_log),
+            _ => None,
+        }))
+    }
+}
+
+impl ProgramStructuredLogs<'_> {
+    /// `Extended` if any invoke or success log of the invocation, inner
+    /// invocations included, carries metadata
+    pub fn log_dialect(&self) -> LogDialect {
+        let dialect = self.logs.iter().map(LogDialect::of).max().unwrap_or_default();
+        self.inner.iter().map(|x| x.log_dialect()).fold(dialect, Ord::max)
+    }
+}
+
+/// Log dialect observed over the result of `build_structured_logs`, to notice
+/// validators changing their log format.
+pub fn log_dialect(structured_logs: &[ProgramStructuredLogs]) -> LogDialect {
+    structured_logs.iter().map(|x| x.log_dialect()).max().unwrap_or_default()
+}
+
+/// Compute units consumed by each program, from the `consumed` logs alone.
+///
+/// The consumption logged by an invocation includes that of its inner
+/// invocations, so each program is attributed its own: what it logged minus
+/// what its direct inner invocations logged. Summed over the programs, this is
+/// what the top level invocations logged. Programs that do not log their
+/// consumption, like builtins, are left out, their units counting towards the
+/// invoking program. `None` when the logs are truncated or do not structure,
+/// see `build_structured_logs`.
+///
+/// ```
+/// use substreams_solana_utils::log::compute_units_by_program;
+///
+/// let (router, pool) = ("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4", "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+/// let log_messages: Vec<String> = [
+///     format!("Program {router} invoke [1]"),
+///     format!("Program {pool} invoke [2]"),
+///     format!("Program {pool} consumed 30000 of 190000 compute units"),
+///     format!("Program {pool} success"),
+///     format!("Program {router} consumed 42000 of 200000 compute units"),
+///     format!("Program {router} success"),
+/// ].into();
+///
+/// let units = compute_units_by_program(&log_messages).unwrap();
+/// assert_eq!((units[router], units[pool]), (12000, 30000));
+/// ```
+pub fn compute_units_by_program<S: AsRef<str>>(log_messages: &[S]) -> Option<HashMap<String, u64>> {
+    fn attribute(node: &ProgramStructuredLogs, units: &mut HashMap<String, u64>) {
+        for inner in &node.inner {
+            attribute(inner, units);
+        }
+        let (Some(program_id), Some(consumed)) = (node.program_id(), node.compute_units_consumed()) else { return };
+        let inner_consumed: u64 = node.inner.iter().filter_map(|x| x.compute_units_consumed()).sum();
+        *units.entry(program_id.to_string()).or_default() += consumed.saturating_sub(inner_consumed);
+    }
+    if is_truncated(log_messages) {
+        return None;
+    }
+    let mut units = HashMap::new();
+    for node in build_structured_logs(log_messages).ok()? {
+        attribute(&node, &mut units);
+    }
+    Some(units)
+}
+
+pub(crate) fn is_truncated<S: AsRef<str>>(log_messages: &[S]) -> bool {
+    log_messages.iter().any(|log| Log::new(log.as_ref()).is_truncated())
+}
+
+/// Invocation trees of the log messages, and the lines outside of any invocation
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StructuredLogs<'a> {
+    /// Lines before the first invoke log, e.g. runtime messages
+    pub preamble: Vec<Log<'a>>,
+    /// Top level invocations, in order
+    pub invocations: Vec<ProgramStructuredLogs<'a>>,
+    /// Lines between or after top level invocations, in order
+    pub orphan_logs: Vec<Log<'a>>,
+    pub warnings: Vec<LogStructureWarning>,
+}
+
+/// Inconsistency of the log messages that does not prevent structuring them
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum LogStructureWarning {
+    /// An invoke log whose depth is not one more than that of the invoking
+    /// invocation, or 1 at the top level
+    UnexpectedInvokeDepth { position: usize, expected: u32, found: u32 },
+}
+
+/// Structures log messages into invocation trees without the instructions.
+///
+/// This runs the same `LogStack` as `get_structured_instructions`, driven by the
+/// invoke, success and failed logs instead of the instructions, so both agree wherever
+/// logs are available. Programs that do not log, like the signature verification
+/// precompiles, do not appear here.
+///
+/// Lines outside of any invocation are left out, see `build_structured_logs_with_preamble`.
+/// Processing stops at a "Log truncated" line, the invocations still open being
+/// marked as `truncated`. Fails with `LogStructureError::UnexpectedTerminator` if
+/// a success or failed log does not belong to the program of the invocation it closes.
+///
+/// ```
+/// use substreams_solana_utils::log::build_structured_logs;
+///
+/// let (router, pool) = ("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4", "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+/// let log_messages: Vec<String> = [
+///     format!("Program {router} invoke [1]"),
+///     "Program log: Instruction: Route".to_string(),
+///     format!("Program {pool} invoke [2]"),
+///     "Program data: AQID".to_string(),
+///     "Unexpected runtime message".to_string(),
+///     format!("Program return: {pool} BAU="),
+///     format!("Program {pool} success"),
+///     format!("Program {router} success"),
+/// ].into();
+///
+/// let structured_logs = build_structured_logs(&log_messages).unwrap();
+/// let route = &structured_logs[0];
+/// assert_eq!(route.program_id(), Some(router));
+/// assert_eq!(route.program_logs(), ["Instruction: Route"]);
+/// let swaps: Vec<_> = route.inner_logs().collect();
+/// assert_eq!(swaps[0].program_id(), Some(pool));
+/// assert_eq!(swaps[0].data(), [vec![1, 2, 3]]);
+/// assert_eq!(swaps[0].return_data(), Some(vec![4, 5]));
+/// assert_eq!(swaps[0].unknown_logs(), ["Unexpected runtime message"]);
+/// ```
+pub fn build_structured_logs<'a, S: AsRef<str>>(log_messages: &'a [S]) -> Result<Vec<ProgramStructuredLogs<'a>>, LogStructureError> {
+    Ok(build_structured_logs_with_preamble(log_messages)?.invocations)
+}
+
+/// Same as `build_structured_logs`, accumulating the outcome in `report`: the
+/// lines read, lines outside of any invocation and error if any.
+///
+/// ```
+/// use substreams_solana_utils::log::{build_structured_logs_with_report, LogParseReport};
+///
+/// let program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+/// let transactions: Vec<Vec<String>> = vec![
+///     vec![format!("Program {program} invoke [1]"), "Unexpected runtime message".into(), format!("Program {program} success")],
+///     vec![format!("Program {program} invoke [1]"), "Log truncated".into()],
+///     vec![format!("Program {program} success"), format!("Program {program} invoke [1]")],
+/// ];
+///
+/// let mut block_report = LogParseReport::default();
+/// for log_messages in &transactions {
+///     let mut report = LogParseReport::default();
+///     let _ = build_structured_logs_with_report(log_messages, &mut report);
+///     block_report.add(report);
+/// }
+/// assert_eq!((block_report.total_lines, block_report.unknown_lines), (7, 1));
+/// assert_eq!((block_report.truncated_transactions, block_report.orphan_lines), (1, 1));
+/// assert_eq!(block_report.errors.len(), 0);
+/// ```
+pub fn build_structured_logs_with_report<'a, S: AsRef<str>>(log_messages: &'a [S], report: &mut LogParseReport) -> Result<Vec<ProgramStructuredLogs<'a>>, LogStructureError> {
+    report.add_lines(log_messages);
+    match build_structured_logs_with_preamble(log_messages) {
+        Ok(structured_logs) => {
+            report.orphan_lines += structured_logs.preamble.len() + structured_logs.orphan_logs.len();
+            Ok(structured_logs.invocations)
+        },
+        Err(error) => {
+            report.errors.push(error.to_string());
+            Err(error)
+        },
+    }
+}
+
+/// Outcome of parsing the logs of one or more transactions, to monitor the
+/// parsers. Reports of transactions merge into a block report with `add`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LogParseReport {
+    pub total_lines: usize,
+    /// Lines classified as `Log::Unknown`
+    pub unknown_lines: usize,
+    /// Transactions whose logs hold a "Log truncated" line
+    pub truncated_transactions: usize,
+    /// Lines attributed to no invocation
+    pub orphan_lines: usize,
+    /// Errors structuring failed on, or that were recovered from, e.g. with
+    /// `LogAssociation::Lenient`
+    pub errors: Vec<String>,
+}
+
+impl LogParseReport {
+    pub fn add(&mut self, other: LogParseReport) {
+        self.total_lines += other.total_lines;
+        self.unknown_lines += other.unknown_lines;
+        self.truncated_transactions += other.truncated_transactions;
+        self.orphan_lines += other.orphan_lines;
+        self.errors.extend(other.errors);
+    }
+
+    /// Counts the lines of a transaction
+    pub(crate) fn add_lines<S: AsRef<str>>(&mut self, log_messages: &[S]) {
+        self.total_lines += log_messages.len();
+        self.unknown_lines += log_messages.iter().filter(|log| Log::new(log.as_ref()).is_unknown()).count();
+        self.truncated_transactions += is_truncated(log_messages) as usize;
+    }
+}
+
+/// Same as `build_structured_logs`, keeping the lines outside of any invocation.
+///
+/// ```
+/// use substreams_solana_utils::log::build_structured_logs_with_preamble;
+///
+/// let program = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+/// let log_messages: Vec<String> = [
+///     "Runtime message".to_string(),
+///     format!("Program {program} success"),
+///     format!("Program {program} invoke [1]"),
+///     format!("Program {program} success"),
+///     "consumed 1500 of 1400000 compute units".to_string(),
+/// ].into();
+///
+/// let structured_logs = build_structured_logs_with_preamble(&log_messages).unwrap();
+/// assert_eq!(structured_logs.preamble.len(), 2);
+/// assert_eq!(structured_logs.invocations.len(), 1);
+/// assert!(structured_logs.orphan_logs[0].is_consumed());
+/// ```
+pub fn build_structured_logs_with_preamble<'a, S: AsRef<str>>(log_messages: &'a [S]) -> Result<StructuredLogs<'a>, LogStructureError> {
+    let mut logs = log_messages.iter().map(|log| Log::new(log.as_ref())).peekable();
+    let mut log_stack = LogStack::new();
+    let mut structured_logs = StructuredLogs::default();
+    // Program id, logged invoke depth and inner invocations of each open invocation
+    let mut open: Vec<(&'a str, u32, Vec<ProgramStructuredLogs<'a>>)> = Vec::new();
+
+    fn attach<'a>(node: ProgramStructuredLogs<'a>, open: &mut [(&'a str, u32, Vec<ProgramStructuredLogs<'a>>)], structured_logs: &mut StructuredLogs<'a>) {
+        match open.last_mut() {
+            Some((_, _, inner)) => inner.push(node),
+            None => structured_logs.invocations.push(node),
+        }
+    }
+
+    while let Some(log) = log_stack.next_log(&mut logs) {
+        if log.is_truncated() {
+            break;
+        } else if let Log::Invoke(invoke_log) = &log {
+            let (expected, found) = (open.len() as u32 + 1, invoke_log.invoke_depth().unwrap_or_default());
+            if found != expected {
+                let position = log_stack.position() - 1;
+                structured_logs.warnings.push(LogStructureWarning::UnexpectedInvokeDepth { position, expected, found });
+            }
+            open.push((log.program_id().unwrap_or_default(), found, Vec::new()));
+            log_stack.push_frame(log);
+        } else if open.is_empty() {
+            if structured_logs.invocations.is_empty() {
+                structured_logs.preamble.push(log);
+            } else {
+                structured_logs.orphan_logs.push(log);
+            }
+        } else if log.is_terminator() {
+            let (program_id, invoke_depth, inner) = open.pop().unwrap();
+            let found = log.program_id().unwrap_or_default();
+            if found != program_id {
+                return Err(LogStructureError::UnexpectedTerminator {
+                    position: log_stack.position() - 1,
+                    expected: program_id.to_string(),
+                    found: found.to_string(),
+                });
+            }
+            let depth = log_stack.depth();
+            let frame = log_stack.pop_frame(log).unwrap();
+            let node = ProgramStructuredLogs { logs: frame.logs, line_indices: frame.line_indices, inner, depth, invoke_depth, range: frame.range, truncated: false };
+            attach(node, &mut open, &mut structured_logs);
+        } else {
+            log_stack.append(log);
+        }
+    }
+
+    for frame in log_stack.drain_frames() {
+        let depth = open.len();
+        let (_, invoke_depth, inner) = open.pop().unwrap();
+        let node = ProgramStructuredLogs { logs: frame.logs, line_indices: frame.line_indices, inner, depth, invoke_depth, range: frame.range, truncated: true };
+        attach(node, &mut open, &mut structured_logs);
+    }
+    Ok(structured_logs)
+}
+
+#[cfg(feature = "serde")]
+mod serialize {
+    use base64::Engine;
+    use serde::{Serialize, Serializer};
+
+    use super::*;
+
+    /// Serialized shape of a log, see `Log`
+    #[derive(Serialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum SerializedLog<'a> {
+        Invoke {
+            program_id: &'a str,
+            depth: u32,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            metadata: LogMetadata<'a>,
+        },
+        Success {
+            program_id: &'a str,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            metadata: LogMetadata<'a>,
+        },
+        Return { program_id: &'a str, data: String },
+        Data { data: String },
+        Program { message: &'a str },
+        Truncated,
+        Consumed { program_id: Option<&'a str>, consumed: u64, budget: u64 },
+        Failed { program_id: &'a str, error: &'a str },
+        Runtime { event: RuntimeEvent<'a> },
+        Unknown { log: &'a str },
+    }
+
+    impl<'a> SerializedLog<'a> {
+        fn of(log: &Log<'a>) -> Self {
+            Self::parsed(log).unwrap_or(Self::Unknown { log: log.raw() })
+        }
+
+        fn parsed(log: &Log<'a>) -> Option<Self> {
+            let encode = |data: Vec<u8>| base64::engine::general_purpose::STANDARD.encode(data);
+            Some(match log {
+                Log::Invoke(x) => Self::Invoke { program_id: x.program_id().ok()?, depth: x.invoke_depth().ok()?, metadata: x.metadata() },
+                Log::Success(x) => Self::Success { program_id: x.program_id().ok()?, metadata: x.metadata() },
+                Log::Return(x) => Self::Return { program_id: x.program_id().ok()?, data: encode(x.data().ok()?) },
+                Log::Data(x) => Self::Data { data: encode(x.data().ok()?) },
+                Log::Program(x) => Self::Program { message: x.message().ok()? },
+                Log::Truncated(_) => Self::Truncated,
+                Log::Consumed(x) => Self::Consumed { program_id: x.program_id().ok()?, consumed: x.consumed().ok()?, budget: x.budget().ok()? },
+                Log::Failed(x) => Self::Failed { program_id: x.program_id().ok()?, error: x.error().ok()? },
+                Log::Runtime(x) => Self::Runtime { event: x.event().ok()? },
+                Log::Unknown(x) => Self::Unknown { log: x.raw() },
+            })
+        }
+    }
+
+    impl Serialize for Log<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            SerializedLog::of(self).serialize(serializer)
+        }
+    }
+
+    /// Each kind of log serializes as the `Log` holding it
+    macro_rules! serialize_as_log {
+        ($($log:ident => $variant:ident),* $(,)?) => {$(
+            impl Serialize for $log<'_> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    Log::$variant(self.clone()).serialize(serializer)
+                }
+            }
+        )*};
+    }
+
+    serialize_as_log!(
+        InvokeLog => Invoke,
+        SuccessLog => Success,
+        ReturnLog => Return,
+        DataLog => Data,
+        ProgramLog => Program,
+        TruncatedLog => Truncated,
+        ConsumedLog => Consumed,
+        FailedLog => Failed,
+        RuntimeLog => Runtime,
+        UnknownLog => Unknown,
+    );
 }
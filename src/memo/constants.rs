@@ -0,0 +1,5 @@
+use crate::pubkey::Pubkey;
+use substreams_solana::b58;
+
+pub const MEMO_PROGRAM_ID: Pubkey = Pubkey(b58!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr"));
+pub const MEMO_V1_PROGRAM_ID: Pubkey = Pubkey(b58!("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo"));
@@ -0,0 +1,147 @@
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::instruction::get_flattened_instructions;
+use crate::log::Log;
+use crate::pubkey::{Pubkey, PubkeyRef};
+use super::{MEMO_PROGRAM_ID, MEMO_V1_PROGRAM_ID};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Memo {
+    pub text: String,
+    /// Set when the memo is not valid UTF-8, the invalid sequences of `text`
+    /// being replaced. The Memo program rejects such memos, so they only
+    /// appear in failed transactions.
+    pub lossy: bool,
+}
+
+impl Memo {
+    pub fn from_bytes(data: &[u8]) -> Self {
+        match std::str::from_utf8(data) {
+            Ok(text) => Self { text: text.to_string(), lossy: false },
+            Err(_) => Self { text: String::from_utf8_lossy(data).into_owned(), lossy: true },
+        }
+    }
+}
+
+pub fn is_memo_program<P>(program_id: &P) -> bool
+where
+    P: PartialEq<Pubkey>
+{
+    *program_id == MEMO_PROGRAM_ID || *program_id == MEMO_V1_PROGRAM_ID
+}
+
+/// Memos of the Memo program instructions of `transaction`, v1 and v2, inner
+/// instructions included, in execution order.
+///
+/// Memos are decoded from the instruction data. When the transaction meta does
+/// not record the inner instructions, they are parsed from the
+/// `Memo (len <n>): "<memo>"` logs instead, which only the v2 program emits.
+///
+/// ```
+/// use substreams_solana_utils::memo::{get_memos, Memo, MEMO_PROGRAM_ID, MEMO_V1_PROGRAM_ID};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, router) = (pubkey(1), pubkey(2));
+/// let memo_id = MEMO_PROGRAM_ID.to_string();
+/// let mut transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .instruction(MEMO_V1_PROGRAM_ID, &[], b"deposit 1".to_vec())
+///     .instruction(router, &[user], vec![0])
+///     .inner(MEMO_PROGRAM_ID, &[user], "dépôt \"2\"".as_bytes().to_vec())
+///     .instruction(MEMO_PROGRAM_ID, &[], vec![0x66, 0x6f, 0xff])
+///     .logs(&[
+///         &format!("Program {} invoke [1]", router.to_string()),
+///         &format!("Program {memo_id} invoke [2]"),
+///         r#"Program log: Memo (len 11): "dépôt \"2\"""#,
+///         &format!("Program {memo_id} success"),
+///         &format!("Program {} success", router.to_string()),
+///     ])
+///     .build();
+///
+/// let memos = get_memos(&transaction);
+/// assert_eq!(memos[..2], [
+///     Memo { text: "deposit 1".into(), lossy: false },
+///     Memo { text: "dépôt \"2\"".into(), lossy: false },
+/// ]);
+/// assert_eq!(memos[2], Memo { text: "fo\u{fffd}".into(), lossy: true });
+///
+/// // Without inner instructions, memos are taken from the logs
+/// transaction.meta.as_mut().unwrap().inner_instructions_none = true;
+/// assert_eq!(get_memos(&transaction), [Memo { text: "dépôt \"2\"".into(), lossy: false }]);
+/// ```
+pub fn get_memos(transaction: &ConfirmedTransaction) -> Vec<Memo> {
+    if let Some(memos) = memos_from_instructions(transaction) {
+        return memos;
+    }
+    match transaction.meta.as_ref() {
+        Some(meta) => memos_from_logs(&meta.log_messages),
+        None => Vec::new(),
+    }
+}
+
+fn memos_from_instructions(transaction: &ConfirmedTransaction) -> Option<Vec<Memo>> {
+    if transaction.meta.as_ref()?.inner_instructions_none {
+        return None;
+    }
+    let instructions = get_flattened_instructions(transaction).ok()?;
+    let accounts = transaction.resolved_accounts();
+    Some(instructions.iter()
+        .filter(|x| accounts.get(x.program_id_index() as usize).is_some_and(|x| is_memo_program(&PubkeyRef(x))))
+        .map(|x| Memo::from_bytes(x.data()))
+        .collect())
+}
+
+/// Memos logged by the v2 Memo program. Logs of other programs are ignored, so
+/// that they cannot forge a memo.
+pub fn memos_from_logs<S: AsRef<str>>(log_messages: &[S]) -> Vec<Memo> {
+    let mut memos = Vec::new();
+    let mut invocations: Vec<&str> = Vec::new();
+    let memo_program_id = MEMO_PROGRAM_ID.to_string();
+    for log in log_messages.iter().map(|log| Log::new(log.as_ref())) {
+        match log {
+            Log::Invoke(_) => invocations.push(log.program_id().unwrap_or_default()),
+            Log::Success(_) | Log::Failed(_) => { invocations.pop(); },
+            Log::Program(program_log) if invocations.last() == Some(&memo_program_id.as_str()) => {
+                if let Some(text) = program_log.message().ok().and_then(parse_memo_message) {
+                    memos.push(Memo { text, lossy: false });
+                }
+            },
+            Log::Truncated(_) => break,
+            _ => (),
+        }
+    }
+    memos
+}
+
+/// Parses `Memo (len <n>): "<memo>"`, the memo being formatted with `{:?}`
+fn parse_memo_message(message: &str) -> Option<String> {
+    let (_, quoted) = message.strip_prefix("Memo (len ")?.split_once("): ")?;
+    unescape_debug(quoted.strip_prefix('"')?.strip_suffix('"')?)
+}
+
+/// Reverses the escaping of a string formatted with `{:?}`
+fn unescape_debug(escaped: &str) -> Option<String> {
+    let mut text = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => text.push('\n'),
+            'r' => text.push('\r'),
+            't' => text.push('\t'),
+            '0' => text.push('\0'),
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let (code, rest) = rest.split_once('}')?;
+                text.push(char::from_u32(u32::from_str_radix(code, 16).ok()?)?);
+                chars = rest.chars();
+            },
+            c @ ('\\' | '"' | '\'') => text.push(c),
+            _ => return None,
+        }
+    }
+    Some(text)
+}
@@ -0,0 +1,109 @@
+//! Structured instructions that own their data.
+//!
+//! `StructuredInstruction` borrows from the `ConfirmedTransaction` it was built
+//! from. `OwnedStructuredInstruction` copies an instruction and its inner
+//! instructions into owned values, so that it can outlive the transaction, e.g.
+//! to collect instructions across the transactions of a block. With the
+//! `serde` feature, it serializes with public keys as base58 strings.
+//!
+//! ```
+//! use substreams_solana_utils::instruction::get_structured_instructions;
+//! use substreams_solana_utils::owned_instruction::OwnedStructuredInstruction;
+//! use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+//!
+//! let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+//! let transactions: Vec<_> = (0..3).map(|i| TransactionBuilder::new()
+//!     .signer(user)
+//!     .instruction(router, &[user, pool], vec![i])
+//!     .inner(pool, &[user], vec![i])
+//!     .build()
+//! ).collect();
+//!
+//! let mut routes: Vec<OwnedStructuredInstruction> = Vec::new();
+//! for transaction in transactions {
+//!     let instructions = get_structured_instructions(&transaction).unwrap();
+//!     routes.push(instructions[0].to_owned_instruction().unwrap());
+//! }
+//! assert_eq!(routes.len(), 3);
+//! let swap = &routes[2].inner_instructions[0];
+//! assert_eq!((swap.program_id, swap.data.clone()), (pool, vec![2]));
+//! assert_eq!(swap.parent_flat_index, Some(routes[2].flat_index));
+//! assert_eq!(swap.logs.as_ref().unwrap()[0], format!("Program {} invoke [2]", pool.to_string()));
+//!
+//! let json = serde_json::to_value(&routes).unwrap();
+//! assert_eq!(json[2]["inner_instructions"][0]["program_id"], pool.to_string());
+//! assert_eq!(json[2]["inner_instructions"][0]["status"], serde_json::json!({ "kind": "success" }));
+//! ```
+
+use anyhow::Error;
+
+use crate::instruction::{InstructionStatus, StructuredInstruction};
+use crate::pubkey::Pubkey;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OwnedStructuredInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+    pub stack_height: Option<u32>,
+    /// See `StructuredInstruction::top_level_index`
+    pub top_level_index: u32,
+    /// Position in the flattened instructions of the transaction
+    pub flat_index: usize,
+    /// `flat_index` of the invoking instruction
+    pub parent_flat_index: Option<usize>,
+    /// The raw log lines of the invocation, see `StructuredInstruction::logs`
+    pub logs: Option<Vec<String>>,
+    /// See `StructuredInstruction::log_line_indices`
+    pub log_line_indices: Option<Vec<usize>>,
+    pub log_range: Option<(usize, usize)>,
+    pub logs_truncated: bool,
+    pub log_mismatch: bool,
+    pub status: InstructionStatus,
+    pub did_execute: bool,
+    pub return_data: Option<Vec<u8>>,
+    pub inner_instructions: Vec<OwnedStructuredInstruction>,
+}
+
+impl OwnedStructuredInstruction {
+    /// Copies `instruction` and its inner instructions. Fails if an account is
+    /// not a 32 bytes public key.
+    pub fn new(instruction: &StructuredInstruction) -> Result<Self, Error> {
+        let parent_flat_index = instruction.parent_instruction().map(|x| x.flat_index());
+        Self::with_parent(instruction, parent_flat_index)
+    }
+
+    fn with_parent(instruction: &StructuredInstruction, parent_flat_index: Option<usize>) -> Result<Self, Error> {
+        let inner_instructions = instruction.inner_instructions().iter()
+            .map(|x| Self::with_parent(x, Some(instruction.flat_index())))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            program_id: instruction.program_id().to_pubkey()?,
+            accounts: instruction.accounts().iter().map(|x| x.to_pubkey()).collect::<Result<Vec<_>, _>>()?,
+            data: instruction.data().clone(),
+            stack_height: instruction.stack_height(),
+            top_level_index: instruction.top_level_index(),
+            flat_index: instruction.flat_index(),
+            parent_flat_index,
+            logs: instruction.logs().as_ref().map(|logs| logs.iter().map(|x| x.raw().to_string()).collect()),
+            log_line_indices: instruction.log_line_indices().clone(),
+            log_range: instruction.raw_log_range(),
+            logs_truncated: instruction.logs_truncated(),
+            log_mismatch: instruction.log_mismatch(),
+            status: instruction.status(),
+            did_execute: instruction.did_execute(),
+            return_data: instruction.return_data().map(<[u8]>::to_vec),
+            inner_instructions,
+        })
+    }
+
+    /// This instruction followed by its inner instructions, in execution order
+    pub fn flattened(&self) -> Vec<&Self> {
+        let mut instructions = vec![self];
+        for inner_instruction in &self.inner_instructions {
+            instructions.extend(inner_instruction.flattened());
+        }
+        instructions
+    }
+}
@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+/// Instruction data above this size is considered a large payload
+pub const LARGE_PAYLOAD_THRESHOLD: usize = 1024;
+
+/// Options for decoders of instructions carrying large data payloads
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PayloadOptions {
+    /// Copy payloads into the decoded values instead of borrowing them from the
+    /// transaction. Only needed when decoded values must outlive the block.
+    pub copy_payloads: bool,
+}
+
+/// Large data blob of an instruction, borrowed from the transaction unless a copy
+/// was explicitly requested. Its sha256 is computed on first use and cached, so
+/// deduping or referencing the payload by hash never copies it.
+pub struct Payload<'a> {
+    bytes: Cow<'a, [u8]>,
+    hash: OnceCell<[u8; 32]>,
+}
+
+impl<'a> Payload<'a> {
+    pub fn borrowed(bytes: &'a [u8]) -> Self {
+        Self { bytes: Cow::Borrowed(bytes), hash: OnceCell::new() }
+    }
+
+    pub fn new(bytes: &'a [u8], options: &PayloadOptions) -> Self {
+        let payload = Self::borrowed(bytes);
+        if options.copy_payloads { payload.into_owned() } else { payload }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.bytes, Cow::Borrowed(_))
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        *self.hash.get_or_init(|| Sha256::digest(&self.bytes).into())
+    }
+
+    /// Copies the payload, if borrowed, detaching it from the transaction
+    pub fn into_owned(self) -> Payload<'static> {
+        Payload { bytes: Cow::Owned(self.bytes.into_owned()), hash: self.hash }
+    }
+}
+
+impl fmt::Debug for Payload<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Payload")
+            .field("len", &self.len())
+            .field("borrowed", &self.is_borrowed())
+            .finish()
+    }
+}
@@ -0,0 +1,54 @@
+//! Protobuf messages of the structured instructions, defined in
+//! `proto/structured_instruction.proto`, for modules outputting them as is.
+//!
+//! ```
+//! use substreams_solana_utils::instruction::get_structured_instructions;
+//! use substreams_solana_utils::pb;
+//! use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+//!
+//! let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+//! let transaction = TransactionBuilder::new()
+//!     .signer(user)
+//!     .instruction(router, &[user, pool], vec![1])
+//!     .inner(pool, &[user], vec![2])
+//!     .build();
+//!
+//! let output = pb::StructuredInstructions::from(&get_structured_instructions(&transaction).unwrap());
+//! let swap = &output.instructions[0].inner_instructions[0];
+//! assert_eq!(swap.program_id, pool.to_string());
+//! assert_eq!(swap.stack_height, Some(2));
+//! assert!(swap.logs_available);
+//! ```
+
+use std::rc::Rc;
+
+use crate::instruction;
+
+// @@protoc_insertion_point(attribute:structured_instruction)
+pub mod structured_instruction {
+    include!("structured_instruction.rs");
+    // @@protoc_insertion_point(structured_instruction)
+}
+
+pub use structured_instruction::{StructuredInstruction, StructuredInstructions};
+
+impl From<&instruction::StructuredInstruction<'_>> for StructuredInstruction {
+    fn from(instruction: &instruction::StructuredInstruction) -> Self {
+        let logs = instruction.logs();
+        Self {
+            program_id: instruction.program_id().to_string(),
+            accounts: instruction.accounts().iter().map(|x| x.to_string()).collect(),
+            data: instruction.data().clone(),
+            inner_instructions: instruction.inner_instructions().iter().map(|x| Self::from(x.as_ref())).collect(),
+            logs: logs.iter().flatten().map(|x| x.to_string()).collect(),
+            logs_available: logs.is_some(),
+            stack_height: instruction.stack_height(),
+        }
+    }
+}
+
+impl From<&Vec<Rc<instruction::StructuredInstruction<'_>>>> for StructuredInstructions {
+    fn from(instructions: &Vec<Rc<instruction::StructuredInstruction>>) -> Self {
+        Self { instructions: instructions.iter().map(|x| StructuredInstruction::from(x.as_ref())).collect() }
+    }
+}
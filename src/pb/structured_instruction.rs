@@ -0,0 +1,28 @@
+// @generated
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StructuredInstruction {
+    #[prost(string, tag="1")]
+    pub program_id: ::prost::alloc::string::String,
+    #[prost(string, repeated, tag="2")]
+    pub accounts: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(bytes="vec", tag="3")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, repeated, tag="4")]
+    pub inner_instructions: ::prost::alloc::vec::Vec<StructuredInstruction>,
+    /// Empty when logs_available is false
+    #[prost(string, repeated, tag="5")]
+    pub logs: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// Unset when the logs were truncated before the invocation ended
+    #[prost(bool, tag="6")]
+    pub logs_available: bool,
+    #[prost(uint32, optional, tag="7")]
+    pub stack_height: ::core::option::Option<u32>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StructuredInstructions {
+    #[prost(message, repeated, tag="1")]
+    pub instructions: ::prost::alloc::vec::Vec<StructuredInstruction>,
+}
+// @@protoc_insertion_point(module)
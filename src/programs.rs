@@ -0,0 +1,263 @@
+use std::borrow::Cow;
+
+use sha2::{Digest, Sha256};
+
+use substreams_solana::b58;
+
+use crate::pubkey::Pubkey;
+use crate::spl_token::{TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID};
+use crate::system_program::SYSTEM_PROGRAM_ID;
+use crate::address_lookup_table::ADDRESS_LOOKUP_TABLE_PROGRAM_ID;
+use crate::associated_token_account::ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID;
+use crate::bpf_loader::{BPF_LOADER_2_PROGRAM_ID, BPF_LOADER_UPGRADEABLE_PROGRAM_ID};
+use crate::compute_budget::COMPUTE_BUDGET_PROGRAM_ID;
+use crate::memo::{MEMO_PROGRAM_ID, MEMO_V1_PROGRAM_ID};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProgramCategory {
+    Native,
+    Vote,
+    Stake,
+    Loader,
+    Precompile,
+    Token,
+    Dex,
+    Nft,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KnownProgram {
+    System,
+    Vote,
+    Stake,
+    ComputeBudget,
+    AddressLookupTable,
+    BpfLoader,
+    BpfLoader2,
+    BpfUpgradeableLoader,
+    LoaderV4,
+    Ed25519,
+    Secp256k1,
+    Token,
+    Token2022,
+    AssociatedTokenAccount,
+    Memo,
+    MemoV1,
+    RaydiumAmmV4,
+    RaydiumClmm,
+    RaydiumCpmm,
+    OrcaWhirlpool,
+    MeteoraDlmm,
+    Phoenix,
+    OpenBook,
+    JupiterV6,
+    PumpFun,
+    TokenMetadata,
+    Bubblegum,
+    CandyMachineV3,
+    MagicEdenV2,
+    TensorSwap,
+}
+
+const KNOWN_PROGRAMS: &[(KnownProgram, Pubkey, &str, ProgramCategory)] = &[
+    (KnownProgram::System, SYSTEM_PROGRAM_ID, "System Program", ProgramCategory::Native),
+    (KnownProgram::Vote, Pubkey(b58!("Vote111111111111111111111111111111111111111")), "Vote Program", ProgramCategory::Vote),
+    (KnownProgram::Stake, Pubkey(b58!("Stake11111111111111111111111111111111111111")), "Stake Program", ProgramCategory::Stake),
+    (KnownProgram::ComputeBudget, COMPUTE_BUDGET_PROGRAM_ID, "Compute Budget Program", ProgramCategory::Native),
+    (KnownProgram::AddressLookupTable, ADDRESS_LOOKUP_TABLE_PROGRAM_ID, "Address Lookup Table Program", ProgramCategory::Native),
+    (KnownProgram::BpfLoader, Pubkey(b58!("BPFLoader1111111111111111111111111111111111")), "BPF Loader", ProgramCategory::Loader),
+    (KnownProgram::BpfLoader2, BPF_LOADER_2_PROGRAM_ID, "BPF Loader 2", ProgramCategory::Loader),
+    (KnownProgram::BpfUpgradeableLoader, BPF_LOADER_UPGRADEABLE_PROGRAM_ID, "BPF Upgradeable Loader", ProgramCategory::Loader),
+    (KnownProgram::LoaderV4, Pubkey(b58!("LoaderV411111111111111111111111111111111111")), "Loader v4", ProgramCategory::Loader),
+    (KnownProgram::Ed25519, Pubkey(b58!("Ed25519SigVerify111111111111111111111111111")), "Ed25519 SigVerify Precompile", ProgramCategory::Precompile),
+    (KnownProgram::Secp256k1, Pubkey(b58!("KeccakSecp256k11111111111111111111111111111")), "Secp256k1 SigVerify Precompile", ProgramCategory::Precompile),
+    (KnownProgram::Token, TOKEN_PROGRAM_ID, "Token Program", ProgramCategory::Token),
+    (KnownProgram::Token2022, TOKEN_2022_PROGRAM_ID, "Token-2022 Program", ProgramCategory::Token),
+    (KnownProgram::AssociatedTokenAccount, ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, "Associated Token Account Program", ProgramCategory::Token),
+    (KnownProgram::Memo, MEMO_PROGRAM_ID, "Memo Program", ProgramCategory::Native),
+    (KnownProgram::MemoV1, MEMO_V1_PROGRAM_ID, "Memo Program v1", ProgramCategory::Native),
+    (KnownProgram::RaydiumAmmV4, Pubkey(b58!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8")), "Raydium AMM v4", ProgramCategory::Dex),
+    (KnownProgram::RaydiumClmm, Pubkey(b58!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK")), "Raydium CLMM", ProgramCategory::Dex),
+    (KnownProgram::RaydiumCpmm, Pubkey(b58!("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C")), "Raydium CPMM", ProgramCategory::Dex),
+    (KnownProgram::OrcaWhirlpool, Pubkey(b58!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc")), "Orca Whirlpool", ProgramCategory::Dex),
+    (KnownProgram::MeteoraDlmm, Pubkey(b58!("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo")), "Meteora DLMM", ProgramCategory::Dex),
+    (KnownProgram::Phoenix, Pubkey(b58!("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY")), "Phoenix", ProgramCategory::Dex),
+    (KnownProgram::OpenBook, Pubkey(b58!("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX")), "OpenBook", ProgramCategory::Dex),
+    (KnownProgram::JupiterV6, Pubkey(b58!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4")), "Jupiter Aggregator v6", ProgramCategory::Dex),
+    (KnownProgram::PumpFun, Pubkey(b58!("6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P")), "Pump.fun", ProgramCategory::Dex),
+    (KnownProgram::TokenMetadata, Pubkey(b58!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s")), "Metaplex Token Metadata", ProgramCategory::Nft),
+    (KnownProgram::Bubblegum, Pubkey(b58!("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY")), "Metaplex Bubblegum", ProgramCategory::Nft),
+    (KnownProgram::CandyMachineV3, Pubkey(b58!("CndyV3LdqHUfDLmE5naZjVN8rBZz4tqhdefbAnjHG3JR")), "Metaplex Candy Machine v3", ProgramCategory::Nft),
+    (KnownProgram::MagicEdenV2, Pubkey(b58!("M2mx93ekt1fmXSVkTrUL9xVFHkmME8HTUi5Cyc5aF7K")), "Magic Eden v2", ProgramCategory::Nft),
+    (KnownProgram::TensorSwap, Pubkey(b58!("TSWAPaqyCSx2KABk68Shruf4rp7CxcNi8hAsbdwmHbN")), "TensorSwap", ProgramCategory::Nft),
+];
+
+/// Anchor instruction discriminators of known programs, `sha256("global:<name>")[..8]`
+pub const INSTRUCTION_DISCRIMINATORS: &[(KnownProgram, &str, [u8; 8])] = &[
+    (KnownProgram::JupiterV6, "route", [0xe5, 0x17, 0xcb, 0x97, 0x7a, 0xe3, 0xad, 0x2a]),
+    (KnownProgram::JupiterV6, "shared_accounts_route", [0xc1, 0x20, 0x9b, 0x33, 0x41, 0xd6, 0x9c, 0x81]),
+    (KnownProgram::JupiterV6, "exact_out_route", [0xd0, 0x33, 0xef, 0x97, 0x7b, 0x2b, 0xed, 0x5c]),
+    (KnownProgram::JupiterV6, "shared_accounts_exact_out_route", [0xb0, 0xd1, 0x69, 0xa8, 0x9a, 0x7d, 0x45, 0x3e]),
+    (KnownProgram::JupiterV6, "route_with_token_ledger", [0x96, 0x56, 0x47, 0x74, 0xa7, 0x5d, 0x0e, 0x68]),
+    (KnownProgram::RaydiumClmm, "swap", [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8]),
+    (KnownProgram::RaydiumClmm, "swap_v2", [0x2b, 0x04, 0xed, 0x0b, 0x1a, 0xc9, 0x1e, 0x62]),
+    (KnownProgram::RaydiumClmm, "open_position", [0x87, 0x80, 0x2f, 0x4d, 0x0f, 0x98, 0xf0, 0x31]),
+    (KnownProgram::RaydiumClmm, "increase_liquidity", [0x2e, 0x9c, 0xf3, 0x76, 0x0d, 0xcd, 0xfb, 0xb2]),
+    (KnownProgram::RaydiumClmm, "decrease_liquidity", [0xa0, 0x26, 0xd0, 0x6f, 0x68, 0x5b, 0x2c, 0x01]),
+    (KnownProgram::RaydiumCpmm, "swap_base_input", [0x8f, 0xbe, 0x5a, 0xda, 0xc4, 0x1e, 0x33, 0xde]),
+    (KnownProgram::RaydiumCpmm, "swap_base_output", [0x37, 0xd9, 0x62, 0x56, 0xa3, 0x4a, 0xb4, 0xad]),
+    (KnownProgram::RaydiumCpmm, "initialize", [0xaf, 0xaf, 0x6d, 0x1f, 0x0d, 0x98, 0x9b, 0xed]),
+    (KnownProgram::RaydiumCpmm, "deposit", [0xf2, 0x23, 0xc6, 0x89, 0x52, 0xe1, 0xf2, 0xb6]),
+    (KnownProgram::RaydiumCpmm, "withdraw", [0xb7, 0x12, 0x46, 0x9c, 0x94, 0x6d, 0xa1, 0x22]),
+    (KnownProgram::OrcaWhirlpool, "swap", [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8]),
+    (KnownProgram::OrcaWhirlpool, "swap_v2", [0x2b, 0x04, 0xed, 0x0b, 0x1a, 0xc9, 0x1e, 0x62]),
+    (KnownProgram::OrcaWhirlpool, "two_hop_swap", [0xc3, 0x60, 0xed, 0x6c, 0x44, 0xa2, 0xdb, 0xe6]),
+    (KnownProgram::OrcaWhirlpool, "increase_liquidity", [0x2e, 0x9c, 0xf3, 0x76, 0x0d, 0xcd, 0xfb, 0xb2]),
+    (KnownProgram::OrcaWhirlpool, "decrease_liquidity", [0xa0, 0x26, 0xd0, 0x6f, 0x68, 0x5b, 0x2c, 0x01]),
+    (KnownProgram::MeteoraDlmm, "swap", [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8]),
+    (KnownProgram::MeteoraDlmm, "swap_exact_out", [0xfa, 0x49, 0x65, 0x21, 0x26, 0xcf, 0x4b, 0xb8]),
+    (KnownProgram::MeteoraDlmm, "add_liquidity", [0xb5, 0x9d, 0x59, 0x43, 0x8f, 0xb6, 0x34, 0x48]),
+    (KnownProgram::MeteoraDlmm, "remove_liquidity", [0x50, 0x55, 0xd1, 0x48, 0x18, 0xce, 0xb1, 0x6c]),
+    (KnownProgram::PumpFun, "create", [0x18, 0x1e, 0xc8, 0x28, 0x05, 0x1c, 0x07, 0x77]),
+    (KnownProgram::PumpFun, "buy", [0x66, 0x06, 0x3d, 0x12, 0x01, 0xda, 0xeb, 0xea]),
+    (KnownProgram::PumpFun, "sell", [0x33, 0xe6, 0x85, 0xa4, 0x01, 0x7f, 0x83, 0xad]),
+];
+
+/// Anchor event discriminators of known programs, `sha256("event:<Name>")[..8]`
+pub const EVENT_DISCRIMINATORS: &[(KnownProgram, &str, [u8; 8])] = &[
+    (KnownProgram::JupiterV6, "SwapEvent", [0x40, 0xc6, 0xcd, 0xe8, 0x26, 0x08, 0x71, 0xe2]),
+    (KnownProgram::PumpFun, "CreateEvent", [0x1b, 0x72, 0xa9, 0x4d, 0xde, 0xeb, 0x63, 0x76]),
+    (KnownProgram::PumpFun, "TradeEvent", [0xbd, 0xdb, 0x7f, 0xd3, 0x4e, 0xe6, 0x61, 0xee]),
+    (KnownProgram::PumpFun, "CompleteEvent", [0x5f, 0x72, 0x61, 0x9c, 0xd4, 0x2e, 0x98, 0x08]),
+];
+
+/// Anchor discriminator `sha256("<namespace>:<name>")[..8]`, e.g. `("global", "swap")`
+/// for instructions and `("event", "SwapEvent")` for events.
+///
+/// ```
+/// use substreams_solana_utils::programs::anchor_sighash;
+///
+/// assert_eq!(anchor_sighash("global", "swap"), [0xf8, 0xc6, 0x9e, 0x91, 0xe1, 0x75, 0x87, 0xc8]);
+/// assert_eq!(anchor_sighash("event", "SwapEvent"), [0x40, 0xc6, 0xcd, 0xe8, 0x26, 0x08, 0x71, 0xe2]);
+/// ```
+pub fn anchor_sighash(namespace: &str, name: &str) -> [u8; 8] {
+    let hash = Sha256::new().chain_update(namespace).chain_update(":").chain_update(name).finalize();
+    let mut sighash = [0u8; 8];
+    sighash.copy_from_slice(&hash[..8]);
+    sighash
+}
+
+/// Prefix of the self-invoked instructions Anchor programs emit events through
+/// with `emit_cpi!`, followed by the event discriminator.
+pub const ANCHOR_EVENT_IX_TAG: [u8; 8] = [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+
+impl KnownProgram {
+    pub fn iter() -> impl Iterator<Item = KnownProgram> {
+        KNOWN_PROGRAMS.iter().map(|(program, ..)| *program)
+    }
+
+    /// Identifies a program id, given either as a `Pubkey` or a `PubkeyRef`
+    pub fn from_program_id<P>(program_id: &P) -> Option<Self>
+    where
+        Pubkey: PartialEq<P>
+    {
+        KNOWN_PROGRAMS.iter().find(|(_, id, ..)| *id == *program_id).map(|(program, ..)| *program)
+    }
+
+    fn entry(&self) -> &'static (KnownProgram, Pubkey, &'static str, ProgramCategory) {
+        KNOWN_PROGRAMS.iter().find(|(program, ..)| program == self).unwrap()
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.entry().1
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.entry().2
+    }
+
+    pub fn category(&self) -> ProgramCategory {
+        self.entry().3
+    }
+
+    /// Names and discriminators of the program instructions
+    pub fn instruction_discriminators(&self) -> impl Iterator<Item = (&'static str, &'static [u8; 8])> {
+        let known_program = *self;
+        INSTRUCTION_DISCRIMINATORS.iter().filter(move |(program, ..)| *program == known_program).map(|(_, name, discriminator)| (*name, discriminator))
+    }
+
+    /// Set of the program instruction discriminators, for `StructuredInstruction::discriminator_in`
+    pub fn instruction_discriminator_set(&self) -> DiscriminatorSet {
+        DiscriminatorSet::new(self.instruction_discriminators().map(|(_, discriminator)| *discriminator))
+    }
+
+    /// Names and discriminators of the program events
+    pub fn event_discriminators(&self) -> impl Iterator<Item = (&'static str, &'static [u8; 8])> {
+        let known_program = *self;
+        EVENT_DISCRIMINATORS.iter().filter(move |(program, ..)| *program == known_program).map(|(_, name, discriminator)| (*name, discriminator))
+    }
+}
+
+/// Sorted set of 8-byte discriminators, with `O(log n)` lookup
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscriminatorSet {
+    discriminators: Cow<'static, [[u8; 8]]>,
+}
+
+impl DiscriminatorSet {
+    pub fn new(discriminators: impl IntoIterator<Item = [u8; 8]>) -> Self {
+        let mut discriminators: Vec<[u8; 8]> = discriminators.into_iter().collect();
+        discriminators.sort_unstable();
+        discriminators.dedup();
+        Self { discriminators: Cow::Owned(discriminators) }
+    }
+
+    /// Set over a static table, usable in constants. Panics, at compile time in
+    /// a constant, if `discriminators` is not strictly increasing.
+    pub const fn from_sorted(discriminators: &'static [[u8; 8]]) -> Self {
+        let mut i = 1;
+        while i < discriminators.len() {
+            assert!(is_less(&discriminators[i - 1], &discriminators[i]), "Discriminators must be sorted and unique");
+            i += 1;
+        }
+        Self { discriminators: Cow::Borrowed(discriminators) }
+    }
+
+    pub fn contains(&self, discriminator: &[u8; 8]) -> bool {
+        self.discriminators.binary_search(discriminator).is_ok()
+    }
+
+    /// Whether `data` starts with one of the discriminators. In debug builds,
+    /// data shorter than a discriminator is reported, since it usually means the
+    /// set is matched against a program with another instruction layout, like
+    /// the 1-byte tags of the token program.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        match data.get(..8) {
+            Some(discriminator) => self.contains(discriminator.try_into().unwrap()),
+            None => {
+                #[cfg(debug_assertions)]
+                eprintln!("warning: discriminator set matched against {} bytes of instruction data", data.len());
+                false
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.discriminators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.discriminators.is_empty()
+    }
+}
+
+const fn is_less(a: &[u8; 8], b: &[u8; 8]) -> bool {
+    let mut i = 0;
+    while i < 8 {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+        i += 1;
+    }
+    false
+}
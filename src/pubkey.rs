@@ -1,4 +1,5 @@
 use std::fmt;
+use std::borrow::Borrow;
 use borsh::BorshDeserialize;
 use anyhow::{anyhow, Context, Error};
 
@@ -28,6 +29,14 @@ impl Pubkey {
     }
 }
 
+/// Serializes as its base58 string, like the program ids of serialized logs
+#[cfg(feature = "serde")]
+impl serde::Serialize for Pubkey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 impl fmt::Debug for Pubkey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("Pubkey")
@@ -48,6 +57,14 @@ impl<'a> PubkeyRef<'a> {
     }
 }
 
+// Hashes and compares the same as the underlying bytes, so maps keyed by
+// `PubkeyRef` can be queried with a plain `&[u8]`.
+impl Borrow<[u8]> for PubkeyRef<'_> {
+    fn borrow(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
 impl fmt::Debug for PubkeyRef<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("Pubkey")
@@ -0,0 +1,111 @@
+//! Text rendering of instruction trees, for debugging.
+//!
+//! One line per instruction, children indented two spaces under their parent:
+//! program id, stack height, data in hex, account count and the first program
+//! log of the invocation.
+//!
+//! ```
+//! use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+//! use substreams_solana_utils::render::RenderOptions;
+//! use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+//!
+//! let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+//! let (router_id, pool_id) = (router.to_string(), pool.to_string());
+//! let transaction = TransactionBuilder::new()
+//!     .signer(user)
+//!     .instruction(router, &[user, pool], vec![0xe5, 0x17, 0xcb, 0x97, 0x7a, 0xe3, 0xad, 0x2a])
+//!     .inner(pool, &[user], vec![9])
+//!     .logs(&[
+//!         &format!("Program {router_id} invoke [1]"),
+//!         "Program log: Instruction: Route",
+//!         &format!("Program {pool_id} invoke [2]"),
+//!         &format!("Program {pool_id} success"),
+//!         &format!("Program {router_id} success"),
+//!     ])
+//!     .build();
+//!
+//! let instructions = get_structured_instructions(&transaction).unwrap();
+//! assert_eq!(instructions.render(), format!("\
+//! {router_id} [1] e517cb977ae3ad2a (2 accounts) Instruction: Route
+//!   {pool_id} [2] 09 (1 account)
+//! "));
+//!
+//! let options = RenderOptions { max_data_bytes: Some(2), show_accounts: true, ..Default::default() };
+//! let rendered = instructions.render_with(&options);
+//! assert!(rendered.starts_with(&format!("{router_id} [1] e517... (2 accounts)")));
+//! assert!(rendered.contains(&format!("\n  - {}\n", user.to_string())));
+//! ```
+
+use std::fmt::Write;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+use crate::instruction::{StructuredInstruction, StructuredInstructions, WalkOrder};
+use crate::log::Log;
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    /// Data bytes shown before eliding the rest, all when `None`
+    pub max_data_bytes: Option<usize>,
+    /// Characters of the program log shown before eliding the rest, all when `None`
+    pub max_log_chars: Option<usize>,
+    /// Show the first `Program log` line of each invocation
+    pub show_first_log: bool,
+    /// List the accounts of each instruction, one per line
+    pub show_accounts: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            max_data_bytes: Some(16),
+            max_log_chars: Some(80),
+            show_first_log: true,
+            show_accounts: false,
+        }
+    }
+}
+
+/// Renders `instructions` and their inner instructions, see the module documentation
+pub fn render(instructions: &Vec<Rc<StructuredInstruction>>, options: &RenderOptions) -> String {
+    let mut rendered = String::new();
+    let _ = instructions.walk(WalkOrder::PreOrder, |instruction, context| {
+        let indent = "  ".repeat(context.depth as usize - 1);
+        render_instruction(&mut rendered, &indent, instruction, options);
+        ControlFlow::Continue(())
+    });
+    rendered
+}
+
+fn render_instruction(rendered: &mut String, indent: &str, instruction: &StructuredInstruction, options: &RenderOptions) {
+    let data = instruction.data();
+    let shown = options.max_data_bytes.map_or(data.len(), |x| x.min(data.len()));
+    let _ = write!(rendered, "{}{} [{}] ", indent, instruction.program_id().to_string(), instruction.stack_height().unwrap_or_default());
+    for byte in &data[..shown] {
+        let _ = write!(rendered, "{:02x}", byte);
+    }
+    if shown < data.len() {
+        rendered.push_str("...");
+    }
+    let accounts = instruction.accounts();
+    let _ = write!(rendered, " ({} account{})", accounts.len(), if accounts.len() == 1 { "" } else { "s" });
+
+    if options.show_first_log {
+        let first_log = instruction.logs().as_ref().and_then(|logs| logs.iter().find_map(|log| match log {
+            Log::Program(program_log) => program_log.message().ok(),
+            _ => None,
+        }));
+        if let Some(message) = first_log {
+            let shown: String = message.chars().take(options.max_log_chars.unwrap_or(usize::MAX)).collect();
+            let elided = if shown.len() < message.len() { "..." } else { "" };
+            let _ = write!(rendered, " {}{}", shown, elided);
+        }
+    }
+    rendered.push('\n');
+
+    if options.show_accounts {
+        for account in accounts {
+            let _ = writeln!(rendered, "{}  - {}", indent, account.to_string());
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Rent parameters, and how they changed over the history of the chain.
+//!
+//! Until rent exemption was enforced, accounts could hold fewer lamports than
+//! the rent-exempt minimum and paid rent every epoch, collected when they were
+//! loaded. Token accounts of that era do not necessarily hold the rent-exempt
+//! reserve, and transactions may lose lamports to rent collection.
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+/// Default `lamports_per_byte_year` of the rent sysvar
+pub const LAMPORTS_PER_BYTE_YEAR: u64 = 3480;
+/// Default `exemption_threshold` of the rent sysvar, in years
+pub const EXEMPTION_THRESHOLD_YEARS: u64 = 2;
+/// Bytes of account metadata charged on top of the account data
+pub const ACCOUNT_STORAGE_OVERHEAD: u64 = 128;
+pub const SLOTS_PER_EPOCH: u64 = 432_000;
+/// Slots per year at 400ms per slot
+pub const SLOTS_PER_YEAR: u64 = 78_894_000;
+pub const TOKEN_ACCOUNT_LEN: u64 = 165;
+
+/// Rent regime a transaction was processed under. The boundary depends on the
+/// cluster, so the caller decides it, e.g. from the block slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RuntimeEra {
+    /// Accounts below the rent-exempt minimum pay rent every epoch
+    RentCollecting,
+    /// Every account is rent exempt
+    #[default]
+    RentExempt,
+}
+
+pub fn rent_exempt_minimum(data_len: u64) -> u64 {
+    (ACCOUNT_STORAGE_OVERHEAD + data_len) * LAMPORTS_PER_BYTE_YEAR * EXEMPTION_THRESHOLD_YEARS
+}
+
+/// Rent owed for one epoch by a rent-paying account of `data_len` bytes
+pub fn epoch_rent(data_len: u64) -> u64 {
+    (ACCOUNT_STORAGE_OVERHEAD + data_len) * LAMPORTS_PER_BYTE_YEAR * SLOTS_PER_EPOCH / SLOTS_PER_YEAR
+}
+
+/// Whether `lamports` lost by a transaction in `era` can be rent collected from
+/// its accounts. Each account can have lost at most one epoch of rent, and no
+/// more than its own lamport decrease. Data lengths are not part of the
+/// transaction: token accounts are charged for `TOKEN_ACCOUNT_LEN` bytes and
+/// other accounts for none, so rent collected from larger accounts is not
+/// recognized.
+pub fn is_consistent_with_rent_collection(transaction: &ConfirmedTransaction, lamports: u64, era: RuntimeEra) -> bool {
+    if era != RuntimeEra::RentCollecting || lamports == 0 {
+        return false;
+    }
+    let Some(meta) = transaction.meta.as_ref() else { return false };
+    let is_token_account = |i: usize| meta.pre_token_balances.iter().chain(&meta.post_token_balances).any(|x| x.account_index as usize == i);
+    let collectable: u64 = meta.pre_balances.iter().zip(&meta.post_balances).enumerate().map(|(i, (pre, post))| {
+        let post = if i == 0 { post + meta.fee } else { *post };
+        let data_len = if is_token_account(i) { TOKEN_ACCOUNT_LEN } else { 0 };
+        pre.saturating_sub(post).min(epoch_rent(data_len))
+    }).sum();
+    lamports <= collectable
+}
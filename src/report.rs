@@ -0,0 +1,220 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use substreams_solana::pb::sf::solana::r#type::v1::{ConfirmedTransaction, TokenBalance};
+
+use crate::error::ParseError;
+use crate::instruction::get_flattened_instructions;
+use crate::pubkey::{Pubkey, PubkeyRef};
+use crate::rent::{is_consistent_with_rent_collection, RuntimeEra};
+use crate::spl_token::{TokenInstruction, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID, WRAPPED_SOL_MINT};
+use crate::transaction::TransactionContext;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Asset {
+    Sol,
+    Mint(Pubkey),
+}
+
+impl Asset {
+    fn sort_key(&self) -> Option<[u8; 32]> {
+        match self {
+            Asset::Sol => None,
+            Asset::Mint(mint) => Some(mint.0),
+        }
+    }
+}
+
+// SOL first, then mints by address
+impl Ord for Asset {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for Asset {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PartyRole {
+    FeePayer,
+    Signer,
+    Counterparty,
+}
+
+/// Net asset changes of a single owner over a transaction
+#[derive(Clone, Debug)]
+pub struct PartyPosition {
+    pub owner: Pubkey,
+    pub role: PartyRole,
+    /// Non zero net deltas, sorted by asset. SOL deltas exclude the transaction fee.
+    pub positions: Vec<(Asset, i128)>,
+    pub fees_paid: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// Nothing in the transaction explains the difference
+    Unexplained,
+    /// SOL lost to rent collection, in an era where accounts paid rent. Not a
+    /// violation as such.
+    RentCollected,
+}
+
+/// Asset whose deltas do not add up to what the transaction instructions explain
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConservationViolation {
+    pub asset: Asset,
+    pub expected: i128,
+    pub actual: i128,
+    pub kind: ViolationKind,
+}
+
+impl ConservationViolation {
+    pub fn is_violation(&self) -> bool {
+        self.kind == ViolationKind::Unexplained
+    }
+}
+
+/// Nets the SOL and token balance changes of a transaction per owner.
+///
+/// Token balances are attributed to the token account owner, and so are the
+/// lamports of token accounts, e.g. their rent when created or closed. Any other
+/// account is its own owner. Parties are sorted by role, then by address.
+/// Fails on a transaction without meta, or with a token balance whose amount is
+/// not a `u64`.
+pub fn balance_sheet(context: &TransactionContext, transaction: &ConfirmedTransaction) -> Result<Vec<PartyPosition>, ParseError> {
+    let meta = transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?;
+    let mut deltas: BTreeMap<[u8; 32], BTreeMap<Asset, i128>> = BTreeMap::new();
+    let fee_payer = context.fee_payer().and_then(|x| x.to_pubkey().ok());
+
+    let mut token_owners: BTreeMap<u32, Pubkey> = BTreeMap::new();
+    for token_balance in meta.pre_token_balances.iter().chain(&meta.post_token_balances) {
+        if let Some(owner) = token_balance_owner(context, token_balance) {
+            token_owners.insert(token_balance.account_index, owner);
+        }
+    }
+
+    for (i, (pre_balance, post_balance)) in meta.pre_balances.iter().zip(&meta.post_balances).enumerate() {
+        let mut delta = *post_balance as i128 - *pre_balance as i128;
+        if i == 0 {
+            delta += meta.fee as i128;
+        }
+        let owner = match token_owners.get(&(i as u32)) {
+            Some(owner) => *owner,
+            None => match context.accounts.get(i).and_then(|x| x.to_pubkey().ok()) {
+                Some(owner) => owner,
+                None => continue,
+            },
+        };
+        *deltas.entry(owner.0).or_default().entry(Asset::Sol).or_default() += delta;
+    }
+
+    for (token_balance, sign) in meta.pre_token_balances.iter().map(|x| (x, -1)).chain(meta.post_token_balances.iter().map(|x| (x, 1))) {
+        let (Some(owner), Ok(mint)) = (token_owners.get(&token_balance.account_index), Pubkey::try_from_string(&token_balance.mint)) else { continue };
+        *deltas.entry(owner.0).or_default().entry(Asset::Mint(mint)).or_default() += sign * token_amount(token_balance)?;
+    }
+    if let Some(fee_payer) = fee_payer {
+        deltas.entry(fee_payer.0).or_default();
+    }
+
+    let mut parties: Vec<PartyPosition> = deltas.into_iter().filter_map(|(owner, positions)| {
+        let owner = Pubkey(owner);
+        let is_fee_payer = fee_payer == Some(owner);
+        let positions: Vec<_> = positions.into_iter().filter(|(_, delta)| *delta != 0).collect();
+        if positions.is_empty() && !is_fee_payer {
+            return None;
+        }
+        let role = if is_fee_payer {
+            PartyRole::FeePayer
+        } else if context.signers.iter().any(|x| *x == owner) {
+            PartyRole::Signer
+        } else {
+            PartyRole::Counterparty
+        };
+        Some(PartyPosition { owner, role, positions, fees_paid: if is_fee_payer { meta.fee } else { 0 } })
+    }).collect();
+    parties.sort_by(|a, b| a.role.cmp(&b.role).then(a.owner.0.cmp(&b.owner.0)));
+    Ok(parties)
+}
+
+/// Checks that the per-asset deltas of `parties` sum up to what the transaction
+/// explains: zero for SOL once fees are set apart, and minted minus burned
+/// amounts for tokens. Wrapped SOL is not checked, since wrapping and unwrapping
+/// change its supply without minting or burning. Fails on a transaction without meta.
+pub fn check_conservation(transaction: &ConfirmedTransaction, parties: &[PartyPosition]) -> Result<Vec<ConservationViolation>, ParseError> {
+    check_conservation_in_era(transaction, parties, RuntimeEra::RentExempt)
+}
+
+/// Same as `check_conservation`, for a transaction processed in `era`. Before
+/// rent exemption was enforced, a SOL shortfall of a magnitude rent collection
+/// can explain is reported as `RentCollected`.
+pub fn check_conservation_in_era(transaction: &ConfirmedTransaction, parties: &[PartyPosition], era: RuntimeEra) -> Result<Vec<ConservationViolation>, ParseError> {
+    let mut expected: BTreeMap<Asset, i128> = BTreeMap::new();
+    let meta = transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?;
+    if meta.err.is_none() {
+        let accounts = transaction.resolved_accounts();
+        let mint = |instruction_accounts: &Vec<u8>, i: usize| -> Option<Asset> {
+            let index = *instruction_accounts.get(i)? as usize;
+            Some(Asset::Mint(Pubkey::try_from(accounts.get(index)?).ok()?))
+        };
+        for instruction in get_flattened_instructions(transaction).unwrap_or_default() {
+            let Some(program_id) = accounts.get(instruction.program_id_index() as usize) else { continue };
+            if PubkeyRef(program_id) != TOKEN_PROGRAM_ID && PubkeyRef(program_id) != TOKEN_2022_PROGRAM_ID {
+                continue;
+            }
+            let (asset, delta) = match TokenInstruction::unpack(instruction.data()) {
+                Ok(TokenInstruction::MintTo { amount }) |
+                Ok(TokenInstruction::MintToChecked { amount, decimals: _ }) => (mint(instruction.accounts(), 0), amount as i128),
+                Ok(TokenInstruction::Burn { amount }) |
+                Ok(TokenInstruction::BurnChecked { amount, decimals: _ }) => (mint(instruction.accounts(), 1), -(amount as i128)),
+                _ => continue,
+            };
+            if let Some(asset) = asset {
+                *expected.entry(asset).or_default() += delta;
+            }
+        }
+    }
+
+    let mut actual: BTreeMap<Asset, i128> = BTreeMap::new();
+    for (asset, delta) in parties.iter().flat_map(|party| &party.positions) {
+        *actual.entry(*asset).or_default() += delta;
+    }
+    expected.entry(Asset::Sol).or_default();
+
+    let mut assets: Vec<Asset> = expected.keys().chain(actual.keys()).copied().collect();
+    assets.sort();
+    assets.dedup();
+    Ok(assets.into_iter().filter(|asset| *asset != Asset::Mint(WRAPPED_SOL_MINT)).filter_map(|asset| {
+        let expected = expected.get(&asset).copied().unwrap_or_default();
+        let actual = actual.get(&asset).copied().unwrap_or_default();
+        if expected == actual {
+            return None;
+        }
+        let shortfall = u64::try_from(expected - actual).unwrap_or(0);
+        let kind = if asset == Asset::Sol && is_consistent_with_rent_collection(transaction, shortfall, era) {
+            ViolationKind::RentCollected
+        } else {
+            ViolationKind::Unexplained
+        };
+        Some(ConservationViolation { asset, expected, actual, kind })
+    }).collect())
+}
+
+fn token_balance_owner(context: &TransactionContext, token_balance: &TokenBalance) -> Option<Pubkey> {
+    if let Ok(owner) = Pubkey::try_from_string(&token_balance.owner) {
+        return Some(owner);
+    }
+    let address = context.accounts.get(token_balance.account_index as usize)?;
+    context.get_token_account(address).map(|x| x.owner)
+}
+
+fn token_amount(token_balance: &TokenBalance) -> Result<i128, ParseError> {
+    token_balance.ui_token_amount.as_ref()
+        .and_then(|x| x.amount.parse::<u64>().ok())
+        .map(|x| x as i128)
+        .ok_or(ParseError::MalformedTokenBalance { account_index: token_balance.account_index })
+}
@@ -3,3 +3,4 @@ use substreams_solana::b58;
 
 pub const WRAPPED_SOL_MINT: Pubkey = Pubkey(b58!("So11111111111111111111111111111111111111112"));
 pub const TOKEN_PROGRAM_ID: Pubkey = Pubkey(b58!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"));
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = Pubkey(b58!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"));
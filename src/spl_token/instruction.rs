@@ -468,6 +468,36 @@ pub enum TokenInstruction<'a> {
 impl<'a> TokenInstruction<'a> {
     /// Unpacks a byte buffer into a
     /// [TokenInstruction](enum.TokenInstruction.html).
+    ///
+    /// Extracting the token transfers of a transaction:
+    ///
+    /// ```
+    /// use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+    /// use substreams_solana_utils::spl_token::{TokenInstruction, TOKEN_PROGRAM_ID};
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, source, destination, program) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    /// let mut transfer = vec![3];
+    /// transfer.extend(250u64.to_le_bytes());
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .instruction(program, &[user], vec![0])
+    ///     .inner(TOKEN_PROGRAM_ID, &[source, destination, user], transfer)
+    ///     .build();
+    ///
+    /// let mut transfers = Vec::new();
+    /// for instruction in get_structured_instructions(&transaction).unwrap().flattened() {
+    ///     if instruction.program_id() != TOKEN_PROGRAM_ID {
+    ///         continue;
+    ///     }
+    ///     if let Ok(TokenInstruction::Transfer { amount }) = TokenInstruction::unpack(instruction.data()) {
+    ///         transfers.push((instruction.accounts()[0], instruction.accounts()[1], amount));
+    ///     }
+    /// }
+    /// assert_eq!(transfers.len(), 1);
+    /// let (from, to, amount) = transfers[0];
+    /// assert!(from == source && to == destination && amount == 250);
+    /// ```
     pub fn unpack(input: &'a [u8]) -> Result<Self, &'static str> {
         let (&tag, rest) = input.split_first().ok_or("Invalid instruction")?;
         Ok(match tag {
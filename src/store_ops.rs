@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use substreams_solana::pb::sf::solana::r#type::v1::{ConfirmedTransaction, TokenBalance};
+
+use crate::pubkey::Pubkey;
+use crate::spl_token::WRAPPED_SOL_MINT;
+use crate::transaction::TransactionContext;
+
+/// Store operation keeping a token account balance and ownership store up to date
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoreOp {
+    SetBalance { key: String, value: u64 },
+    SetOwner { key: String, value: Pubkey },
+    /// Deletes both the balance and the owner of the account, under `key`'s address
+    DeleteAccount { key: String },
+}
+
+impl StoreOp {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::SetBalance { key, .. } | Self::SetOwner { key, .. } | Self::DeleteAccount { key } => key,
+        }
+    }
+}
+
+pub fn balance_key(address: &Pubkey) -> String {
+    format!("balance:{}", address.to_string())
+}
+
+pub fn owner_key(address: &Pubkey) -> String {
+    format!("owner:{}", address.to_string())
+}
+
+/// Account key of `DeleteAccount`; delete both `balance_key` and `owner_key` of
+/// the address, e.g. with `delete_prefix`.
+pub fn account_key(address: &Pubkey) -> String {
+    address.to_string()
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StoreOpOptions {
+    /// Emit no `SetBalance` for wrapped SOL accounts, whose balance also follows
+    /// lamports moved outside the token program until a `SyncNative`. Their
+    /// `SetOwner` and `DeleteAccount` are still emitted.
+    pub skip_wrapped_sol_balances: bool,
+}
+
+/// Converts the token account changes of a transaction into store operations,
+/// comparing the pre and post token balances:
+///
+/// - Accounts created by the transaction get a `SetOwner` and a `SetBalance`.
+/// - Accounts whose balance or owner changed get a `SetBalance` or `SetOwner`.
+/// - Accounts closed by the transaction get a `DeleteAccount`.
+/// - Accounts closed and re-created within the transaction are treated as
+///   changed, so only their final state is written.
+/// - Accounts created and closed within the transaction, which appear in
+///   neither balance list, never produce an operation.
+/// - Balances that cannot be read, e.g. a missing amount, are skipped. The owner
+///   operation of the account is still emitted.
+/// - Wrapped SOL balances are skipped with `StoreOpOptions::skip_wrapped_sol_balances`.
+///
+/// Operations are sorted by account address. For a single account, a
+/// `SetOwner` comes before its `SetBalance`. A transaction without meta has none.
+///
+/// A store handler applies them in order, see `store_token_balances` in the
+/// examples for a substreams store module. Here a map stands for the store, on
+/// which `delete_prefix` removes both keys of a closed account:
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use substreams_solana_utils::store_ops::{balance_key, from_context, owner_key, StoreOp};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+/// use substreams_solana_utils::transaction::TransactionContext;
+///
+/// fn store_token_balances(store: &mut BTreeMap<String, String>, ops: Vec<StoreOp>) {
+///     for op in ops {
+///         match op {
+///             StoreOp::SetBalance { key, value } => store.insert(key, value.to_string()),
+///             StoreOp::SetOwner { key, value } => store.insert(key, value.to_string()),
+///             StoreOp::DeleteAccount { key } => {
+///                 store.remove(&format!("balance:{}", key));
+///                 store.remove(&format!("owner:{}", key))
+///             },
+///         };
+///     }
+/// }
+///
+/// let (user, mint, closed, created) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+/// let mut store = BTreeMap::new();
+/// store.insert(balance_key(&closed), "0".to_string());
+/// store.insert(owner_key(&closed), user.to_string());
+///
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .token_account(closed, mint, user, Some(0), None)
+///     .token_account(created, mint, user, None, Some(250))
+///     .build();
+/// let context = TransactionContext::build(&transaction).unwrap();
+/// store_token_balances(&mut store, from_context(&context, &transaction));
+///
+/// assert_eq!(store.len(), 2);
+/// assert_eq!(store[&balance_key(&created)], "250");
+/// assert_eq!(store[&owner_key(&created)], user.to_string());
+/// ```
+pub fn from_context(context: &TransactionContext, transaction: &ConfirmedTransaction) -> Vec<StoreOp> {
+    from_context_with_options(context, transaction, &StoreOpOptions::default())
+}
+
+pub fn from_context_with_options(context: &TransactionContext, transaction: &ConfirmedTransaction, options: &StoreOpOptions) -> Vec<StoreOp> {
+    let Some(meta) = transaction.meta.as_ref() else { return Vec::new() };
+    let mut accounts: BTreeMap<[u8; 32], (Option<&TokenBalance>, Option<&TokenBalance>)> = BTreeMap::new();
+    for token_balance in &meta.pre_token_balances {
+        let Some(address) = address(context, token_balance) else { continue };
+        accounts.entry(address.0).or_default().0 = Some(token_balance);
+    }
+    for token_balance in &meta.post_token_balances {
+        let Some(address) = address(context, token_balance) else { continue };
+        accounts.entry(address.0).or_default().1 = Some(token_balance);
+    }
+
+    let mut ops = Vec::new();
+    for (address, balances) in accounts {
+        let address = Pubkey(address);
+        match balances {
+            (Some(_), None) => ops.push(StoreOp::DeleteAccount { key: account_key(&address) }),
+            (pre, Some(post)) => {
+                if let Some(owner) = token_owner(context, &address, post) {
+                    if pre.and_then(|pre| token_owner(context, &address, pre)) != Some(owner) {
+                        ops.push(StoreOp::SetOwner { key: owner_key(&address), value: owner });
+                    }
+                }
+                if options.skip_wrapped_sol_balances && Pubkey::try_from_string(&post.mint).is_ok_and(|x| x == WRAPPED_SOL_MINT) {
+                    continue;
+                }
+                if let Some(balance) = amount(post) {
+                    if pre.and_then(amount) != Some(balance) {
+                        ops.push(StoreOp::SetBalance { key: balance_key(&address), value: balance });
+                    }
+                }
+            },
+            (None, None) => (),
+        }
+    }
+    ops
+}
+
+fn address(context: &TransactionContext, token_balance: &TokenBalance) -> Option<Pubkey> {
+    context.accounts.get(token_balance.account_index as usize)?.to_pubkey().ok()
+}
+
+fn token_owner(context: &TransactionContext, address: &Pubkey, token_balance: &TokenBalance) -> Option<Pubkey> {
+    Pubkey::try_from_string(&token_balance.owner).ok()
+        .or_else(|| context.token_accounts.get(address.0.as_slice()).map(|x| x.owner))
+}
+
+fn amount(token_balance: &TokenBalance) -> Option<u64> {
+    token_balance.ui_token_amount.as_ref()?.amount.parse().ok()
+}
@@ -0,0 +1,103 @@
+//! Instruction trees stored together with the transaction they borrow from.
+//!
+//! Trees borrow from their `ConfirmedTransaction`, so one built from a
+//! transaction of `block.transactions_owned()` cannot outlive the loop
+//! iteration. `StructuredTransaction` owns the transaction next to its tree, and
+//! the pair can be moved and kept as a single value.
+//!
+//! ```
+//! use substreams_solana_utils::structured_transaction::StructuredTransaction;
+//! use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+//!
+//! let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+//! let transactions = (0..3).map(|i| TransactionBuilder::new()
+//!     .signer(user)
+//!     .instruction(router, &[user, pool], vec![i])
+//!     .inner(pool, &[user], vec![i])
+//!     .build()
+//! );
+//!
+//! let structured: Vec<StructuredTransaction> = transactions.map(StructuredTransaction::try_new).collect::<Result<_, _>>().unwrap();
+//! let swap = structured[2].tree().inner_instructions(0).next().unwrap();
+//! assert_eq!(swap.program_id(), pool);
+//! assert_eq!(swap.data(), &vec![2]);
+//! assert_eq!(structured[2].transaction().meta.as_ref().unwrap().inner_instructions.len(), 1);
+//! ```
+
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::error::ParseError;
+use crate::instruction::{get_structured_instructions, StructuredInstruction};
+use crate::instruction_tree::{get_instruction_tree, InstructionTree};
+
+/// A transaction and its `InstructionTree`, see the module documentation
+pub struct StructuredTransaction {
+    /// Borrows from `transaction`, and is dropped before it
+    tree: ManuallyDrop<InstructionTree<'static>>,
+    /// Allocated with `Box`, so that its address does not change when `self` is
+    /// moved. Only ever accessed through shared references.
+    transaction: NonNull<ConfirmedTransaction>,
+}
+
+// SAFETY: `StructuredTransaction` uniquely owns the transaction, and both the
+// transaction and the tree are `Send` and `Sync`.
+unsafe impl Send for StructuredTransaction {}
+unsafe impl Sync for StructuredTransaction {}
+
+impl StructuredTransaction {
+    /// Builds the tree of `transaction`. Fails as `get_instruction_tree`.
+    pub fn try_new(transaction: ConfirmedTransaction) -> Result<Self, ParseError> {
+        let transaction = NonNull::from(Box::leak(Box::new(transaction)));
+        // SAFETY: the transaction lives until `drop`, which drops the tree first.
+        let tree = match get_instruction_tree(unsafe { transaction.as_ref() }) {
+            Ok(tree) => tree,
+            Err(error) => {
+                // SAFETY: allocated above with `Box`, and no longer borrowed.
+                drop(unsafe { Box::from_raw(transaction.as_ptr()) });
+                return Err(error);
+            },
+        };
+        Ok(Self { tree: ManuallyDrop::new(tree), transaction })
+    }
+
+    pub fn transaction(&self) -> &ConfirmedTransaction {
+        // SAFETY: valid until `drop`, and never mutated.
+        unsafe { self.transaction.as_ref() }
+    }
+
+    /// The tree, borrowing from `self` instead of the transaction
+    pub fn tree(&self) -> &InstructionTree<'_> {
+        &self.tree
+    }
+
+    /// The `Rc` based tree of the transaction, built on each call
+    pub fn structured_instructions(&self) -> Result<Vec<Rc<StructuredInstruction<'_>>>, ParseError> {
+        get_structured_instructions(self.transaction())
+    }
+
+    /// Drops the tree and returns the transaction
+    pub fn into_transaction(self) -> ConfirmedTransaction {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is not used after its fields are taken, and its `Drop`
+        // does not run. The tree is dropped before the transaction is released.
+        unsafe {
+            ManuallyDrop::drop(&mut this.tree);
+            *Box::from_raw(this.transaction.as_ptr())
+        }
+    }
+}
+
+impl Drop for StructuredTransaction {
+    fn drop(&mut self) {
+        // SAFETY: the tree is not used afterwards, and nothing borrows from the
+        // transaction once it is dropped.
+        unsafe {
+            ManuallyDrop::drop(&mut self.tree);
+            drop(Box::from_raw(self.transaction.as_ptr()));
+        }
+    }
+}
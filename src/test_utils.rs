@@ -0,0 +1,308 @@
+//! Builders for small synthetic transactions, to test code built on this crate
+//! without block fixtures.
+//!
+//! ```
+//! use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+//! use substreams_solana_utils::instruction::get_structured_instructions;
+//!
+//! let (user, program, token_program) = (pubkey(1), pubkey(2), substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID);
+//! let transaction = TransactionBuilder::new()
+//!     .signer(user)
+//!     .instruction(program, &[user], vec![0])
+//!     .inner(token_program, &[user], vec![9])
+//!     .build();
+//!
+//! let instructions = get_structured_instructions(&transaction).unwrap();
+//! assert_eq!(instructions.len(), 1);
+//! assert_eq!(instructions[0].inner_instructions().len(), 1);
+//! ```
+
+use substreams_solana::pb::sf::solana::r#type::v1 as pb;
+
+use crate::programs::{KnownProgram, ProgramCategory};
+use crate::pubkey::Pubkey;
+use crate::transaction_error::TransactionError;
+
+/// A distinct public key for each seed, `[seed; 32]`. Seed 0 is the system program.
+pub fn pubkey(seed: u8) -> Pubkey {
+    Pubkey([seed; 32])
+}
+
+struct BuiltTokenAccount {
+    address: Pubkey,
+    mint: Pubkey,
+    owner: Pubkey,
+    pre_balance: Option<u64>,
+    post_balance: Option<u64>,
+}
+
+struct BuiltInstruction {
+    stack_height: u32,
+    program_id: Pubkey,
+    accounts: Vec<Pubkey>,
+    data: Vec<u8>,
+}
+
+/// Builds a `ConfirmedTransaction`, filling in the message header, account
+/// indexes, inner instruction groups and stack heights.
+///
+/// Signers come first in the account keys, in the order they were added, and the
+/// first one is the fee payer. Other accounts follow in order of first use.
+/// Accounts are writable unless marked `readonly`, readonly ones being moved after
+/// the writable ones of their kind as the message header requires. Addresses
+/// passed to `lookup` are loaded from lookup tables instead, after the account
+/// keys. Unless `logs` is called, invoke and success logs are synthesized for
+/// every instruction.
+pub struct TransactionBuilder {
+    signers: Vec<Pubkey>,
+    accounts: Vec<Pubkey>,
+    readonly: Vec<Pubkey>,
+    loaded_writable: Vec<Pubkey>,
+    loaded_readonly: Vec<Pubkey>,
+    instructions: Vec<BuiltInstruction>,
+    logs: Option<Vec<String>>,
+    balances: Vec<(Pubkey, u64, u64)>,
+    token_accounts: Vec<BuiltTokenAccount>,
+    fee: u64,
+    err: Option<Vec<u8>>,
+    without_stack_heights: bool,
+    signature: Vec<u8>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self {
+            signers: Vec::new(),
+            accounts: Vec::new(),
+            readonly: Vec::new(),
+            loaded_writable: Vec::new(),
+            loaded_readonly: Vec::new(),
+            instructions: Vec::new(),
+            logs: None,
+            balances: Vec::new(),
+            token_accounts: Vec::new(),
+            fee: 0,
+            err: None,
+            without_stack_heights: false,
+            signature: vec![1; 64],
+        }
+    }
+
+    pub fn signer(mut self, pubkey: Pubkey) -> Self {
+        if !self.signers.contains(&pubkey) {
+            self.signers.push(pubkey);
+        }
+        self
+    }
+
+    pub fn account(mut self, pubkey: Pubkey) -> Self {
+        self.add_account(pubkey);
+        self
+    }
+
+    /// Marks a signer or account as readonly
+    pub fn readonly(mut self, pubkey: Pubkey) -> Self {
+        self.add_account(pubkey);
+        self.readonly.push(pubkey);
+        self
+    }
+
+    /// Loads the address from a lookup table instead of the account keys
+    pub fn lookup(mut self, pubkey: Pubkey, writable: bool) -> Self {
+        if writable {
+            self.loaded_writable.push(pubkey);
+        } else {
+            self.loaded_readonly.push(pubkey);
+        }
+        self
+    }
+
+    /// Adds a top level instruction
+    pub fn instruction(self, program_id: Pubkey, accounts: &[Pubkey], data: Vec<u8>) -> Self {
+        self.inner_at(1, program_id, accounts, data)
+    }
+
+    /// Adds an instruction invoked by the last top level instruction
+    pub fn inner(self, program_id: Pubkey, accounts: &[Pubkey], data: Vec<u8>) -> Self {
+        self.inner_at(2, program_id, accounts, data)
+    }
+
+    /// Adds an instruction at the given stack height, invoked by the last
+    /// instruction added one level up.
+    pub fn inner_at(mut self, stack_height: u32, program_id: Pubkey, accounts: &[Pubkey], data: Vec<u8>) -> Self {
+        accounts.iter().for_each(|x| self.add_account(*x));
+        self.add_account(program_id);
+        self.instructions.push(BuiltInstruction { stack_height, program_id, accounts: accounts.to_vec(), data });
+        self
+    }
+
+    /// Sets the log messages, instead of synthesizing them
+    pub fn logs(mut self, logs: &[&str]) -> Self {
+        self.logs = Some(logs.iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    pub fn lamports(mut self, pubkey: Pubkey, pre_balance: u64, post_balance: u64) -> Self {
+        self.add_account(pubkey);
+        self.balances.push((pubkey, pre_balance, post_balance));
+        self
+    }
+
+    /// Records the pre and post balances of a token account. `None` leaves the
+    /// account out of the corresponding balance list.
+    pub fn token_account(mut self, address: Pubkey, mint: Pubkey, owner: Pubkey, pre_balance: Option<u64>, post_balance: Option<u64>) -> Self {
+        self.add_account(address);
+        self.token_accounts.push(BuiltTokenAccount { address, mint, owner, pre_balance, post_balance });
+        self
+    }
+
+    pub fn fee(mut self, fee: u64) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Marks the transaction failed, with an empty `meta.err`
+    pub fn failed(mut self) -> Self {
+        self.err = Some(Vec::new());
+        self
+    }
+
+    /// Marks the transaction failed with `error`
+    pub fn failed_with(mut self, error: TransactionError) -> Self {
+        self.err = Some(error.encode());
+        self
+    }
+
+    /// Leaves the inner instruction stack heights unset, as in blocks produced
+    /// before they were recorded
+    pub fn without_stack_heights(mut self) -> Self {
+        self.without_stack_heights = true;
+        self
+    }
+
+    pub fn signature(mut self, signature: &[u8]) -> Self {
+        self.signature = signature.to_vec();
+        self
+    }
+
+    fn add_account(&mut self, pubkey: Pubkey) {
+        if !self.signers.contains(&pubkey) && !self.accounts.contains(&pubkey) {
+            self.accounts.push(pubkey);
+        }
+    }
+
+    pub fn build(self) -> pb::ConfirmedTransaction {
+        let is_loaded = |x: &Pubkey| self.loaded_writable.contains(x) || self.loaded_readonly.contains(x);
+        let (readonly_signers, writable_signers): (Vec<Pubkey>, Vec<Pubkey>) = self.signers.iter().partition(|x| self.readonly.contains(x));
+        let (readonly_accounts, writable_accounts): (Vec<Pubkey>, Vec<Pubkey>) = self.accounts.iter()
+            .filter(|x| !self.signers.contains(x) && !is_loaded(x))
+            .partition(|x| self.readonly.contains(x));
+        let account_keys: Vec<Pubkey> = [&writable_signers, &readonly_signers, &writable_accounts, &readonly_accounts].into_iter().flatten().copied().collect();
+        let resolved_accounts: Vec<Pubkey> = [&account_keys, &self.loaded_writable, &self.loaded_readonly].into_iter().flatten().copied().collect();
+        let index = |pubkey: &Pubkey| resolved_accounts.iter().position(|x| x == pubkey).unwrap();
+
+        let mut instructions = Vec::new();
+        let mut inner_instructions: Vec<pb::InnerInstructions> = Vec::new();
+        for instruction in &self.instructions {
+            let program_id_index = index(&instruction.program_id) as u32;
+            let accounts = instruction.accounts.iter().map(|x| index(x) as u8).collect();
+            let data = instruction.data.clone();
+            if instruction.stack_height <= 1 {
+                instructions.push(pb::CompiledInstruction { program_id_index, accounts, data });
+                continue;
+            }
+            let group_index = instructions.len().saturating_sub(1) as u32;
+            if inner_instructions.last().map(|x| x.index) != Some(group_index) {
+                inner_instructions.push(pb::InnerInstructions { index: group_index, instructions: Vec::new() });
+            }
+            inner_instructions.last_mut().unwrap().instructions.push(pb::InnerInstruction {
+                program_id_index,
+                accounts,
+                data,
+                stack_height: if self.without_stack_heights { None } else { Some(instruction.stack_height) },
+            });
+        }
+
+        let log_messages = match self.logs {
+            Some(ref logs) => logs.clone(),
+            None => self.synthesize_logs(),
+        };
+        let mut pre_balances = vec![0; resolved_accounts.len()];
+        let mut post_balances = vec![0; resolved_accounts.len()];
+        for (pubkey, pre_balance, post_balance) in &self.balances {
+            pre_balances[index(pubkey)] = *pre_balance;
+            post_balances[index(pubkey)] = *post_balance;
+        }
+        let token_balance = |account: &BuiltTokenAccount, amount: u64| pb::TokenBalance {
+            account_index: index(&account.address) as u32,
+            mint: account.mint.to_string(),
+            owner: account.owner.to_string(),
+            ui_token_amount: Some(pb::UiTokenAmount { amount: amount.to_string(), ..Default::default() }),
+            ..Default::default()
+        };
+        let pre_token_balances = self.token_accounts.iter().filter_map(|x| x.pre_balance.map(|amount| token_balance(x, amount))).collect();
+        let post_token_balances = self.token_accounts.iter().filter_map(|x| x.post_balance.map(|amount| token_balance(x, amount))).collect();
+
+        pb::ConfirmedTransaction {
+            transaction: Some(pb::Transaction {
+                signatures: vec![self.signature.clone()],
+                message: Some(pb::Message {
+                    header: Some(pb::MessageHeader {
+                        num_required_signatures: self.signers.len() as u32,
+                        num_readonly_signed_accounts: readonly_signers.len() as u32,
+                        num_readonly_unsigned_accounts: readonly_accounts.len() as u32,
+                    }),
+                    account_keys: account_keys.iter().map(|x| x.0.to_vec()).collect(),
+                    instructions,
+                    versioned: !self.loaded_writable.is_empty() || !self.loaded_readonly.is_empty(),
+                    ..Default::default()
+                }),
+            }),
+            meta: Some(pb::TransactionStatusMeta {
+                err: self.err.clone().map(|err| pb::TransactionError { err }),
+                fee: self.fee,
+                pre_balances,
+                post_balances,
+                inner_instructions,
+                log_messages,
+                pre_token_balances,
+                post_token_balances,
+                loaded_writable_addresses: self.loaded_writable.iter().map(|x| x.0.to_vec()).collect(),
+                loaded_readonly_addresses: self.loaded_readonly.iter().map(|x| x.0.to_vec()).collect(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn synthesize_logs(&self) -> Vec<String> {
+        let mut logs = Vec::new();
+        let mut stack: Vec<&BuiltInstruction> = Vec::new();
+        let is_logging = |instruction: &BuiltInstruction| {
+            KnownProgram::from_program_id(&instruction.program_id).map(|x| x.category()) != Some(ProgramCategory::Precompile)
+        };
+        for instruction in &self.instructions {
+            while stack.last().is_some_and(|x| x.stack_height >= instruction.stack_height) {
+                let closed = stack.pop().unwrap();
+                if is_logging(closed) {
+                    logs.push(format!("Program {} success", closed.program_id.to_string()));
+                }
+            }
+            if is_logging(instruction) {
+                logs.push(format!("Program {} invoke [{}]", instruction.program_id.to_string(), instruction.stack_height));
+            }
+            stack.push(instruction);
+        }
+        while let Some(closed) = stack.pop() {
+            if is_logging(closed) {
+                logs.push(format!("Program {} success", closed.program_id.to_string()));
+            }
+        }
+        logs
+    }
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
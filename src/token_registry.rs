@@ -0,0 +1,176 @@
+//! Token accounts and mint decimals discovered across the transactions of a block.
+//!
+//! Applying a transaction can be undone at transaction granularity: `snapshot`
+//! is O(1), and the writes made after it are journaled so that `restore` is
+//! O(changes since the snapshot). Snapshots nest; restoring or committing one
+//! also ends every snapshot taken after it.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+
+use crate::error::ParseError;
+use crate::pubkey::Pubkey;
+use crate::transaction::TransactionContext;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegisteredTokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+}
+
+/// Previous value of an entry written while a snapshot was open
+enum Change {
+    Account(Pubkey, Option<RegisteredTokenAccount>),
+    Decimals(Pubkey, Option<u8>),
+}
+
+/// Handle of a registry state, consumed by `restore` or `commit`
+#[derive(Debug)]
+pub struct RegistrySnapshot {
+    id: u64,
+}
+
+pub struct BlockTokenRegistry {
+    accounts: HashMap<Pubkey, RegisteredTokenAccount>,
+    decimals: HashMap<Pubkey, u8>,
+    journal: Vec<Change>,
+    /// Open snapshots, oldest first, with the journal length when they were taken
+    snapshots: Vec<(u64, usize)>,
+    next_snapshot_id: u64,
+}
+
+impl BlockTokenRegistry {
+    pub fn new() -> Self {
+        Self {
+            accounts: HashMap::new(),
+            decimals: HashMap::new(),
+            journal: Vec::new(),
+            snapshots: Vec::new(),
+            next_snapshot_id: 0,
+        }
+    }
+
+    pub fn get_token_account(&self, address: &Pubkey) -> Option<&RegisteredTokenAccount> {
+        self.accounts.get(address)
+    }
+
+    /// Decimals of `mint`, from the token balances of the applied transactions
+    pub fn decimals(&self, mint: &Pubkey) -> Option<u8> {
+        self.decimals.get(mint).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn insert_account(&mut self, address: Pubkey, account: RegisteredTokenAccount) {
+        let previous = self.accounts.insert(address, account);
+        if previous != Some(account) {
+            self.record(Change::Account(address, previous));
+        }
+    }
+
+    pub fn remove_account(&mut self, address: &Pubkey) {
+        if let Some(previous) = self.accounts.remove(address) {
+            self.record(Change::Account(*address, Some(previous)));
+        }
+    }
+
+    pub fn set_decimals(&mut self, mint: Pubkey, decimals: u8) {
+        let previous = self.decimals.insert(mint, decimals);
+        if previous != Some(decimals) {
+            self.record(Change::Decimals(mint, previous));
+        }
+    }
+
+    /// Registers the token accounts known to `context` and the mint decimals of
+    /// the transaction token balances. Accounts that only appear in the pre
+    /// token balances were closed by the transaction and are removed. Fails
+    /// without writing anything if the transaction has no meta.
+    pub fn apply(&mut self, context: &TransactionContext, transaction: &ConfirmedTransaction) -> Result<(), ParseError> {
+        let meta = transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?;
+        for token_account in context.token_accounts.values() {
+            let Ok(address) = token_account.address.to_pubkey() else { continue };
+            self.insert_account(address, RegisteredTokenAccount { mint: token_account.mint, owner: token_account.owner });
+        }
+        for token_balance in meta.pre_token_balances.iter().chain(&meta.post_token_balances) {
+            let (Ok(mint), Some(amount)) = (Pubkey::try_from_string(&token_balance.mint), token_balance.ui_token_amount.as_ref()) else { continue };
+            if let Ok(decimals) = u8::try_from(amount.decimals) {
+                self.set_decimals(mint, decimals);
+            }
+        }
+        for token_balance in &meta.pre_token_balances {
+            let index = token_balance.account_index;
+            if meta.post_token_balances.iter().any(|x| x.account_index == index) {
+                continue;
+            }
+            if let Some(Ok(address)) = context.accounts.get(index as usize).map(|x| x.to_pubkey()) {
+                self.remove_account(&address);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn snapshot(&mut self) -> RegistrySnapshot {
+        let id = self.next_snapshot_id;
+        self.next_snapshot_id += 1;
+        self.snapshots.push((id, self.journal.len()));
+        RegistrySnapshot { id }
+    }
+
+    /// Undoes every write made since `snapshot` was taken. Fails if the snapshot
+    /// already ended, through an earlier snapshot being restored or committed.
+    pub fn restore(&mut self, snapshot: RegistrySnapshot) -> Result<(), Error> {
+        let position = self.end_snapshot(&snapshot)?;
+        while self.journal.len() > position {
+            match self.journal.pop().unwrap() {
+                Change::Account(address, Some(account)) => { self.accounts.insert(address, account); },
+                Change::Account(address, None) => { self.accounts.remove(&address); },
+                Change::Decimals(mint, Some(decimals)) => { self.decimals.insert(mint, decimals); },
+                Change::Decimals(mint, None) => { self.decimals.remove(&mint); },
+            }
+        }
+        self.release_journal();
+        Ok(())
+    }
+
+    /// Keeps the writes made since `snapshot` was taken. They can still be
+    /// undone by restoring an enclosing snapshot.
+    pub fn commit(&mut self, snapshot: RegistrySnapshot) -> Result<(), Error> {
+        self.end_snapshot(&snapshot)?;
+        self.release_journal();
+        Ok(())
+    }
+
+    fn end_snapshot(&mut self, snapshot: &RegistrySnapshot) -> Result<usize, Error> {
+        let index = self.snapshots.iter().position(|(id, _)| *id == snapshot.id)
+            .ok_or_else(|| anyhow!("Snapshot {} already ended", snapshot.id))?;
+        let (_, position) = self.snapshots[index];
+        self.snapshots.truncate(index);
+        Ok(position)
+    }
+
+    fn record(&mut self, change: Change) {
+        if !self.snapshots.is_empty() {
+            self.journal.push(change);
+        }
+    }
+
+    fn release_journal(&mut self) {
+        if self.snapshots.is_empty() {
+            self.journal.clear();
+        }
+    }
+}
+
+impl Default for BlockTokenRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 
-use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana::pb::sf::solana::r#type::v1::{ConfirmedTransaction, Message};
 
 use crate::pubkey::{Pubkey, PubkeyRef};
-use crate::instruction::{WrappedInstruction, get_flattened_instructions};
-use crate::spl_token::{TokenAccount, TokenInstruction, TOKEN_PROGRAM_ID, WRAPPED_SOL_MINT};
-use crate::account::AccountBalance;
+use crate::error::ParseError;
+use crate::instruction::{WrappedInstruction, check_account_indices, get_flattened_instructions, get_message_and_meta};
+use crate::spl_token::{TokenAccount, TokenInstruction, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID, WRAPPED_SOL_MINT};
+use crate::account::{AccountBalance, SolBalance};
+use crate::transaction_error::TransactionError;
 
 use anyhow::{anyhow, Error};
 
@@ -14,59 +16,129 @@ pub struct TransactionContext<'a> {
     pub accounts: Vec<PubkeyRef<'a>>,
     pub account_balances: Vec<AccountBalance>,
     pub token_accounts: HashMap<PubkeyRef<'a>, TokenAccount<'a>>,
+    /// Lamports of each account, lookup table addresses included. An address
+    /// listed more than once keeps the balances of its first index; the runtime
+    /// rejects transactions loading an account twice.
+    pub sol_balances: HashMap<PubkeyRef<'a>, SolBalance>,
+    fee: u64,
     pub signers: Vec<PubkeyRef<'a>>,
+    num_readonly_signers: usize,
     pub signature: String,
+    error: Option<TransactionError>,
+    /// Set when neither token program is among the transaction accounts, in which
+    /// case instructions are not scanned for token account creations.
+    pub token_tracking_skipped: bool,
 }
 
 impl<'a> TransactionContext<'a> {
-    fn new(transaction: &'a ConfirmedTransaction) -> Self {
-        let accounts = transaction.resolved_accounts().iter().map(|x| PubkeyRef { 0: x }).collect::<Vec<_>>();
-        let signature = bs58::encode(transaction.transaction.as_ref().unwrap().signatures.get(0).unwrap()).into_string();
-        let num_required_signatures = transaction.transaction.as_ref().unwrap().message.as_ref().unwrap().header.as_ref().unwrap().num_required_signatures;
-        let signers = accounts[..num_required_signatures as usize].to_vec();
-
+    fn empty() -> Self {
         Self {
-            accounts,
+            accounts: Vec::new(),
             token_accounts: HashMap::new(),
             account_balances: Vec::new(),
-            signers,
-            signature,
+            sol_balances: HashMap::new(),
+            fee: 0,
+            signers: Vec::new(),
+            num_readonly_signers: 0,
+            signature: String::new(),
+            error: None,
+            token_tracking_skipped: false,
         }
     }
 
-    pub fn build(transaction: &'a ConfirmedTransaction) -> Result<Self, &'static str> {
-        let mut context = Self::new(transaction);
+    /// Collects the accounts, signers and token accounts of a transaction.
+    ///
+    /// ```
+    /// use substreams_solana_utils::transaction::TransactionContext;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, mint, token_account) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .token_account(token_account, mint, user, Some(100), Some(40))
+    ///     .build();
+    ///
+    /// let context = TransactionContext::build(&transaction).unwrap();
+    /// assert_eq!(context.fee_payer().unwrap(), user);
+    /// assert_eq!(context.signers.len(), 1);
+    /// let account = context.get_token_account(&context.accounts[1]).unwrap();
+    /// assert_eq!((account.mint, account.owner, account.pre_balance), (mint, user, Some(100)));
+    /// ```
+    pub fn build(transaction: &'a ConfirmedTransaction) -> Result<Self, ParseError> {
+        let mut context = Self::empty();
+        context.load(transaction)?;
+        Ok(context)
+    }
+
+    /// Replaces the content of the context with that of `transaction`, keeping allocations.
+    fn load(&mut self, transaction: &'a ConfirmedTransaction) -> Result<(), ParseError> {
+        let (message, meta) = get_message_and_meta(transaction)?;
+        let signature = get_first_signature(transaction)?;
+        self.accounts.clear();
+        self.token_accounts.clear();
+        self.account_balances.clear();
+        self.sol_balances.clear();
+        self.signers.clear();
+        self.signature.clear();
+
+        self.accounts.extend(transaction.resolved_accounts().into_iter().map(PubkeyRef));
+        bs58::encode(signature).onto(&mut self.signature).expect("Encoding into a String does not fail");
+        let num_required_signatures = get_num_required_signatures(message);
+        let signers = self.accounts.get(..num_required_signatures)
+            .ok_or(ParseError::TooManySignatures { num_required_signatures, len: self.accounts.len() })?;
+        self.signers.extend_from_slice(signers);
+        self.num_readonly_signers = get_num_readonly_signed_accounts(message).min(num_required_signatures);
 
-        for token_balance in &transaction.meta.as_ref().unwrap().pre_token_balances {
-            let address = context.accounts[token_balance.account_index as usize].clone();
-            let balance = Some(token_balance.ui_token_amount.as_ref().unwrap().amount.parse::<u64>().expect("Failed to parse u64"));
+        for token_balance in &meta.pre_token_balances {
+            let account_index = token_balance.account_index;
+            let malformed = || ParseError::MalformedTokenBalance { account_index };
+            let address = *self.accounts.get(account_index as usize)
+                .ok_or(ParseError::AccountIndexOutOfBounds { index: account_index as usize, len: self.accounts.len() })?;
+            let balance = token_balance.ui_token_amount.as_ref()
+                .and_then(|x| x.amount.parse::<u64>().ok())
+                .ok_or_else(malformed)?;
             let token_account = TokenAccount {
-                address: address.clone(),
-                mint: Pubkey::try_from_string(&token_balance.mint).unwrap(),
-                owner: Pubkey::try_from_string(&token_balance.owner).unwrap(),
-                pre_balance: balance,
-                post_balance: balance,
+                address,
+                mint: Pubkey::try_from_string(&token_balance.mint).map_err(|_| malformed())?,
+                owner: Pubkey::try_from_string(&token_balance.owner).map_err(|_| malformed())?,
+                pre_balance: Some(balance),
+                post_balance: Some(balance),
             };
-            context.token_accounts.insert(address, token_account);
+            self.token_accounts.insert(address, token_account);
         }
 
-        let pre_balances = &transaction.meta.as_ref().unwrap().pre_balances;
-        let post_balances = &transaction.meta.as_ref().unwrap().post_balances;
+        let pre_balances = &meta.pre_balances;
+        let post_balances = &meta.post_balances;
         for (pre_balance, post_balance) in pre_balances.iter().cloned().zip(post_balances.iter().cloned()) {
-            context.account_balances.push(AccountBalance { pre_balance, post_balance });
+            self.account_balances.push(AccountBalance { pre_balance, post_balance });
         }
+        for (address, balance) in self.accounts.iter().zip(&self.account_balances) {
+            self.sol_balances.entry(*address).or_insert(SolBalance { pre: balance.pre_balance, post: balance.post_balance });
+        }
+        self.fee = meta.fee;
+        self.error = get_transaction_error(transaction);
 
-        let instructions = get_flattened_instructions(transaction);
+        // Resolved accounts include lookup table addresses, so a token program
+        // invoked through a loaded program id is still found here.
+        self.token_tracking_skipped = !self.accounts.iter().any(is_token_program);
+        if self.token_tracking_skipped {
+            return Ok(());
+        }
+        let instructions = get_flattened_instructions(transaction)?;
         for instruction in instructions {
-            context.update_accounts(&instruction);
+            self.update_accounts(&instruction)?;
         }
 
-        Ok(context)
+        Ok(())
     }
 
-    pub fn update_accounts(&mut self, instruction: &WrappedInstruction) {
-        if self.accounts[instruction.program_id_index() as usize] != TOKEN_PROGRAM_ID {
-            return;
+    /// Registers the token account initialized by `instruction`, of either token
+    /// program, if any. Fails if the instruction references an account index out
+    /// of the transaction accounts.
+    pub fn update_accounts(&mut self, instruction: &WrappedInstruction) -> Result<(), ParseError> {
+        check_account_indices(instruction, self.accounts.len())?;
+        if !is_token_program(&self.accounts[instruction.program_id_index() as usize]) {
+            return Ok(());
         }
         match TokenInstruction::unpack(&instruction.data()) {
             Ok(TokenInstruction::InitializeAccount) => {
@@ -80,6 +152,7 @@ impl<'a> TransactionContext<'a> {
             }
             _ => ()
         }
+        Ok(())
     }
 
     pub fn update_balance(&mut self, instruction: &WrappedInstruction) {
@@ -159,6 +232,86 @@ impl<'a> TransactionContext<'a> {
     pub fn get_token_account(&self, address: &PubkeyRef<'a>) -> Option<&TokenAccount> {
         self.token_accounts.get(address)
     }
+
+    pub fn get_sol_balance(&self, address: &PubkeyRef<'a>) -> Option<&SolBalance> {
+        self.sol_balances.get(address)
+    }
+
+    /// Lamports gained by `address` in the transaction, negative when lost, 0
+    /// for an account without balances. The fee payer's delta includes the fee.
+    ///
+    /// ```
+    /// use substreams_solana_utils::transaction::TransactionContext;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, recipient, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .lookup(pool, true)
+    ///     .lamports(user, 10_000_000, 8_995_000)
+    ///     .lamports(recipient, 0, 1_000_000)
+    ///     .lamports(pool, 5_000_000, 5_000_000)
+    ///     .fee(5_000)
+    ///     .build();
+    ///
+    /// let context = TransactionContext::build(&transaction).unwrap();
+    /// let fee_payer = context.fee_payer().unwrap();
+    /// assert_eq!(context.sol_delta(&fee_payer), -1_005_000);
+    /// assert_eq!(context.fee_payer_sol_delta_excluding_fee(), Some(-1_000_000));
+    /// let pool = context.accounts.iter().find(|x| **x == pool).unwrap();
+    /// assert_eq!(context.get_sol_balance(pool).unwrap().pre, 5_000_000);
+    /// ```
+    pub fn sol_delta(&self, address: &PubkeyRef<'a>) -> i128 {
+        self.get_sol_balance(address).map_or(0, SolBalance::delta)
+    }
+
+    /// The first account of the message, or `None` for a message without account keys
+    pub fn fee_payer(&self) -> Option<PubkeyRef<'a>> {
+        self.accounts.first().copied()
+    }
+
+    /// Signers whose accounts the message requests as writable, the fee payer first
+    ///
+    /// ```
+    /// use substreams_solana_utils::transaction::TransactionContext;
+    /// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+    ///
+    /// let (user, authority) = (pubkey(1), pubkey(2));
+    /// let transaction = TransactionBuilder::new()
+    ///     .signer(user)
+    ///     .signer(authority)
+    ///     .readonly(authority)
+    ///     .build();
+    ///
+    /// let context = TransactionContext::build(&transaction).unwrap();
+    /// assert_eq!(context.signers, [user, authority]);
+    /// assert_eq!(context.writable_signers(), [user]);
+    /// assert_eq!(context.readonly_signers(), [authority]);
+    /// ```
+    pub fn writable_signers(&self) -> &[PubkeyRef<'a>] {
+        &self.signers[..self.signers.len() - self.num_readonly_signers]
+    }
+
+    /// Signers whose accounts the message requests as readonly
+    pub fn readonly_signers(&self) -> &[PubkeyRef<'a>] {
+        &self.signers[self.signers.len() - self.num_readonly_signers..]
+    }
+
+    /// Fee paid by the fee payer, in lamports
+    pub fn fee(&self) -> u64 {
+        self.fee
+    }
+
+    /// Error of the transaction if it failed, see `get_transaction_error`
+    pub fn transaction_error(&self) -> Option<&TransactionError> {
+        self.error.as_ref()
+    }
+
+    /// `sol_delta` of the fee payer with the fee added back, i.e. the lamports
+    /// moved by the instructions alone. `None` without a fee payer.
+    pub fn fee_payer_sol_delta_excluding_fee(&self) -> Option<i128> {
+        self.fee_payer().map(|fee_payer| self.sol_delta(&fee_payer) + self.fee as i128)
+    }
 }
 
 /// Parses the Initialize SPL Token Instruction and returns a TokenAccount
@@ -180,19 +333,245 @@ fn parse_token_account_from_initialize_account_instruction<'a>(instruction: &Wra
     }
 }
 
+fn is_token_program(program_id: &PubkeyRef) -> bool {
+    *program_id == TOKEN_PROGRAM_ID || *program_id == TOKEN_2022_PROGRAM_ID
+}
+
+/// Builds the contexts of the transactions of a block, reusing the maps and
+/// vectors of contexts handed back through `recycle`.
+///
+/// ```
+/// use substreams_solana_utils::transaction::ContextFactory;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let block: Vec<_> = (1..4).map(|i| TransactionBuilder::new().signer(pubkey(i)).build()).collect();
+/// let mut factory = ContextFactory::new();
+/// for (i, transaction) in (1..4).zip(&block) {
+///     let context = factory.context_for(transaction).unwrap();
+///     assert_eq!(context.fee_payer().unwrap(), pubkey(i));
+///     factory.recycle(context);
+/// }
+/// ```
+pub struct ContextFactory<'a> {
+    recycled: Vec<TransactionContext<'a>>,
+}
+
+impl<'a> ContextFactory<'a> {
+    pub fn new() -> Self {
+        Self { recycled: Vec::new() }
+    }
+
+    /// Same as `get_context`, but reuses the allocations of a recycled context if available.
+    /// A context left half loaded by a malformed transaction is kept for reuse.
+    pub fn context_for(&mut self, transaction: &'a ConfirmedTransaction) -> Result<TransactionContext<'a>, Error> {
+        if transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.err.is_some() {
+            return Err(anyhow!("Cannot get context of failed instruction."));
+        }
+        let mut context = self.recycled.pop().unwrap_or_else(TransactionContext::empty);
+        if let Err(error) = context.load(transaction) {
+            self.recycled.push(context);
+            return Err(error.into());
+        }
+        Ok(context)
+    }
+
+    /// Hands a context back so its allocations can be reused by the next `context_for`.
+    pub fn recycle(&mut self, context: TransactionContext<'a>) {
+        self.recycled.push(context);
+    }
+}
+
+impl Default for ContextFactory<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub fn get_context<'a>(transaction: &'a ConfirmedTransaction) -> Result<TransactionContext<'a>, Error> {
-    if let Some(_) = transaction.meta.as_ref().unwrap().err {
+    if transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.err.is_some() {
         return Err(anyhow!("Cannot get context of failed instruction."));
     }
-    TransactionContext::build(transaction).map_err(|x| anyhow!(x))
+    Ok(TransactionContext::build(transaction)?)
 }
 
-pub fn get_signature(transaction: &ConfirmedTransaction) -> String {
-    bs58::encode(transaction.transaction.as_ref().unwrap().signatures.get(0).unwrap()).into_string()
+/// Returns the first signature of the transaction, which identifies it
+///
+/// ```
+/// use substreams_solana_utils::error::ParseError;
+/// use substreams_solana_utils::transaction::get_signature;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let mut transaction = TransactionBuilder::new().signer(pubkey(1)).signature(&[2; 64]).build();
+/// assert_eq!(get_signature(&transaction), Ok(bs58::encode([2; 64]).into_string()));
+///
+/// transaction.transaction.as_mut().unwrap().signatures.clear();
+/// assert_eq!(get_signature(&transaction), Err(ParseError::MissingSignature));
+/// ```
+pub fn get_signature(transaction: &ConfirmedTransaction) -> Result<String, ParseError> {
+    Ok(bs58::encode(get_first_signature(transaction)?).into_string())
 }
 
+fn get_first_signature(transaction: &ConfirmedTransaction) -> Result<&Vec<u8>, ParseError> {
+    let signatures = &transaction.transaction.as_ref().ok_or(ParseError::MissingTransaction)?.signatures;
+    signatures.first().ok_or(ParseError::MissingSignature)
+}
+
+/// Fee paid by the fee payer of the transaction, in lamports
+///
+/// ```
+/// use substreams_solana_utils::error::ParseError;
+/// use substreams_solana_utils::transaction::get_fee;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let mut transaction = TransactionBuilder::new().signer(pubkey(1)).fee(5_000).build();
+/// assert_eq!(get_fee(&transaction), Ok(5_000));
+///
+/// transaction.meta = None;
+/// assert_eq!(get_fee(&transaction), Err(ParseError::MissingMeta));
+/// ```
+pub fn get_fee(transaction: &ConfirmedTransaction) -> Result<u64, ParseError> {
+    Ok(transaction.meta.as_ref().ok_or(ParseError::MissingMeta)?.fee)
+}
+
+/// Error of a failed transaction, decoded from `meta.err`. `None` if the
+/// transaction succeeded, or if its error could not be decoded.
+///
+/// ```
+/// use substreams_solana_utils::transaction::get_transaction_error;
+/// use substreams_solana_utils::transaction_error::TransactionError;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let transaction = TransactionBuilder::new().signer(pubkey(1)).build();
+/// assert_eq!(get_transaction_error(&transaction), None);
+///
+/// let transaction = TransactionBuilder::new()
+///     .signer(pubkey(1))
+///     .failed_with(TransactionError::BlockhashNotFound)
+///     .build();
+/// assert_eq!(get_transaction_error(&transaction), Some(TransactionError::BlockhashNotFound));
+/// ```
+pub fn get_transaction_error(transaction: &ConfirmedTransaction) -> Option<TransactionError> {
+    TransactionError::decode(&transaction.meta.as_ref()?.err.as_ref()?.err)
+}
+
+/// Addresses of the signers of the transaction. A header claiming more
+/// signatures than accounts yields the accounts there are.
 pub fn get_signers(transaction: &ConfirmedTransaction) -> Vec<String> {
-    let accounts = transaction.resolved_accounts().iter().map(|x| PubkeyRef { 0: x }).collect::<Vec<_>>();
-    let num_required_signatures = transaction.transaction.as_ref().unwrap().message.as_ref().unwrap().header.as_ref().unwrap().num_required_signatures;
-    accounts[..num_required_signatures as usize].iter().map(|x| x.to_string()).collect()
+    get_signer_metas(transaction).into_iter().map(|(address, _)| address).collect()
+}
+
+/// Addresses of the signers of the transaction, each with whether the message
+/// requests its account as writable
+///
+/// ```
+/// use substreams_solana_utils::transaction::get_signer_metas;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, authority) = (pubkey(1), pubkey(2));
+/// let transaction = TransactionBuilder::new().signer(user).signer(authority).readonly(authority).build();
+/// assert_eq!(get_signer_metas(&transaction), [(user.to_string(), true), (authority.to_string(), false)]);
+/// ```
+pub fn get_signer_metas(transaction: &ConfirmedTransaction) -> Vec<(String, bool)> {
+    let Some(message) = transaction.transaction.as_ref().and_then(|x| x.message.as_ref()) else { return Vec::new() };
+    let accounts = transaction.resolved_accounts();
+    let num_required_signatures = get_num_required_signatures(message).min(accounts.len());
+    let num_writable_signers = num_required_signatures.saturating_sub(get_num_readonly_signed_accounts(message));
+    accounts[..num_required_signatures].iter().enumerate()
+        .map(|(index, x)| (PubkeyRef(x).to_string(), index < num_writable_signers))
+        .collect()
+}
+
+/// Data last set with `set_return_data` in the transaction, and the program
+/// that set it, from `meta.return_data`. Unlike `Program return` logs, it
+/// survives log truncation. `None` when unset or not recorded.
+///
+/// ```
+/// use substreams_solana::pb::sf::solana::r#type::v1::ReturnData;
+/// use substreams_solana_utils::transaction::get_return_data;
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let mut transaction = TransactionBuilder::new()
+///     .signer(pubkey(1))
+///     .instruction(pubkey(2), &[pubkey(1)], vec![0])
+///     .build();
+/// assert_eq!(get_return_data(&transaction), None);
+///
+/// let return_data = ReturnData { program_id: pubkey(2).0.to_vec(), data: vec![1, 2, 3] };
+/// transaction.meta.as_mut().unwrap().return_data = Some(return_data);
+/// assert_eq!(get_return_data(&transaction), Some((pubkey(2), vec![1, 2, 3])));
+/// ```
+pub fn get_return_data(transaction: &ConfirmedTransaction) -> Option<(Pubkey, Vec<u8>)> {
+    let return_data = transaction.meta.as_ref()?.return_data.as_ref()?;
+    Some((Pubkey::try_from(&return_data.program_id).ok()?, return_data.data.clone()))
+}
+
+/// Whether an instruction of the transaction, inner instructions included,
+/// invokes `program`. Only reads the accounts and the program id indices of the
+/// instructions, to cheaply skip transactions before structuring them.
+///
+/// ```
+/// use substreams_solana_utils::transaction::{transaction_invokes_program, transaction_invokes_programs};
+/// use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+///
+/// let (user, router, pool, unused) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+/// let transaction = TransactionBuilder::new()
+///     .signer(user)
+///     .lookup(pool, false)
+///     .instruction(router, &[user, unused], vec![0])
+///     .inner(pool, &[user], vec![1])
+///     .build();
+///
+/// assert!(transaction_invokes_program(&transaction, &pool));
+/// assert!(!transaction_invokes_program(&transaction, &unused));
+/// assert_eq!(transaction_invokes_programs(&transaction, &[unused, pool, router]), [pool, router]);
+/// ```
+pub fn transaction_invokes_program(transaction: &ConfirmedTransaction, program: &Pubkey) -> bool {
+    match account_index(transaction, program) {
+        Some(index) => program_id_indices(transaction).any(|x| x == index),
+        None => false,
+    }
+}
+
+/// The `programs` invoked by the transaction, in the order of `programs`. See
+/// `transaction_invokes_program`.
+pub fn transaction_invokes_programs(transaction: &ConfirmedTransaction, programs: &[Pubkey]) -> Vec<Pubkey> {
+    let mut candidates: Vec<(u32, Pubkey, bool)> = programs.iter()
+        .filter_map(|program| Some((account_index(transaction, program)?, *program, false)))
+        .collect();
+    if candidates.is_empty() {
+        return Vec::new();
+    }
+    for index in program_id_indices(transaction) {
+        for (_, _, invoked) in candidates.iter_mut().filter(|(x, ..)| *x == index) {
+            *invoked = true;
+        }
+    }
+    candidates.into_iter().filter(|(.., invoked)| *invoked).map(|(_, program, _)| program).collect()
+}
+
+/// Position of `pubkey` among the account keys followed by the loaded addresses,
+/// without collecting them as `resolved_accounts` does
+fn account_index(transaction: &ConfirmedTransaction, pubkey: &Pubkey) -> Option<u32> {
+    let message = transaction.transaction.as_ref()?.message.as_ref()?;
+    let loaded_addresses = transaction.meta.as_ref().map(|meta| meta.loaded_writable_addresses.iter().chain(&meta.loaded_readonly_addresses));
+    message.account_keys.iter().chain(loaded_addresses.into_iter().flatten())
+        .position(|x| x[..] == pubkey.0[..])
+        .map(|x| x as u32)
+}
+
+/// Program id indices of the top level instructions, then of the inner instructions
+fn program_id_indices(transaction: &ConfirmedTransaction) -> impl Iterator<Item = u32> + '_ {
+    let instructions = transaction.transaction.as_ref().and_then(|x| x.message.as_ref())
+        .map(|message| message.instructions.iter().map(|x| x.program_id_index));
+    let inner_instructions = transaction.meta.as_ref()
+        .map(|meta| meta.inner_instructions.iter().flat_map(|x| x.instructions.iter().map(|x| x.program_id_index)));
+    instructions.into_iter().flatten().chain(inner_instructions.into_iter().flatten())
+}
+
+fn get_num_required_signatures(message: &Message) -> usize {
+    message.header.as_ref().map_or(0, |header| header.num_required_signatures as usize)
+}
+
+fn get_num_readonly_signed_accounts(message: &Message) -> usize {
+    message.header.as_ref().map_or(0, |header| header.num_readonly_signed_accounts as usize)
 }
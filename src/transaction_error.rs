@@ -0,0 +1,376 @@
+//! Errors of failed transactions, decoded from `meta.err`.
+//!
+//! `meta.err` holds the bincode encoding of the runtime `TransactionError`: a
+//! little endian `u32` variant index followed by the fields of the variant.
+//! Variants added to the runtime after this module was written decode as
+//! `Unknown`, with their index.
+//!
+//! ```
+//! use substreams_solana_utils::transaction::get_transaction_error;
+//! use substreams_solana_utils::transaction_error::{InstructionError, TransactionError};
+//! use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+//!
+//! let error = TransactionError::InstructionError { index: 1, error: InstructionError::Custom(6001) };
+//! let transaction = TransactionBuilder::new()
+//!     .signer(pubkey(1))
+//!     .instruction(pubkey(2), &[pubkey(1)], vec![0])
+//!     .instruction(pubkey(3), &[pubkey(1)], vec![0])
+//!     .failed_with(error.clone())
+//!     .build();
+//!
+//! let decoded = get_transaction_error(&transaction).unwrap();
+//! assert_eq!(decoded, error);
+//! assert_eq!((decoded.instruction_index(), decoded.custom_error_code()), (Some(1), Some(6001)));
+//! ```
+
+/// Error of a failed transaction, see the module documentation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransactionError {
+    AccountInUse,
+    AccountLoadedTwice,
+    AccountNotFound,
+    ProgramAccountNotFound,
+    InsufficientFundsForFee,
+    InvalidAccountForFee,
+    AlreadyProcessed,
+    BlockhashNotFound,
+    /// Error of the top level instruction at `index`, inner instructions included
+    InstructionError { index: u8, error: InstructionError },
+    CallChainTooDeep,
+    MissingSignatureForFee,
+    InvalidAccountIndex,
+    SignatureFailure,
+    InvalidProgramForExecution,
+    SanitizeFailure,
+    ClusterMaintenance,
+    AccountBorrowOutstanding,
+    WouldExceedMaxBlockCostLimit,
+    UnsupportedVersion,
+    InvalidWritableAccount,
+    WouldExceedMaxAccountCostLimit,
+    WouldExceedAccountDataBlockLimit,
+    TooManyAccountLocks,
+    AddressLookupTableNotFound,
+    InvalidAddressLookupTableOwner,
+    InvalidAddressLookupTableData,
+    InvalidAddressLookupTableIndex,
+    InvalidRentPayingAccount,
+    WouldExceedMaxVoteCostLimit,
+    WouldExceedAccountDataTotalLimit,
+    /// Index of the duplicated instruction
+    DuplicateInstruction(u8),
+    InsufficientFundsForRent { account_index: u8 },
+    MaxLoadedAccountsDataSizeExceeded,
+    InvalidLoadedAccountsDataSizeLimit,
+    ResanitizationNeeded,
+    ProgramExecutionTemporarilyRestricted { account_index: u8 },
+    UnbalancedTransaction,
+    ProgramCacheHitMaxLimit,
+    /// A variant this module does not know, by its index
+    Unknown(u32),
+}
+
+/// Variants of `TransactionError` without fields, by index
+const TRANSACTION_ERRORS: &[(u32, TransactionError)] = &[
+    (0, TransactionError::AccountInUse),
+    (1, TransactionError::AccountLoadedTwice),
+    (2, TransactionError::AccountNotFound),
+    (3, TransactionError::ProgramAccountNotFound),
+    (4, TransactionError::InsufficientFundsForFee),
+    (5, TransactionError::InvalidAccountForFee),
+    (6, TransactionError::AlreadyProcessed),
+    (7, TransactionError::BlockhashNotFound),
+    (9, TransactionError::CallChainTooDeep),
+    (10, TransactionError::MissingSignatureForFee),
+    (11, TransactionError::InvalidAccountIndex),
+    (12, TransactionError::SignatureFailure),
+    (13, TransactionError::InvalidProgramForExecution),
+    (14, TransactionError::SanitizeFailure),
+    (15, TransactionError::ClusterMaintenance),
+    (16, TransactionError::AccountBorrowOutstanding),
+    (17, TransactionError::WouldExceedMaxBlockCostLimit),
+    (18, TransactionError::UnsupportedVersion),
+    (19, TransactionError::InvalidWritableAccount),
+    (20, TransactionError::WouldExceedMaxAccountCostLimit),
+    (21, TransactionError::WouldExceedAccountDataBlockLimit),
+    (22, TransactionError::TooManyAccountLocks),
+    (23, TransactionError::AddressLookupTableNotFound),
+    (24, TransactionError::InvalidAddressLookupTableOwner),
+    (25, TransactionError::InvalidAddressLookupTableData),
+    (26, TransactionError::InvalidAddressLookupTableIndex),
+    (27, TransactionError::InvalidRentPayingAccount),
+    (28, TransactionError::WouldExceedMaxVoteCostLimit),
+    (29, TransactionError::WouldExceedAccountDataTotalLimit),
+    (32, TransactionError::MaxLoadedAccountsDataSizeExceeded),
+    (33, TransactionError::InvalidLoadedAccountsDataSizeLimit),
+    (34, TransactionError::ResanitizationNeeded),
+    (36, TransactionError::UnbalancedTransaction),
+    (37, TransactionError::ProgramCacheHitMaxLimit),
+];
+
+const INSTRUCTION_ERROR: u32 = 8;
+const DUPLICATE_INSTRUCTION: u32 = 30;
+const INSUFFICIENT_FUNDS_FOR_RENT: u32 = 31;
+const PROGRAM_EXECUTION_TEMPORARILY_RESTRICTED: u32 = 35;
+
+impl TransactionError {
+    /// Decodes the bincode encoded `meta.err`. `None` if `data` is truncated or
+    /// has trailing bytes.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader(data);
+        let error = match reader.u32()? {
+            INSTRUCTION_ERROR => {
+                let index = reader.u8()?;
+                Self::InstructionError { index, error: InstructionError::read(&mut reader)? }
+            },
+            DUPLICATE_INSTRUCTION => Self::DuplicateInstruction(reader.u8()?),
+            INSUFFICIENT_FUNDS_FOR_RENT => Self::InsufficientFundsForRent { account_index: reader.u8()? },
+            PROGRAM_EXECUTION_TEMPORARILY_RESTRICTED => Self::ProgramExecutionTemporarilyRestricted { account_index: reader.u8()? },
+            tag => match TRANSACTION_ERRORS.iter().find(|(x, _)| *x == tag) {
+                Some((_, error)) => error.clone(),
+                // The fields of an unknown variant cannot be skipped
+                None => return Some(Self::Unknown(tag)),
+            },
+        };
+        reader.0.is_empty().then_some(error)
+    }
+
+    /// The bincode encoding of the error, as in `meta.err`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        match self {
+            Self::InstructionError { index, error } => {
+                data.extend(INSTRUCTION_ERROR.to_le_bytes());
+                data.push(*index);
+                error.write(&mut data);
+            },
+            Self::DuplicateInstruction(index) => {
+                data.extend(DUPLICATE_INSTRUCTION.to_le_bytes());
+                data.push(*index);
+            },
+            Self::InsufficientFundsForRent { account_index } => {
+                data.extend(INSUFFICIENT_FUNDS_FOR_RENT.to_le_bytes());
+                data.push(*account_index);
+            },
+            Self::ProgramExecutionTemporarilyRestricted { account_index } => {
+                data.extend(PROGRAM_EXECUTION_TEMPORARILY_RESTRICTED.to_le_bytes());
+                data.push(*account_index);
+            },
+            Self::Unknown(tag) => data.extend(tag.to_le_bytes()),
+            error => {
+                let (tag, _) = TRANSACTION_ERRORS.iter().find(|(_, x)| x == error).unwrap();
+                data.extend(tag.to_le_bytes());
+            },
+        }
+        data
+    }
+
+    /// Finds the variant without fields named `name`, e.g. `BlockhashNotFound`
+    pub fn from_name(name: &str) -> Option<Self> {
+        TRANSACTION_ERRORS.iter().map(|(_, x)| x).find(|x| format!("{:?}", x) == name).cloned()
+    }
+
+    /// Index of the failing top level instruction
+    pub fn instruction_index(&self) -> Option<u8> {
+        match self {
+            Self::InstructionError { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Code of a `Custom` instruction error
+    pub fn custom_error_code(&self) -> Option<u32> {
+        match self {
+            Self::InstructionError { error: InstructionError::Custom(code), .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Error of the failing instruction of a `TransactionError::InstructionError`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InstructionError {
+    GenericError,
+    InvalidArgument,
+    InvalidInstructionData,
+    InvalidAccountData,
+    AccountDataTooSmall,
+    InsufficientFunds,
+    IncorrectProgramId,
+    MissingRequiredSignature,
+    AccountAlreadyInitialized,
+    UninitializedAccount,
+    UnbalancedInstruction,
+    ModifiedProgramId,
+    ExternalAccountLamportSpend,
+    ReadonlyLamportChange,
+    ReadonlyDataModified,
+    DuplicateAccountIndex,
+    ExecutableModified,
+    RentEpochModified,
+    NotEnoughAccountKeys,
+    AccountDataSizeChanged,
+    AccountNotExecutable,
+    AccountBorrowFailed,
+    AccountBorrowOutstanding,
+    DuplicateAccountOutOfSync,
+    /// `custom program error`, e.g. an Anchor error code
+    Custom(u32),
+    InvalidError,
+    ExecutableDataModified,
+    ExecutableLamportChange,
+    ExecutableAccountNotRentExempt,
+    UnsupportedProgramId,
+    CallDepth,
+    MissingAccount,
+    ReentrancyNotAllowed,
+    MaxSeedLengthExceeded,
+    InvalidSeeds,
+    InvalidRealloc,
+    ComputationalBudgetExceeded,
+    PrivilegeEscalation,
+    ProgramEnvironmentSetupFailure,
+    ProgramFailedToComplete,
+    ProgramFailedToCompile,
+    Immutable,
+    IncorrectAuthority,
+    BorshIoError(String),
+    AccountNotRentExempt,
+    InvalidAccountOwner,
+    ArithmeticOverflow,
+    UnsupportedSysvar,
+    IllegalOwner,
+    MaxAccountsDataAllocationsExceeded,
+    MaxAccountsExceeded,
+    MaxInstructionTraceLengthExceeded,
+    BuiltinProgramsMustConsumeComputeUnits,
+    /// A variant this module does not know, by its index
+    Unknown(u32),
+}
+
+/// Variants of `InstructionError` without fields, by index
+const INSTRUCTION_ERRORS: &[(u32, InstructionError)] = &[
+    (0, InstructionError::GenericError),
+    (1, InstructionError::InvalidArgument),
+    (2, InstructionError::InvalidInstructionData),
+    (3, InstructionError::InvalidAccountData),
+    (4, InstructionError::AccountDataTooSmall),
+    (5, InstructionError::InsufficientFunds),
+    (6, InstructionError::IncorrectProgramId),
+    (7, InstructionError::MissingRequiredSignature),
+    (8, InstructionError::AccountAlreadyInitialized),
+    (9, InstructionError::UninitializedAccount),
+    (10, InstructionError::UnbalancedInstruction),
+    (11, InstructionError::ModifiedProgramId),
+    (12, InstructionError::ExternalAccountLamportSpend),
+    (13, InstructionError::ReadonlyLamportChange),
+    (14, InstructionError::ReadonlyDataModified),
+    (15, InstructionError::DuplicateAccountIndex),
+    (16, InstructionError::ExecutableModified),
+    (17, InstructionError::RentEpochModified),
+    (18, InstructionError::NotEnoughAccountKeys),
+    (19, InstructionError::AccountDataSizeChanged),
+    (20, InstructionError::AccountNotExecutable),
+    (21, InstructionError::AccountBorrowFailed),
+    (22, InstructionError::AccountBorrowOutstanding),
+    (23, InstructionError::DuplicateAccountOutOfSync),
+    (25, InstructionError::InvalidError),
+    (26, InstructionError::ExecutableDataModified),
+    (27, InstructionError::ExecutableLamportChange),
+    (28, InstructionError::ExecutableAccountNotRentExempt),
+    (29, InstructionError::UnsupportedProgramId),
+    (30, InstructionError::CallDepth),
+    (31, InstructionError::MissingAccount),
+    (32, InstructionError::ReentrancyNotAllowed),
+    (33, InstructionError::MaxSeedLengthExceeded),
+    (34, InstructionError::InvalidSeeds),
+    (35, InstructionError::InvalidRealloc),
+    (36, InstructionError::ComputationalBudgetExceeded),
+    (37, InstructionError::PrivilegeEscalation),
+    (38, InstructionError::ProgramEnvironmentSetupFailure),
+    (39, InstructionError::ProgramFailedToComplete),
+    (40, InstructionError::ProgramFailedToCompile),
+    (41, InstructionError::Immutable),
+    (42, InstructionError::IncorrectAuthority),
+    (44, InstructionError::AccountNotRentExempt),
+    (45, InstructionError::InvalidAccountOwner),
+    (46, InstructionError::ArithmeticOverflow),
+    (47, InstructionError::UnsupportedSysvar),
+    (48, InstructionError::IllegalOwner),
+    (49, InstructionError::MaxAccountsDataAllocationsExceeded),
+    (50, InstructionError::MaxAccountsExceeded),
+    (51, InstructionError::MaxInstructionTraceLengthExceeded),
+    (52, InstructionError::BuiltinProgramsMustConsumeComputeUnits),
+];
+
+const CUSTOM: u32 = 24;
+const BORSH_IO_ERROR: u32 = 43;
+
+impl InstructionError {
+    fn read(reader: &mut Reader) -> Option<Self> {
+        let error = match reader.u32()? {
+            CUSTOM => Self::Custom(reader.u32()?),
+            BORSH_IO_ERROR => Self::BorshIoError(reader.string()?),
+            tag => match INSTRUCTION_ERRORS.iter().find(|(x, _)| *x == tag) {
+                Some((_, error)) => error.clone(),
+                None => {
+                    // The fields of an unknown variant cannot be skipped
+                    reader.0 = &[];
+                    Self::Unknown(tag)
+                },
+            },
+        };
+        Some(error)
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        match self {
+            Self::Custom(code) => {
+                data.extend(CUSTOM.to_le_bytes());
+                data.extend(code.to_le_bytes());
+            },
+            Self::BorshIoError(message) => {
+                data.extend(BORSH_IO_ERROR.to_le_bytes());
+                data.extend((message.len() as u64).to_le_bytes());
+                data.extend(message.as_bytes());
+            },
+            Self::Unknown(tag) => data.extend(tag.to_le_bytes()),
+            error => {
+                let (tag, _) = INSTRUCTION_ERRORS.iter().find(|(_, x)| x == error).unwrap();
+                data.extend(tag.to_le_bytes());
+            },
+        }
+    }
+
+    /// Finds the variant without fields named `name`, e.g. `InvalidAccountData`
+    pub fn from_name(name: &str) -> Option<Self> {
+        INSTRUCTION_ERRORS.iter().map(|(_, x)| x).find(|x| format!("{:?}", x) == name).cloned()
+    }
+}
+
+/// Reads bincode values from the front of a byte slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = usize::try_from(u64::from_le_bytes(self.take(8)?.try_into().ok()?)).ok()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+}
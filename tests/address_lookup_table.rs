@@ -0,0 +1,78 @@
+//! Lifecycle events of address lookup tables, see `get_lookup_table_events`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana_utils::address_lookup_table::{get_lookup_table_events, LookupTableEvent, ADDRESS_LOOKUP_TABLE_PROGRAM_ID};
+use substreams_solana_utils::error::ParseError;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+fn create(recent_slot: u64) -> Vec<u8> {
+    [&0u32.to_le_bytes()[..], &recent_slot.to_le_bytes(), &[254]].concat()
+}
+
+fn extend(new_addresses: &[Pubkey]) -> Vec<u8> {
+    let mut data = [2u32.to_le_bytes().to_vec(), (new_addresses.len() as u64).to_le_bytes().to_vec()].concat();
+    new_addresses.iter().for_each(|x| data.extend(x.0));
+    data
+}
+
+/// Start index and new addresses of the `Extended` events
+fn extensions(events: &[LookupTableEvent]) -> Vec<(Pubkey, Option<u32>, usize)> {
+    events.iter().filter_map(|event| match event {
+        LookupTableEvent::Extended { table, new_addresses, start_index, .. } => Some((*table, *start_index, new_addresses.len())),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn extensions_of_a_created_table_know_their_start_index() {
+    let (authority, table, other_table, router) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    let transaction = TransactionBuilder::new()
+        .signer(authority)
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority, authority], create(55))
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority, authority], extend(&[pubkey(7), pubkey(8)]))
+        .instruction(router, &[table, authority], vec![0])
+        .inner(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority, authority], extend(&[pubkey(9)]))
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority, authority], extend(&[pubkey(10), pubkey(11), pubkey(12)]))
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[other_table, authority, authority], extend(&[pubkey(13)]))
+        .build();
+    let events = get_lookup_table_events(&transaction).unwrap();
+
+    assert_eq!(events[0], LookupTableEvent::Created { table, authority, payer: authority, recent_slot: 55 });
+    assert_eq!(extensions(&events), [
+        (table, Some(0), 2),
+        (table, Some(2), 1),
+        (table, Some(3), 3),
+        (other_table, None, 1),
+    ]);
+}
+
+#[test]
+fn undecodable_instructions_are_skipped() {
+    let (authority, table) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new()
+        .signer(authority)
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority], 9u32.to_le_bytes().to_vec())
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority, authority], [2u32.to_le_bytes(), [0xff; 4]].concat())
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority], 1u32.to_le_bytes().to_vec())
+        .build();
+    assert_eq!(get_lookup_table_events(&transaction).unwrap(), [LookupTableEvent::Frozen { table, authority }]);
+}
+
+#[test]
+fn account_indices_out_of_bounds_are_errors() {
+    let (authority, table) = (pubkey(1), pubkey(2));
+    let mut transaction = TransactionBuilder::new()
+        .signer(authority)
+        .instruction(ADDRESS_LOOKUP_TABLE_PROGRAM_ID, &[table, authority], 1u32.to_le_bytes().to_vec())
+        .build();
+    let instruction = &mut transaction.transaction.as_mut().unwrap().message.as_mut().unwrap().instructions[0];
+    instruction.accounts[1] = 3;
+    let error = get_lookup_table_events(&transaction).unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&ParseError::AccountIndexOutOfBounds { index: 3, len: 3 }));
+
+    transaction.transaction.as_mut().unwrap().message.as_mut().unwrap().instructions[0].program_id_index = 200;
+    let error = get_lookup_table_events(&transaction).unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&ParseError::AccountIndexOutOfBounds { index: 200, len: 3 }));
+}
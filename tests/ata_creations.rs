@@ -0,0 +1,182 @@
+//! Associated token account creations of a block and the accounts funding
+//! them, see `ata_creations`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::{Block, ConfirmedTransaction};
+use substreams_solana_utils::associated_token_account::ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID;
+use substreams_solana_utils::block::ata_creations;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::spl_token::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use substreams_solana_utils::system_program::SYSTEM_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const RENT: u64 = 2_039_280;
+
+fn transfer(lamports: u64) -> Vec<u8> {
+    [2u32.to_le_bytes().to_vec(), lamports.to_le_bytes().to_vec()].concat()
+}
+
+fn create_account(lamports: u64) -> Vec<u8> {
+    [0u32.to_le_bytes().to_vec(), lamports.to_le_bytes().to_vec(), 165u64.to_le_bytes().to_vec(), TOKEN_PROGRAM_ID.0.to_vec()].concat()
+}
+
+fn allocate(space: u64) -> Vec<u8> {
+    [8u32.to_le_bytes().to_vec(), space.to_le_bytes().to_vec()].concat()
+}
+
+fn assign(owner: Pubkey) -> Vec<u8> {
+    [1u32.to_le_bytes().to_vec(), owner.0.to_vec()].concat()
+}
+
+fn initialize_account3(owner: Pubkey) -> Vec<u8> {
+    [vec![18], owner.0.to_vec()].concat()
+}
+
+fn block(transactions: Vec<ConfirmedTransaction>) -> Block {
+    Block { transactions, ..Default::default() }
+}
+
+/// A Jupiter route whose program PDA pays for the output token account of the
+/// user. The user funds the PDA through the second pool, a few instructions
+/// after a smaller transfer wrapping SOL and a transfer inside the first pool.
+#[test]
+fn jupiter_pda_funding() {
+    let (user, jupiter, pda, ata, mint, first_pool, second_pool, pool_vault, compute_budget) =
+        (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5), pubkey(6), pubkey(7), pubkey(8), pubkey(9));
+    let builder = || TransactionBuilder::new()
+        .signer(user)
+        .instruction(compute_budget, &[], vec![2, 0, 0, 0, 0])
+        .instruction(jupiter, &[user, pda, first_pool, second_pool, mint], vec![0])
+        .inner(SYSTEM_PROGRAM_ID, &[user, pda], transfer(1_000))
+        .inner(first_pool, &[user, pool_vault], vec![1]);
+    let route = |builder: TransactionBuilder| builder
+        .inner(second_pool, &[user, pda], vec![2])
+        .inner_at(3, SYSTEM_PROGRAM_ID, &[user, pda], transfer(RENT))
+        .inner(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[pda, ata, user, mint, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID], vec![1])
+        .inner_at(3, SYSTEM_PROGRAM_ID, &[pda, ata], create_account(RENT))
+        .inner_at(3, TOKEN_PROGRAM_ID, &[ata, mint], initialize_account3(user))
+        .signature(&[1; 64])
+        .build();
+    let transaction = route(builder().inner_at(3, SYSTEM_PROGRAM_ID, &[pool_vault, user], transfer(RENT)));
+    let creations = ata_creations(&block(vec![transaction]));
+
+    assert_eq!(creations.len(), 1);
+    let creation = &creations[0];
+    assert_eq!((creation.owner, creation.mint, creation.ata_address, creation.payer), (user, mint, ata, pda));
+    assert_eq!((creation.rent, creation.idempotent_noop), (Some(RENT), false));
+    assert_eq!(creation.funder_candidates, [user]);
+    let economic_payer = creation.economic_payer.as_ref().unwrap();
+    assert_eq!(economic_payer.payer, user);
+    // First inner instruction of the second pool, the third inner instruction of the route
+    assert_eq!(economic_payer.transfer_event_id, format!("{}-1.2-i0.0", bs58::encode([1; 64]).into_string()));
+
+    // Same id when the transfer inside the first pool is missing
+    let creations = ata_creations(&block(vec![route(builder())]));
+    let economic_payer = creations[0].economic_payer.as_ref().unwrap();
+    assert_eq!(economic_payer.transfer_event_id, format!("{}-1.2-i0.0", bs58::encode([1; 64]).into_string()));
+}
+
+#[test]
+fn top_level_funding() {
+    let (user, pda, ata, mint, program) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(SYSTEM_PROGRAM_ID, &[user, pda], transfer(RENT))
+        .instruction(program, &[pda, ata, user, mint], vec![0])
+        .inner(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[pda, ata, user, mint, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID], vec![0])
+        .inner_at(3, SYSTEM_PROGRAM_ID, &[pda, ata], create_account(RENT))
+        .inner_at(3, TOKEN_PROGRAM_ID, &[ata, mint], initialize_account3(user))
+        .signature(&[2; 64])
+        .build();
+    let creations = ata_creations(&block(vec![transaction]));
+    let economic_payer = creations[0].economic_payer.as_ref().unwrap();
+    assert_eq!(economic_payer.transfer_event_id, format!("{}-i0.0", bs58::encode([2; 64]).into_string()));
+}
+
+#[test]
+fn ambiguous_funders() {
+    let (user, other, pda, ata, mint, program) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5), pubkey(6));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .signer(other)
+        .instruction(program, &[user, other, pda], vec![0])
+        .inner(SYSTEM_PROGRAM_ID, &[user, pda], transfer(RENT))
+        .inner(SYSTEM_PROGRAM_ID, &[other, pda], transfer(3_000_000))
+        .inner(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[pda, ata, user, mint, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID], vec![1])
+        .inner_at(3, SYSTEM_PROGRAM_ID, &[pda, ata], create_account(RENT))
+        .inner_at(3, TOKEN_PROGRAM_ID, &[ata, mint], initialize_account3(user))
+        .build();
+    let creations = ata_creations(&block(vec![transaction]));
+    assert!(creations[0].economic_payer.is_none());
+    assert_eq!(creations[0].funder_candidates, [other, user]);
+}
+
+#[test]
+fn idempotent_creations_of_existing_accounts() {
+    let (user, ata, mint) = (pubkey(1), pubkey(2), pubkey(3));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[user, ata, user, mint, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID], vec![1])
+        .build();
+    let creations = ata_creations(&block(vec![transaction]));
+    assert!(creations[0].idempotent_noop);
+    assert_eq!((creations[0].rent, creations[0].payer), (None, user));
+    assert!(creations[0].economic_payer.is_none() && creations[0].funder_candidates.is_empty());
+}
+
+/// Creations of accounts that already held lamports, which the associated token
+/// account program tops up, allocates and assigns instead of creating, and a
+/// Token-2022 account whose only recorded CPI is its initialization
+#[test]
+fn creations_of_prefunded_accounts() {
+    let (user, ata, token_2022_ata, mint) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[user, ata, user, mint, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID], vec![1])
+        .inner(SYSTEM_PROGRAM_ID, &[user, ata], transfer(1_000))
+        .inner(SYSTEM_PROGRAM_ID, &[ata], allocate(165))
+        .inner(SYSTEM_PROGRAM_ID, &[ata], assign(TOKEN_PROGRAM_ID))
+        .instruction(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[user, token_2022_ata, user, mint, SYSTEM_PROGRAM_ID, TOKEN_2022_PROGRAM_ID], vec![1])
+        .inner(TOKEN_2022_PROGRAM_ID, &[token_2022_ata, mint], initialize_account3(user))
+        .build();
+    let creations = ata_creations(&block(vec![transaction]));
+    assert_eq!(creations.len(), 2);
+    assert_eq!((creations[0].ata_address, creations[0].idempotent_noop, creations[0].rent), (ata, false, Some(1_000)));
+    assert_eq!((creations[1].ata_address, creations[1].idempotent_noop, creations[1].rent), (token_2022_ata, false, None));
+}
+
+/// A program creating the account of its user through CPI, paid by its PDA,
+/// then the same creation once the account exists
+#[test]
+fn idempotent_creations_through_cpi() {
+    let (user, program, pda, ata, mint) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let create = |existing: bool| {
+        let builder = TransactionBuilder::new()
+            .signer(user)
+            .instruction(program, &[pda, ata, user, mint], vec![0])
+            .inner(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[pda, ata, user, mint, SYSTEM_PROGRAM_ID, TOKEN_PROGRAM_ID], vec![1]);
+        match existing {
+            true => builder.inner(program, &[user], vec![1]).build(),
+            false => builder
+                .inner_at(3, SYSTEM_PROGRAM_ID, &[pda, ata], create_account(RENT))
+                .inner_at(3, TOKEN_PROGRAM_ID, &[ata, mint], initialize_account3(user))
+                .build(),
+        }
+    };
+    let creations = ata_creations(&block(vec![create(false), create(true)]));
+    let summary = |i: usize| (creations[i].payer, creations[i].owner, creations[i].idempotent_noop, creations[i].rent);
+    assert_eq!(summary(0), (pda, user, false, Some(RENT)));
+    assert_eq!(summary(1), (pda, user, true, None));
+    assert_eq!(creations[0].key(), creations[1].key());
+}
+
+#[test]
+fn creations_missing_accounts_are_skipped() {
+    let (user, ata) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(ASSOCIATED_TOKEN_ACCOUNT_PROGRAM_ID, &[user, ata, user], vec![1])
+        .build();
+    assert!(ata_creations(&block(vec![transaction])).is_empty());
+}
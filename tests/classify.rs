@@ -0,0 +1,121 @@
+//! Coarse categories of transactions from the programs they invoke, see
+//! `transaction_category`. Each category has a fixture, and the precedence
+//! between categories is pinned by transactions matching several.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::classify::{transaction_category, TransactionCategory};
+use substreams_solana_utils::programs::KnownProgram;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+fn program(program: KnownProgram) -> Pubkey {
+    program.program_id()
+}
+
+fn system_transfer(lamports: u64) -> Vec<u8> {
+    [2u32.to_le_bytes().to_vec(), lamports.to_le_bytes().to_vec()].concat()
+}
+
+fn token_transfer(amount: u64) -> Vec<u8> {
+    [vec![3], amount.to_le_bytes().to_vec()].concat()
+}
+
+/// A transaction of `user` with the top level instructions of `programs`
+fn invoking(programs: &[KnownProgram]) -> TransactionBuilder {
+    let user = pubkey(1);
+    programs.iter().fold(TransactionBuilder::new().signer(user), |builder, x| builder.instruction(program(*x), &[user], vec![0]))
+}
+
+fn category(transaction: &ConfirmedTransaction) -> TransactionCategory {
+    transaction_category(transaction)
+}
+
+#[test]
+fn one_fixture_per_category() {
+    let (user, recipient, source, destination, mint, pool, unknown) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5), pubkey(6), pubkey(7));
+    let vote = TransactionBuilder::new().signer(user).instruction(program(KnownProgram::Vote), &[user, recipient], vec![2, 0, 0, 0]).build();
+    let system_only = TransactionBuilder::new()
+        .signer(user)
+        .instruction(program(KnownProgram::ComputeBudget), &[], vec![2, 0, 0, 0, 0])
+        .instruction(program(KnownProgram::System), &[user, recipient], system_transfer(10))
+        .build();
+    let token_transfer_only = TransactionBuilder::new()
+        .signer(user)
+        .instruction(program(KnownProgram::AssociatedTokenAccount), &[user, destination, recipient, mint], vec![1])
+        .instruction(program(KnownProgram::Token), &[source, destination, user], token_transfer(7))
+        .token_account(source, mint, user, Some(7), Some(0))
+        .build();
+    let dex_swap = TransactionBuilder::new()
+        .signer(user)
+        .instruction(program(KnownProgram::RaydiumAmmV4), &[pool, source, destination, user], vec![9])
+        .inner(program(KnownProgram::Token), &[source, pool, user], token_transfer(7))
+        .inner(program(KnownProgram::Token), &[pool, destination, pool], token_transfer(3))
+        .token_account(source, mint, user, Some(7), Some(0))
+        .build();
+    let nft_activity = TransactionBuilder::new()
+        .signer(user)
+        .instruction(program(KnownProgram::CandyMachineV3), &[user, mint], vec![0])
+        .inner(program(KnownProgram::TokenMetadata), &[mint, user], vec![42])
+        .build();
+    let program_deploy = invoking(&[KnownProgram::System, KnownProgram::BpfUpgradeableLoader]).build();
+    let stake_ops = invoking(&[KnownProgram::System, KnownProgram::Stake]).build();
+    let mixed = TransactionBuilder::new()
+        .signer(user)
+        .instruction(unknown, &[user, source], vec![0])
+        .inner(program(KnownProgram::Token), &[source, destination, user], token_transfer(7))
+        .token_account(source, mint, user, Some(7), Some(0))
+        .build();
+    let unknown = TransactionBuilder::new().signer(user).instruction(unknown, &[user], vec![0]).build();
+
+    assert_eq!(category(&vote), TransactionCategory::Vote);
+    assert_eq!(category(&system_only), TransactionCategory::SystemOnly);
+    assert_eq!(category(&token_transfer_only), TransactionCategory::TokenTransferOnly);
+    assert_eq!(category(&dex_swap), TransactionCategory::DexSwap);
+    assert_eq!(category(&nft_activity), TransactionCategory::NftActivity);
+    assert_eq!(category(&program_deploy), TransactionCategory::ProgramDeploy);
+    assert_eq!(category(&stake_ops), TransactionCategory::StakeOps);
+    assert_eq!(category(&mixed), TransactionCategory::Mixed);
+    assert_eq!(category(&unknown), TransactionCategory::Unknown);
+}
+
+#[test]
+fn precedence() {
+    use KnownProgram::*;
+    for (programs, expected) in [
+        (&[Token, RaydiumAmmV4, Vote][..], TransactionCategory::Vote),
+        (&[JupiterV6, BpfLoader2], TransactionCategory::ProgramDeploy),
+        (&[TensorSwap, OrcaWhirlpool, Token], TransactionCategory::DexSwap),
+        (&[Stake, Bubblegum], TransactionCategory::NftActivity),
+        (&[Token2022, Stake], TransactionCategory::StakeOps),
+        (&[System, Token2022, Memo], TransactionCategory::TokenTransferOnly),
+        (&[ComputeBudget, Ed25519], TransactionCategory::Unknown),
+    ] {
+        assert_eq!(category(&invoking(programs).build()), expected, "{:?}", programs);
+    }
+
+    // Recognized programs take precedence over unrecognized ones
+    let swap = invoking(&[PumpFun]).instruction(pubkey(9), &[pubkey(1)], vec![0]).build();
+    assert_eq!(category(&swap), TransactionCategory::DexSwap);
+}
+
+#[test]
+fn programs_invoked_through_cpi_or_lookup_tables() {
+    let (user, router) = (pubkey(1), pubkey(2));
+    let through_cpi = TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user], vec![0])
+        .inner(program(KnownProgram::MeteoraDlmm), &[user], vec![1])
+        .build();
+    assert_eq!(category(&through_cpi), TransactionCategory::DexSwap);
+
+    let stake = program(KnownProgram::Stake);
+    let through_lookup_table = TransactionBuilder::new()
+        .signer(user)
+        .lookup(stake, false)
+        .instruction(stake, &[user], vec![0])
+        .build();
+    assert_eq!(category(&through_lookup_table), TransactionCategory::StakeOps);
+    assert_eq!(category(&ConfirmedTransaction::default()), TransactionCategory::Unknown);
+}
@@ -0,0 +1,54 @@
+//! Deprecated names kept for crates pinned to the older API, which must keep
+//! behaving as their replacements do, see the `compat` module.
+
+#![cfg(feature = "test-utils")]
+#![allow(deprecated)]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::compat::LegacyTokenAccount;
+use substreams_solana_utils::pubkey::PubkeyRef;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::TransactionContext;
+
+#[test]
+fn construct_builds_the_same_context() {
+    let (user, program) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new().signer(user).instruction(program, &[user], vec![0]).build();
+    let (constructed, built) = (TransactionContext::construct(&transaction).unwrap(), TransactionContext::build(&transaction).unwrap());
+    assert_eq!((constructed.accounts, constructed.signature), (built.accounts, built.signature));
+
+    let transaction = ConfirmedTransaction::default();
+    assert_eq!(TransactionContext::construct(&transaction).err(), TransactionContext::build(&transaction).err());
+}
+
+#[test]
+fn account_from_index() {
+    let (user, program) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new().signer(user).instruction(program, &[user], vec![0]).build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert_eq!(context.get_account_from_index(0), Some(&user.0.to_vec()));
+    assert_eq!(context.get_account_from_index(1).map(PubkeyRef), context.accounts.get(1).copied());
+    assert_eq!(context.get_account_from_index(2), None);
+}
+
+#[test]
+fn token_account_from_address() {
+    let (user, account, mint) = (pubkey(1), pubkey(2), pubkey(3));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .account(account)
+        .token_account(account, mint, user, Some(10), Some(10))
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    let token_account = context.get_token_account_from_address(&account.0.to_vec()).unwrap();
+    assert!(std::ptr::eq(token_account, context.get_token_account(&context.accounts[1]).unwrap()));
+    assert!(context.get_token_account_from_address(&user.0.to_vec()).is_none());
+
+    assert_eq!(LegacyTokenAccount::from(token_account), LegacyTokenAccount {
+        address: account.to_string(),
+        mint: mint.to_string(),
+        owner: user.to_string(),
+        pre_balance: Some(10),
+        post_balance: Some(10),
+    });
+}
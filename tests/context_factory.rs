@@ -0,0 +1,88 @@
+//! Contexts built from the recycled allocations of earlier ones, which must
+//! not keep anything of the transactions they were built from, see
+//! `ContextFactory`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::{ContextFactory, TransactionContext};
+
+/// Everything a context holds, in a comparable form
+fn summary(context: &TransactionContext) -> String {
+    let mut token_accounts: Vec<_> = context.token_accounts.values()
+        .map(|x| format!("{:?} {:?} {:?} {:?} {:?}", x.address, x.mint, x.owner, x.pre_balance, x.post_balance))
+        .collect();
+    token_accounts.sort();
+    let mut sol_balances: Vec<_> = context.sol_balances.iter().map(|(address, balance)| format!("{:?} {:?}", address, balance)).collect();
+    sol_balances.sort();
+    let account_balances: Vec<_> = context.account_balances.iter().map(|x| (x.pre_balance, x.post_balance)).collect();
+    format!(
+        "{:?} {:?} {:?} {} {} {:?} {:?} {:?} {:?} {}",
+        context.accounts, context.signers, context.readonly_signers(), context.signature, context.fee(),
+        context.transaction_error(), token_accounts, sol_balances, account_balances, context.token_tracking_skipped,
+    )
+}
+
+/// A swap between two token accounts, one of them created by the transaction
+fn swap(seed: u8) -> ConfirmedTransaction {
+    let (user, readonly_signer, source, created, mint) = (pubkey(seed), pubkey(seed + 1), pubkey(seed + 2), pubkey(seed + 3), pubkey(seed + 4));
+    TransactionBuilder::new()
+        .signer(user)
+        .signer(readonly_signer)
+        .readonly(readonly_signer)
+        .token_account(source, mint, user, Some(100), Some(40))
+        .instruction(TOKEN_PROGRAM_ID, &[created, mint], [vec![18], user.0.to_vec()].concat())
+        .lamports(user, 5_000_000, 3_000_000)
+        .fee(5_000)
+        .signature(&[seed; 64])
+        .build()
+}
+
+/// A bare transfer of lamports, sharing no account with `swap`
+fn transfer(seed: u8) -> ConfirmedTransaction {
+    TransactionBuilder::new().signer(pubkey(seed)).account(pubkey(seed + 1)).signature(&[seed; 64]).build()
+}
+
+fn assert_built_from_scratch(factory: &mut ContextFactory<'static>, transaction: &'static ConfirmedTransaction) {
+    let context = factory.context_for(transaction).unwrap();
+    assert_eq!(summary(&context), summary(&TransactionContext::build(transaction).unwrap()));
+    factory.recycle(context);
+}
+
+fn leak(transaction: ConfirmedTransaction) -> &'static ConfirmedTransaction {
+    Box::leak(Box::new(transaction))
+}
+
+#[test]
+fn consecutive_contexts() {
+    let mut factory = ContextFactory::new();
+    assert_built_from_scratch(&mut factory, leak(swap(10)));
+    assert_built_from_scratch(&mut factory, leak(transfer(20)));
+    assert_built_from_scratch(&mut factory, leak(swap(30)));
+
+    let context = factory.context_for(leak(swap(40))).unwrap();
+    assert_eq!((context.token_accounts.len(), context.readonly_signers().len()), (2, 1));
+    factory.recycle(context);
+    let context = factory.context_for(leak(transfer(50))).unwrap();
+    assert!(context.token_accounts.is_empty() && context.token_tracking_skipped);
+    assert_eq!(context.accounts.len(), 2);
+}
+
+#[test]
+fn reuse_after_a_failed_build() {
+    let mut factory = ContextFactory::new();
+    assert_built_from_scratch(&mut factory, leak(swap(10)));
+
+    // Fails once the accounts and the first token balance are loaded
+    let mut malformed = swap(20);
+    let meta = malformed.meta.as_mut().unwrap();
+    meta.pre_token_balances.push(meta.pre_token_balances[0].clone());
+    meta.pre_token_balances[1].account_index = 99;
+    assert!(factory.context_for(leak(malformed)).is_err());
+    assert!(factory.context_for(leak(TransactionBuilder::new().signer(pubkey(1)).failed().build())).is_err());
+
+    assert_built_from_scratch(&mut factory, leak(transfer(30)));
+    assert_built_from_scratch(&mut factory, leak(swap(40)));
+}
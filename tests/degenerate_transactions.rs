@@ -0,0 +1,90 @@
+//! Transactions missing parts a confirmed transaction normally has. Building
+//! their context fails with a `ParseError` and never panics.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::{ConfirmedTransaction, Message, MessageHeader, Transaction, TransactionStatusMeta};
+use substreams_solana_utils::error::ParseError;
+use substreams_solana_utils::transaction::{get_context, get_signature, get_signer_metas, TransactionContext};
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+/// A transaction with a signature and nothing else
+fn bare(num_required_signatures: Option<u32>) -> ConfirmedTransaction {
+    let header = num_required_signatures.map(|num_required_signatures| MessageHeader { num_required_signatures, ..Default::default() });
+    ConfirmedTransaction {
+        transaction: Some(Transaction { signatures: vec![vec![1; 64]], message: Some(Message { header, ..Default::default() }) }),
+        meta: Some(TransactionStatusMeta::default()),
+    }
+}
+
+fn build_error(transaction: &ConfirmedTransaction) -> ParseError {
+    TransactionContext::build(transaction).err().unwrap()
+}
+
+#[test]
+fn missing_parts() {
+    let transaction = TransactionBuilder::new().signer(pubkey(1)).build();
+
+    let mut without_transaction = transaction.clone();
+    without_transaction.transaction = None;
+    assert_eq!(build_error(&without_transaction), ParseError::MissingTransaction);
+    assert_eq!(get_signature(&without_transaction), Err(ParseError::MissingTransaction));
+    assert_eq!(get_signer_metas(&without_transaction), []);
+
+    let mut without_message = transaction.clone();
+    without_message.transaction.as_mut().unwrap().message = None;
+    assert_eq!(build_error(&without_message), ParseError::MissingMessage);
+    assert_eq!(get_signer_metas(&without_message), []);
+
+    let mut without_meta = transaction.clone();
+    without_meta.meta = None;
+    assert_eq!(build_error(&without_meta), ParseError::MissingMeta);
+    assert_eq!(get_context(&without_meta).err().unwrap().downcast_ref(), Some(&ParseError::MissingMeta));
+}
+
+#[test]
+fn missing_signature() {
+    let mut transaction = TransactionBuilder::new().signer(pubkey(1)).build();
+    transaction.transaction.as_mut().unwrap().signatures.clear();
+    assert_eq!(build_error(&transaction), ParseError::MissingSignature);
+    assert_eq!(get_signature(&transaction), Err(ParseError::MissingSignature));
+}
+
+#[test]
+fn empty_messages() {
+    let transaction = bare(None);
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert!(context.fee_payer().is_none());
+    assert!(context.signers.is_empty());
+    assert_eq!(context.signature, bs58::encode([1; 64]).into_string());
+
+    let transaction = bare(Some(0));
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert!(context.accounts.is_empty() && context.signers.is_empty());
+    assert_eq!(get_signer_metas(&transaction), []);
+
+    assert_eq!(build_error(&bare(Some(1))), ParseError::TooManySignatures { num_required_signatures: 1, len: 0 });
+}
+
+#[test]
+fn malformed_token_balances() {
+    let (user, token_account, mint) = (pubkey(1), pubkey(2), pubkey(3));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .token_account(token_account, mint, user, Some(10), Some(10))
+        .build();
+    let account_index = transaction.meta.as_ref().unwrap().pre_token_balances[0].account_index;
+    let malformed = ParseError::MalformedTokenBalance { account_index };
+
+    let mut without_owner = transaction.clone();
+    without_owner.meta.as_mut().unwrap().pre_token_balances[0].owner = String::new();
+    assert_eq!(build_error(&without_owner), malformed);
+
+    let mut without_amount = transaction.clone();
+    without_amount.meta.as_mut().unwrap().pre_token_balances[0].ui_token_amount = None;
+    assert_eq!(build_error(&without_amount), malformed);
+
+    let mut out_of_bounds = transaction.clone();
+    out_of_bounds.meta.as_mut().unwrap().pre_token_balances[0].account_index = 9;
+    assert_eq!(build_error(&out_of_bounds), ParseError::AccountIndexOutOfBounds { index: 9, len: 2 });
+}
@@ -0,0 +1,65 @@
+//! Matching instruction data against a `DiscriminatorSet`, see
+//! `StructuredInstruction::discriminator_in`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana_utils::instruction::get_structured_instructions;
+use substreams_solana_utils::programs::{anchor_sighash, DiscriminatorSet, KnownProgram};
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const SET: DiscriminatorSet = DiscriminatorSet::from_sorted(&[[0; 8], [1, 0, 0, 0, 0, 0, 0, 0], [1, 2, 0, 0, 0, 0, 0, 0]]);
+
+#[test]
+fn short_and_exact_length_data() {
+    let (user, program) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(program, &[user], vec![1, 2, 0, 0, 0, 0, 0, 0, 9])
+        .instruction(program, &[user], vec![1, 2, 0, 0, 0, 0, 0, 0])
+        .instruction(program, &[user], vec![1, 2, 0, 0, 0, 0, 0])
+        .instruction(program, &[user], vec![1])
+        .instruction(program, &[user], vec![])
+        .build();
+    let instructions = get_structured_instructions(&transaction).unwrap();
+
+    let matched: Vec<_> = instructions.iter().map(|x| x.discriminator_in(&SET)).collect();
+    assert_eq!(matched, [true, true, false, false, false]);
+    assert!(instructions[3].data_starts_with(&[1]) && !instructions[3].data_starts_with(&[1, 2]));
+    assert!(instructions[4].data_starts_with(&[]));
+}
+
+#[test]
+fn large_sets() {
+    let discriminators: Vec<[u8; 8]> = (0..10_000u64).map(|i| anchor_sighash("global", &format!("instruction_{i}"))).collect();
+    let set = DiscriminatorSet::new(discriminators.iter().rev().chain(&discriminators).copied());
+    assert_eq!(set.len(), 10_000);
+    assert!(discriminators.iter().all(|x| set.contains(x)));
+    assert!(discriminators.iter().all(|x| set.matches(&[&x[..], &[7; 16]].concat())));
+    assert!(!set.contains(&anchor_sighash("global", "instruction_10000")));
+
+    let set = DiscriminatorSet::new((0..10_000u64).map(|i| (i * 2).to_be_bytes()));
+    assert!(set.contains(&0u64.to_be_bytes()) && set.contains(&19_998u64.to_be_bytes()));
+    assert!(!set.contains(&1u64.to_be_bytes()) && !set.contains(&20_000u64.to_be_bytes()));
+    assert!(DiscriminatorSet::new([]).is_empty());
+}
+
+#[test]
+fn known_program_sets() {
+    let set = KnownProgram::JupiterV6.instruction_discriminator_set();
+    assert_eq!(set.len(), KnownProgram::JupiterV6.instruction_discriminators().count());
+    assert!(set.matches(&anchor_sighash("global", "shared_accounts_route")));
+    assert!(!set.matches(&anchor_sighash("global", "swap")));
+    assert!(KnownProgram::Token.instruction_discriminator_set().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Discriminators must be sorted and unique")]
+fn unsorted_static_sets() {
+    DiscriminatorSet::from_sorted(&[[1; 8], [0; 8]]);
+}
+
+#[test]
+#[should_panic(expected = "Discriminators must be sorted and unique")]
+fn duplicated_static_sets() {
+    DiscriminatorSet::from_sorted(&[[1; 8], [1; 8]]);
+}
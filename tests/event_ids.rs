@@ -0,0 +1,150 @@
+//! Event ids of a transaction whose logs or inner instructions are partly
+//! missing, see `EventOrdinal`.
+
+#![cfg(feature = "test-utils")]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::event::{event_id, EventOrdinal};
+use substreams_solana_utils::instruction::{get_structured_instructions_with_options, LogAssociation, StructureOptions, StructuredInstructions};
+use substreams_solana_utils::log::Log;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const SIGNATURE: &str = "signature";
+
+fn invoke(program: Pubkey, depth: u32) -> String {
+    format!("Program {} invoke [{}]", program.to_string(), depth)
+}
+
+fn success(program: Pubkey) -> String {
+    format!("Program {} success", program.to_string())
+}
+
+/// Two routes through a pool, the second with three transfers, with the first
+/// `inner` inner instructions of the second route and `log_messages`
+fn routes(inner: usize, log_messages: &[String]) -> ConfirmedTransaction {
+    let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    let builder = TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user, pool], vec![0])
+        .inner(pool, &[user], vec![1])
+        .inner(pool, &[user], vec![2])
+        .instruction(router, &[user, pool], vec![3]);
+    let builder = [4, 5, 6].into_iter().take(inner).fold(builder, |builder, x| builder.inner(pool, &[user], vec![x]));
+    let log_messages: Vec<&str> = log_messages.iter().map(|x| x.as_str()).collect();
+    builder.logs(&log_messages).build()
+}
+
+fn full_logs() -> Vec<String> {
+    let (router, pool) = (pubkey(2), pubkey(3));
+    let pool_invocation = |name: &str| vec![invoke(pool, 2), format!("Program log: {name}"), success(pool)];
+    [
+        vec![invoke(router, 1), "Program log: Route 1".to_string()],
+        pool_invocation("Swap 1"),
+        pool_invocation("Swap 2"),
+        vec!["Program log: Routed 1".to_string(), success(router), invoke(router, 1), "Program log: Route 2".to_string()],
+        pool_invocation("Swap 4"),
+        pool_invocation("Swap 5"),
+        pool_invocation("Swap 6"),
+        vec!["Program log: Routed 2".to_string(), success(router)],
+    ].concat()
+}
+
+/// Id of each event, named after the data of the inner instruction or the
+/// message of the log it originates from, in execution order
+fn all_event_ids(transaction: &ConfirmedTransaction, association: LogAssociation) -> Vec<(String, String)> {
+    let options = StructureOptions { association, ..Default::default() };
+    let instructions = get_structured_instructions_with_options(transaction, &options).unwrap();
+    let mut ids = Vec::new();
+    for instruction in instructions.flattened() {
+        if let Some(ordinal) = EventOrdinal::from_inner_instruction(&instruction) {
+            let parent = instruction.parent_instruction().unwrap();
+            ids.push((format!("instruction {}", instruction.data()[0]), event_id(SIGNATURE, &parent.path(), &ordinal)));
+        }
+        for (position, log) in instruction.logs().iter().flatten().enumerate() {
+            if let Log::Program(program_log) = log {
+                let ordinal = EventOrdinal::from_log_position(position as u32);
+                ids.push((program_log.message().unwrap().to_string(), event_id(SIGNATURE, &instruction.path(), &ordinal)));
+            }
+        }
+    }
+    ids
+}
+
+fn event_ids(transaction: &ConfirmedTransaction, association: LogAssociation) -> BTreeMap<String, String> {
+    all_event_ids(transaction, association).into_iter().collect()
+}
+
+/// Asserts that every event of `partial` has the id it has in the full
+/// transaction, and returns the names of the events
+fn assert_same_ids(partial: &BTreeMap<String, String>) -> Vec<&str> {
+    let full = event_ids(&routes(3, &full_logs()), LogAssociation::Strict);
+    for (name, id) in partial {
+        assert_eq!(Some(id), full.get(name), "id of {name}");
+    }
+    partial.keys().map(|x| x.as_str()).collect()
+}
+
+#[test]
+fn full_transaction() {
+    let ids = event_ids(&routes(3, &full_logs()), LogAssociation::Strict);
+    assert_eq!(ids.len(), 14);
+    assert_eq!(ids["instruction 5"], "signature-1-i1.0");
+    assert_eq!(ids["Swap 5"], "signature-1.1-l1.0");
+    assert_eq!(ids["Routed 2"], "signature-1-l2.0");
+}
+
+#[test]
+fn ids_are_unique() {
+    for (inner, logs) in [(3, full_logs()), (1, full_logs()[..15].to_vec())] {
+        let ids = all_event_ids(&routes(inner, &logs), LogAssociation::Strict);
+        let unique: BTreeSet<&str> = ids.iter().map(|(_, id)| id.as_str()).collect();
+        assert_eq!(unique.len(), ids.len(), "{:?}", ids);
+    }
+
+    // Events at the same position under different parents, or in the logs of
+    // different instructions
+    let ids = event_ids(&routes(3, &full_logs()), LogAssociation::Strict);
+    assert_eq!(ids["instruction 1"], "signature-0-i0.0");
+    assert_eq!(ids["instruction 4"], "signature-1-i0.0");
+    assert_eq!(ids["Route 2"], "signature-1-l1.0");
+    assert_eq!(ids["Swap 4"], "signature-1.0-l1.0");
+    assert_eq!(ids["Swap 6"], "signature-1.2-l1.0");
+}
+
+#[test]
+fn truncated_logs() {
+    let full_logs = full_logs();
+    // Within the second swap of the second route, then right after its invoke log
+    for cut in [17, 14] {
+        let logs = [&full_logs[..cut], &["Log truncated".to_string()]].concat();
+        let ids = event_ids(&routes(3, &logs), LogAssociation::Strict);
+        let names = assert_same_ids(&ids);
+        assert!(names.contains(&"Routed 1") && names.contains(&"instruction 6"));
+        assert!(!names.contains(&"Routed 2"));
+    }
+}
+
+#[test]
+fn missing_inner_instructions() {
+    let full_logs = full_logs();
+    // Without the logs of the missing inner instructions, then without any log of the second route
+    for (inner, logs) in [(1, [&full_logs[..15], &full_logs[21..]].concat()), (0, full_logs[..10].to_vec())] {
+        let ids = event_ids(&routes(inner, &logs), LogAssociation::Strict);
+        let names = assert_same_ids(&ids);
+        assert!(names.contains(&"Swap 2") && !names.contains(&"instruction 5"));
+        assert_eq!(names.contains(&"instruction 4"), inner == 1);
+    }
+}
+
+#[test]
+fn mismatched_logs() {
+    let mut logs = full_logs();
+    logs[17] = invoke(pubkey(9), 2);
+    let ids = event_ids(&routes(3, &logs), LogAssociation::Lenient);
+    let names = assert_same_ids(&ids);
+    assert!(names.contains(&"Swap 4") && names.contains(&"instruction 6"));
+    assert!(!names.contains(&"Swap 5") && !names.contains(&"Routed 2"));
+}
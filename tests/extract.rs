@@ -0,0 +1,145 @@
+//! Routing of instructions to extractors, see `ExtractorRegistry`.
+
+#![cfg(feature = "test-utils")]
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::extract::{CustomEvent, Extractor, ExtractorRegistry, ProtocolEvent, SystemEvent, TokenEvent};
+use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstruction};
+use substreams_solana_utils::programs::KnownProgram;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::spl_token::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use substreams_solana_utils::system_program::SYSTEM_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::TransactionContext;
+
+/// Extractor emitting the data of every instruction of its programs, which
+/// records when it is dropped
+struct Protocol {
+    name: &'static str,
+    program_ids: Vec<Pubkey>,
+    dropped: Rc<Cell<bool>>,
+}
+
+impl Protocol {
+    fn new(name: &'static str, program_ids: Vec<Pubkey>) -> Self {
+        Self { name, program_ids, dropped: Rc::new(Cell::new(false)) }
+    }
+}
+
+impl Drop for Protocol {
+    fn drop(&mut self) {
+        self.dropped.set(true);
+    }
+}
+
+impl Extractor for Protocol {
+    fn name(&self) -> &'static str { self.name }
+
+    fn program_ids(&self) -> Vec<Pubkey> {
+        self.program_ids.clone()
+    }
+
+    fn extract(&self, instruction: &StructuredInstruction, _context: &TransactionContext) -> Vec<ProtocolEvent> {
+        let event = CustomEvent { extractor: self.name, name: "instruction".to_string(), data: instruction.data().clone() };
+        vec![ProtocolEvent::Custom { schema_version: 1, event }]
+    }
+}
+
+/// Names of the extractors of the custom events, and the variants of the others
+fn sources(transaction: &ConfirmedTransaction, registry: &ExtractorRegistry) -> Vec<&'static str> {
+    let context = TransactionContext::build(transaction).unwrap();
+    let instructions = get_structured_instructions(transaction).unwrap();
+    registry.extract(transaction, &context, &instructions).iter().map(|event| match event {
+        ProtocolEvent::Custom { event, .. } => event.extractor,
+        ProtocolEvent::Token { .. } => "token",
+        ProtocolEvent::System { .. } => "system",
+        _ => "other",
+    }).collect()
+}
+
+/// A route through two protocols, each moving tokens, then a Token-2022 transfer
+fn route() -> ConfirmedTransaction {
+    let (user, router, pool, source, destination) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let transfer = [vec![3], 7u64.to_le_bytes().to_vec()].concat();
+    let system_transfer = [2u32.to_le_bytes().to_vec(), 5u64.to_le_bytes().to_vec()].concat();
+    TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user, pool], vec![0])
+        .inner(SYSTEM_PROGRAM_ID, &[user, source], system_transfer)
+        .inner(pool, &[user], vec![1])
+        .inner_at(3, TOKEN_PROGRAM_ID, &[source, destination, user], transfer.clone())
+        .instruction(TOKEN_2022_PROGRAM_ID, &[source, destination, user], transfer)
+        .build()
+}
+
+#[test]
+fn two_protocols() {
+    let transaction = route();
+    let mut registry = ExtractorRegistry::default();
+    registry.register(Box::new(Protocol::new("router", vec![pubkey(2)]))).unwrap();
+    registry.register(Box::new(Protocol::new("pool", vec![pubkey(3)]))).unwrap();
+    assert_eq!(sources(&transaction, &registry), ["router", "system", "pool", "token", "token"]);
+
+    let error = registry.register(Box::new(Protocol::new("fork", vec![pubkey(6), pubkey(3)]))).unwrap_err();
+    assert_eq!(error.to_string(), format!("Program {} is already claimed by the pool extractor", pubkey(3).to_string()));
+    assert!(!registry.is_enabled(&pubkey(6)));
+}
+
+#[test]
+fn one_protocol_disabled() {
+    let transaction = route();
+    let mut registry = ExtractorRegistry::default();
+    registry.register(Box::new(Protocol::new("router", vec![pubkey(2)]))).unwrap();
+
+    registry.disable(KnownProgram::System);
+    assert!(!registry.is_enabled(&SYSTEM_PROGRAM_ID));
+    assert_eq!(sources(&transaction, &registry), ["router", "token", "token"]);
+
+    registry.enable(KnownProgram::System);
+    assert_eq!(sources(&transaction, &registry), ["router", "system", "token", "token"]);
+}
+
+#[test]
+fn replaced_extractors_are_dropped() {
+    let transaction = route();
+    let mut registry = ExtractorRegistry::empty();
+    let router = Protocol::new("router", vec![pubkey(2), pubkey(3)]);
+    let router_dropped = Rc::clone(&router.dropped);
+    registry.register(Box::new(router)).unwrap();
+    let token = Protocol::new("token-v1", vec![TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID]);
+    let token_dropped = Rc::clone(&token.dropped);
+    registry.register(Box::new(token)).unwrap();
+
+    // Taking one of its programs over keeps the extractor for the other
+    registry.replace(Box::new(Protocol::new("token-v2", vec![TOKEN_PROGRAM_ID])));
+    assert!(!token_dropped.get());
+    assert_eq!(sources(&transaction, &registry), ["router", "router", "token-v2", "token-v1"]);
+
+    registry.replace(Box::new(Protocol::new("token-2022", vec![TOKEN_2022_PROGRAM_ID])));
+    assert!(token_dropped.get());
+    assert_eq!(sources(&transaction, &registry), ["router", "router", "token-v2", "token-2022"]);
+
+    registry.replace(Box::new(Protocol::new("aggregator", vec![pubkey(2), pubkey(3)])));
+    assert!(router_dropped.get());
+    assert_eq!(sources(&transaction, &registry), ["aggregator", "aggregator", "token-v2", "token-2022"]);
+}
+
+#[test]
+fn default_extractors() {
+    let (user, recipient) = (pubkey(1), pubkey(2));
+    let system_transfer = [2u32.to_le_bytes().to_vec(), 5u64.to_le_bytes().to_vec()].concat();
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(SYSTEM_PROGRAM_ID, &[user, recipient], system_transfer)
+        .instruction(TOKEN_PROGRAM_ID, &[user, recipient, user], [vec![3], 7u64.to_le_bytes().to_vec()].concat())
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    let instructions = get_structured_instructions(&transaction).unwrap();
+    assert_eq!(ExtractorRegistry::default().extract(&transaction, &context, &instructions), [
+        ProtocolEvent::System { schema_version: 1, event: SystemEvent::Transfer { from: user, to: recipient, lamports: 5 } },
+        ProtocolEvent::Token { schema_version: 1, event: TokenEvent::Transfer { source: user, destination: recipient, authority: user, amount: 7 } },
+    ]);
+}
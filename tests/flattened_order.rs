@@ -0,0 +1,85 @@
+#![cfg(feature = "test-utils")]
+
+//! `StructuredInstructions::flattened` must list the instructions in the order
+//! of `get_flattened_instructions`, which is their execution order. Checked on
+//! pseudo-random transactions with sparse inner instruction groups and nested
+//! invocations.
+
+use substreams_solana::pb::sf::solana::r#type::v1 as pb;
+use substreams_solana_utils::instruction::{get_flattened_instructions, get_structured_instructions, StructuredInstructions};
+use substreams_solana_utils::instruction_tree::get_instruction_tree;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+/// Deterministic xorshift generator, so that failures can be replayed
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+/// A transaction of 1 to 5 top level instructions, each with up to 8 inner
+/// instructions nested up to a stack height of 5. Every instruction data is
+/// unique.
+fn random_transaction(rng: &mut Rng, builder: TransactionBuilder) -> pb::ConfirmedTransaction {
+    let user = pubkey(1);
+    let mut builder = builder.signer(user);
+    let mut id: u16 = 0;
+    for _ in 0..1 + rng.below(5) {
+        builder = builder.instruction(pubkey(2 + rng.below(4) as u8), &[user], id.to_le_bytes().to_vec());
+        id += 1;
+        let mut stack_height: u32 = 1;
+        for _ in 0..rng.below(9) {
+            stack_height = 2 + rng.below(stack_height.min(4) as u64) as u32;
+            builder = builder.inner_at(stack_height, pubkey(2 + rng.below(4) as u8), &[user], id.to_le_bytes().to_vec());
+            id += 1;
+        }
+    }
+    builder.build()
+}
+
+fn assert_same_order(transaction: &pb::ConfirmedTransaction) {
+    let expected: Vec<_> = get_flattened_instructions(transaction).unwrap().iter().map(|x| x.data().clone()).collect();
+
+    let instructions = get_structured_instructions(transaction).unwrap();
+    let flattened = instructions.flattened();
+    let actual: Vec<_> = flattened.iter().map(|x| x.data().clone()).collect();
+    assert_eq!(actual, expected);
+    assert!(flattened.iter().enumerate().all(|(i, x)| x.flat_index() == i));
+    let lazy: Vec<_> = instructions.iter_dfs().map(|x| x.data().clone()).collect();
+    assert_eq!(lazy, expected);
+
+    let tree = get_instruction_tree(transaction).unwrap();
+    let arena: Vec<_> = tree.flattened().iter().map(|x| x.data().clone()).collect();
+    assert_eq!(arena, expected);
+}
+
+#[test]
+fn flattened_follows_execution_order() {
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    for _ in 0..500 {
+        assert_same_order(&random_transaction(&mut rng, TransactionBuilder::new()));
+    }
+}
+
+#[test]
+fn flattened_follows_execution_order_with_unsorted_inner_groups() {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    for _ in 0..500 {
+        let mut transaction = random_transaction(&mut rng, TransactionBuilder::new());
+        transaction.meta.as_mut().unwrap().inner_instructions.reverse();
+        assert_same_order(&transaction);
+    }
+}
+
+#[test]
+fn flattened_follows_execution_order_with_derived_stack_heights() {
+    let mut rng = Rng(0xd1b54a32d192ed03);
+    for _ in 0..500 {
+        assert_same_order(&random_transaction(&mut rng, TransactionBuilder::new().without_stack_heights()));
+    }
+}
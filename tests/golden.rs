@@ -0,0 +1,47 @@
+#![cfg(feature = "test-utils")]
+
+use std::path::Path;
+
+use substreams_solana_utils::golden::{cross_validate, run_golden_cases};
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+#[test]
+fn instruction_logs_match_golden_data() {
+    let reports = run_golden_cases(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")).unwrap();
+    assert!(!reports.is_empty(), "No golden cases found");
+    let failures: Vec<String> = reports.iter()
+        .filter(|report| !report.mismatches.is_empty())
+        .map(|report| {
+            let mismatches: Vec<String> = report.mismatches.iter().map(|x| x.to_string()).collect();
+            format!("{}:\n{}", report.name, mismatches.join("\n"))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{} of {} golden cases failed\n\n{}", failures.len(), reports.len(), failures.join("\n\n"));
+}
+
+#[test]
+fn cross_validation_compares_built_transactions() {
+    let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    let (router_id, pool_id) = (router.to_string(), pool.to_string());
+    let logs = [
+        format!("Program {router_id} invoke [1]"),
+        "Program log: Instruction: Route".to_string(),
+        format!("Program {pool_id} invoke [2]"),
+        "Program data: AQID".to_string(),
+        format!("Program {pool_id} success"),
+        format!("Program {router_id} success"),
+    ];
+    let build = |logs: &[String]| TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user, pool], vec![0])
+        .inner(pool, &[user], vec![1])
+        .logs(&logs.iter().map(String::as_str).collect::<Vec<_>>())
+        .build();
+
+    assert!(cross_validate(&build(&logs)).unwrap().is_empty());
+
+    let mut with_orphan = vec!["Unexpected runtime message".to_string()];
+    with_orphan.extend(logs);
+    let mismatches = cross_validate(&build(&with_orphan)).unwrap();
+    assert_eq!(mismatches.iter().map(|x| x.path.as_str()).collect::<Vec<_>>(), ["orphan_logs[0]"]);
+}
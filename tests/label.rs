@@ -0,0 +1,72 @@
+//! Address labels loaded from CSV and TSV files, see `LabelRegistry`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana_utils::label::{Label, LabelRegistry};
+use substreams_solana_utils::pubkey::PubkeyRef;
+use substreams_solana_utils::spl_token::{TOKEN_PROGRAM_ID, WRAPPED_SOL_MINT};
+use substreams_solana_utils::test_utils::pubkey;
+
+fn label(label: &str, category: &str) -> Label {
+    Label { label: label.to_string(), category: category.to_string() }
+}
+
+#[test]
+fn later_sources_take_precedence() {
+    let token_program = TOKEN_PROGRAM_ID.to_string();
+    let mut registry = LabelRegistry::default();
+    assert_eq!(registry.lookup(&TOKEN_PROGRAM_ID), Some(&label("Token Program", "program")));
+
+    registry.load_csv(&format!("{token_program},Token,program\n{token_program},SPL Token,program")).unwrap();
+    assert_eq!(registry.lookup(&TOKEN_PROGRAM_ID), Some(&label("SPL Token", "program")));
+
+    let mut other = LabelRegistry::new();
+    other.insert(TOKEN_PROGRAM_ID, "Tokenkeg", "token");
+    other.insert(pubkey(1), "Treasury", "wallet");
+    registry.extend(other);
+    assert_eq!(registry.lookup(&TOKEN_PROGRAM_ID), Some(&label("Tokenkeg", "token")));
+    assert_eq!(registry.lookup(&pubkey(1)), Some(&label("Treasury", "wallet")));
+
+    registry.insert(pubkey(1), "Cold wallet", "wallet");
+    assert_eq!(registry.lookup(&pubkey(1)), Some(&label("Cold wallet", "wallet")));
+    assert_eq!(registry.lookup(&WRAPPED_SOL_MINT), Some(&label("Wrapped SOL", "mint")));
+}
+
+#[test]
+fn headers_comments_and_whitespace() {
+    let (treasury, vault) = (pubkey(1).to_string(), pubkey(2).to_string());
+    let csv = format!("# Labels of the team\r\n\r\n Address , Label, Category\r\n{treasury} , \"Treasury, main\" ,wallet\r\n  {vault},Vault,  vault  \r\n");
+    let registry = LabelRegistry::from_csv(&csv).unwrap();
+    assert_eq!(registry.len(), 2);
+    assert_eq!(registry.lookup(&pubkey(1)), Some(&label("Treasury, main", "wallet")));
+    assert_eq!(registry.lookup(&pubkey(2)), Some(&label("Vault", "vault")));
+
+    let tsv = format!("address\tlabel\tcategory\n{treasury}\tTreasury\twallet\n");
+    assert_eq!(LabelRegistry::from_tsv(&tsv).unwrap().lookup(&pubkey(1)), Some(&label("Treasury", "wallet")));
+    assert_eq!(LabelRegistry::from_csv("").unwrap().len(), 0);
+}
+
+#[test]
+fn malformed_rows() {
+    let treasury = pubkey(1).to_string();
+    let error = |csv: &str| LabelRegistry::from_csv(csv).unwrap_err().to_string();
+
+    assert_eq!(error(&format!("{treasury},Treasury")), "Malformed label row 1: expected 3 fields, found 2");
+    assert_eq!(error(&format!("# comment\n{treasury},Treasury,wallet,extra")), "Malformed label row 2: expected 3 fields, found 4");
+    assert_eq!(error("not an address,Treasury,wallet"), "Malformed label row 1: invalid address");
+    assert_eq!(error(&format!("{treasury},\"Treasury,wallet")), "Malformed label row 1: unterminated quoted field");
+    // A header past the first row is a row like any other
+    assert_eq!(error(&format!("{treasury},Treasury,wallet\naddress,label,category")), "Malformed label row 2: invalid address");
+}
+
+#[test]
+fn annotation() {
+    let mut registry = LabelRegistry::new();
+    registry.insert(pubkey(1), "Treasury", "wallet");
+    assert_eq!(registry.annotate(&pubkey(1)), format!("{} (Treasury)", pubkey(1).to_string()));
+    assert_eq!(registry.annotate(&pubkey(2)), pubkey(2).to_string());
+
+    let address = pubkey(1).0.to_vec();
+    assert_eq!(registry.lookup_ref(&PubkeyRef(&address)), Some(&label("Treasury", "wallet")));
+    assert_eq!(registry.lookup_ref(&PubkeyRef(&vec![1; 31])), None);
+}
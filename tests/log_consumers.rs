@@ -0,0 +1,133 @@
+//! One corpus of log messages read by both consumers of `Log`: the log trees of
+//! `build_structured_logs` and the logs attached to structured instructions.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::golden::cross_validate;
+use substreams_solana_utils::instruction::{get_structured_instructions, StructuredInstructions};
+use substreams_solana_utils::log::build_structured_logs;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const COMPUTE_BUDGET: &str = "ComputeBudget111111111111111111111111111111";
+const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const RAYDIUM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+const WHIRLPOOL: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const LOADER: &str = "BPFLoaderUpgradeab1e11111111111111111111111";
+
+fn id(program_id: &str) -> Pubkey {
+    Pubkey::try_from_string(program_id).unwrap()
+}
+
+/// A compute budget instruction, then a Jupiter route through Raydium and
+/// Whirlpool, with every kind of log inside the invocations
+fn route() -> ConfirmedTransaction {
+    let user = pubkey(1);
+    TransactionBuilder::new()
+        .signer(user)
+        .instruction(id(COMPUTE_BUDGET), &[], vec![2])
+        .instruction(id(JUPITER), &[user], vec![0])
+        .inner(id(RAYDIUM), &[user], vec![1])
+        .inner_at(3, id(TOKEN), &[user], vec![3])
+        .inner(id(WHIRLPOOL), &[user], vec![2])
+        .logs(&[
+            &format!("Program {COMPUTE_BUDGET} invoke [1]"),
+            &format!("Program {COMPUTE_BUDGET} success"),
+            &format!("Program {JUPITER} invoke [1]"),
+            "Program log: Instruction: Route",
+            &format!("Program {RAYDIUM} invoke [2]"),
+            "Program log: ray_log: A0BCDwAAAAAAAAAAAAAAAAACAAAAAAAAAEBCDwAAAAAA",
+            &format!("Program {TOKEN} invoke [3]"),
+            "Program log: Instruction: Transfer",
+            &format!("Program {TOKEN} consumed 4645 of 180123 compute units"),
+            &format!("Program {TOKEN} success"),
+            &format!("Program {RAYDIUM} consumed 31040 of 201737 compute units"),
+            &format!("Program {RAYDIUM} success"),
+            &format!("Program {WHIRLPOOL} invoke [2]"),
+            "Program log: Instruction: Swap",
+            "Program data: 4cGHOd0Dd0MAAAAAAAAAAA==",
+            "Program data: not base64!",
+            "Unexpected runtime message",
+            &format!("Program {WHIRLPOOL} success"),
+            &format!("Program return: {JUPITER} 6F4AAAAAAAA="),
+            &format!("Program {JUPITER} consumed 98211 of 199850 compute units"),
+            &format!("Program {JUPITER} success"),
+        ])
+        .build()
+}
+
+/// A program upgrade, logging a runtime notice
+fn upgrade() -> ConfirmedTransaction {
+    let user = pubkey(1);
+    TransactionBuilder::new()
+        .signer(user)
+        .instruction(id(LOADER), &[user], vec![3])
+        .logs(&[
+            &format!("Program {LOADER} invoke [1]"),
+            &format!("Upgraded program {JUPITER}"),
+            &format!("Program {LOADER} success"),
+        ])
+        .build()
+}
+
+/// A route whose logs are truncated inside the Raydium swap
+fn truncated_route() -> ConfirmedTransaction {
+    let user = pubkey(1);
+    TransactionBuilder::new()
+        .signer(user)
+        .instruction(id(JUPITER), &[user], vec![0])
+        .inner(id(RAYDIUM), &[user], vec![1])
+        .inner_at(3, id(TOKEN), &[user], vec![3])
+        .logs(&[
+            &format!("Program {JUPITER} invoke [1]"),
+            "Program log: Instruction: Route",
+            &format!("Program {RAYDIUM} invoke [2]"),
+            "Program log: ray_log: A0BCDwAAAAAAAAAAAAAAAAACAAAAAAAAAEBCDwAAAAAA",
+            "Log truncated",
+        ])
+        .build()
+}
+
+#[test]
+fn both_consumers_attribute_the_same_lines() {
+    for transaction in [route(), upgrade(), truncated_route()] {
+        let mismatches: Vec<String> = cross_validate(&transaction).unwrap().iter().map(|x| x.to_string()).collect();
+        assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    }
+}
+
+#[test]
+fn both_consumers_read_the_same_payloads() {
+    let transaction = route();
+    let instructions = get_structured_instructions(&transaction).unwrap();
+    let structured_logs = build_structured_logs(&transaction.meta.as_ref().unwrap().log_messages).unwrap();
+
+    let jupiter = &structured_logs[1];
+    let swap = jupiter.inner_logs().nth(1).unwrap();
+    let swap_instruction = &instructions[1].inner_instructions()[1];
+    assert_eq!(swap.data(), swap_instruction.events().iter().map(|x| x.to_vec()).collect::<Vec<_>>());
+    assert_eq!(swap.encoded_data(), ["4cGHOd0Dd0MAAAAAAAAAAA==", "not base64!"]);
+    assert_eq!(swap.unknown_logs(), ["Unexpected runtime message"]);
+    assert_eq!(jupiter.return_data().as_deref(), instructions[1].return_data());
+    assert_eq!(jupiter.compute_units_consumed(), instructions[1].compute_units_consumed());
+}
+
+#[test]
+fn both_consumers_mark_the_same_invocations_truncated() {
+    let transaction = truncated_route();
+    let instructions = get_structured_instructions(&transaction).unwrap();
+    let structured_logs = build_structured_logs(&transaction.meta.as_ref().unwrap().log_messages).unwrap();
+
+    let mut truncated = Vec::new();
+    let mut frames = vec![&structured_logs[0]];
+    while let Some(frame) = frames.pop() {
+        truncated.push((frame.program_id(), frame.truncated));
+        frames.extend(frame.inner_logs());
+    }
+    let instructions = instructions.flattened();
+    assert_eq!(truncated, [(Some(JUPITER), true), (Some(RAYDIUM), true)]);
+    assert_eq!(instructions.iter().map(|x| x.logs_truncated()).collect::<Vec<_>>(), [true, true, true]);
+    assert!(instructions[2].logs().as_ref().is_none_or(|logs| logs.is_empty()));
+}
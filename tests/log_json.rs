@@ -0,0 +1,48 @@
+//! JSON payloads of `Program log` lines, see `ProgramLog::as_json`.
+
+use serde_json::json;
+use substreams_solana_utils::log::{build_structured_logs, Log};
+
+const ORACLE: &str = "FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH";
+
+fn json_payload(line: &str) -> Option<serde_json::Value> {
+    match Log::new(line) {
+        Log::Program(program_log) => program_log.as_json(),
+        other => panic!("{:?} is not a program log", other),
+    }
+}
+
+#[test]
+fn nested_json_is_parsed() {
+    let line = r#"Program log: {"price":{"value":"123.45","expo":-8},"feeds":[1,[2,3]]}"#;
+    assert_eq!(json_payload(line), Some(json!({"price": {"value": "123.45", "expo": -8}, "feeds": [1, [2, 3]]})));
+    assert_eq!(json_payload("Program log: [1, 2]"), Some(json!([1, 2])));
+}
+
+#[test]
+fn json_mentioning_success_stays_a_program_log() {
+    let line = format!(r#"Program log: {{"status":"Program {} success"}}"#, ORACLE);
+    assert_eq!(json_payload(&line), Some(json!({"status": format!("Program {} success", ORACLE)})));
+}
+
+#[test]
+fn malformed_or_non_json_messages_are_none() {
+    assert_eq!(json_payload(r#"Program log: {"price": 1"#), None);
+    assert_eq!(json_payload("Program log: [1, 2,]"), None);
+    assert_eq!(json_payload("Program log: Instruction: Transfer"), None);
+    assert_eq!(json_payload("Program log: 42"), None);
+}
+
+#[test]
+fn json_logs_of_a_frame() {
+    let logs: Vec<String> = vec![
+        format!("Program {} invoke [1]", ORACLE),
+        r#"Program log: {"slot":1}"#.to_string(),
+        "Program log: Instruction: Update".to_string(),
+        r#"Program log: {"slot":"#.to_string(),
+        r#"Program log: [{"slot":2}]"#.to_string(),
+        format!("Program {} success", ORACLE),
+    ];
+    let structured_logs = build_structured_logs(&logs).unwrap();
+    assert_eq!(structured_logs[0].json_logs(), vec![json!({"slot": 1}), json!([{"slot": 2}])]);
+}
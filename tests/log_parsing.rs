@@ -0,0 +1,565 @@
+//! Classification and field parsing of log lines, including malformed ones.
+
+use substreams_solana_utils::error::{LogDecodeError, LogStructureError};
+use substreams_solana_utils::log::{build_structured_logs, build_structured_logs_with_preamble, compute_units_by_program, extract_error, Log, LogKind, LogStructureWarning, ProgramError, RuntimeEvent};
+
+const PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+fn log(line: &str) -> Log<'static> {
+    Log::new(Box::leak(Box::new(line.to_string())))
+}
+
+#[test]
+fn program_log_messages() {
+    let message = |line: &str| match log(line) {
+        Log::Program(program_log) => program_log.message(),
+        other => panic!("{:?} is not a program log", other),
+    };
+    assert_eq!(message("Program log: Instruction: Transfer"), Ok("Instruction: Transfer"));
+    assert_eq!(message("Program log:  leading space"), Ok(" leading space"));
+    assert_eq!(message("Program log: Program log: nested"), Ok("Program log: nested"));
+    assert_eq!(message("Program log: first line\nsecond line"), Ok("first line"));
+    assert!(message("Program log: ").is_err());
+}
+
+#[test]
+fn data_logs() {
+    let data = |line: &str| match log(line) {
+        Log::Data(data_log) => data_log.data(),
+        other => panic!("{:?} is not a data log", other),
+    };
+    assert_eq!(data("Program data: AQID"), Ok(vec![1, 2, 3]));
+    assert_eq!(data("Program data: AQID\nBAUG"), Ok(vec![1, 2, 3]));
+    assert!(data("Program data: AQID BAUG").is_err());
+    assert!(data("Program data: not base64!").is_err());
+    assert!(data("Program data: ").is_err());
+}
+
+#[test]
+fn return_logs() {
+    let parse = |line: &str| match log(line) {
+        Log::Return(return_log) => (return_log.program_id(), return_log.data()),
+        other => panic!("{:?} is not a return log", other),
+    };
+    assert_eq!(parse(&format!("Program return: {} AQID", PROGRAM)), (Ok(PROGRAM), Ok(vec![1, 2, 3])));
+    assert_eq!(parse(&format!("Program return: {} AQID\nBAUG", PROGRAM)).1, Ok(vec![1, 2, 3]));
+    assert_eq!(parse(&format!("Program return: {} BA==", PROGRAM)).1, Ok(vec![4]));
+    assert_eq!(parse(&format!("Program return: {} BA", PROGRAM)).1, Ok(vec![4]));
+    assert_eq!(parse(&format!("Program return: {} BAU=", PROGRAM)).1, Ok(vec![4, 5]));
+    assert_eq!(parse(&format!("Program return: {} BAU", PROGRAM)).1, Ok(vec![4, 5]));
+    assert_eq!(parse(&format!("Program return: {} +/8=", PROGRAM)), (Ok(PROGRAM), Ok(vec![0xfb, 0xff])));
+    // Classified by their shape, the payload being decoded on demand
+    for payload in ["AQ ID", "AQID extra diagnostics", "-_8=", "not base64!", " AQID"] {
+        let line = format!("Program return: {} {}", PROGRAM, payload);
+        assert!(parse(&line).1.is_err(), "{:?}", line);
+        assert_eq!(log(&line).as_return().unwrap().encoded_data(), Ok(payload));
+    }
+    let malformed = [
+        format!("Program return: {} ", PROGRAM),
+        "Program return: a b AQID".into(),
+        "Program return: AQID".into(),
+        "Program return: ".into(),
+    ];
+    for line in malformed {
+        assert!(log(&line).is_unknown(), "{:?}", line);
+    }
+}
+
+#[test]
+fn consumed_logs() {
+    let parse = |line: &str| match log(line) {
+        Log::Consumed(consumed_log) => (consumed_log.program_id(), consumed_log.consumed(), consumed_log.budget()),
+        other => panic!("{:?} is not a consumed log", other),
+    };
+    let line = format!("Program {} consumed 54012 of 200000 compute units", PROGRAM);
+    assert_eq!(parse(&line), (Ok(Some(PROGRAM)), Ok(54012), Ok(200000)));
+    assert_eq!(parse("consumed 1500 of 1400000 compute units"), (Ok(None), Ok(1500), Ok(1400000)));
+}
+
+#[test]
+fn compute_units_are_attributed_without_counting_inner_invocations_twice() {
+    let other = "11111111111111111111111111111111";
+    let consumed = |program: &str, units: u64| format!("Program {} consumed {} of 200000 compute units", program, units);
+    let mut log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        format!("Program {} invoke [2]", other),
+        format!("Program {} success", other),
+        format!("Program {} invoke [2]", PROGRAM),
+        consumed(PROGRAM, 3000),
+        format!("Program {} success", PROGRAM),
+        consumed(PROGRAM, 10000),
+        format!("Program {} success", PROGRAM),
+        format!("Program {} invoke [1]", PROGRAM),
+        consumed(PROGRAM, 500),
+        format!("Program {} success", PROGRAM),
+        "consumed 10650 of 400000 compute units".to_string(),
+    ];
+    let units = compute_units_by_program(&log_messages).unwrap();
+    assert_eq!(units.len(), 1);
+    assert_eq!(units[PROGRAM], 10500);
+
+    log_messages.insert(9, "Log truncated".to_string());
+    assert!(compute_units_by_program(&log_messages).is_none());
+}
+
+#[test]
+fn failed_logs() {
+    let parse = |line: &str| match log(line) {
+        Log::Failed(failed_log) => (failed_log.program_id(), failed_log.custom_error_code(), failed_log.program_error()),
+        other => panic!("{:?} is not a failed log", other),
+    };
+    let line = format!("Program {} failed: custom program error: 0x1771", PROGRAM);
+    assert_eq!(parse(&line), (Ok(PROGRAM), Some(0x1771), Some(ProgramError::Custom(0x1771))));
+    let line = format!("Program {} failed: insufficient funds", PROGRAM);
+    assert_eq!(parse(&line), (Ok(PROGRAM), None, Some(ProgramError::InsufficientFunds)));
+}
+
+#[test]
+fn errors_are_extracted_from_the_failing_invocation() {
+    let other = "11111111111111111111111111111111";
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        format!("Program {} invoke [2]", other),
+        format!("Program {} failed: custom program error: 6001", other),
+        format!("Program {} failed: custom program error: 6001", PROGRAM),
+    ];
+    let error = extract_error(&log_messages).unwrap();
+    assert_eq!((error.program_id.as_str(), error.code), (other, Some(0x1771)));
+    assert_eq!(error.message, "custom program error: 6001");
+    let structured_logs = build_structured_logs(&log_messages).unwrap();
+    assert_eq!(structured_logs[0].error(), Some(error.clone()));
+    assert_eq!(structured_logs[0].inner[0].error(), Some(error));
+
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        format!("Program {} failed: custom program error: 0x1771", PROGRAM),
+    ];
+    let error = extract_error(&log_messages).unwrap();
+    assert_eq!((error.program_id.as_str(), error.anchor_error_number()), (PROGRAM, Some(6001)));
+
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        format!("Program {} failed: insufficient funds", PROGRAM),
+    ];
+    let error = extract_error(&log_messages).unwrap();
+    assert_eq!((error.code, error.program_error()), (None, ProgramError::InsufficientFunds));
+    assert!(extract_error(&log_messages[..1]).is_none());
+}
+
+#[test]
+fn failed_logs_close_invocations() {
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        format!("Program {} invoke [2]", PROGRAM),
+        "Program log: Error: insufficient funds".to_string(),
+        format!("Program {} failed: custom program error: 0x1", PROGRAM),
+        format!("Program {} failed: custom program error: 0x1", PROGRAM),
+    ];
+    let structured_logs = build_structured_logs(&log_messages).unwrap();
+    assert_eq!(structured_logs.len(), 1);
+    assert!(!structured_logs[0].truncated && structured_logs[0].logs.last().unwrap().is_failed());
+    assert_eq!(structured_logs[0].inner[0].logs.len(), 3);
+    assert!(!structured_logs[0].inner[0].truncated);
+}
+
+#[test]
+fn truncation_mid_cpi_keeps_partial_logs() {
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        "Program log: outer".to_string(),
+        format!("Program {} invoke [2]", PROGRAM),
+        "Program log: inner".to_string(),
+        "Log truncated".to_string(),
+        format!("Program {} success", PROGRAM),
+    ];
+    let structured_logs = build_structured_logs(&log_messages).unwrap();
+    assert_eq!(structured_logs.len(), 1);
+    let (outer, inner) = (&structured_logs[0], &structured_logs[0].inner[0]);
+    assert!(outer.truncated && inner.truncated);
+    let raw = |logs: &[Log]| logs.iter().map(|x| x.raw().to_string()).collect::<Vec<_>>();
+    assert_eq!(raw(&outer.logs), [log_messages[0].as_str(), "Program log: outer"]);
+    assert_eq!(raw(&inner.logs), [log_messages[2].as_str(), "Program log: inner"]);
+    assert_eq!((outer.range, inner.range), (Some((0, 5)), Some((2, 5))));
+    assert_eq!((outer.line_indices.as_slice(), inner.line_indices.as_slice()), (&[0, 1][..], &[2, 3][..]));
+}
+
+#[test]
+fn data_logs_of_an_invocation_are_all_kept_in_order() {
+    let other = "11111111111111111111111111111111";
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        "Program data: AQ==".to_string(),
+        format!("Program {} invoke [2]", other),
+        "Program data: Ag==".to_string(),
+        format!("Program {} success", other),
+        "Program data: Aw==".to_string(),
+        format!("Program return: {} BA==", PROGRAM),
+        format!("Program {} success", PROGRAM),
+    ];
+    let structured_logs = build_structured_logs(&log_messages).unwrap();
+    assert_eq!(structured_logs[0].data(), [vec![1], vec![3]]);
+    assert_eq!(structured_logs[0].inner[0].data(), [vec![2]]);
+    assert_eq!(structured_logs[0].return_data(), Some(vec![4]));
+}
+
+#[test]
+fn unexpected_invoke_depths_are_warnings() {
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        format!("Program {} invoke [3]", PROGRAM),
+        format!("Program {} success", PROGRAM),
+        format!("Program {} success", PROGRAM),
+    ];
+    let structured_logs = build_structured_logs_with_preamble(&log_messages).unwrap();
+    assert_eq!(structured_logs.warnings, [LogStructureWarning::UnexpectedInvokeDepth { position: 1, expected: 2, found: 3 }]);
+    let inner = &structured_logs.invocations[0].inner[0];
+    assert_eq!((inner.depth, inner.invoke_depth), (2, 3));
+}
+
+#[test]
+fn terminator_of_another_program_fails() {
+    let other = "11111111111111111111111111111111";
+    let log_messages = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        format!("Program {} invoke [2]", other),
+        format!("Program {} success", PROGRAM),
+    ];
+    assert_eq!(build_structured_logs(&log_messages).unwrap_err(), LogStructureError::UnexpectedTerminator {
+        position: 2,
+        expected: other.to_string(),
+        found: PROGRAM.to_string(),
+    });
+}
+
+#[test]
+fn classification() {
+    let kind = |line: &str| match log(line) {
+        Log::Invoke(_) => "invoke",
+        Log::Success(_) => "success",
+        Log::Return(_) => "return",
+        Log::Data(_) => "data",
+        Log::Program(_) => "program",
+        Log::Truncated(_) => "truncated",
+        Log::Consumed(_) => "consumed",
+        Log::Failed(_) => "failed",
+        Log::Runtime(_) => "runtime",
+        Log::Unknown(_) => "unknown",
+    };
+    assert_eq!(kind(&format!("Program {} invoke [1]", PROGRAM)), "invoke");
+    assert_eq!(kind(&format!("Program {} invoke [x]", PROGRAM)), "unknown");
+    assert_eq!(kind(&format!("Program {} success", PROGRAM)), "success");
+    assert_eq!(kind(&format!("Program {} consumed 10 of 20 compute units", PROGRAM)), "consumed");
+    assert_eq!(kind(&format!("Program {} failed: custom program error: 0x1", PROGRAM)), "failed");
+    assert_eq!(kind("Program log: x"), "program");
+    assert_eq!(kind("Program log:"), "unknown");
+    assert_eq!(kind("Program data: x"), "data");
+    assert_eq!(kind(&format!("Program return: {} AQID", PROGRAM)), "return");
+    assert_eq!(kind("Program return: x y"), "unknown");
+    assert_eq!(kind("consumed 10 of 20 compute units"), "consumed");
+    assert_eq!(kind("consumed 10 of many compute units"), "unknown");
+    assert_eq!(kind("Log truncated"), "truncated");
+    assert_eq!(kind("program log: x"), "unknown");
+}
+
+/// Program logs quoting other log kinds, and lines that only resemble them
+#[test]
+fn adversarial_program_logs_are_not_misclassified() {
+    let program_logs = [
+        "Program log: migration success".to_string(),
+        format!("Program log: Program {} success", PROGRAM),
+        format!("Program log: Program {} invoke [2]", PROGRAM),
+        format!("Program log: Program return: {} AQID", PROGRAM),
+        format!("Program log: Program {} failed: custom program error: 0x1", PROGRAM),
+        "Program log: Program log: nested".to_string(),
+    ];
+    for line in &program_logs {
+        assert!(log(line).is_program(), "{:?}", line);
+    }
+    let unknown_logs = [
+        "Program migration success".to_string(),
+        "Program migration invoke [1]".to_string(),
+        format!("Program {}X success", PROGRAM),
+        format!("Program {} success{}", PROGRAM, "!"),
+        format!("Program {} invoke [1] trailing", PROGRAM),
+        format!("Program {} {} success", PROGRAM, PROGRAM),
+        format!(" Program {} success", PROGRAM),
+        "Program return: migration AQID".to_string(),
+    ];
+    for line in &unknown_logs {
+        assert!(log(line).is_unknown(), "{:?}", line);
+    }
+
+    let logs: Vec<String> = [
+        format!("Program {} invoke [1]", PROGRAM),
+        "Program log: migration success".to_string(),
+        format!("Program log: Program {} invoke [2]", PROGRAM),
+        format!("Program {} success", PROGRAM),
+    ].into();
+    let structured_logs = build_structured_logs(&logs).unwrap();
+    assert_eq!(structured_logs.len(), 1);
+    assert!(structured_logs[0].inner_logs().next().is_none());
+    assert_eq!(structured_logs[0].program_logs(), vec!["migration success", &format!("Program {} invoke [2]", PROGRAM)]);
+}
+
+#[test]
+fn runtime_logs() {
+    let event = |line: &str| match log(line) {
+        Log::Runtime(runtime_log) => runtime_log.event().unwrap(),
+        other => panic!("{:?} is not a runtime log", other),
+    };
+    assert_eq!(event(&format!("Deployed program {}", PROGRAM)), RuntimeEvent::Deployed { program_id: PROGRAM });
+    assert_eq!(event(&format!("Upgraded program {}", PROGRAM)), RuntimeEvent::Upgraded { program_id: PROGRAM });
+    assert_eq!(event(&format!("Closed Program {}", PROGRAM)), RuntimeEvent::Closed { program_id: PROGRAM });
+    assert_eq!(event("Transfer: insufficient lamports 1000, need 2039280"), RuntimeEvent::InsufficientLamports { available: 1000, needed: 2039280 });
+    assert_eq!(
+        event(&format!("Allocate: account Address {{ address: {}, base: None }} already in use", PROGRAM)),
+        RuntimeEvent::SystemProgramError { instruction: "Allocate", message: &format!("account Address {{ address: {}, base: None }} already in use", PROGRAM) },
+    );
+    assert_eq!(event("Transfer: `from` must not carry data"), RuntimeEvent::SystemProgramError { instruction: "Transfer", message: "`from` must not carry data" });
+    assert_eq!(
+        event(&format!("Program {} failed to complete: exceeded CUs meter at BPF instruction #1234", PROGRAM)),
+        RuntimeEvent::FailedToComplete { program_id: Some(PROGRAM), error: "exceeded CUs meter at BPF instruction #1234" },
+    );
+    assert_eq!(event("Program failed to complete: Access violation in stack frame 5"), RuntimeEvent::FailedToComplete { program_id: None, error: "Access violation in stack frame 5" });
+    assert_eq!(event(&format!("{}'s writable privilege escalated", PROGRAM)), RuntimeEvent::PrivilegeEscalated { account: PROGRAM, privilege: "writable" });
+    assert_eq!(event(&format!("{}'s signer privilege escalated", PROGRAM)), RuntimeEvent::PrivilegeEscalated { account: PROGRAM, privilege: "signer" });
+    assert_eq!(event(&format!("Unknown program {}", PROGRAM)), RuntimeEvent::UnknownProgram { program_id: PROGRAM });
+    assert_eq!(event(&format!("Instruction references an unknown account {}", PROGRAM)), RuntimeEvent::UnknownAccount { account: PROGRAM });
+
+    for line in [
+        "Deployed program x".to_string(),
+        "Transfer: insufficient lamports many, need 1".to_string(),
+        "Transfer: ".to_string(),
+        "Swap: slippage exceeded".to_string(),
+        format!("{}'s owner privilege escalated", PROGRAM),
+        "Program x failed to complete: error".to_string(),
+    ] {
+        assert!(log(&line).is_unknown(), "{:?}", line);
+    }
+    // Failed logs whose error mentions another program are not runtime logs
+    assert!(log(&format!("Program {} failed: Program failed to complete", PROGRAM)).is_failed());
+}
+
+#[test]
+fn kinds_and_accessors_agree_with_predicates() {
+    let lines = FIXTURE.iter().chain(RUNTIME_FIXTURE).copied().chain(["Program data: AQID", "Program return: JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 AQID", "Swap: slippage exceeded"]);
+    for line in lines {
+        let log = log(line);
+        let predicates = [
+            (LogKind::Invoke, log.is_invoke(), log.as_invoke().is_some()),
+            (LogKind::Success, log.is_success(), log.as_success().is_some()),
+            (LogKind::Return, log.is_return(), log.as_return().is_some()),
+            (LogKind::Data, log.is_data(), log.as_data().is_some()),
+            (LogKind::Program, log.is_program(), log.as_program().is_some()),
+            (LogKind::Truncated, log.is_truncated(), log.as_truncated().is_some()),
+            (LogKind::Consumed, log.is_consumed(), log.as_consumed().is_some()),
+            (LogKind::Failed, log.is_failed(), log.as_failed().is_some()),
+            (LogKind::Runtime, log.is_runtime(), log.as_runtime().is_some()),
+            (LogKind::Unknown, log.is_unknown(), log.as_unknown().is_some()),
+        ];
+        for (kind, is, as_some) in predicates {
+            assert_eq!(log.kind() == kind, is, "{:?} as {:?}", line, kind);
+            assert_eq!(is, as_some, "{:?} as {:?}", line, kind);
+        }
+    }
+}
+
+#[test]
+fn program_ids_of_logs() {
+    let program_id = |line: &str| log(line).program_id();
+    assert_eq!(program_id(&format!("Program {} invoke [1]", PROGRAM)), Some(PROGRAM));
+    assert_eq!(program_id(&format!("Program {} success", PROGRAM)), Some(PROGRAM));
+    assert_eq!(program_id(&format!("Program return: {} AQID", PROGRAM)), Some(PROGRAM));
+    assert_eq!(program_id(&format!("Program {} failed: custom program error: 0x1", PROGRAM)), Some(PROGRAM));
+    assert_eq!(program_id(&format!("Program {} consumed 10 of 20 compute units", PROGRAM)), Some(PROGRAM));
+    assert_eq!(program_id(&format!("Program {} failed to complete: exceeded CUs meter", PROGRAM)), Some(PROGRAM));
+    assert_eq!(program_id("consumed 10 of 20 compute units"), None);
+    assert_eq!(program_id(&format!("Program log: Program {} success", PROGRAM)), None);
+    assert_eq!(program_id("Program data: AQID"), None);
+    assert_eq!(program_id("Log truncated"), None);
+}
+
+/// Payloads that fail to decode keep their kind, program id and raw payload
+#[test]
+fn undecodable_payloads_keep_their_raw_base64() {
+    use base64::Engine;
+    let url_safe = base64::engine::general_purpose::URL_SAFE;
+    let logs: Vec<String> = vec![
+        format!("Program {} invoke [1]", PROGRAM),
+        "Program data: -_8=".to_string(),
+        "Program data: AQIDBA".to_string(),
+        "Program data: AQID".to_string(),
+        format!("Program return: {} -_8=", PROGRAM),
+        format!("Program {} success", PROGRAM),
+    ];
+    let structured_logs = build_structured_logs(&logs).unwrap();
+    let frame = &structured_logs[0];
+    assert_eq!(frame.encoded_data(), vec!["-_8=", "AQIDBA", "AQID"]);
+    assert_eq!(frame.data(), vec![vec![1, 2, 3]]);
+    assert_eq!(url_safe.decode(frame.encoded_data()[0]), Ok(vec![0xfb, 0xff]));
+
+    let return_log = Log::new(&logs[4]);
+    let return_log = return_log.as_return().unwrap();
+    assert_eq!(return_log.program_id(), Ok(PROGRAM));
+    assert!(return_log.data().is_err());
+    assert_eq!(url_safe.decode(return_log.encoded_data().unwrap()), Ok(vec![0xfb, 0xff]));
+    assert_eq!(frame.return_data(), None);
+
+    // Truncated mid-character
+    assert!(log("Program data: AQIDB").as_data().unwrap().data().is_err());
+    assert!(log(&format!("Program return: {} AQIDB", PROGRAM)).as_return().unwrap().data().is_err());
+}
+
+/// Payloads decode to the same bytes as with the former `base64::decode`
+#[test]
+#[allow(deprecated)]
+fn payloads_decode_as_with_the_former_api() {
+    let mut payloads: Vec<&str> = FIXTURE.iter().filter_map(|line| {
+        line.strip_prefix("Program data: ").or_else(|| line.strip_prefix("Program return: ")?.split_once(' ').map(|x| x.1))
+    }).collect();
+    assert_eq!(payloads.len(), 2);
+    payloads.extend(["AQID", "BA==", "BAU=", "+/8=", "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="]);
+    for payload in payloads {
+        let expected = base64::decode(payload).unwrap();
+        let data_line = format!("Program data: {}", payload);
+        assert_eq!(log(&data_line).as_data().unwrap().bytes().map(<[u8]>::to_vec), Ok(expected.clone()), "{:?}", payload);
+        let return_line = format!("Program return: {} {}", PROGRAM, payload);
+        assert_eq!(log(&return_line).as_return().unwrap().decode(), Ok(expected), "{:?}", payload);
+    }
+}
+
+#[test]
+fn accepted_alphabet_and_padding() {
+    let data = |payload: &str| log(&format!("Program data: {}", payload)).as_data().unwrap().bytes().map(<[u8]>::to_vec);
+    let return_data = |payload: &str| log(&format!("Program return: {} {}", PROGRAM, payload)).as_return().unwrap().decode();
+    let base64_error = |source| Err(LogDecodeError::Base64 { line_index: None, source });
+
+    // Data logs are padded, as logged by the runtime; return logs may not be
+    assert_eq!(data("BAU"), base64_error(base64::DecodeError::InvalidPadding));
+    assert_eq!(return_data("BAU"), Ok(vec![4, 5]));
+    for decode in [&data as &dyn Fn(&str) -> _, &return_data] {
+        assert_eq!(decode("BAU="), Ok(vec![4, 5]));
+        // Standard alphabet only
+        assert_eq!(decode("-_8="), base64_error(base64::DecodeError::InvalidByte(0, b'-')));
+        // Non-zero trailing bits
+        assert_eq!(decode("AQJ="), base64_error(base64::DecodeError::InvalidLastSymbol(2, b'J')));
+        assert_eq!(decode("AQI D"), base64_error(base64::DecodeError::InvalidByte(3, b' ')));
+    }
+    assert_eq!(log("Program data: ").as_data().unwrap().bytes(), Err(LogDecodeError::Malformed { line_index: None }));
+}
+
+/// Log lines shaped after mainnet transactions: a Jupiter route through
+/// Raydium and Whirlpool, a failed Whirlpool swap and a Metaplex mint.
+const FIXTURE: &[&str] = &[
+    "Program ComputeBudget111111111111111111111111111111 invoke [1]",
+    "Program ComputeBudget111111111111111111111111111111 success",
+    "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 invoke [1]",
+    "Program log: Instruction: Route",
+    "Program 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8 invoke [2]",
+    "Program log: ray_log: A0BCDwAAAAAAAAAAAAAAAAACAAAAAAAAAEBCDwAAAAAA",
+    "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA invoke [3]",
+    "Program log: Instruction: Transfer",
+    "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA consumed 4645 of 180123 compute units",
+    "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA success",
+    "Program 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8 consumed 31040 of 201737 compute units",
+    "Program 675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8 success",
+    "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [2]",
+    "Program log: Instruction: Swap",
+    "Program data: 4cGHOd0Dd0MAAAAAAAAAAA==",
+    "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc success",
+    "Program return: JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 6F4AAAAAAAA=",
+    "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 consumed 98211 of 199850 compute units",
+    "Program JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4 success",
+    "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [1]",
+    "Program log: AnchorError occurred. Error Code: AmountOutBelowMinimum. Error Number: 6036. Error Message: Amount out below minimum threshold.",
+    "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc failed: custom program error: 0x1794",
+    "Program metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s invoke [1]",
+    "Program log: IX: Create Metadata Accounts v3",
+    "Program 11111111111111111111111111111111 invoke [2]",
+    "Program 11111111111111111111111111111111 success",
+    "Program metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s success",
+    "consumed 142350 of 1400000 compute units",
+    "Log truncated",
+];
+
+/// Runtime notices and diagnostics shaped after mainnet transactions
+const RUNTIME_FIXTURE: &[&str] = &[
+    "Program BPFLoaderUpgradeab1e11111111111111111111111 invoke [1]",
+    "Upgraded program CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK",
+    "Program BPFLoaderUpgradeab1e11111111111111111111111 success",
+    "Program BPFLoaderUpgradeab1e11111111111111111111111 invoke [1]",
+    "Deployed program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P",
+    "Program BPFLoaderUpgradeab1e11111111111111111111111 success",
+    "Program BPFLoaderUpgradeab1e11111111111111111111111 invoke [1]",
+    "Closed Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P",
+    "Program BPFLoaderUpgradeab1e11111111111111111111111 success",
+    "Program 11111111111111111111111111111111 invoke [1]",
+    "Transfer: insufficient lamports 890880, need 2039280",
+    "Program 11111111111111111111111111111111 failed: custom program error: 0x1",
+    "Program 11111111111111111111111111111111 invoke [2]",
+    "Allocate: account Address { address: 9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM, base: None } already in use",
+    "Program 11111111111111111111111111111111 failed: custom program error: 0x0",
+    "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P invoke [1]",
+    "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P consumed 200000 of 200000 compute units",
+    "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P failed to complete: exceeded CUs meter at BPF instruction #18442",
+    "Program 6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P failed: Program failed to complete",
+    "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc invoke [1]",
+    "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM's writable privilege escalated",
+    "Program whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc failed: Cross-program invocation with unauthorized signer or writable account",
+    "Unknown program 9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM",
+    "Instruction references an unknown account 9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM",
+];
+
+#[test]
+fn known_lines_are_never_unknown() {
+    let unknown: Vec<_> = FIXTURE.iter().chain(RUNTIME_FIXTURE).filter(|line| log(line).is_unknown()).collect();
+    assert_eq!(unknown, Vec::<&&str>::new());
+    assert_eq!(RUNTIME_FIXTURE.iter().filter(|line| log(line).is_runtime()).count(), 9);
+}
+
+/// The same results from the `Vec<String>` of a transaction meta and from
+/// string slices
+#[test]
+fn log_messages_as_strings_or_slices() {
+    let owned: Vec<String> = FIXTURE.iter().map(|x| x.to_string()).collect();
+    let slices: Vec<&str> = FIXTURE.to_vec();
+
+    let shape = |structured_logs: Vec<substreams_solana_utils::log::ProgramStructuredLogs>| structured_logs.iter()
+        .map(|x| (x.program_id().map(str::to_string), x.logs.len(), x.inner_logs().count()))
+        .collect::<Vec<_>>();
+    assert_eq!(shape(build_structured_logs(&owned).unwrap()), shape(build_structured_logs(&slices).unwrap()));
+    assert_eq!(shape(build_structured_logs(FIXTURE).unwrap()).len(), 4);
+    assert_eq!(extract_error(&owned), extract_error(&slices));
+    assert!(extract_error(FIXTURE).is_some());
+
+    let untruncated = &slices[..slices.len() - 1];
+    let owned_untruncated = &owned[..owned.len() - 1];
+    assert_eq!(compute_units_by_program(untruncated), compute_units_by_program(owned_untruncated));
+    assert!(compute_units_by_program(untruncated).is_some());
+}
+
+#[test]
+fn display_round_trips_fixture_lines() {
+    for line in FIXTURE.iter().chain(RUNTIME_FIXTURE) {
+        assert_eq!(log(line).to_string(), *line);
+    }
+}
+
+/// Rebuilding the canonical line from the parsed fields, with the data
+/// re-encoded in base64, gives back the original line.
+#[test]
+fn parsed_fields_rebuild_fixture_lines() {
+    use base64::Engine;
+    let encode = |data: Vec<u8>| base64::engine::general_purpose::STANDARD.encode(data);
+    for line in FIXTURE {
+        let rebuilt = match log(line) {
+            Log::Invoke(x) => format!("Program {} invoke [{}]", x.program_id().unwrap(), x.invoke_depth().unwrap()),
+            Log::Success(x) => format!("Program {} success", x.program_id().unwrap()),
+            Log::Return(x) => format!("Program return: {} {}", x.program_id().unwrap(), encode(x.data().unwrap())),
+            Log::Data(x) => format!("Program data: {}", encode(x.data().unwrap())),
+            Log::Program(x) => format!("Program log: {}", x.message().unwrap()),
+            other => other.raw().to_string(),
+        };
+        assert_eq!(rebuilt, *line);
+    }
+}
@@ -0,0 +1,148 @@
+//! Spans of the invocations in the log messages, which with the lines left
+//! out of every invocation reproduce the log messages exactly, see
+//! `StructuredInstruction::raw_log_range` and `ProgramStructuredLogs::range`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::instruction::{get_structured_instructions_with_orphan_logs, StructureOptions, PROGRAMS_WITHOUT_LOGGING};
+use substreams_solana_utils::instruction_tree::get_instruction_tree;
+use substreams_solana_utils::log::{build_structured_logs_with_preamble, Log};
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+/// Log messages rebuilt from the spans of the top level invocations, filling
+/// the lines between them with `orphans` in order and with the "Log truncated"
+/// line. Spans must be ordered and disjoint, and every orphan used.
+fn reconstruct(log_messages: &[String], ranges: &[(usize, usize)], orphans: &[Log], truncated_at: Option<usize>) -> Vec<String> {
+    assert!(ranges.windows(2).all(|x| x[0].1 <= x[1].0), "spans {:?} overlap", ranges);
+    let (mut lines, mut ranges, mut orphans) = (Vec::new(), ranges.iter().peekable(), orphans.iter());
+    let mut i = 0;
+    while i < log_messages.len() {
+        if let Some((start, end)) = ranges.next_if(|(start, _)| *start == i) {
+            lines.extend_from_slice(&log_messages[*start..*end]);
+            i = *end;
+            continue;
+        }
+        match truncated_at {
+            Some(truncated_at) if truncated_at == i => lines.push(log_messages[i].clone()),
+            _ => lines.push(orphans.next().expect("a line is in no span and no orphan").raw().to_string()),
+        }
+        i += 1;
+    }
+    assert!(ranges.next().is_none() && orphans.next().is_none());
+    lines
+}
+
+/// Checks the spans of both instruction structurings and of the logs alone
+fn assert_reproduced(transaction: &ConfirmedTransaction, truncated_at: Option<usize>) {
+    let log_messages = &transaction.meta.as_ref().unwrap().log_messages;
+    let output = get_structured_instructions_with_orphan_logs(transaction, &StructureOptions::default()).unwrap();
+    let ranges: Vec<_> = output.instructions.iter().filter_map(|x| x.raw_log_range()).collect();
+    assert_eq!(&reconstruct(log_messages, &ranges, &output.orphan_logs, truncated_at), log_messages);
+    for instruction in output.instructions.iter().flat_map(|x| x.iter_dfs()) {
+        let (Some((start, end)), Some(indices)) = (instruction.raw_log_range(), instruction.log_line_indices().clone()) else { continue };
+        assert!(indices.iter().all(|x| (start..end).contains(x)));
+    }
+
+    let tree = get_instruction_tree(transaction).unwrap();
+    let tree_ranges: Vec<_> = tree.roots().iter().filter_map(|x| tree.get(*x).unwrap().raw_log_range()).collect();
+    assert_eq!(tree_ranges, ranges);
+
+    let structured_logs = build_structured_logs_with_preamble(log_messages).unwrap();
+    let ranges: Vec<_> = structured_logs.invocations.iter().map(|x| x.range.unwrap()).collect();
+    let orphans: Vec<_> = structured_logs.preamble.iter().chain(&structured_logs.orphan_logs).cloned().collect();
+    assert_eq!(&reconstruct(log_messages, &ranges, &orphans, truncated_at), log_messages);
+}
+
+fn invoke(program: Pubkey, depth: u32) -> String {
+    format!("Program {} invoke [{}]", program.to_string(), depth)
+}
+
+fn success(program: Pubkey) -> String {
+    format!("Program {} success", program.to_string())
+}
+
+#[test]
+fn unknown_and_invalid_utf8_lines() {
+    let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    let invalid_utf8 = String::from_utf8_lossy(b"Program log: \xF0\x90\x80 swap").into_owned();
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user, pool], vec![0])
+        .inner(pool, &[user], vec![1])
+        .instruction(pool, &[user], vec![2])
+        .logs(&[
+            "Runtime preamble",
+            &invoke(router, 1),
+            "not a log line the parser knows",
+            &invoke(pool, 2),
+            &invalid_utf8,
+            &success(pool),
+            &success(router),
+            "between the instructions",
+            &invoke(pool, 1),
+            "",
+            &success(pool),
+            "after the instructions",
+        ])
+        .build();
+    assert_reproduced(&transaction, None);
+}
+
+#[test]
+fn program_without_logging() {
+    let (user, program, precompile) = (pubkey(1), pubkey(2), PROGRAMS_WITHOUT_LOGGING[0]);
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(precompile, &[], vec![0])
+        .instruction(program, &[user], vec![1])
+        .logs(&[&invoke(program, 1), "Program log: logged", &success(program)])
+        .build();
+    let output = get_structured_instructions_with_orphan_logs(&transaction, &StructureOptions::default()).unwrap();
+    assert_eq!(output.instructions[0].raw_log_range(), None);
+    assert_eq!(output.instructions[1].raw_log_range(), Some((0, 3)));
+    assert_reproduced(&transaction, None);
+}
+
+#[test]
+fn truncation_inside_an_invocation() {
+    let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user, pool], vec![0])
+        .inner(pool, &[user], vec![1])
+        .instruction(pool, &[user], vec![2])
+        .inner(router, &[user], vec![3])
+        .logs(&[
+            &invoke(router, 1),
+            &invoke(pool, 2),
+            &success(pool),
+            &success(router),
+            "between the instructions",
+            &invoke(pool, 1),
+            "Program log: cut",
+            "Log truncated",
+        ])
+        .build();
+    let output = get_structured_instructions_with_orphan_logs(&transaction, &StructureOptions::default()).unwrap();
+    let truncated = &output.instructions[1];
+    assert!(truncated.logs_truncated() && truncated.logs().is_none());
+    assert_eq!(truncated.raw_log_range(), Some((5, 8)));
+    assert_eq!(truncated.inner_instructions()[0].raw_log_range(), None);
+    assert_reproduced(&transaction, Some(7));
+}
+
+#[test]
+fn truncation_between_instructions() {
+    let (user, program) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(program, &[user], vec![0])
+        .instruction(program, &[user], vec![1])
+        .logs(&[&invoke(program, 1), &success(program), "Log truncated"])
+        .build();
+    let output = get_structured_instructions_with_orphan_logs(&transaction, &StructureOptions::default()).unwrap();
+    assert_eq!(output.instructions[1].raw_log_range(), None);
+    assert_reproduced(&transaction, Some(2));
+}
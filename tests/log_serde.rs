@@ -0,0 +1,90 @@
+//! Serialized shape of the log types, see `Log`.
+
+use serde_json::{json, Value};
+use substreams_solana_utils::log::{build_structured_logs_with_preamble, Log};
+
+const ROUTER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+const POOL: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+
+/// The canonical line of a serialized log
+fn line(log: &Value) -> String {
+    let field = |name: &str| log[name].as_str().unwrap().to_string();
+    match log["type"].as_str().unwrap() {
+        "invoke" => format!("Program {} invoke [{}]", field("program_id"), log["depth"]),
+        "success" => format!("Program {} success", field("program_id")),
+        "return" => format!("Program return: {} {}", field("program_id"), field("data")),
+        "data" => format!("Program data: {}", field("data")),
+        "program" => format!("Program log: {}", field("message")),
+        "truncated" => "Log truncated".to_string(),
+        "consumed" => match log["program_id"].as_str() {
+            Some(program_id) => format!("Program {} consumed {} of {} compute units", program_id, log["consumed"], log["budget"]),
+            None => format!("consumed {} of {} compute units", log["consumed"], log["budget"]),
+        },
+        "failed" => format!("Program {} failed: {}", field("program_id"), field("error")),
+        "unknown" => field("log"),
+        other => panic!("unexpected log type {}", other),
+    }
+}
+
+/// Lines of an invocation and of its inner invocations, with their index
+fn invocation_lines(invocation: &Value, lines: &mut Vec<(u64, String)>) {
+    let logs = invocation["logs"].as_array().unwrap();
+    let indices = invocation["line_indices"].as_array().unwrap();
+    for (log, index) in logs.iter().zip(indices) {
+        lines.push((index.as_u64().unwrap(), line(log)));
+    }
+    for inner in invocation["inner"].as_array().unwrap() {
+        invocation_lines(inner, lines);
+    }
+}
+
+#[test]
+fn structured_logs_serialize_back_to_the_log_messages() {
+    let log_messages: Vec<String> = [
+        "Runtime message".to_string(),
+        format!("Program {} invoke [1]", ROUTER),
+        "Program log: Instruction: Route".to_string(),
+        format!("Program {} invoke [2]", POOL),
+        "Program data: AQID".to_string(),
+        format!("Program return: {} BAU=", POOL),
+        format!("Program {} consumed 30000 of 190000 compute units", POOL),
+        format!("Program {} success", POOL),
+        format!("Program {} consumed 42000 of 200000 compute units", ROUTER),
+        format!("Program {} success", ROUTER),
+        format!("Program {} invoke [1]", POOL),
+        format!("Program {} failed: custom program error: 0x1771", POOL),
+        "consumed 42150 of 400000 compute units".to_string(),
+    ].into();
+    let structured_logs = build_structured_logs_with_preamble(&log_messages).unwrap();
+    let json: Value = serde_json::from_str(&serde_json::to_string(&structured_logs).unwrap()).unwrap();
+
+    let mut lines = Vec::new();
+    for invocation in json["invocations"].as_array().unwrap() {
+        invocation_lines(invocation, &mut lines);
+    }
+    lines.sort();
+    let preamble = json["preamble"].as_array().unwrap().iter().map(line);
+    let orphan_logs = json["orphan_logs"].as_array().unwrap().iter().map(line);
+    let rebuilt: Vec<String> = preamble.chain(lines.into_iter().map(|(_, line)| line)).chain(orphan_logs).collect();
+    assert_eq!(rebuilt, log_messages);
+
+    let swap = &json["invocations"][0]["inner"][0];
+    assert_eq!((&swap["depth"], &swap["range"], &swap["truncated"]), (&json!(2), &json!([3, 8]), &json!(false)));
+}
+
+#[test]
+fn unparsable_lines_serialize_as_unknown() {
+    for line in ["Program data: not base64!", "Program log: ", "Program return: AQID"] {
+        let line = line.to_string();
+        assert_eq!(serde_json::to_value(Log::new(&line)).unwrap(), json!({"type": "unknown", "log": line}));
+    }
+}
+
+#[test]
+fn runtime_logs_serialize_their_event() {
+    let line = "Transfer: insufficient lamports 890880, need 2039280".to_string();
+    assert_eq!(
+        serde_json::to_value(Log::new(&line)).unwrap(),
+        json!({"type": "runtime", "event": {"kind": "insufficient_lamports", "available": 890880, "needed": 2039280}}),
+    );
+}
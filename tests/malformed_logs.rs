@@ -0,0 +1,114 @@
+//! Log sequences that do not match the instructions of their transaction. They
+//! fail with a `ParseError`, or abandon log association in lenient mode, and
+//! never panic.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::error::ParseError;
+use substreams_solana_utils::instruction::{get_structured_instructions, get_structured_instructions_lenient, get_structured_instructions_with_orphan_logs, LogAssociation, StructureOptions, StructuredInstructions};
+use substreams_solana_utils::log::Log;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+const VOTE: &str = "Vote111111111111111111111111111111111111111";
+
+/// A router instruction invoking a pool, with `logs` instead of the logs it would produce
+fn route(logs: &[&str]) -> ConfirmedTransaction {
+    let (user, router, pool) = (pubkey(1), pubkey(2), pubkey(3));
+    TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user, pool], vec![0])
+        .inner(pool, &[user], vec![1])
+        .instruction(pool, &[user], vec![2])
+        .logs(logs)
+        .build()
+}
+
+fn lenient() -> StructureOptions {
+    StructureOptions { association: LogAssociation::Lenient, ..Default::default() }
+}
+
+#[test]
+fn unexpected_invoke() {
+    let (router, pool, other) = (pubkey(2).to_string(), pubkey(3).to_string(), pubkey(4).to_string());
+    let transaction = route(&[
+        &format!("Program {router} invoke [1]"),
+        &format!("Program {pool} invoke [2]"),
+        &format!("Program {other} invoke [3]"),
+        &format!("Program {other} success"),
+        &format!("Program {pool} success"),
+        &format!("Program {router} success"),
+        &format!("Program {pool} invoke [1]"),
+        &format!("Program {pool} success"),
+    ]);
+    assert_eq!(get_structured_instructions(&transaction).unwrap_err(), ParseError::UnexpectedInvoke { position: 2 });
+
+    let output = get_structured_instructions_with_orphan_logs(&transaction, &lenient()).unwrap();
+    assert_eq!(output.recovered_error, Some(ParseError::UnexpectedInvoke { position: 2 }));
+    assert!(output.instructions.flattened().iter().all(|x| x.logs().is_none()));
+}
+
+#[test]
+fn success_of_another_program() {
+    let (router, pool) = (pubkey(2).to_string(), pubkey(3).to_string());
+    let transaction = route(&[
+        &format!("Program {router} invoke [1]"),
+        &format!("Program {pool} invoke [2]"),
+        &format!("Program {router} success"),
+        &format!("Program {router} success"),
+        &format!("Program {pool} invoke [1]"),
+        &format!("Program {pool} success"),
+    ]);
+    let error = ParseError::UnexpectedProgram { position: 2, expected: pool.clone(), found: router.clone() };
+    assert_eq!(get_structured_instructions(&transaction).unwrap_err(), error);
+
+    let instructions = get_structured_instructions_lenient(&transaction).unwrap();
+    assert_eq!(instructions.flattened().len(), 3);
+    assert!(instructions.flattened().iter().all(|x| x.logs().is_none()));
+}
+
+#[test]
+fn invoke_inside_a_program_log() {
+    let (router, pool) = (pubkey(2).to_string(), pubkey(3).to_string());
+    let fake_invoke = format!("Program log: Program {pool} invoke [2]");
+    let transaction = route(&[
+        &format!("Program {router} invoke [1]"),
+        &fake_invoke,
+        &format!("Program {pool} invoke [2]"),
+        &format!("Program log: Program {router} success"),
+        &format!("Program {pool} success"),
+        &format!("Program {router} success"),
+        &format!("Program {pool} invoke [1]"),
+        &format!("Program {pool} success"),
+    ]);
+    let instructions = get_structured_instructions(&transaction).unwrap();
+    let logs = instructions[0].logs();
+    let logs = logs.as_ref().unwrap();
+    assert!(matches!(&logs[1], Log::Program(_)));
+    assert_eq!(logs[1].raw(), fake_invoke);
+    let swap = &instructions[0].inner_instructions()[0];
+    assert_eq!(swap.logs().as_ref().unwrap().len(), 3);
+    assert_eq!(instructions[1].logs().as_ref().unwrap().len(), 2);
+}
+
+#[test]
+fn vote_transaction_without_logs() {
+    let (validator, vote_account) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new()
+        .signer(validator)
+        .instruction(Pubkey::try_from_string(VOTE).unwrap(), &[vote_account, validator], vec![2, 0, 0, 0])
+        .logs(&[])
+        .build();
+    let instructions = get_structured_instructions(&transaction).unwrap();
+    assert_eq!(instructions.len(), 1);
+    assert!(instructions[0].logs().is_none());
+}
+
+#[test]
+fn logs_ending_before_the_instructions() {
+    let router = pubkey(2).to_string();
+    let transaction = route(&[&format!("Program {router} invoke [1]")]);
+    let instructions = get_structured_instructions(&transaction).unwrap();
+    assert!(instructions.flattened().iter().all(|x| x.logs().is_none()));
+}
@@ -0,0 +1,86 @@
+//! Decoding the `Write` instructions of a program deploy borrows their payloads
+//! from the transaction: no payload byte is allocated while decoding or hashing
+//! them, unless `PayloadOptions::copy_payloads` is set.
+//!
+//! A counting global allocator measures the allocations, so this file holds a
+//! single test: tests of the same binary run on parallel threads.
+
+#![cfg(feature = "test-utils")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::bpf_loader::{UpgradeableLoaderInstruction, BPF_LOADER_UPGRADEABLE_PROGRAM_ID};
+use substreams_solana_utils::instruction::get_structured_instructions;
+use substreams_solana_utils::payload::PayloadOptions;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const WRITES: u32 = 300;
+const CHUNK_LEN: usize = 1012;
+
+/// Writes of a program to its buffer account, one chunk per instruction
+fn deploy() -> ConfirmedTransaction {
+    let (authority, buffer) = (pubkey(1), pubkey(2));
+    (0..WRITES).fold(TransactionBuilder::new().signer(authority), |builder, i| {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend((i * CHUNK_LEN as u32).to_le_bytes());
+        data.extend((CHUNK_LEN as u64).to_le_bytes());
+        data.extend(vec![i as u8; CHUNK_LEN]);
+        builder.instruction(BPF_LOADER_UPGRADEABLE_PROGRAM_ID, &[buffer, authority], data)
+    }).build()
+}
+
+/// Bytes allocated by `f`
+fn allocated<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    let result = f();
+    (result, ALLOCATED.load(Ordering::SeqCst) - before)
+}
+
+#[test]
+fn write_payloads_are_copied_only_on_request() {
+    let transaction = deploy();
+    let instructions = get_structured_instructions(&transaction).unwrap();
+    let payloads_len = WRITES as usize * CHUNK_LEN;
+
+    let decode = |options: &PayloadOptions| instructions.iter().map(|instruction| {
+        match UpgradeableLoaderInstruction::unpack_with_options(instruction.data(), options).unwrap() {
+            UpgradeableLoaderInstruction::Write { bytes, .. } => (bytes.len(), bytes.is_borrowed()),
+            other => panic!("{:?} is not a write", other),
+        }
+    }).collect::<Vec<_>>();
+
+    let (writes, borrowed_allocations) = allocated(|| decode(&PayloadOptions::default()));
+    assert_eq!(writes.len(), WRITES as usize);
+    assert!(writes.iter().all(|&(len, borrowed)| len == CHUNK_LEN && borrowed));
+    // Only the vector of results is allocated
+    assert_eq!(borrowed_allocations, writes.capacity() * size_of::<(usize, bool)>());
+
+    let (hashes, hash_allocations) = allocated(|| instructions.iter().map(|x| x.large_payload().unwrap().hash()).collect::<Vec<_>>());
+    assert_eq!(hashes.len(), WRITES as usize);
+    assert_eq!(hash_allocations, hashes.capacity() * size_of::<[u8; 32]>());
+
+    let (writes, copied_allocations) = allocated(|| decode(&PayloadOptions { copy_payloads: true }));
+    assert!(writes.iter().all(|&(_, borrowed)| !borrowed));
+    assert!(copied_allocations >= payloads_len, "{} bytes allocated copying {} payload bytes", copied_allocations, payloads_len);
+}
@@ -0,0 +1,160 @@
+//! Balance sheets of transactions and their conservation check, see the `report`
+//! module.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::error::ParseError;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::rent::{epoch_rent, RuntimeEra, TOKEN_ACCOUNT_LEN};
+use substreams_solana_utils::report::{balance_sheet, check_conservation, check_conservation_in_era, Asset, PartyRole, ViolationKind};
+use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::TransactionContext;
+
+#[test]
+fn transfers_are_conserved() {
+    let (user, user_token, recipient, recipient_token, mint) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(TOKEN_PROGRAM_ID, &[user_token, recipient_token, user], [vec![3], 40u64.to_le_bytes().to_vec()].concat())
+        .lamports(user, 1_000_000, 894_000)
+        .lamports(recipient, 0, 100_000)
+        .token_account(user_token, mint, user, Some(100), Some(60))
+        .token_account(recipient_token, mint, recipient, Some(0), Some(40))
+        .fee(6_000)
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+
+    let parties = balance_sheet(&context, &transaction).unwrap();
+    assert_eq!(parties.iter().map(|x| (x.owner, x.role, x.fees_paid)).collect::<Vec<_>>(), [
+        (user, PartyRole::FeePayer, 6_000),
+        (recipient, PartyRole::Counterparty, 0),
+    ]);
+    assert_eq!(parties[0].positions, [(Asset::Sol, -100_000), (Asset::Mint(mint), -40)]);
+    assert_eq!(parties[1].positions, [(Asset::Sol, 100_000), (Asset::Mint(mint), 40)]);
+    assert_eq!(check_conservation(&transaction, &parties).unwrap(), []);
+}
+
+#[test]
+fn mints_explain_supply_changes() {
+    let (user, user_token, mint) = (pubkey(1), pubkey(2), pubkey(3));
+    let mint_to = |amount: u64| TransactionBuilder::new()
+        .signer(user)
+        .instruction(TOKEN_PROGRAM_ID, &[mint, user_token, user], [vec![7], amount.to_le_bytes().to_vec()].concat())
+        .token_account(user_token, mint, user, Some(0), Some(500))
+        .build();
+
+    let transaction = mint_to(500);
+    let parties = balance_sheet(&TransactionContext::build(&transaction).unwrap(), &transaction).unwrap();
+    assert_eq!(parties[0].positions, [(Asset::Mint(mint), 500)]);
+    assert_eq!(check_conservation(&transaction, &parties).unwrap(), []);
+
+    let transaction = mint_to(300);
+    let parties = balance_sheet(&TransactionContext::build(&transaction).unwrap(), &transaction).unwrap();
+    let violations = check_conservation(&transaction, &parties).unwrap();
+    assert_eq!(violations.iter().map(|x| (x.asset, x.expected, x.actual, x.kind)).collect::<Vec<_>>(), [
+        (Asset::Mint(mint), 300, 500, ViolationKind::Unexplained),
+    ]);
+    assert!(violations[0].is_violation());
+}
+
+#[test]
+fn malformed_token_balances_are_errors() {
+    let (user, user_token, mint) = (pubkey(1), pubkey(2), pubkey(3));
+    let mut transaction = TransactionBuilder::new()
+        .signer(user)
+        .token_account(user_token, mint, user, Some(10), Some(10))
+        .build();
+    let built = transaction.clone();
+    let context = TransactionContext::build(&built).unwrap();
+    let account_index = transaction.meta.as_ref().unwrap().post_token_balances[0].account_index;
+    let error = ParseError::MalformedTokenBalance { account_index };
+
+    let meta = transaction.meta.as_mut().unwrap();
+    meta.post_token_balances[0].ui_token_amount.as_mut().unwrap().amount = "-1".to_string();
+    assert_eq!(balance_sheet(&context, &transaction).unwrap_err(), error);
+
+    transaction.meta.as_mut().unwrap().post_token_balances[0].ui_token_amount = None;
+    assert_eq!(balance_sheet(&context, &transaction).unwrap_err(), error);
+}
+
+#[test]
+fn transactions_without_meta_are_errors() {
+    let user = pubkey(1);
+    let transaction = TransactionBuilder::new().signer(user).build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    let mut without_meta = transaction.clone();
+    without_meta.meta = None;
+
+    assert_eq!(balance_sheet(&context, &without_meta).unwrap_err(), ParseError::MissingMeta);
+    let parties = balance_sheet(&context, &transaction).unwrap();
+    assert_eq!(check_conservation(&without_meta, &parties).unwrap_err(), ParseError::MissingMeta);
+}
+
+/// A token transfer of an old block, between two token accounts created before
+/// rent exemption was enforced, which hold 1,000,000 lamports and lose
+/// `rent_collected` lamports each to rent collection
+fn old_block_transfer(rent_collected: u64) -> ConfirmedTransaction {
+    let (user, user_token, recipient, recipient_token, mint) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    TransactionBuilder::new()
+        .signer(user)
+        .instruction(TOKEN_PROGRAM_ID, &[user_token, recipient_token, user], [vec![3], 40u64.to_le_bytes().to_vec()].concat())
+        .lamports(user, 1_000_000_000, 999_995_000)
+        .lamports(user_token, 1_000_000, 1_000_000 - rent_collected)
+        .lamports(recipient_token, 1_000_000, 1_000_000 - rent_collected)
+        .token_account(user_token, mint, user, Some(100), Some(60))
+        .token_account(recipient_token, mint, recipient, Some(0), Some(40))
+        .fee(5_000)
+        .build()
+}
+
+fn sol_violations(transaction: &ConfirmedTransaction, era: RuntimeEra) -> Vec<(i128, ViolationKind)> {
+    let parties = balance_sheet(&TransactionContext::build(transaction).unwrap(), transaction).unwrap();
+    check_conservation_in_era(transaction, &parties, era).unwrap().into_iter()
+        .filter(|x| x.asset == Asset::Sol)
+        .map(|x| (x.actual, x.kind))
+        .collect()
+}
+
+#[test]
+fn rent_collected_in_old_blocks() {
+    let rent = epoch_rent(TOKEN_ACCOUNT_LEN);
+    assert_eq!(rent, 5_583);
+    let transaction = old_block_transfer(rent);
+    let shortfall = -2 * rent as i128;
+
+    let violations = sol_violations(&transaction, RuntimeEra::RentCollecting);
+    assert_eq!(violations, [(shortfall, ViolationKind::RentCollected)]);
+    let parties = balance_sheet(&TransactionContext::build(&transaction).unwrap(), &transaction).unwrap();
+    assert!(check_conservation_in_era(&transaction, &parties, RuntimeEra::RentCollecting).unwrap().iter().all(|x| !x.is_violation()));
+
+    // The same debits are violations once every account is rent exempt
+    assert_eq!(sol_violations(&transaction, RuntimeEra::RentExempt), [(shortfall, ViolationKind::Unexplained)]);
+    assert_eq!(check_conservation(&transaction, &parties).unwrap()[0].kind, ViolationKind::Unexplained);
+}
+
+#[test]
+fn shortfalls_beyond_epoch_rent_in_old_blocks() {
+    // More than one epoch of rent from each token account
+    let rent = epoch_rent(TOKEN_ACCOUNT_LEN);
+    let transaction = old_block_transfer(rent + 1);
+    assert_eq!(sol_violations(&transaction, RuntimeEra::RentCollecting), [(-2 * (rent as i128 + 1), ViolationKind::Unexplained)]);
+
+    // 20 wallets losing 0.2 SOL each, far beyond the rent of accounts without data
+    let wallets: Vec<Pubkey> = (1..=20).map(pubkey).collect();
+    let transaction = wallets.iter().fold(TransactionBuilder::new().signer(wallets[0]), |builder, wallet| {
+        builder.lamports(*wallet, 1_000_000_000, 800_000_000)
+    }).build();
+    assert_eq!(sol_violations(&transaction, RuntimeEra::RentCollecting), [(-4_000_000_000, ViolationKind::Unexplained)]);
+
+    // One epoch of rent from each wallet, but a shortfall larger than what they lost
+    let wallet_rent = epoch_rent(0);
+    let transaction = wallets.iter().fold(TransactionBuilder::new().signer(wallets[0]), |builder, wallet| {
+        builder.lamports(*wallet, 1_000_000, 1_000_000 - wallet_rent)
+    }).build();
+    assert_eq!(sol_violations(&transaction, RuntimeEra::RentCollecting), [(-20 * wallet_rent as i128, ViolationKind::RentCollected)]);
+    let mut transaction = transaction;
+    transaction.meta.as_mut().unwrap().post_balances[0] -= 1;
+    assert_eq!(sol_violations(&transaction, RuntimeEra::RentCollecting), [(-20 * wallet_rent as i128 - 1, ViolationKind::Unexplained)]);
+}
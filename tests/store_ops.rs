@@ -0,0 +1,98 @@
+//! Store operations keeping token account balances and owners up to date, see
+//! `store_ops::from_context`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::store_ops::{account_key, balance_key, from_context, from_context_with_options, owner_key, StoreOp, StoreOpOptions};
+use substreams_solana_utils::spl_token::{TOKEN_PROGRAM_ID, WRAPPED_SOL_MINT};
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::TransactionContext;
+
+fn ops(transaction: &ConfirmedTransaction) -> Vec<StoreOp> {
+    from_context(&TransactionContext::build(transaction).unwrap(), transaction)
+}
+
+#[test]
+fn changed_accounts_only_in_address_order() {
+    let (user, mint, created, closed, changed, unchanged) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5), pubkey(6));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .token_account(unchanged, mint, user, Some(10), Some(10))
+        .token_account(changed, mint, user, Some(10), Some(3))
+        .token_account(closed, mint, user, Some(0), None)
+        .token_account(created, mint, user, None, Some(7))
+        .build();
+    assert_eq!(ops(&transaction), [
+        StoreOp::SetOwner { key: owner_key(&created), value: user },
+        StoreOp::SetBalance { key: balance_key(&created), value: 7 },
+        StoreOp::DeleteAccount { key: account_key(&closed) },
+        StoreOp::SetBalance { key: balance_key(&changed), value: 3 },
+    ]);
+}
+
+#[test]
+fn accounts_closed_and_recreated() {
+    let (user, other, mint, account, same_state) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let mut transaction = TransactionBuilder::new()
+        .signer(user)
+        .token_account(account, mint, user, Some(10), Some(0))
+        .token_account(same_state, mint, user, Some(4), Some(4))
+        .build();
+    // Re-created for another owner, with what was left of the balance sent back
+    transaction.meta.as_mut().unwrap().post_token_balances[0].owner = other.to_string();
+    assert_eq!(ops(&transaction), [
+        StoreOp::SetOwner { key: owner_key(&account), value: other },
+        StoreOp::SetBalance { key: balance_key(&account), value: 0 },
+    ]);
+}
+
+#[test]
+fn accounts_created_and_closed_within_the_transaction() {
+    let (user, mint, account) = (pubkey(1), pubkey(2), pubkey(3));
+    let initialize_account3 = [vec![18], user.0.to_vec()].concat();
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(TOKEN_PROGRAM_ID, &[account, mint], initialize_account3)
+        .instruction(TOKEN_PROGRAM_ID, &[account, user, user], vec![9])
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert_eq!(context.token_accounts.len(), 1);
+    assert!(from_context(&context, &transaction).is_empty());
+}
+
+#[test]
+fn unreadable_balances() {
+    let (user, mint, account, out_of_bounds) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    let mut transaction = TransactionBuilder::new()
+        .signer(user)
+        .token_account(account, mint, user, None, Some(7))
+        .token_account(out_of_bounds, mint, user, None, Some(7))
+        .build();
+    let meta = transaction.meta.as_mut().unwrap();
+    meta.post_token_balances[0].ui_token_amount = None;
+    meta.post_token_balances[1].account_index = 99;
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert_eq!(from_context(&context, &transaction), [StoreOp::SetOwner { key: owner_key(&account), value: user }]);
+
+    let without_meta = ConfirmedTransaction { meta: None, ..transaction.clone() };
+    assert!(from_context(&context, &without_meta).is_empty());
+}
+
+#[test]
+fn wrapped_sol_balances() {
+    let (user, mint, wrapped, account) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .token_account(wrapped, WRAPPED_SOL_MINT, user, None, Some(2_000_000))
+        .token_account(account, mint, user, Some(1), Some(2))
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert_eq!(from_context(&context, &transaction).len(), 3);
+
+    let options = StoreOpOptions { skip_wrapped_sol_balances: true };
+    assert_eq!(from_context_with_options(&context, &transaction, &options), [
+        StoreOp::SetOwner { key: owner_key(&wrapped), value: user },
+        StoreOp::SetBalance { key: balance_key(&account), value: 2 },
+    ]);
+}
@@ -0,0 +1,98 @@
+//! Supply changes of the mints of a block, see `supply_deltas`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::{Block, ConfirmedTransaction};
+use substreams_solana_utils::block::{supply_deltas, supply_deltas_with_options, SupplyDelta, SupplyDeltaOptions, INCINERATOR};
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::spl_token::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+
+fn amount_instruction(tag: u8, amount: u64) -> Vec<u8> {
+    [vec![tag], amount.to_le_bytes().to_vec()].concat()
+}
+
+fn block(transactions: Vec<ConfirmedTransaction>) -> Block {
+    Block { transactions, ..Default::default() }
+}
+
+/// Mint, minted, burned, incinerated and whether amounts are unknown
+fn summary(deltas: &[SupplyDelta]) -> Vec<(Pubkey, u128, u128, u128, bool)> {
+    deltas.iter().map(|x| (x.mint, x.minted, x.burned, x.incinerated, x.amounts_unknown)).collect()
+}
+
+#[test]
+fn mints_and_burns_of_both_token_programs() {
+    let (authority, token_2022_mint, mint, account) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    let deltas = supply_deltas(&block(vec![
+        TransactionBuilder::new()
+            .signer(authority)
+            .instruction(TOKEN_PROGRAM_ID, &[mint, account, authority], amount_instruction(7, 1000))
+            .instruction(TOKEN_2022_PROGRAM_ID, &[token_2022_mint, account, authority], [amount_instruction(14, 500), vec![6]].concat())
+            .signature(&[1; 64])
+            .build(),
+        TransactionBuilder::new()
+            .signer(authority)
+            .instruction(TOKEN_2022_PROGRAM_ID, &[account, token_2022_mint, authority], amount_instruction(8, 200))
+            .signature(&[2; 64])
+            .build(),
+        TransactionBuilder::new()
+            .signer(authority)
+            .instruction(TOKEN_PROGRAM_ID, &[mint, account, authority], amount_instruction(7, 1000))
+            .failed()
+            .build(),
+    ]));
+
+    assert_eq!(summary(&deltas), [(token_2022_mint, 500, 200, 0, false), (mint, 1000, 0, 0, false)]);
+    assert_eq!(deltas[0].net, 300);
+    assert_eq!(deltas[0].transaction_signatures, [bs58::encode([1; 64]).into_string(), bs58::encode([2; 64]).into_string()]);
+    assert_eq!((&deltas[0].minting_authorities[..], &deltas[0].burning_sources[..]), (&[authority][..], &[account][..]));
+}
+
+#[test]
+fn mints_only_transferred_are_not_reported() {
+    let (user, burned_mint, transferred_mint, source, incinerated) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let incinerate = |mint: Pubkey, amount: u64| TransactionBuilder::new()
+        .signer(user)
+        .instruction(TOKEN_PROGRAM_ID, &[source, incinerated, user], amount_instruction(3, amount))
+        .token_account(source, mint, user, Some(amount), Some(0))
+        .token_account(incinerated, mint, INCINERATOR, Some(0), Some(amount))
+        .build();
+    let block = block(vec![
+        TransactionBuilder::new()
+            .signer(user)
+            .instruction(TOKEN_PROGRAM_ID, &[source, burned_mint, user], amount_instruction(8, 10))
+            .build(),
+        incinerate(burned_mint, 40),
+        incinerate(transferred_mint, 70),
+    ]);
+
+    assert_eq!(summary(&supply_deltas(&block)), [(burned_mint, 0, 10, 0, false)]);
+    let options = SupplyDeltaOptions { include_incinerator_transfers: true };
+    assert_eq!(summary(&supply_deltas_with_options(&block, &options)), [(burned_mint, 0, 10, 40, false)]);
+}
+
+#[test]
+fn token_2022_mints_hiding_amounts() {
+    let (authority, account, confidential_mint, fee_mint, other_fee_mint, destination) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5), pubkey(6));
+    let transfer_checked_with_fee = [vec![26, 1], 100u64.to_le_bytes().to_vec(), vec![6], 1u64.to_le_bytes().to_vec()].concat();
+    let deltas = supply_deltas(&block(vec![
+        TransactionBuilder::new()
+            .signer(authority)
+            .instruction(TOKEN_2022_PROGRAM_ID, &[account, confidential_mint, authority], vec![42, 3, 0])
+            .instruction(TOKEN_2022_PROGRAM_ID, &[account, confidential_mint, authority], vec![42, 4, 0])
+            .build(),
+        TransactionBuilder::new()
+            .signer(authority)
+            .instruction(TOKEN_2022_PROGRAM_ID, &[fee_mint, account, authority], amount_instruction(7, 100))
+            .build(),
+        TransactionBuilder::new()
+            .signer(authority)
+            .instruction(TOKEN_2022_PROGRAM_ID, &[account, fee_mint, destination, authority], transfer_checked_with_fee.clone())
+            .instruction(TOKEN_2022_PROGRAM_ID, &[account, other_fee_mint, destination, authority], transfer_checked_with_fee)
+            .build(),
+    ]));
+
+    assert_eq!(summary(&deltas), [(confidential_mint, 0, 0, 0, true), (fee_mint, 100, 0, 0, true)]);
+    assert_eq!(deltas[0].transaction_signatures.len(), 1);
+}
@@ -0,0 +1,107 @@
+//! Token accounts registered across the transactions of a block, and undoing
+//! them through nested snapshots, see `BlockTokenRegistry`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana::pb::sf::solana::r#type::v1::ConfirmedTransaction;
+use substreams_solana_utils::error::ParseError;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::spl_token::TOKEN_PROGRAM_ID;
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::token_registry::{BlockTokenRegistry, RegisteredTokenAccount};
+use substreams_solana_utils::transaction::TransactionContext;
+
+fn apply(registry: &mut BlockTokenRegistry, transaction: &ConfirmedTransaction) {
+    registry.apply(&TransactionContext::build(transaction).unwrap(), transaction).unwrap();
+}
+
+/// A transaction moving tokens of `mint` out of `closed`, if any, into `account`
+fn transfer(mint: Pubkey, account: Pubkey, closed: Option<Pubkey>) -> ConfirmedTransaction {
+    let user = pubkey(1);
+    let builder = TransactionBuilder::new().signer(user).account(TOKEN_PROGRAM_ID).token_account(account, mint, user, Some(0), Some(5));
+    match closed {
+        Some(closed) => builder.token_account(closed, mint, user, Some(5), None).build(),
+        None => builder.build(),
+    }
+}
+
+/// Registered accounts among `addresses`, in order
+fn registered(registry: &BlockTokenRegistry, addresses: &[Pubkey]) -> Vec<Pubkey> {
+    addresses.iter().filter(|x| registry.get_token_account(x).is_some()).copied().collect()
+}
+
+#[test]
+fn nested_snapshots() {
+    let (owner, mint, a, b, c) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let account = RegisteredTokenAccount { mint, owner };
+    let mut registry = BlockTokenRegistry::new();
+    registry.insert_account(a, account);
+
+    let outer = registry.snapshot();
+    registry.insert_account(b, account);
+    registry.set_decimals(mint, 6);
+    let middle = registry.snapshot();
+    registry.remove_account(&a);
+    let inner = registry.snapshot();
+    registry.insert_account(c, account);
+    registry.set_decimals(mint, 9);
+
+    // Committed writes are kept, until an enclosing snapshot is restored
+    registry.commit(inner).unwrap();
+    assert_eq!((registered(&registry, &[a, b, c]), registry.decimals(&mint)), (vec![b, c], Some(9)));
+    registry.restore(middle).unwrap();
+    assert_eq!((registered(&registry, &[a, b, c]), registry.decimals(&mint)), (vec![a, b], Some(6)));
+    registry.restore(outer).unwrap();
+    assert_eq!((registered(&registry, &[a, b, c]), registry.decimals(&mint)), (vec![a], None));
+}
+
+#[test]
+fn snapshots_ended_by_an_enclosing_one() {
+    let (owner, mint, a) = (pubkey(1), pubkey(2), pubkey(3));
+    let mut registry = BlockTokenRegistry::new();
+    let outer = registry.snapshot();
+    let inner = registry.snapshot();
+    registry.insert_account(a, RegisteredTokenAccount { mint, owner });
+
+    registry.commit(outer).unwrap();
+    assert_eq!(registry.restore(inner).unwrap_err().to_string(), "Snapshot 1 already ended");
+    assert_eq!(registered(&registry, &[a]), [a]);
+}
+
+#[test]
+fn restore_then_continue() {
+    let (mint, a, b, c) = (pubkey(2), pubkey(3), pubkey(4), pubkey(5));
+    let mut registry = BlockTokenRegistry::new();
+
+    // A failed attempt at a transaction, undone before applying the next one
+    let attempt = registry.snapshot();
+    apply(&mut registry, &transfer(mint, a, None));
+    registry.restore(attempt).unwrap();
+    assert!(registry.is_empty() && registry.decimals(&mint).is_none());
+
+    apply(&mut registry, &transfer(mint, b, None));
+    let next = registry.snapshot();
+    apply(&mut registry, &transfer(mint, c, Some(b)));
+    assert_eq!(registered(&registry, &[a, b, c]), [c]);
+    registry.restore(next).unwrap();
+    assert_eq!(registered(&registry, &[a, b, c]), [b]);
+
+    // Writes made once every snapshot ended are no longer undone
+    apply(&mut registry, &transfer(mint, a, None));
+    let last = registry.snapshot();
+    registry.remove_account(&b);
+    registry.restore(last).unwrap();
+    assert_eq!(registered(&registry, &[a, b, c]), [a, b]);
+    assert_eq!(registry.decimals(&mint), Some(0));
+}
+
+#[test]
+fn transactions_without_meta() {
+    let (mint, a) = (pubkey(2), pubkey(3));
+    let transaction = transfer(mint, a, None);
+    let context = TransactionContext::build(&transaction).unwrap();
+    let without_meta = ConfirmedTransaction { meta: None, ..transaction.clone() };
+    let mut registry = BlockTokenRegistry::new();
+    assert_eq!(registry.apply(&context, &without_meta), Err(ParseError::MissingMeta));
+    assert!(registry.is_empty() && registry.decimals(&mint).is_none());
+}
@@ -0,0 +1,87 @@
+//! Contexts of transactions that involve no token program, whose instructions
+//! are not scanned for token account creations, see
+//! `TransactionContext::token_tracking_skipped`.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana_utils::programs::KnownProgram;
+use substreams_solana_utils::pubkey::Pubkey;
+use substreams_solana_utils::report::{balance_sheet, check_conservation, PartyRole};
+use substreams_solana_utils::spl_token::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use substreams_solana_utils::test_utils::{pubkey, TransactionBuilder};
+use substreams_solana_utils::transaction::TransactionContext;
+
+fn initialize_account3(owner: Pubkey) -> Vec<u8> {
+    [vec![18], owner.0.to_vec()].concat()
+}
+
+/// Mint and owner of the token account at `address` in `context`
+fn token_account(context: &TransactionContext, address: Pubkey) -> Option<(Pubkey, Pubkey)> {
+    let address = context.accounts.iter().find(|x| **x == address)?;
+    context.get_token_account(address).map(|x| (x.mint, x.owner))
+}
+
+#[test]
+fn transactions_without_token_program() {
+    let (user, recipient) = (pubkey(1), pubkey(2));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(KnownProgram::ComputeBudget.program_id(), &[], vec![2, 0, 0, 0, 0])
+        .instruction(KnownProgram::System.program_id(), &[user, recipient], [2u32.to_le_bytes().to_vec(), 100u64.to_le_bytes().to_vec()].concat())
+        .lamports(user, 1_000, 895)
+        .lamports(recipient, 0, 100)
+        .fee(5)
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert!(context.token_tracking_skipped && context.token_accounts.is_empty());
+    assert_eq!(context.sol_delta(context.accounts.iter().find(|x| **x == recipient).unwrap()), 100);
+
+    let parties = balance_sheet(&context, &transaction).unwrap();
+    assert_eq!(parties.iter().map(|x| (x.owner, x.role)).collect::<Vec<_>>(), [(user, PartyRole::FeePayer), (recipient, PartyRole::Counterparty)]);
+    assert!(check_conservation(&transaction, &parties).unwrap().is_empty());
+}
+
+#[test]
+fn token_2022_creations_are_tracked() {
+    let (user, account, mint) = (pubkey(1), pubkey(2), pubkey(3));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(TOKEN_2022_PROGRAM_ID, &[account, mint], initialize_account3(user))
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert!(!context.token_tracking_skipped);
+    assert_eq!(token_account(&context, account), Some((mint, user)));
+}
+
+#[test]
+fn token_program_invoked_through_cpi_only() {
+    let (user, router, account, mint) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    let transaction = TransactionBuilder::new()
+        .signer(user)
+        .instruction(router, &[user, account, mint], vec![0])
+        .inner(TOKEN_PROGRAM_ID, &[account, mint], initialize_account3(user))
+        .build();
+    let context = TransactionContext::build(&transaction).unwrap();
+    assert!(!context.token_tracking_skipped);
+    assert_eq!(token_account(&context, account), Some((mint, user)));
+}
+
+#[test]
+fn token_program_loaded_from_a_lookup_table() {
+    let (user, router, account, mint) = (pubkey(1), pubkey(2), pubkey(3), pubkey(4));
+    for token_program in [TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID] {
+        let transaction = TransactionBuilder::new()
+            .signer(user)
+            .lookup(router, false)
+            .lookup(token_program, false)
+            .lookup(account, true)
+            .instruction(router, &[user, account, mint], vec![0])
+            .inner(token_program, &[account, mint], initialize_account3(user))
+            .build();
+        let context = TransactionContext::build(&transaction).unwrap();
+        let message = transaction.transaction.as_ref().unwrap().message.as_ref().unwrap();
+        assert!(!message.account_keys.contains(&token_program.0.to_vec()));
+        assert!(!context.token_tracking_skipped);
+        assert_eq!(token_account(&context, account), Some((mint, user)));
+    }
+}
@@ -0,0 +1,107 @@
+//! Errors of failed transactions, decoded from `meta.err`, see the
+//! `transaction_error` module.
+
+#![cfg(feature = "test-utils")]
+
+use substreams_solana_utils::golden::transaction_from_rpc_json;
+use substreams_solana_utils::transaction::{get_transaction_error, TransactionContext};
+use substreams_solana_utils::transaction_error::{InstructionError, TransactionError};
+
+/// Failed transactions: the `err` of the `getTransaction` RPC response, the
+/// bincode encoded `meta.err` of the same transaction, and the decoded error
+fn fixtures() -> Vec<(&'static str, Vec<u8>, TransactionError)> {
+    vec![
+        (r#"{"InstructionError": [2, {"Custom": 6001}]}"#, vec![8, 0, 0, 0, 2, 24, 0, 0, 0, 0x71, 0x17, 0, 0], TransactionError::InstructionError { index: 2, error: InstructionError::Custom(6001) }),
+        (r#"{"InstructionError": [0, {"Custom": 1}]}"#, vec![8, 0, 0, 0, 0, 24, 0, 0, 0, 1, 0, 0, 0], TransactionError::InstructionError { index: 0, error: InstructionError::Custom(1) }),
+        (r#"{"InstructionError": [0, "InvalidAccountData"]}"#, vec![8, 0, 0, 0, 0, 3, 0, 0, 0], TransactionError::InstructionError { index: 0, error: InstructionError::InvalidAccountData }),
+        (r#"{"InstructionError": [3, "ComputationalBudgetExceeded"]}"#, vec![8, 0, 0, 0, 3, 36, 0, 0, 0], TransactionError::InstructionError { index: 3, error: InstructionError::ComputationalBudgetExceeded }),
+        (r#"{"InstructionError": [1, "ProgramFailedToComplete"]}"#, vec![8, 0, 0, 0, 1, 39, 0, 0, 0], TransactionError::InstructionError { index: 1, error: InstructionError::ProgramFailedToComplete }),
+        (
+            r#"{"InstructionError": [1, {"BorshIoError": "Unknown"}]}"#,
+            [&[8, 0, 0, 0, 1, 43, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0][..], b"Unknown"].concat(),
+            TransactionError::InstructionError { index: 1, error: InstructionError::BorshIoError("Unknown".into()) },
+        ),
+        (r#""InsufficientFundsForFee""#, vec![4, 0, 0, 0], TransactionError::InsufficientFundsForFee),
+        (r#""AlreadyProcessed""#, vec![6, 0, 0, 0], TransactionError::AlreadyProcessed),
+        (r#""BlockhashNotFound""#, vec![7, 0, 0, 0], TransactionError::BlockhashNotFound),
+        (r#""WouldExceedMaxBlockCostLimit""#, vec![17, 0, 0, 0], TransactionError::WouldExceedMaxBlockCostLimit),
+        (r#"{"DuplicateInstruction": 1}"#, vec![30, 0, 0, 0, 1], TransactionError::DuplicateInstruction(1)),
+        (r#"{"InsufficientFundsForRent": {"account_index": 3}}"#, vec![31, 0, 0, 0, 3], TransactionError::InsufficientFundsForRent { account_index: 3 }),
+    ]
+}
+
+/// A failed transfer of the system program, as returned by the RPC
+fn rpc_transaction(err: &str) -> String {
+    format!(r#"{{
+        "slot": 1,
+        "transaction": {{
+            "signatures": ["{signature}"],
+            "message": {{
+                "header": {{"numRequiredSignatures": 1, "numReadonlySignedAccounts": 0, "numReadonlyUnsignedAccounts": 1}},
+                "accountKeys": ["{user}", "{recipient}", "11111111111111111111111111111111"],
+                "recentBlockhash": "{user}",
+                "instructions": [{{"programIdIndex": 2, "accounts": [0, 1], "data": "3Bxs4NN8M2Yn4TLb"}}]
+            }}
+        }},
+        "meta": {{
+            "err": {err},
+            "fee": 5000,
+            "preBalances": [10000000, 0, 1],
+            "postBalances": [9995000, 0, 1],
+            "innerInstructions": [],
+            "logMessages": [],
+            "preTokenBalances": [],
+            "postTokenBalances": []
+        }}
+    }}"#,
+        signature = bs58::encode([1; 64]).into_string(),
+        user = bs58::encode([1; 32]).into_string(),
+        recipient = bs58::encode([2; 32]).into_string(),
+    )
+}
+
+#[test]
+fn fixtures_decode_to_their_errors() {
+    for (rpc_err, err, error) in fixtures() {
+        assert_eq!(TransactionError::decode(&err).as_ref(), Some(&error), "{}", rpc_err);
+        assert_eq!(error.encode(), err, "{}", rpc_err);
+    }
+}
+
+#[test]
+fn rpc_transactions_keep_their_errors() {
+    for (rpc_err, err, error) in fixtures() {
+        let transaction = transaction_from_rpc_json(&rpc_transaction(rpc_err)).unwrap();
+        assert_eq!(transaction.meta.as_ref().unwrap().err.as_ref().unwrap().err, err, "{}", rpc_err);
+        assert_eq!(get_transaction_error(&transaction).as_ref(), Some(&error), "{}", rpc_err);
+        assert_eq!(TransactionContext::build(&transaction).unwrap().transaction_error(), Some(&error), "{}", rpc_err);
+    }
+
+    let transaction = transaction_from_rpc_json(&rpc_transaction("null")).unwrap();
+    assert_eq!(get_transaction_error(&transaction), None);
+    assert!(transaction_from_rpc_json(&rpc_transaction(r#""NotAnError""#)).is_err());
+}
+
+#[test]
+fn failing_instruction_and_custom_code() {
+    let errors: Vec<_> = fixtures().into_iter().map(|(_, _, error)| error).collect();
+    let attributed: Vec<_> = errors.iter().map(|x| (x.instruction_index(), x.custom_error_code())).collect();
+    assert_eq!(attributed[..3], [(Some(2), Some(6001)), (Some(0), Some(1)), (Some(0), None)]);
+    assert_eq!(errors[6].instruction_index(), None);
+}
+
+#[test]
+fn unknown_variants_keep_their_index() {
+    assert_eq!(TransactionError::decode(&[99, 0, 0, 0, 1, 2]), Some(TransactionError::Unknown(99)));
+    let error = TransactionError::decode(&[8, 0, 0, 0, 1, 99, 0, 0, 0, 1, 2]);
+    assert_eq!(error, Some(TransactionError::InstructionError { index: 1, error: InstructionError::Unknown(99) }));
+}
+
+#[test]
+fn malformed_errors_are_none() {
+    assert_eq!(TransactionError::decode(&[]), None);
+    assert_eq!(TransactionError::decode(&[7, 0, 0]), None);
+    assert_eq!(TransactionError::decode(&[7, 0, 0, 0, 0]), None);
+    assert_eq!(TransactionError::decode(&[8, 0, 0, 0, 2, 24, 0, 0, 0, 0x71]), None);
+    assert_eq!(TransactionError::decode(&[8, 0, 0, 0, 1, 43, 0, 0, 0, 200, 0, 0, 0, 0, 0, 0, 0, b'a']), None);
+}